@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
 use std::env;
 
+use crate::utils::{FuzzyMethod, NormalizationRules, DEFAULT_SYSTEM_PROMPT};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub server: ServerConfig,
@@ -8,6 +11,18 @@ pub struct Config {
     pub security: SecurityConfig,
     pub cache: CacheSettings,
     pub openrouter: OpenRouterSettings,
+    pub conversations: ConversationSettings,
+    pub sandbox: SandboxSettings,
+    pub handoff: HandoffSettings,
+    pub provider_capture: ProviderCaptureSettings,
+    pub alerts: AlertSettings,
+    pub agents: AgentSettings,
+    pub guardrails: GuardrailSettings,
+    pub loop_guard: LoopGuardSettings,
+    pub log_store: LogStoreSettings,
+    pub outbound: OutboundSettings,
+    pub knowledge_base: KnowledgeBaseSettings,
+    pub trial: TrialSettings,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,41 +31,344 @@ pub struct ServerConfig {
     pub port: u16,
     pub workers: usize,
     pub max_json_payload_size: usize,
+    pub blocking_threads: usize,
+    /// Serves the manual-testing playground at `/ui`; keep off in production.
+    pub ui_enabled: bool,
+    /// Refuse to start when `.env` or the environment holds variables the
+    /// service does not read, such as a misspelled setting.
+    pub strict_config: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AiConfig {
     pub model_name: String,
+    /// Model directory, or for GGUF weights the file itself.
     pub model_path: Option<String>,
+    /// One of [`MODEL_FORMATS`]; `auto` picks by file extension.
+    pub model_format: String,
+    /// Small model sharing the main model's vocabulary that proposes tokens
+    /// for speculative decoding; found or downloaded like `model_name`.
+    pub draft_model_name: Option<String>,
+    /// Directory of the draft model's safetensors weights. Either this or
+    /// `draft_model_name` turns speculative decoding on.
+    pub draft_model_path: Option<String>,
+    /// Tokens the draft model proposes for each verification by the main model.
+    pub speculative_tokens: usize,
+    /// `auto`, `cpu`, `cuda[:N]` or `metal[:N]`; an unavailable device falls
+    /// back to the CPU.
+    pub device: String,
+    /// One of [`LOCAL_BACKENDS`]: `candle` generates in-process, the others
+    /// proxy the local tier to a model server.
+    pub local_backend: String,
+    /// Base URL of the `llama-server` used when `local_backend` is `llama_cpp`.
+    pub llama_cpp_url: String,
+    /// Sent as a bearer token when `llama-server` runs with `--api-key`.
+    pub llama_cpp_api_key: Option<String>,
+    /// Base URL of the Ollama daemon used when `local_backend` is `ollama`.
+    pub ollama_url: String,
+    /// Ollama model the local tier asks for, e.g. `llama3.2:3b`.
+    pub ollama_model: String,
+    pub system_prompt: String,
     pub huggingface_cache_dir: Option<String>,
+    /// Download missing weights from the Hugging Face Hub into the cache dir.
+    pub model_auto_download: bool,
+    pub huggingface_token: Option<String>,
+    /// Downloads are refused if they would leave less than this much free disk.
+    pub model_min_free_disk_mb: u64,
+    /// Weight files fetched in parallel across all models.
+    pub model_max_concurrent_downloads: usize,
     pub context_length: usize,
+    /// One of [`PROMPT_OVERFLOW_STRATEGIES`]: how a local prompt whose input
+    /// would not fit in `context_length` with its answer is handled.
+    pub prompt_overflow: String,
     pub temperature: f32,
     pub top_p: f32,
     pub max_tokens: usize,
     pub quantized: bool,
     pub quantization_bits: Option<usize>,
+    pub strict_grounding: bool,
+    pub grounding_threshold: f32,
+    /// Search results kept, most relevant first, when enriching a local
+    /// prompt; 0 keeps them all.
+    pub enrichment_max_sources: usize,
+    /// Screens search results for prompt injection before they enrich a prompt.
+    pub injection_detection: bool,
+    /// One of [`INJECTION_ACTIONS`]: what happens to a suspicious result.
+    pub injection_action: String,
+    /// JSON logistic-regression classifier that judges results instead of
+    /// the heuristics alone.
+    pub injection_classifier_path: Option<String>,
+    /// Messages scoring at least this much are routed as Medium complexity.
+    /// The score is the length in characters plus the signal weights below.
+    pub complexity_medium_threshold: usize,
+    /// Messages scoring at least this much are routed as High complexity.
+    pub complexity_high_threshold: usize,
+    /// Added to the score of a message holding a stack trace.
+    pub complexity_stack_trace_weight: usize,
+    /// Added per fenced code block, for up to three.
+    pub complexity_code_block_weight: usize,
+    /// Added per question after the first, for up to five.
+    pub complexity_question_weight: usize,
+    /// Added when the message is not in English.
+    pub complexity_language_weight: usize,
+    /// JSON logistic-regression classifier that picks the tier instead of
+    /// the thresholds.
+    pub complexity_classifier_path: Option<String>,
+    pub inference_cpu_cores: Vec<usize>,
+    pub inference_thread_nice: Option<i32>,
+    /// Local model weights are dropped after this many seconds without a
+    /// generation and loaded again on the next one; 0 keeps them loaded.
+    pub model_idle_unload_secs: u64,
+    /// Dedicated threads local generations run on.
+    pub inference_workers: usize,
+    /// Generations waiting for a worker before new ones fail.
+    pub inference_queue_depth: usize,
+    /// Generations run at once; 0 removes the limit.
+    pub max_concurrent_generations: usize,
+    /// Requests allowed to wait for a generation slot before new ones get a 429.
+    pub max_queued_generations: usize,
+    /// Answer in the language the user writes in when the request doesn't name one.
+    pub detect_language: bool,
+    /// Wall-clock budget for one local generation in seconds (0 = none). Output
+    /// produced before the budget runs out is kept as a partial answer.
+    pub generation_timeout_secs: u64,
+    /// Version label for prompt templates; cached answers are tagged with it.
+    pub prompt_version: String,
+    /// Identifier of the knowledge-base snapshot answers are grounded in.
+    pub knowledge_base_snapshot: Option<String>,
+    /// Seeds every generation with `deterministic_seed` and disables the response cache.
+    pub deterministic: bool,
+    pub deterministic_seed: u64,
+    /// Directory of `<name>.txt` prompt templates merged over the built-ins.
+    pub prompt_templates_dir: Option<String>,
+    /// Sentence encoder for semantic ranking, e.g. `sentence-transformers/all-MiniLM-L6-v2`;
+    /// unset keeps ranking lexical.
+    pub embedding_model: Option<String>,
+    /// Local directory of the encoder; otherwise it is found in (or downloaded
+    /// to) the Hugging Face cache like the chat model.
+    pub embedding_model_path: Option<String>,
+    /// Further local models a chat request can select by name with `model`.
+    pub local_models: Vec<LocalModelSettings>,
+    /// LoRA adapters for the default local model; a chat request selects
+    /// one with `adapter`.
+    pub lora_adapters: Vec<LoraAdapterSettings>,
+    /// Adapters from `lora_adapters` merged into the default model itself.
+    pub lora_merge: Vec<String>,
+    /// Adapter models kept loaded at once; loading another unloads the least
+    /// recently used one.
+    pub lora_max_loaded: usize,
+}
+
+/// A named local model besides the default one, loaded on first use.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalModelSettings {
+    /// Name requests use to select it.
+    pub name: String,
+    /// Hugging Face model id, also used to find or download the weights.
+    pub model_name: String,
+    pub model_path: Option<String>,
 }
 
+/// A PEFT LoRA adapter, merged into the base weights when loaded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoraAdapterSettings {
+    /// Name requests use to select it.
+    pub name: String,
+    /// Directory holding `adapter_config.json` and `adapter_model.safetensors`.
+    pub path: String,
+    /// Multiplies the adapter's own `lora_alpha / r` scaling.
+    pub scale: f32,
+}
+
+/// What `INJECTION_ACTION` accepts to do with a search result that looks like
+/// prompt injection: leave it out of the prompt, or keep it marked as untrusted.
+pub const INJECTION_ACTIONS: &[&str] = &["strip", "flag"];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecurityConfig {
     pub rate_limit_requests: u32,
     pub rate_limit_period: u64,
     pub allowed_origins: Vec<String>,
+    /// Bearer token for `/api/admin`; the admin API is disabled when unset.
+    pub admin_token: Option<String>,
+    /// Keys accepted on the generation endpoints. When empty, callers need no
+    /// key unless the trial tier is enabled.
+    pub api_keys: Vec<String>,
+    /// Tenant of each API key that has one, keyed by the key. Per-tenant
+    /// limits apply by the authenticated key, never by a caller's header.
+    pub key_tenants: HashMap<String, String>,
+    /// Endpoint groups from [`ENDPOINT_GROUPS`] that are not mounted at all.
+    pub disabled_endpoint_groups: Vec<String>,
+}
+
+/// Endpoint groups that can be switched off with `DISABLED_ENDPOINT_GROUPS`.
+/// Health, chat, conversations, feedback and agents are always mounted.
+pub const ENDPOINT_GROUPS: &[&str] = &["scripts", "log_analysis", "admin", "compat"];
+
+impl SecurityConfig {
+    pub fn endpoint_group_enabled(&self, group: &str) -> bool {
+        !self.disabled_endpoint_groups.iter().any(|g| g == group)
+    }
+}
+
+/// Keyless access to `/api/chat` for trying the service: tightly
+/// rate-limited per client and never routed to the cloud model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrialSettings {
+    pub enabled: bool,
+    /// Requests each client may make per `window_secs`.
+    pub requests_per_window: u32,
+    pub window_secs: u64,
+    /// Cap on `max_tokens` for trial requests.
+    pub max_tokens: usize,
+    /// Identify clients by `X-Forwarded-For`; only safe behind a proxy that sets it.
+    pub trust_forwarded_for: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheSettings {
+    /// Tiers in lookup order. A tier missing from the list is not used at all.
+    pub tiers: Vec<CacheTierSettings>,
+    /// Applied to the message before it is hashed into the cache key.
+    pub key_normalization: NormalizationRules,
     pub redis_url: String,
+    pub redis_username: Option<String>,
+    pub redis_password: Option<String>,
+    pub redis_ca_cert_path: Option<String>,
+    pub redis_client_cert_path: Option<String>,
+    pub redis_client_key_path: Option<String>,
     pub redis_max_memory_mb: u64,
     pub redis_ttl_seconds: u64,
     pub sqlite_path: String,
     pub sqlite_max_size_gb: u64,
     pub sqlite_ttl_days: u32,
+    /// Durable tier behind Redis: `sqlite` (at `sqlite_path`) or `postgres`
+    /// (at `postgres_url`). Size and TTL limits use the `sqlite_*` settings.
+    pub durable_backend: String,
+    pub postgres_url: String,
+    /// Extra CA trusted for the Postgres server, on top of the public roots.
+    pub postgres_ca_cert_path: Option<String>,
+    pub postgres_client_cert_path: Option<String>,
+    pub postgres_client_key_path: Option<String>,
+    /// On an exact miss, serve the answer to an earlier question in the same
+    /// namespace that is at least `similarity_threshold` similar.
+    pub similar_lookup: bool,
     pub similarity_threshold: f32,
+    /// Earlier questions passing the fuzzy pre-filter that are compared by embedding.
     pub max_similar_results: usize,
+    /// Cheap string comparison that picks candidates for the similar lookup.
+    pub fuzzy_method: FuzzyMethod,
+    /// Candidates scoring below this fuzzy ratio are never embedded.
+    pub fuzzy_min_ratio: f32,
     pub memory_cache_entries: usize,
+    /// Memory-tier eviction: `lru`, `lfu` or `tinylfu` (LRU order, but new
+    /// entries are only admitted once requested more often than what they evict).
+    pub memory_eviction_policy: String,
+    /// Memory-tier budget for serialized entries; 0 limits by count only.
+    pub memory_max_mb: u64,
     pub memory_ttl_seconds: u64,
-    pub cache_probability: f32,
+    /// Which generated answers are stored.
+    pub write_policy: CacheWritePolicy,
+    /// TTL for answers to time-sensitive questions (latest versions, current
+    /// status, dates); 0 keeps them out of the cache entirely.
+    pub time_sensitive_ttl_seconds: u64,
+    /// How long past its TTL an entry may still be served while a fresh
+    /// answer is generated in the background; 0 disables it. Memory and the
+    /// durable tier only, since Redis deletes expired keys.
+    pub stale_while_revalidate_seconds: u64,
+    /// Redis pub/sub channel on which instances announce invalidations so the
+    /// others drop the same entries from memory; empty disables it.
+    pub invalidation_channel: String,
+    /// Distinct callers whose negative ratings evict a cached answer; 0 never
+    /// evicts.
+    pub feedback_eviction_threshold: u64,
+    /// How long responses are replayed for a repeated `Idempotency-Key`.
+    pub idempotency_ttl_seconds: u64,
+    /// Redis/SQLite writes buffered before the oldest is dropped.
+    pub write_queue_capacity: usize,
+    /// Tries per buffered write before it is given up.
+    pub write_max_attempts: u32,
+    /// Seconds between expired-entry sweeps; 0 disables them.
+    pub cleanup_interval_secs: u64,
+    /// Seconds between snapshots of the hit counters into the SQLite cache
+    /// file for `GET /api/admin/cache/stats/history`; 0 disables them.
+    pub stats_flush_interval_secs: u64,
+    /// Snapshots older than this are deleted; 0 keeps them forever.
+    pub stats_retention_days: u32,
+    /// Redis/SQLite entries at least this large are stored zstd-compressed; 0 disables.
+    pub compression_min_bytes: usize,
+    /// Policy for `/api/generate-script` responses.
+    pub scripts: EndpointCacheSettings,
+    /// Policy for `/api/analyze-logs` responses.
+    pub log_analysis: EndpointCacheSettings,
+}
+
+/// Caching for an endpoint outside the chat path. Entries go through the same
+/// tiers and write policy as chat answers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointCacheSettings {
+    pub enabled: bool,
+    pub ttl_seconds: u64,
+    /// Key on the normalized input, so requests differing only in case,
+    /// punctuation or incidental detail share an entry; otherwise the exact input.
+    pub match_similar: bool,
+}
+
+/// One cache tier: `memory`, `redis` or `durable` (the SQLite or Postgres
+/// store chosen by `durable_backend`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheTierSettings {
+    pub name: String,
+    /// New answers are stored in this tier; when off it is only read.
+    pub write: bool,
+}
+
+/// Most tokens `SPECULATIVE_TOKENS` lets the draft model propose at once.
+pub const MAX_SPECULATIVE_TOKENS: usize = 16;
+
+/// Weight formats `MODEL_FORMAT` accepts.
+pub const MODEL_FORMATS: &[&str] = &["auto", "safetensors", "gguf"];
+
+/// Local generation backends `LOCAL_BACKEND` accepts.
+pub const LOCAL_BACKENDS: &[&str] = &["candle", "llama_cpp", "ollama"];
+
+/// Ways `PROMPT_OVERFLOW` accepts of handling a prompt over the context
+/// window: refuse it, drop the start or the middle of its input, or condense
+/// the input with the model.
+pub const PROMPT_OVERFLOW_STRATEGIES: &[&str] =
+    &["reject", "truncate_head", "truncate_middle", "summarize"];
+
+/// Names accepted in [`CacheSettings::tiers`].
+pub const CACHE_TIER_NAMES: &[&str] = &["memory", "redis", "durable"];
+
+/// Names accepted by `CACHE_WRITE_POLICY`.
+pub const CACHE_WRITE_POLICIES: &[&str] = &["always", "cloud", "never"];
+
+/// Which generated answers [`CacheSettings::write_policy`] stores.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheWritePolicy {
+    #[default]
+    Always,
+    /// Only answers from the enriched or cloud paths.
+    Cloud,
+    /// Serve existing entries only.
+    Never,
+}
+
+impl CacheWritePolicy {
+    pub fn parse(name: &str) -> anyhow::Result<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "always" => Ok(Self::Always),
+            "cloud" => Ok(Self::Cloud),
+            "never" => Ok(Self::Never),
+            other => anyhow::bail!(
+                "Unknown CACHE_WRITE_POLICY {:?}, expected one of {}",
+                other,
+                CACHE_WRITE_POLICIES.join(", ")
+            ),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,6 +376,170 @@ pub struct OpenRouterSettings {
     pub api_key: String,
     pub base_url: String,
     pub default_model: String,
+    /// Used for requests with image attachments unless they name a model.
+    pub vision_model: String,
+    /// USD per million prompt tokens, used when the provider does not report a cost.
+    pub prompt_price_per_mtok: f64,
+    /// USD per million completion tokens, used when the provider does not report a cost.
+    pub completion_price_per_mtok: f64,
+    /// Once reached, High-complexity traffic is served locally until the next UTC day.
+    pub daily_spend_cap_usd: Option<f64>,
+    /// Once reached, High-complexity traffic is served locally until the next UTC month.
+    pub monthly_spend_cap_usd: Option<f64>,
+    /// Spend ledger; accounting and caps are disabled when empty.
+    pub spend_sqlite_path: String,
+    /// Mask emails, IPs, hostnames, keys and phone numbers before forwarding;
+    /// requests can opt out with `redact_pii: false`.
+    pub redact_pii: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSettings {
+    pub sqlite_path: String,
+    /// Optional read replica of `sqlite_path` used for admin reporting queries.
+    pub analytics_replica_path: Option<String>,
+    /// Idle read-only connections kept for reporting queries.
+    pub analytics_pool_size: usize,
+    /// Seconds between topic labeling passes; 0 disables labeling.
+    pub topic_label_interval_secs: u64,
+    /// Conversations idle this long count as finished and get a topic.
+    pub topic_idle_minutes: u64,
+    /// Messages kept per conversation; older turns are archived. 0 keeps all.
+    pub max_messages: usize,
+    /// Messages older than this are archived; 0 keeps them regardless of age.
+    pub max_message_age_days: u64,
+    /// Seconds between archiving passes; 0 disables archiving.
+    pub archive_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxSettings {
+    pub enabled: bool,
+    pub python_path: String,
+    /// bubblewrap binary every snippet runs under.
+    pub bwrap_path: String,
+    pub timeout_ms: u64,
+    pub memory_limit_mb: u64,
+    /// Processes a snippet may start, beyond the threads the service runs.
+    pub max_processes: u64,
+    pub max_output_bytes: usize,
+    pub max_snippets: usize,
+    pub max_snippet_chars: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandoffSettings {
+    pub webhook_url: Option<String>,
+    /// Grounded answers whose supported ratio falls below this are escalated.
+    pub confidence_threshold: f32,
+    pub webhook_timeout_ms: u64,
+}
+
+/// Debug capture of outbound provider payloads, stored redacted for `retention_hours`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderCaptureSettings {
+    pub enabled: bool,
+    pub sqlite_path: String,
+    pub retention_hours: u64,
+}
+
+/// Webhook notifications when a dependency changes health state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertSettings {
+    /// Slack-compatible incoming webhook; alerting is disabled when unset.
+    pub webhook_url: Option<String>,
+    pub check_interval_secs: u64,
+    /// Consecutive checks a new state must hold before it is reported.
+    pub debounce_checks: u32,
+    /// Directory whose filesystem is watched for free space.
+    pub disk_path: String,
+    pub disk_min_free_mb: u64,
+    pub webhook_timeout_ms: u64,
+}
+
+/// Heartbeats from installed selfcare agents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentSettings {
+    pub sqlite_path: String,
+    /// Bearer token agents must send; heartbeats are refused when unset.
+    pub token: Option<String>,
+    /// Current release; agents are never reported outdated when unset.
+    pub latest_version: Option<String>,
+    /// Agents silent for longer are listed as disconnected.
+    pub offline_after_secs: u64,
+}
+
+/// Content policy screening of requests and completions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GuardrailSettings {
+    pub enabled: bool,
+    /// Built-in categories to apply.
+    pub categories: Vec<String>,
+    /// JSON array of additional rules; a rule with a built-in category replaces it.
+    pub rules_path: Option<String>,
+    /// What to do when an answer repeats the system prompt or a template:
+    /// `refuse`, `redact` or `allow`. Applies even when `enabled` is false.
+    pub prompt_leak_policy: String,
+    /// Consecutive words an answer must share with a prompt to count as a leak.
+    pub prompt_leak_min_words: usize,
+}
+
+/// Per-conversation limits that stop automated clients stuck in a loop.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoopGuardSettings {
+    /// Messages accepted per conversation in any 60 second window; 0 disables the limit.
+    pub max_messages_per_minute: u32,
+    /// Consecutive messages repeating one of the conversation's recent answers; 0 disables.
+    pub echo_threshold: u32,
+    /// `max_messages_per_minute` overrides keyed by the tenant of the caller's
+    /// API key (see `SecurityConfig::key_tenants`).
+    pub tenant_limits: HashMap<String, u32>,
+}
+
+/// Parsed log uploads keyed by content hash, reused when the same file is analyzed again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogStoreSettings {
+    /// Empty disables the store; every upload is then parsed.
+    pub sqlite_path: String,
+    /// Digests unused for longer are dropped; 0 keeps them forever.
+    pub retention_days: u32,
+}
+
+/// Runbooks and docs pulled on a schedule and retrieved alongside web search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnowledgeBaseSettings {
+    /// Document URLs, and Git repositories as `git+<url>[#branch]`; empty disables syncing.
+    pub sources: Vec<String>,
+    /// Holds the index versions; empty disables the knowledge base.
+    pub sqlite_path: String,
+    /// Working copies of the Git sources.
+    pub checkout_dir: String,
+    /// Seconds between syncs; 0 only loads the stored index.
+    pub sync_interval_secs: u64,
+    /// Documents are split into chunks of about this many characters.
+    pub chunk_chars: usize,
+    /// Chunks added to the sources of a searched request.
+    pub max_results: usize,
+    /// Chunks scoring below this similarity to the question are not used.
+    pub min_score: f32,
+}
+
+/// TLS settings for calls to providers and webhooks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OutboundSettings {
+    /// The first rule whose pattern matches the destination host applies.
+    pub client_certs: Vec<ClientCertRule>,
+}
+
+/// Client certificate presented to hosts matching `host_pattern`, either an
+/// exact host or `*.example.com` for its subdomains.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientCertRule {
+    pub host_pattern: String,
+    pub cert_path: String,
+    pub key_path: String,
+    /// Extra CA trusted for these hosts, for gateways behind a private CA.
+    pub ca_cert_path: Option<String>,
 }
 
 impl Default for Config {
@@ -68,40 +550,229 @@ impl Default for Config {
                 port: 5732,
                 workers: num_cpus::get(),
                 max_json_payload_size: 2_000_000, // 2MB
+                blocking_threads: 512,
+                ui_enabled: false,
+                strict_config: false,
             },
             ai: AiConfig {
                 model_name: "TinyLlama/TinyLlama-1.1B-Chat-v1.0".to_string(),
                 model_path: None,
+                model_format: "auto".to_string(),
+                draft_model_name: None,
+                draft_model_path: None,
+                speculative_tokens: 4,
+                device: "auto".to_string(),
+                local_backend: "candle".to_string(),
+                llama_cpp_url: "http://127.0.0.1:8080".to_string(),
+                llama_cpp_api_key: None,
+                ollama_url: "http://127.0.0.1:11434".to_string(),
+                ollama_model: String::new(),
+                system_prompt: DEFAULT_SYSTEM_PROMPT.to_string(),
                 huggingface_cache_dir: None,
+                model_auto_download: false,
+                huggingface_token: None,
+                model_min_free_disk_mb: 2_048,
+                model_max_concurrent_downloads: 2,
                 context_length: 2048,
+                prompt_overflow: "reject".to_string(),
                 temperature: 0.7,
                 top_p: 0.9,
                 max_tokens: 2048,
                 quantized: true,
                 quantization_bits: Some(4),
+                strict_grounding: false,
+                grounding_threshold: 0.2,
+                enrichment_max_sources: 5,
+                injection_detection: true,
+                injection_action: "strip".to_string(),
+                injection_classifier_path: None,
+                complexity_medium_threshold: 200,
+                complexity_high_threshold: 800,
+                complexity_stack_trace_weight: 400,
+                complexity_code_block_weight: 150,
+                complexity_question_weight: 75,
+                complexity_language_weight: 150,
+                complexity_classifier_path: None,
+                inference_cpu_cores: Vec::new(),
+                inference_thread_nice: None,
+                model_idle_unload_secs: 0,
+                inference_workers: 4,
+                inference_queue_depth: 64,
+                max_concurrent_generations: 4,
+                max_queued_generations: 32,
+                detect_language: true,
+                generation_timeout_secs: 300,
+                prompt_version: "v1".to_string(),
+                knowledge_base_snapshot: None,
+                deterministic: false,
+                deterministic_seed: 42,
+                prompt_templates_dir: None,
+                embedding_model: None,
+                embedding_model_path: None,
+                local_models: Vec::new(),
+                lora_adapters: Vec::new(),
+                lora_merge: Vec::new(),
+                lora_max_loaded: 1,
             },
             security: SecurityConfig {
                 rate_limit_requests: 100,
                 rate_limit_period: 3600,
                 allowed_origins: vec!["*".to_string()],
+                admin_token: None,
+                api_keys: Vec::new(),
+                key_tenants: HashMap::new(),
+                disabled_endpoint_groups: Vec::new(),
             },
             cache: CacheSettings {
+                tiers: CACHE_TIER_NAMES
+                    .iter()
+                    .map(|name| CacheTierSettings {
+                        name: name.to_string(),
+                        write: true,
+                    })
+                    .collect(),
+                key_normalization: NormalizationRules::default(),
                 redis_url: "redis://127.0.0.1:6379".to_string(),
+                redis_username: None,
+                redis_password: None,
+                redis_ca_cert_path: None,
+                redis_client_cert_path: None,
+                redis_client_key_path: None,
                 redis_max_memory_mb: 2048,
                 redis_ttl_seconds: 86_400,
                 sqlite_path: "data/ai_cache.sqlite".to_string(),
                 sqlite_max_size_gb: 10,
                 sqlite_ttl_days: 30,
+                durable_backend: "sqlite".to_string(),
+                postgres_url: "".to_string(),
+                postgres_ca_cert_path: None,
+                postgres_client_cert_path: None,
+                postgres_client_key_path: None,
+                similar_lookup: false,
                 similarity_threshold: 0.92,
                 max_similar_results: 3,
+                fuzzy_method: FuzzyMethod::default(),
+                fuzzy_min_ratio: 0.6,
                 memory_cache_entries: 512,
+                memory_eviction_policy: "lru".to_string(),
+                memory_max_mb: 64,
                 memory_ttl_seconds: 3_600,
-                cache_probability: 0.3,
+                write_policy: CacheWritePolicy::default(),
+                time_sensitive_ttl_seconds: 300,
+                stale_while_revalidate_seconds: 0,
+                invalidation_channel: "selfcare:cache:invalidations".to_string(),
+                feedback_eviction_threshold: 3,
+                idempotency_ttl_seconds: 86_400,
+                write_queue_capacity: 1_024,
+                write_max_attempts: 5,
+                cleanup_interval_secs: 3_600,
+                stats_flush_interval_secs: 300,
+                stats_retention_days: 30,
+                compression_min_bytes: 2_048,
+                scripts: EndpointCacheSettings {
+                    enabled: true,
+                    ttl_seconds: 604_800,
+                    match_similar: true,
+                },
+                log_analysis: EndpointCacheSettings {
+                    enabled: false,
+                    ttl_seconds: 3_600,
+                    match_similar: false,
+                },
             },
             openrouter: OpenRouterSettings {
                 api_key: "".to_string(),
                 base_url: "https://openrouter.ai/api/v1".to_string(),
                 default_model: "openrouter/auto".to_string(),
+                vision_model: "openai/gpt-4o-mini".to_string(),
+                prompt_price_per_mtok: 0.0,
+                completion_price_per_mtok: 0.0,
+                daily_spend_cap_usd: None,
+                monthly_spend_cap_usd: None,
+                spend_sqlite_path: "data/openrouter_spend.sqlite".to_string(),
+                redact_pii: true,
+            },
+            conversations: ConversationSettings {
+                sqlite_path: "data/conversations.sqlite".to_string(),
+                analytics_replica_path: None,
+                analytics_pool_size: 4,
+                topic_label_interval_secs: 900,
+                topic_idle_minutes: 30,
+                max_messages: 200,
+                max_message_age_days: 0,
+                archive_interval_secs: 600,
+            },
+            sandbox: SandboxSettings {
+                enabled: false,
+                python_path: "python3".to_string(),
+                bwrap_path: "bwrap".to_string(),
+                timeout_ms: 2_000,
+                memory_limit_mb: 256,
+                max_processes: 32,
+                max_output_bytes: 8_192,
+                max_snippets: 3,
+                max_snippet_chars: 2_000,
+            },
+            handoff: HandoffSettings {
+                webhook_url: None,
+                confidence_threshold: 0.5,
+                webhook_timeout_ms: 5_000,
+            },
+            provider_capture: ProviderCaptureSettings {
+                enabled: false,
+                sqlite_path: "data/provider_captures.sqlite".to_string(),
+                retention_hours: 24,
+            },
+            alerts: AlertSettings {
+                webhook_url: None,
+                check_interval_secs: 30,
+                debounce_checks: 2,
+                disk_path: "data".to_string(),
+                disk_min_free_mb: 1_024,
+                webhook_timeout_ms: 5_000,
+            },
+            agents: AgentSettings {
+                sqlite_path: "data/agents.sqlite".to_string(),
+                token: None,
+                latest_version: None,
+                offline_after_secs: 300,
+            },
+            guardrails: GuardrailSettings {
+                enabled: false,
+                categories: vec![
+                    "self_harm".to_string(),
+                    "malware".to_string(),
+                    "credential_exfiltration".to_string(),
+                ],
+                rules_path: None,
+                prompt_leak_policy: "redact".to_string(),
+                prompt_leak_min_words: 8,
+            },
+            loop_guard: LoopGuardSettings {
+                max_messages_per_minute: 20,
+                echo_threshold: 2,
+                tenant_limits: HashMap::new(),
+            },
+            log_store: LogStoreSettings {
+                sqlite_path: "data/log_store.sqlite".to_string(),
+                retention_days: 7,
+            },
+            outbound: OutboundSettings::default(),
+            knowledge_base: KnowledgeBaseSettings {
+                sources: Vec::new(),
+                sqlite_path: "data/knowledge_base.sqlite".to_string(),
+                checkout_dir: "data/kb_sources".to_string(),
+                sync_interval_secs: 3_600,
+                chunk_chars: 1_200,
+                max_results: 4,
+                min_score: 0.15,
+            },
+            trial: TrialSettings {
+                enabled: false,
+                requests_per_window: 5,
+                window_secs: 3_600,
+                max_tokens: 256,
+                trust_forwarded_for: false,
             },
         }
     }
@@ -112,110 +783,941 @@ impl Config {
         dotenv::dotenv().ok();
 
         let mut config = Config::default();
+        let mut vars = EnvReader::default();
+
+        if let Ok(strict) = vars.var("CONFIG_STRICT") {
+            config.server.strict_config = strict.parse()?;
+        }
 
         // Server configuration
-        if let Ok(host) = env::var("HOST") {
+        if let Ok(host) = vars.var("HOST") {
             config.server.host = host;
         }
-        if let Ok(port) = env::var("PORT") {
+        if let Ok(port) = vars.var("PORT") {
             config.server.port = port.parse()?;
         }
-        if let Ok(workers) = env::var("WORKERS") {
+        if let Ok(workers) = vars.var("WORKERS") {
             config.server.workers = workers.parse()?;
         }
-        if let Ok(max_json_payload_size) = env::var("MAX_JSON_PAYLOAD_SIZE") {
+        if let Ok(max_json_payload_size) = vars.var("MAX_JSON_PAYLOAD_SIZE") {
             config.server.max_json_payload_size = max_json_payload_size.parse()?;
         }
+        if let Ok(blocking_threads) = vars.var("BLOCKING_THREADS") {
+            config.server.blocking_threads = blocking_threads.parse()?;
+        }
+        if let Ok(ui_enabled) = vars.var("UI_ENABLED") {
+            config.server.ui_enabled = ui_enabled.parse()?;
+        }
 
         // AI configuration
-        if let Ok(model_name) = env::var("MODEL_NAME") {
+        if let Ok(model_name) = vars.var("MODEL_NAME") {
             config.ai.model_name = model_name;
         }
-        if let Ok(model_path) = env::var("MODEL_PATH") {
+        if let Ok(model_path) = vars.var("MODEL_PATH") {
             config.ai.model_path = Some(model_path);
         }
-        if let Ok(huggingface_cache_dir) = env::var("HUGGINGFACE_CACHE_DIR") {
+        if let Ok(format) = vars.var("MODEL_FORMAT") {
+            let format = format.trim().to_lowercase();
+            if !MODEL_FORMATS.contains(&format.as_str()) {
+                anyhow::bail!(
+                    "Unknown MODEL_FORMAT {:?}, expected one of {}",
+                    format,
+                    MODEL_FORMATS.join(", ")
+                );
+            }
+            config.ai.model_format = format;
+        }
+        if let Ok(draft_model) = vars.var("DRAFT_MODEL") {
+            config.ai.draft_model_name =
+                Some(draft_model.trim().to_string()).filter(|v| !v.is_empty());
+        }
+        if let Ok(draft_path) = vars.var("DRAFT_MODEL_PATH") {
+            config.ai.draft_model_path =
+                Some(draft_path.trim().to_string()).filter(|v| !v.is_empty());
+        }
+        if let Ok(tokens) = vars.var("SPECULATIVE_TOKENS") {
+            config.ai.speculative_tokens = tokens.parse()?;
+            if !(1..=MAX_SPECULATIVE_TOKENS).contains(&config.ai.speculative_tokens) {
+                anyhow::bail!(
+                    "SPECULATIVE_TOKENS must be between 1 and {}",
+                    MAX_SPECULATIVE_TOKENS
+                );
+            }
+        }
+        if let Ok(device) = vars.var("DEVICE") {
+            let device = device.trim().to_lowercase();
+            let (kind, ordinal) = device.split_once(':').unwrap_or((device.as_str(), "0"));
+            let valid = match kind {
+                "auto" | "cpu" => !device.contains(':'),
+                "cuda" | "metal" => ordinal.parse::<usize>().is_ok(),
+                _ => false,
+            };
+            if !valid {
+                anyhow::bail!(
+                    "Unknown DEVICE {:?}, expected auto, cpu, cuda[:N] or metal[:N]",
+                    device
+                );
+            }
+            config.ai.device = device;
+        }
+        if let Ok(backend) = vars.var("LOCAL_BACKEND") {
+            let backend = backend.trim().to_lowercase();
+            if !LOCAL_BACKENDS.contains(&backend.as_str()) {
+                anyhow::bail!(
+                    "Unknown LOCAL_BACKEND {:?}, expected one of {}",
+                    backend,
+                    LOCAL_BACKENDS.join(", ")
+                );
+            }
+            config.ai.local_backend = backend;
+        }
+        if let Ok(url) = vars.var("LLAMA_CPP_URL") {
+            config.ai.llama_cpp_url = url.trim().trim_end_matches('/').to_string();
+        }
+        if let Ok(api_key) = vars.var("LLAMA_CPP_API_KEY") {
+            config.ai.llama_cpp_api_key = Some(api_key).filter(|v| !v.is_empty());
+        }
+        if let Ok(url) = vars.var("OLLAMA_URL") {
+            config.ai.ollama_url = url.trim().trim_end_matches('/').to_string();
+        }
+        if let Ok(model) = vars.var("OLLAMA_MODEL") {
+            config.ai.ollama_model = model.trim().to_string();
+        }
+        if config.ai.local_backend == "ollama" && config.ai.ollama_model.is_empty() {
+            anyhow::bail!("LOCAL_BACKEND=ollama needs OLLAMA_MODEL");
+        }
+        if let Ok(system_prompt) = vars.var("SYSTEM_PROMPT") {
+            if !system_prompt.trim().is_empty() {
+                config.ai.system_prompt = system_prompt;
+            }
+        }
+        if let Ok(huggingface_cache_dir) = vars.var("HUGGINGFACE_CACHE_DIR") {
             config.ai.huggingface_cache_dir = Some(huggingface_cache_dir);
         }
-        if let Ok(context_length) = env::var("CONTEXT_LENGTH") {
+        if let Ok(auto_download) = vars.var("MODEL_AUTO_DOWNLOAD") {
+            config.ai.model_auto_download = auto_download.parse()?;
+        }
+        if let Ok(token) = vars.var("HF_TOKEN") {
+            config.ai.huggingface_token = Some(token).filter(|v| !v.is_empty());
+        }
+        if let Ok(min_free_disk_mb) = vars.var("MODEL_MIN_FREE_DISK_MB") {
+            config.ai.model_min_free_disk_mb = min_free_disk_mb.parse()?;
+        }
+        if let Ok(max_downloads) = vars.var("MODEL_MAX_CONCURRENT_DOWNLOADS") {
+            config.ai.model_max_concurrent_downloads = max_downloads.parse()?;
+        }
+        if let Ok(context_length) = vars.var("CONTEXT_LENGTH") {
             config.ai.context_length = context_length.parse()?;
         }
-        if let Ok(temperature) = env::var("TEMPERATURE") {
+        if let Ok(overflow) = vars.var("PROMPT_OVERFLOW") {
+            let overflow = overflow.trim().to_lowercase();
+            if !PROMPT_OVERFLOW_STRATEGIES.contains(&overflow.as_str()) {
+                anyhow::bail!(
+                    "Unknown PROMPT_OVERFLOW {:?}, expected one of {}",
+                    overflow,
+                    PROMPT_OVERFLOW_STRATEGIES.join(", ")
+                );
+            }
+            config.ai.prompt_overflow = overflow;
+        }
+        if let Ok(temperature) = vars.var("TEMPERATURE") {
             config.ai.temperature = temperature.parse()?;
         }
-        if let Ok(top_p) = env::var("TOP_P") {
+        if let Ok(top_p) = vars.var("TOP_P") {
             config.ai.top_p = top_p.parse()?;
         }
-        if let Ok(max_tokens) = env::var("MAX_TOKENS") {
+        if let Ok(max_tokens) = vars.var("MAX_TOKENS") {
             config.ai.max_tokens = max_tokens.parse()?;
         }
-        if let Ok(quantized) = env::var("QUANTIZED") {
+        if let Ok(quantized) = vars.var("QUANTIZED") {
             config.ai.quantized = quantized.parse()?;
         }
-        if let Ok(quantization_bits) = env::var("QUANTIZATION_BITS") {
+        if let Ok(quantization_bits) = vars.var("QUANTIZATION_BITS") {
             config.ai.quantization_bits = Some(quantization_bits.parse()?);
         }
+        if let Ok(strict_grounding) = vars.var("STRICT_GROUNDING") {
+            config.ai.strict_grounding = strict_grounding.parse()?;
+        }
+        if let Ok(grounding_threshold) = vars.var("GROUNDING_THRESHOLD") {
+            config.ai.grounding_threshold = grounding_threshold.parse()?;
+        }
+        if let Ok(max_sources) = vars.var("ENRICHMENT_MAX_SOURCES") {
+            config.ai.enrichment_max_sources = max_sources.parse()?;
+        }
+        if let Ok(enabled) = vars.var("INJECTION_DETECTION") {
+            config.ai.injection_detection = enabled.parse()?;
+        }
+        if let Ok(action) = vars.var("INJECTION_ACTION") {
+            let action = action.trim().to_lowercase();
+            if !INJECTION_ACTIONS.contains(&action.as_str()) {
+                anyhow::bail!(
+                    "Unknown INJECTION_ACTION {:?}, expected one of {}",
+                    action,
+                    INJECTION_ACTIONS.join(", ")
+                );
+            }
+            config.ai.injection_action = action;
+        }
+        if let Ok(path) = vars.var("INJECTION_CLASSIFIER_PATH") {
+            config.ai.injection_classifier_path = Some(path).filter(|v| !v.is_empty());
+        }
+        if let Ok(threshold) = vars.var("COMPLEXITY_MEDIUM_THRESHOLD") {
+            config.ai.complexity_medium_threshold = threshold.parse()?;
+        }
+        if let Ok(threshold) = vars.var("COMPLEXITY_HIGH_THRESHOLD") {
+            config.ai.complexity_high_threshold = threshold.parse()?;
+        }
+        if config.ai.complexity_medium_threshold > config.ai.complexity_high_threshold {
+            anyhow::bail!("COMPLEXITY_MEDIUM_THRESHOLD must not exceed COMPLEXITY_HIGH_THRESHOLD");
+        }
+        if let Ok(weight) = vars.var("COMPLEXITY_STACK_TRACE_WEIGHT") {
+            config.ai.complexity_stack_trace_weight = weight.parse()?;
+        }
+        if let Ok(weight) = vars.var("COMPLEXITY_CODE_BLOCK_WEIGHT") {
+            config.ai.complexity_code_block_weight = weight.parse()?;
+        }
+        if let Ok(weight) = vars.var("COMPLEXITY_QUESTION_WEIGHT") {
+            config.ai.complexity_question_weight = weight.parse()?;
+        }
+        if let Ok(weight) = vars.var("COMPLEXITY_LANGUAGE_WEIGHT") {
+            config.ai.complexity_language_weight = weight.parse()?;
+        }
+        if let Ok(path) = vars.var("COMPLEXITY_CLASSIFIER_PATH") {
+            config.ai.complexity_classifier_path = Some(path).filter(|v| !v.is_empty());
+        }
+        if let Ok(inference_cpu_cores) = vars.var("INFERENCE_CPU_CORES") {
+            config.ai.inference_cpu_cores = inference_cpu_cores
+                .split(',')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse())
+                .collect::<Result<_, _>>()?;
+        }
+        if let Ok(inference_thread_nice) = vars.var("INFERENCE_THREAD_NICE") {
+            if !inference_thread_nice.is_empty() {
+                config.ai.inference_thread_nice = Some(inference_thread_nice.parse()?);
+            }
+        }
+        if let Ok(idle) = vars.var("MODEL_IDLE_UNLOAD_SECS") {
+            config.ai.model_idle_unload_secs = idle.parse()?;
+        }
+        if let Ok(workers) = vars.var("INFERENCE_WORKERS") {
+            config.ai.inference_workers = workers.parse()?;
+            if config.ai.inference_workers == 0 {
+                anyhow::bail!("INFERENCE_WORKERS must be at least 1");
+            }
+        }
+        if let Ok(depth) = vars.var("INFERENCE_QUEUE_DEPTH") {
+            config.ai.inference_queue_depth = depth.parse()?;
+        }
+        if let Ok(max_concurrent) = vars.var("MAX_CONCURRENT_GENERATIONS") {
+            config.ai.max_concurrent_generations = max_concurrent.parse()?;
+        }
+        if let Ok(max_queued) = vars.var("MAX_QUEUED_GENERATIONS") {
+            config.ai.max_queued_generations = max_queued.parse()?;
+        }
+        if let Ok(detect_language) = vars.var("DETECT_RESPONSE_LANGUAGE") {
+            config.ai.detect_language = detect_language.parse()?;
+        }
+        if let Ok(timeout) = vars.var("GENERATION_TIMEOUT_SECS") {
+            config.ai.generation_timeout_secs = timeout.parse()?;
+        }
+        if let Ok(prompt_version) = vars.var("PROMPT_VERSION") {
+            config.ai.prompt_version = prompt_version;
+        }
+        if let Ok(snapshot) = vars.var("KNOWLEDGE_BASE_SNAPSHOT") {
+            config.ai.knowledge_base_snapshot = Some(snapshot).filter(|v| !v.is_empty());
+        }
+        if let Ok(deterministic) = vars.var("DETERMINISTIC_GENERATION") {
+            config.ai.deterministic = deterministic.parse()?;
+        }
+        if let Ok(seed) = vars.var("DETERMINISTIC_SEED") {
+            config.ai.deterministic_seed = seed.parse()?;
+        }
+        if let Ok(dir) = vars.var("PROMPT_TEMPLATES_DIR") {
+            config.ai.prompt_templates_dir = Some(dir).filter(|v| !v.is_empty());
+        }
+        if let Ok(model) = vars.var("EMBEDDING_MODEL") {
+            config.ai.embedding_model = Some(model).filter(|v| !v.is_empty());
+        }
+        if let Ok(path) = vars.var("EMBEDDING_MODEL_PATH") {
+            config.ai.embedding_model_path = Some(path).filter(|v| !v.is_empty());
+        }
+        if let Ok(models) = vars.var("LOCAL_MODELS") {
+            // Comma-separated `name=org/model`, with `@/path/to/weights` for a local copy.
+            let mut parsed: Vec<LocalModelSettings> = Vec::new();
+            for entry in models.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+                let Some((name, model)) = entry.split_once('=') else {
+                    anyhow::bail!(
+                        "Invalid LOCAL_MODELS entry (expected name=model): {}",
+                        entry
+                    );
+                };
+                let name = name.trim().to_string();
+                let (model_name, model_path) = match model.split_once('@') {
+                    Some((model_name, path)) => (model_name.trim(), Some(path.trim().to_string())),
+                    None => (model.trim(), None),
+                };
+                if name.is_empty() || model_name.is_empty() {
+                    anyhow::bail!(
+                        "Invalid LOCAL_MODELS entry (expected name=model): {}",
+                        entry
+                    );
+                }
+                if parsed.iter().any(|m| m.name == name) {
+                    anyhow::bail!("Model listed twice in LOCAL_MODELS: {}", name);
+                }
+                parsed.push(LocalModelSettings {
+                    name,
+                    model_name: model_name.to_string(),
+                    model_path: model_path.filter(|p| !p.is_empty()),
+                });
+            }
+            config.ai.local_models = parsed;
+        }
+        if let Ok(adapters) = vars.var("LORA_ADAPTERS") {
+            // Comma-separated `name=/path/to/adapter`, with `:scale` to weaken or strengthen it.
+            let mut parsed: Vec<LoraAdapterSettings> = Vec::new();
+            for entry in adapters.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+                let Some((name, path)) = entry.split_once('=') else {
+                    anyhow::bail!(
+                        "Invalid LORA_ADAPTERS entry (expected name=path): {}",
+                        entry
+                    );
+                };
+                let name = name.trim().to_string();
+                let (path, scale) = match path.rsplit_once(':') {
+                    Some((path, scale)) if scale.trim().parse::<f32>().is_ok() => {
+                        (path.trim(), scale.trim().parse()?)
+                    }
+                    _ => (path.trim(), 1.0),
+                };
+                if name.is_empty() || path.is_empty() {
+                    anyhow::bail!(
+                        "Invalid LORA_ADAPTERS entry (expected name=path): {}",
+                        entry
+                    );
+                }
+                if parsed.iter().any(|a| a.name == name) {
+                    anyhow::bail!("Adapter listed twice in LORA_ADAPTERS: {}", name);
+                }
+                parsed.push(LoraAdapterSettings {
+                    name,
+                    path: path.to_string(),
+                    scale,
+                });
+            }
+            config.ai.lora_adapters = parsed;
+        }
+        if let Ok(merge) = vars.var("LORA_MERGE") {
+            config.ai.lora_merge = merge
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect();
+            if let Some(unknown) = config
+                .ai
+                .lora_merge
+                .iter()
+                .find(|name| !config.ai.lora_adapters.iter().any(|a| &a.name == *name))
+            {
+                anyhow::bail!(
+                    "LORA_MERGE names an adapter not in LORA_ADAPTERS: {}",
+                    unknown
+                );
+            }
+        }
+        if let Ok(max_loaded) = vars.var("LORA_MAX_LOADED") {
+            config.ai.lora_max_loaded = max_loaded.parse()?;
+            if config.ai.lora_max_loaded == 0 {
+                anyhow::bail!("LORA_MAX_LOADED must be at least 1");
+            }
+        }
 
         // Security configuration
-        if let Ok(rate_limit_requests) = env::var("RATE_LIMIT_REQUESTS") {
+        if let Ok(rate_limit_requests) = vars.var("RATE_LIMIT_REQUESTS") {
             config.security.rate_limit_requests = rate_limit_requests.parse()?;
         }
-        if let Ok(rate_limit_period) = env::var("RATE_LIMIT_PERIOD") {
+        if let Ok(rate_limit_period) = vars.var("RATE_LIMIT_PERIOD") {
             config.security.rate_limit_period = rate_limit_period.parse()?;
         }
-        if let Ok(allowed_origins) = env::var("ALLOWED_ORIGINS") {
+        if let Ok(allowed_origins) = vars.var("ALLOWED_ORIGINS") {
             config.security.allowed_origins = allowed_origins
                 .split(',')
                 .map(|s| s.trim().to_string())
                 .collect();
         }
+        if let Ok(admin_token) = vars.var("ADMIN_API_TOKEN") {
+            config.security.admin_token = Some(admin_token).filter(|v| !v.is_empty());
+        }
+        if let Ok(api_keys) = vars.var("API_KEYS") {
+            config.security.api_keys = api_keys
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Ok(key_tenants) = vars.var("API_KEY_TENANTS") {
+            // Comma-separated `tenant=key` pairs; each key must be in API_KEYS.
+            config.security.key_tenants = key_tenants
+                .split(',')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (tenant, key) = pair
+                        .split_once('=')
+                        .map(|(tenant, key)| (tenant.trim(), key.trim()))
+                        .filter(|(tenant, key)| !tenant.is_empty() && !key.is_empty())
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("Invalid API_KEY_TENANTS entry, expected tenant=key")
+                        })?;
+                    if !config.security.api_keys.iter().any(|k| k == key) {
+                        anyhow::bail!(
+                            "API_KEY_TENANTS maps tenant {:?} to a key missing from API_KEYS",
+                            tenant
+                        );
+                    }
+                    Ok((key.to_string(), tenant.to_string()))
+                })
+                .collect::<anyhow::Result<_>>()?;
+        }
+        if let Ok(groups) = vars.var("DISABLED_ENDPOINT_GROUPS") {
+            let mut parsed = Vec::new();
+            for group in groups.split(',').map(|g| g.trim().to_lowercase()) {
+                if group.is_empty() {
+                    continue;
+                }
+                if !ENDPOINT_GROUPS.contains(&group.as_str()) {
+                    anyhow::bail!(
+                        "Unknown DISABLED_ENDPOINT_GROUPS entry: {} (expected one of {})",
+                        group,
+                        ENDPOINT_GROUPS.join(", ")
+                    );
+                }
+                if !parsed.contains(&group) {
+                    parsed.push(group);
+                }
+            }
+            config.security.disabled_endpoint_groups = parsed;
+        }
+
+        // Trial tier configuration
+        if let Ok(enabled) = vars.var("TRIAL_ACCESS_ENABLED") {
+            config.trial.enabled = enabled.parse()?;
+        }
+        if let Ok(requests) = vars.var("TRIAL_RATE_LIMIT_REQUESTS") {
+            config.trial.requests_per_window = requests.parse()?;
+        }
+        if let Ok(period) = vars.var("TRIAL_RATE_LIMIT_PERIOD") {
+            config.trial.window_secs = period.parse()?;
+        }
+        if let Ok(max_tokens) = vars.var("TRIAL_MAX_TOKENS") {
+            config.trial.max_tokens = max_tokens.parse()?;
+        }
+        if let Ok(trust) = vars.var("TRIAL_TRUST_FORWARDED_FOR") {
+            config.trial.trust_forwarded_for = trust.parse()?;
+        }
+        if config.trial.enabled && config.security.api_keys.is_empty() {
+            anyhow::bail!("TRIAL_ACCESS_ENABLED=true needs API_KEYS for full access");
+        }
 
         // Cache configuration
-        if let Ok(redis_url) = env::var("REDIS_URL") {
+        if let Ok(redis_url) = vars.var("REDIS_URL") {
             config.cache.redis_url = redis_url;
         }
-        if let Ok(redis_max_memory_mb) = env::var("REDIS_MAX_MEMORY_MB") {
+        if let Ok(redis_username) = vars.var("REDIS_USERNAME") {
+            config.cache.redis_username = Some(redis_username).filter(|v| !v.is_empty());
+        }
+        if let Ok(redis_password) = vars.var("REDIS_PASSWORD") {
+            config.cache.redis_password = Some(redis_password).filter(|v| !v.is_empty());
+        }
+        if let Ok(redis_ca_cert) = vars.var("REDIS_CA_CERT") {
+            config.cache.redis_ca_cert_path = Some(redis_ca_cert).filter(|v| !v.is_empty());
+        }
+        if let Ok(redis_client_cert) = vars.var("REDIS_CLIENT_CERT") {
+            config.cache.redis_client_cert_path = Some(redis_client_cert).filter(|v| !v.is_empty());
+        }
+        if let Ok(redis_client_key) = vars.var("REDIS_CLIENT_KEY") {
+            config.cache.redis_client_key_path = Some(redis_client_key).filter(|v| !v.is_empty());
+        }
+        if let Ok(redis_max_memory_mb) = vars.var("REDIS_MAX_MEMORY_MB") {
             config.cache.redis_max_memory_mb = redis_max_memory_mb.parse()?;
         }
-        if let Ok(redis_ttl_seconds) = env::var("REDIS_TTL_SECONDS") {
+        if let Ok(redis_ttl_seconds) = vars.var("REDIS_TTL_SECONDS") {
             config.cache.redis_ttl_seconds = redis_ttl_seconds.parse()?;
         }
-        if let Ok(sqlite_path) = env::var("SQLITE_PATH") {
+        if let Ok(sqlite_path) = vars.var("SQLITE_PATH") {
             config.cache.sqlite_path = sqlite_path;
         }
-        if let Ok(sqlite_max_size_gb) = env::var("SQLITE_MAX_SIZE_GB") {
+        if let Ok(sqlite_max_size_gb) = vars.var("SQLITE_MAX_SIZE_GB") {
             config.cache.sqlite_max_size_gb = sqlite_max_size_gb.parse()?;
         }
-        if let Ok(sqlite_ttl_days) = env::var("SQLITE_TTL_DAYS") {
+        if let Ok(sqlite_ttl_days) = vars.var("SQLITE_TTL_DAYS") {
             config.cache.sqlite_ttl_days = sqlite_ttl_days.parse()?;
         }
-        if let Ok(similarity_threshold) = env::var("SIMILARITY_THRESHOLD") {
+        if let Ok(tiers) = vars.var("CACHE_TIERS") {
+            // Comma-separated tier names in lookup order, `:readonly` to skip writes.
+            let mut parsed: Vec<CacheTierSettings> = Vec::new();
+            for entry in tiers.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+                let (name, write) = match entry.split_once(':') {
+                    Some((name, "readonly")) => (name.trim().to_lowercase(), false),
+                    None => (entry.to_lowercase(), true),
+                    Some(_) => anyhow::bail!("Invalid CACHE_TIERS entry: {}", entry),
+                };
+                if !CACHE_TIER_NAMES.contains(&name.as_str()) {
+                    anyhow::bail!("Unknown cache tier in CACHE_TIERS: {}", name);
+                }
+                if parsed.iter().any(|tier| tier.name == name) {
+                    anyhow::bail!("Cache tier listed twice in CACHE_TIERS: {}", name);
+                }
+                parsed.push(CacheTierSettings { name, write });
+            }
+            config.cache.tiers = parsed;
+        }
+        if let Ok(rules) = vars.var("CACHE_KEY_NORMALIZATION") {
+            config.cache.key_normalization = NormalizationRules::parse(&rules.to_lowercase())
+                .map_err(|e| anyhow::anyhow!("Invalid CACHE_KEY_NORMALIZATION: {}", e))?;
+        }
+        if let Ok(backend) = vars.var("CACHE_DURABLE_BACKEND") {
+            config.cache.durable_backend = backend.trim().to_lowercase();
+        }
+        if let Ok(postgres_url) = vars.var("CACHE_POSTGRES_URL") {
+            config.cache.postgres_url = postgres_url;
+        }
+        if let Ok(ca_cert) = vars.var("CACHE_POSTGRES_CA_CERT") {
+            config.cache.postgres_ca_cert_path = Some(ca_cert).filter(|v| !v.is_empty());
+        }
+        if let Ok(client_cert) = vars.var("CACHE_POSTGRES_CLIENT_CERT") {
+            config.cache.postgres_client_cert_path = Some(client_cert).filter(|v| !v.is_empty());
+        }
+        if let Ok(client_key) = vars.var("CACHE_POSTGRES_CLIENT_KEY") {
+            config.cache.postgres_client_key_path = Some(client_key).filter(|v| !v.is_empty());
+        }
+        if let Ok(similarity_threshold) = vars.var("SIMILARITY_THRESHOLD") {
             config.cache.similarity_threshold = similarity_threshold.parse()?;
         }
-        if let Ok(max_similar_results) = env::var("MAX_SIMILAR_RESULTS") {
+        if let Ok(max_similar_results) = vars.var("MAX_SIMILAR_RESULTS") {
             config.cache.max_similar_results = max_similar_results.parse()?;
         }
-        if let Ok(memory_cache_entries) = env::var("MEMORY_CACHE_ENTRIES") {
+        if let Ok(similar_lookup) = vars.var("CACHE_SIMILAR_LOOKUP") {
+            config.cache.similar_lookup = similar_lookup.parse()?;
+        }
+        if let Ok(method) = vars.var("CACHE_FUZZY_METHOD") {
+            config.cache.fuzzy_method = FuzzyMethod::parse(&method.to_lowercase())
+                .map_err(|e| anyhow::anyhow!("Invalid CACHE_FUZZY_METHOD: {}", e))?;
+        }
+        if let Ok(ratio) = vars.var("CACHE_FUZZY_MIN_RATIO") {
+            config.cache.fuzzy_min_ratio = ratio.parse()?;
+        }
+        if let Ok(memory_cache_entries) = vars.var("MEMORY_CACHE_ENTRIES") {
             config.cache.memory_cache_entries = memory_cache_entries.parse()?;
         }
-        if let Ok(memory_ttl_seconds) = env::var("MEMORY_TTL_SECONDS") {
+        if let Ok(policy) = vars.var("MEMORY_CACHE_POLICY") {
+            config.cache.memory_eviction_policy = policy;
+        }
+        if let Ok(max_mb) = vars.var("MEMORY_CACHE_MAX_MB") {
+            config.cache.memory_max_mb = max_mb.parse()?;
+        }
+        if let Ok(memory_ttl_seconds) = vars.var("MEMORY_TTL_SECONDS") {
             config.cache.memory_ttl_seconds = memory_ttl_seconds.parse()?;
         }
-        if let Ok(cache_probability) = env::var("CACHE_PROBABILITY") {
-            config.cache.cache_probability = cache_probability.parse()?;
+        if let Ok(write_policy) = vars.var("CACHE_WRITE_POLICY") {
+            config.cache.write_policy = CacheWritePolicy::parse(&write_policy)?;
+        }
+        if let Ok(ttl) = vars.var("CACHE_TIME_SENSITIVE_TTL_SECONDS") {
+            config.cache.time_sensitive_ttl_seconds = ttl.parse()?;
+        }
+        if let Ok(grace) = vars.var("CACHE_STALE_WHILE_REVALIDATE_SECONDS") {
+            config.cache.stale_while_revalidate_seconds = grace.parse()?;
+        }
+        if let Ok(channel) = vars.var("CACHE_INVALIDATION_CHANNEL") {
+            config.cache.invalidation_channel = channel.trim().to_string();
+        }
+        if let Ok(threshold) = vars.var("CACHE_FEEDBACK_EVICTION_THRESHOLD") {
+            config.cache.feedback_eviction_threshold = threshold.parse()?;
+        }
+        if let Ok(idempotency_ttl_seconds) = vars.var("IDEMPOTENCY_TTL_SECONDS") {
+            config.cache.idempotency_ttl_seconds = idempotency_ttl_seconds.parse()?;
+        }
+        if let Ok(capacity) = vars.var("CACHE_WRITE_QUEUE_SIZE") {
+            config.cache.write_queue_capacity = capacity.parse()?;
+        }
+        if let Ok(attempts) = vars.var("CACHE_WRITE_MAX_ATTEMPTS") {
+            config.cache.write_max_attempts = attempts.parse()?;
+        }
+        if let Ok(interval) = vars.var("CACHE_CLEANUP_INTERVAL_SECS") {
+            config.cache.cleanup_interval_secs = interval.parse()?;
+        }
+        if let Ok(interval) = vars.var("CACHE_STATS_FLUSH_INTERVAL_SECS") {
+            config.cache.stats_flush_interval_secs = interval.parse()?;
+        }
+        if let Ok(days) = vars.var("CACHE_STATS_RETENTION_DAYS") {
+            config.cache.stats_retention_days = days.parse()?;
+        }
+        if let Ok(min_bytes) = vars.var("CACHE_COMPRESSION_MIN_BYTES") {
+            config.cache.compression_min_bytes = min_bytes.parse()?;
+        }
+        if let Ok(enabled) = vars.var("CACHE_SCRIPTS_ENABLED") {
+            config.cache.scripts.enabled = enabled.parse()?;
+        }
+        if let Ok(ttl) = vars.var("CACHE_SCRIPTS_TTL_SECONDS") {
+            config.cache.scripts.ttl_seconds = ttl.parse()?;
+        }
+        if let Ok(similar) = vars.var("CACHE_SCRIPTS_MATCH_SIMILAR") {
+            config.cache.scripts.match_similar = similar.parse()?;
+        }
+        if let Ok(enabled) = vars.var("CACHE_LOG_ANALYSIS_ENABLED") {
+            config.cache.log_analysis.enabled = enabled.parse()?;
+        }
+        if let Ok(ttl) = vars.var("CACHE_LOG_ANALYSIS_TTL_SECONDS") {
+            config.cache.log_analysis.ttl_seconds = ttl.parse()?;
+        }
+        if let Ok(similar) = vars.var("CACHE_LOG_ANALYSIS_MATCH_SIMILAR") {
+            config.cache.log_analysis.match_similar = similar.parse()?;
         }
 
         // OpenRouter configuration
-        if let Ok(api_key) = env::var("OPENROUTER_API_KEY") {
+        if let Ok(api_key) = vars.var("OPENROUTER_API_KEY") {
             config.openrouter.api_key = api_key;
         }
-        if let Ok(base_url) = env::var("OPENROUTER_BASE_URL") {
+        if let Ok(base_url) = vars.var("OPENROUTER_BASE_URL") {
             config.openrouter.base_url = base_url;
         }
-        if let Ok(default_model) = env::var("OPENROUTER_DEFAULT_MODEL") {
+        if let Ok(default_model) = vars.var("OPENROUTER_DEFAULT_MODEL") {
             config.openrouter.default_model = default_model;
         }
+        if let Ok(vision_model) = vars.var("OPENROUTER_VISION_MODEL") {
+            config.openrouter.vision_model = vision_model;
+        }
+        if let Ok(price) = vars.var("OPENROUTER_PROMPT_PRICE_PER_MTOK") {
+            config.openrouter.prompt_price_per_mtok = price.parse()?;
+        }
+        if let Ok(price) = vars.var("OPENROUTER_COMPLETION_PRICE_PER_MTOK") {
+            config.openrouter.completion_price_per_mtok = price.parse()?;
+        }
+        if let Ok(cap) = vars.var("OPENROUTER_DAILY_CAP_USD") {
+            if !cap.is_empty() {
+                config.openrouter.daily_spend_cap_usd = Some(cap.parse()?);
+            }
+        }
+        if let Ok(cap) = vars.var("OPENROUTER_MONTHLY_CAP_USD") {
+            if !cap.is_empty() {
+                config.openrouter.monthly_spend_cap_usd = Some(cap.parse()?);
+            }
+        }
+        if let Ok(sqlite_path) = vars.var("OPENROUTER_SPEND_SQLITE_PATH") {
+            config.openrouter.spend_sqlite_path = sqlite_path;
+        }
+        if let Ok(redact_pii) = vars.var("OPENROUTER_REDACT_PII") {
+            config.openrouter.redact_pii = redact_pii.parse()?;
+        }
+
+        // Conversation configuration
+        if let Ok(sqlite_path) = vars.var("CONVERSATION_SQLITE_PATH") {
+            config.conversations.sqlite_path = sqlite_path;
+        }
+        if let Ok(replica_path) = vars.var("ANALYTICS_SQLITE_PATH") {
+            config.conversations.analytics_replica_path =
+                Some(replica_path).filter(|v| !v.is_empty());
+        }
+        if let Ok(pool_size) = vars.var("ANALYTICS_POOL_SIZE") {
+            config.conversations.analytics_pool_size = pool_size.parse()?;
+        }
+        if let Ok(interval) = vars.var("TOPIC_LABEL_INTERVAL_SECS") {
+            config.conversations.topic_label_interval_secs = interval.parse()?;
+        }
+        if let Ok(idle_minutes) = vars.var("TOPIC_IDLE_MINUTES") {
+            config.conversations.topic_idle_minutes = idle_minutes.parse()?;
+        }
+        if let Ok(max_messages) = vars.var("CONVERSATION_MAX_MESSAGES") {
+            config.conversations.max_messages = max_messages.parse()?;
+        }
+        if let Ok(max_age) = vars.var("CONVERSATION_MAX_MESSAGE_AGE_DAYS") {
+            config.conversations.max_message_age_days = max_age.parse()?;
+        }
+        if let Ok(interval) = vars.var("CONVERSATION_ARCHIVE_INTERVAL_SECS") {
+            config.conversations.archive_interval_secs = interval.parse()?;
+        }
+
+        // Sandbox configuration
+        if let Ok(enabled) = vars.var("SANDBOX_ENABLED") {
+            config.sandbox.enabled = enabled.parse()?;
+        }
+        if let Ok(python_path) = vars.var("SANDBOX_PYTHON") {
+            config.sandbox.python_path = python_path;
+        }
+        if let Ok(bwrap_path) = vars.var("SANDBOX_BWRAP") {
+            config.sandbox.bwrap_path = bwrap_path;
+        }
+        if let Ok(timeout_ms) = vars.var("SANDBOX_TIMEOUT_MS") {
+            config.sandbox.timeout_ms = timeout_ms.parse()?;
+        }
+        if let Ok(memory_limit_mb) = vars.var("SANDBOX_MEMORY_MB") {
+            config.sandbox.memory_limit_mb = memory_limit_mb.parse()?;
+        }
+        if let Ok(max_processes) = vars.var("SANDBOX_MAX_PROCESSES") {
+            config.sandbox.max_processes = max_processes.parse()?;
+        }
+        if let Ok(max_output_bytes) = vars.var("SANDBOX_MAX_OUTPUT_BYTES") {
+            config.sandbox.max_output_bytes = max_output_bytes.parse()?;
+        }
+        if let Ok(max_snippets) = vars.var("SANDBOX_MAX_SNIPPETS") {
+            config.sandbox.max_snippets = max_snippets.parse()?;
+        }
+
+        // Handoff configuration
+        if let Ok(webhook_url) = vars.var("HANDOFF_WEBHOOK_URL") {
+            config.handoff.webhook_url = Some(webhook_url).filter(|v| !v.is_empty());
+        }
+        if let Ok(confidence_threshold) = vars.var("HANDOFF_CONFIDENCE_THRESHOLD") {
+            config.handoff.confidence_threshold = confidence_threshold.parse()?;
+        }
+        if let Ok(webhook_timeout_ms) = vars.var("HANDOFF_WEBHOOK_TIMEOUT_MS") {
+            config.handoff.webhook_timeout_ms = webhook_timeout_ms.parse()?;
+        }
+
+        // Provider capture configuration
+        if let Ok(enabled) = vars.var("PROVIDER_CAPTURE_ENABLED") {
+            config.provider_capture.enabled = enabled.parse()?;
+        }
+        if let Ok(sqlite_path) = vars.var("PROVIDER_CAPTURE_SQLITE_PATH") {
+            config.provider_capture.sqlite_path = sqlite_path;
+        }
+        if let Ok(retention_hours) = vars.var("PROVIDER_CAPTURE_RETENTION_HOURS") {
+            config.provider_capture.retention_hours = retention_hours.parse()?;
+        }
+
+        // Alerting configuration
+        if let Ok(webhook_url) = vars.var("ALERT_WEBHOOK_URL") {
+            config.alerts.webhook_url = Some(webhook_url).filter(|v| !v.is_empty());
+        }
+        if let Ok(interval) = vars.var("ALERT_CHECK_INTERVAL_SECS") {
+            config.alerts.check_interval_secs = interval.parse()?;
+        }
+        if let Ok(debounce_checks) = vars.var("ALERT_DEBOUNCE_CHECKS") {
+            config.alerts.debounce_checks = debounce_checks.parse()?;
+        }
+        if let Ok(disk_path) = vars.var("ALERT_DISK_PATH") {
+            config.alerts.disk_path = disk_path;
+        }
+        if let Ok(min_free) = vars.var("ALERT_DISK_MIN_FREE_MB") {
+            config.alerts.disk_min_free_mb = min_free.parse()?;
+        }
+        if let Ok(timeout) = vars.var("ALERT_WEBHOOK_TIMEOUT_MS") {
+            config.alerts.webhook_timeout_ms = timeout.parse()?;
+        }
+
+        // Agent heartbeat configuration
+        if let Ok(sqlite_path) = vars.var("AGENT_SQLITE_PATH") {
+            config.agents.sqlite_path = sqlite_path;
+        }
+        if let Ok(token) = vars.var("AGENT_TOKEN") {
+            config.agents.token = Some(token).filter(|v| !v.is_empty());
+        }
+        if let Ok(latest_version) = vars.var("AGENT_LATEST_VERSION") {
+            config.agents.latest_version = Some(latest_version).filter(|v| !v.is_empty());
+        }
+        if let Ok(offline_after) = vars.var("AGENT_OFFLINE_AFTER_SECS") {
+            config.agents.offline_after_secs = offline_after.parse()?;
+        }
+
+        // Guardrail configuration
+        if let Ok(enabled) = vars.var("GUARDRAILS_ENABLED") {
+            config.guardrails.enabled = enabled.parse()?;
+        }
+        if let Ok(categories) = vars.var("GUARDRAILS_CATEGORIES") {
+            config.guardrails.categories = categories
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Ok(rules_path) = vars.var("GUARDRAILS_RULES_PATH") {
+            config.guardrails.rules_path = Some(rules_path).filter(|v| !v.is_empty());
+        }
+        if let Ok(policy) = vars.var("PROMPT_LEAK_POLICY") {
+            config.guardrails.prompt_leak_policy = policy.trim().to_lowercase();
+        }
+        if let Ok(min_words) = vars.var("PROMPT_LEAK_MIN_WORDS") {
+            config.guardrails.prompt_leak_min_words = min_words.parse()?;
+        }
+
+        // Conversation loop guard configuration
+        if let Ok(max_messages) = vars.var("LOOP_GUARD_MAX_MESSAGES_PER_MINUTE") {
+            config.loop_guard.max_messages_per_minute = max_messages.parse()?;
+        }
+        if let Ok(echo_threshold) = vars.var("LOOP_GUARD_ECHO_THRESHOLD") {
+            config.loop_guard.echo_threshold = echo_threshold.parse()?;
+        }
+        if let Ok(tenant_limits) = vars.var("LOOP_GUARD_TENANT_LIMITS") {
+            // Comma-separated `tenant=limit` pairs, e.g. `acme=60,trial=10`.
+            config.loop_guard.tenant_limits = tenant_limits
+                .split(',')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| {
+                    let (tenant, limit) = pair.split_once('=').ok_or_else(|| {
+                        anyhow::anyhow!("Invalid LOOP_GUARD_TENANT_LIMITS entry: {}", pair)
+                    })?;
+                    Ok((tenant.trim().to_string(), limit.trim().parse()?))
+                })
+                .collect::<anyhow::Result<_>>()?;
+        }
+
+        // Log digest store configuration
+        if let Ok(sqlite_path) = vars.var("LOG_STORE_SQLITE_PATH") {
+            config.log_store.sqlite_path = sqlite_path;
+        }
+        if let Ok(retention) = vars.var("LOG_STORE_RETENTION_DAYS") {
+            config.log_store.retention_days = retention.parse()?;
+        }
+
+        // Knowledge base configuration
+        if let Ok(sources) = vars.var("KB_SOURCES") {
+            config.knowledge_base.sources = sources
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Ok(sqlite_path) = vars.var("KB_SQLITE_PATH") {
+            config.knowledge_base.sqlite_path = sqlite_path;
+        }
+        if let Ok(checkout_dir) = vars.var("KB_CHECKOUT_DIR") {
+            config.knowledge_base.checkout_dir = checkout_dir;
+        }
+        if let Ok(interval) = vars.var("KB_SYNC_INTERVAL_SECS") {
+            config.knowledge_base.sync_interval_secs = interval.parse()?;
+        }
+        if let Ok(chunk_chars) = vars.var("KB_CHUNK_CHARS") {
+            config.knowledge_base.chunk_chars = chunk_chars.parse()?;
+        }
+        if let Ok(max_results) = vars.var("KB_MAX_RESULTS") {
+            config.knowledge_base.max_results = max_results.parse()?;
+        }
+        if let Ok(min_score) = vars.var("KB_MIN_SCORE") {
+            config.knowledge_base.min_score = min_score.parse()?;
+        }
+
+        // Outbound mTLS configuration
+        if let Ok(client_certs) = vars.var("OUTBOUND_CLIENT_CERTS") {
+            // Comma-separated `host=cert.pem;key.pem[;ca.pem]` rules.
+            config.outbound.client_certs = client_certs
+                .split(',')
+                .map(str::trim)
+                .filter(|rule| !rule.is_empty())
+                .map(|rule| {
+                    let invalid =
+                        || anyhow::anyhow!("Invalid OUTBOUND_CLIENT_CERTS entry: {}", rule);
+                    let (host_pattern, paths) = rule.split_once('=').ok_or_else(invalid)?;
+                    let mut paths = paths.split(';').map(str::trim);
+                    let cert_path = paths.next().filter(|p| !p.is_empty()).ok_or_else(invalid)?;
+                    let key_path = paths.next().filter(|p| !p.is_empty()).ok_or_else(invalid)?;
+                    let ca_cert_path = paths.next().filter(|p| !p.is_empty());
+                    if paths.next().is_some() {
+                        return Err(invalid());
+                    }
+                    Ok(ClientCertRule {
+                        host_pattern: host_pattern.trim().to_ascii_lowercase(),
+                        cert_path: cert_path.to_string(),
+                        key_path: key_path.to_string(),
+                        ca_cert_path: ca_cert_path.map(str::to_string),
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?;
+        }
+
+        if config.server.strict_config {
+            vars.reject_unknown()?;
+        }
 
         Ok(config)
     }
 }
+
+/// Variables read outside [`Config::from_env`].
+const OTHER_KNOWN_VARS: &[&str] = &["RUST_LOG", "RUST_BACKTRACE"];
+/// Environment variables with this prefix are always meant for the service.
+const SERVICE_PREFIX: &str = "SELFCARE_";
+
+/// Reads configuration variables and records every name the service knows,
+/// so strict mode can reject the rest.
+#[derive(Default)]
+struct EnvReader {
+    known: BTreeSet<&'static str>,
+}
+
+impl EnvReader {
+    fn var(&mut self, name: &'static str) -> Result<String, env::VarError> {
+        self.known.insert(name);
+        env::var(name)
+    }
+
+    /// Fails on `.env` keys the service does not read, and on environment
+    /// variables that carry the service prefix or look like a misspelled
+    /// setting. Other environment variables belong to the system and are ignored.
+    fn reject_unknown(&self) -> anyhow::Result<()> {
+        let known = |name: &str| self.known.contains(name) || OTHER_KNOWN_VARS.contains(&name);
+        let mut unknown = BTreeSet::new();
+        if let Ok(entries) = dotenv::dotenv_iter() {
+            for (name, _) in entries.flatten() {
+                if !known(&name) {
+                    unknown.insert(name);
+                }
+            }
+        }
+        for (name, _) in env::vars_os() {
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if !known(name) && (name.starts_with(SERVICE_PREFIX) || self.closest(name).is_some()) {
+                unknown.insert(name.to_string());
+            }
+        }
+        if unknown.is_empty() {
+            return Ok(());
+        }
+
+        let described: Vec<String> = unknown
+            .iter()
+            .map(|name| match self.closest(name) {
+                Some(suggestion) => format!("{} (did you mean {}?)", name, suggestion),
+                None => name.clone(),
+            })
+            .collect();
+        anyhow::bail!(
+            "Unknown configuration variables with CONFIG_STRICT=true: {}",
+            described.join(", ")
+        )
+    }
+
+    /// A known name at most two edits away. Short names are skipped, since
+    /// system variables like `HOME` are that close to `HOST`.
+    fn closest(&self, name: &str) -> Option<&'static str> {
+        if name.len() < 8 {
+            return None;
+        }
+        self.known
+            .iter()
+            .filter(|known| known.len() >= 8)
+            .map(|known| (edit_distance(name, known), *known))
+            .filter(|(distance, _)| (1..=2).contains(distance))
+            .min()
+            .map(|(_, known)| known)
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,137 @@
+use actix_web::{HttpRequest, HttpResponse};
+
+use crate::models::{ChatRequest, ErrorResponse, RequestContext};
+use crate::utils::cache_key;
+use crate::AppState;
+
+/// What a caller of a generation endpoint may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    /// Holds a valid API key, or no keys are configured.
+    Full,
+    /// Keyless trial caller: local model only, with a capped answer length
+    /// and no code execution.
+    Trial,
+}
+
+impl Access {
+    /// Applies the trial restrictions to `ctx` and `req`; full access leaves
+    /// them untouched.
+    pub(crate) fn restrict(
+        self,
+        state: &AppState,
+        ctx: &mut RequestContext,
+        req: &mut ChatRequest,
+    ) {
+        if self == Access::Trial {
+            let cap = state.config.trial.max_tokens;
+            ctx.flags.local_only = true;
+            req.max_tokens = Some(req.max_tokens.map_or(cap, |n| n.min(cap)));
+            req.execute_code = None;
+        }
+    }
+}
+
+/// Resolves the caller's access from `Authorization: Bearer` or `X-API-Key`.
+/// Keyless callers are charged against the trial limiter on endpoints where
+/// `trial_allowed`; otherwise the rejection to send is returned.
+pub(crate) fn check_access(
+    state: &AppState,
+    http_req: &HttpRequest,
+    trial_allowed: bool,
+) -> Result<Access, HttpResponse> {
+    let keys = &state.config.security.api_keys;
+    let provided = presented_key(http_req);
+
+    let trial = &state.config.trial;
+    if let Some(provided) = provided {
+        // With the trial tier on, a key only counts when it is a configured one.
+        let open = keys.is_empty() && !trial.enabled;
+        if open || keys.iter().any(|key| key == provided) {
+            return Ok(Access::Full);
+        }
+        return Err(HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid API key")));
+    }
+
+    if !trial.enabled && keys.is_empty() {
+        return Ok(Access::Full);
+    }
+    if !trial.enabled || !trial_allowed {
+        let message = if trial.enabled {
+            "This endpoint requires an API key; keyless trial access covers /api/chat only"
+        } else {
+            "An API key is required"
+        };
+        return Err(HttpResponse::Unauthorized()
+            .json(ErrorResponse::with_code(message, "api_key_required")));
+    }
+
+    let client = client_address(http_req, trial.trust_forwarded_for);
+    match state.trial_limiter.check(&client) {
+        Ok(()) => Ok(Access::Trial),
+        Err(exhausted) => {
+            tracing::info!(client = %client, "Trial rate limit reached");
+            let mut body = ErrorResponse::with_code("Trial limit reached", "trial_rate_limited");
+            body.details = Some(exhausted.to_string());
+            Err(HttpResponse::TooManyRequests()
+                .insert_header((
+                    actix_web::http::header::RETRY_AFTER,
+                    exhausted.retry_after_secs.to_string(),
+                ))
+                .json(body))
+        }
+    }
+}
+
+/// Identifies the caller for per-caller counting: a hash of a configured API
+/// key, or else the client address, so made-up keys can't pose as new callers.
+pub(crate) fn caller_id(state: &AppState, http_req: &HttpRequest) -> String {
+    let configured = presented_key(http_req)
+        .filter(|key| state.config.security.api_keys.iter().any(|k| k == key));
+    match configured {
+        Some(key) => format!("key:{}", cache_key(&[key])),
+        None => format!(
+            "ip:{}",
+            client_address(http_req, state.config.trial.trust_forwarded_for)
+        ),
+    }
+}
+
+/// The tenant `API_KEY_TENANTS` assigns to the caller's key. Unlike the
+/// `X-Tenant-Id` header, a caller cannot choose it.
+pub(crate) fn key_tenant<'a>(state: &'a AppState, http_req: &HttpRequest) -> Option<&'a str> {
+    let key = presented_key(http_req)?;
+    state
+        .config
+        .security
+        .key_tenants
+        .get(key)
+        .map(String::as_str)
+}
+
+/// The API key from `Authorization: Bearer` or `X-API-Key`, if any.
+fn presented_key(http_req: &HttpRequest) -> Option<&str> {
+    let headers = http_req.headers();
+    headers
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+        .map(str::trim)
+}
+
+/// The caller's IP without the port, taken from `X-Forwarded-For` only when
+/// the deployment says a trusted proxy sets it.
+fn client_address(http_req: &HttpRequest, trust_forwarded_for: bool) -> String {
+    let info = http_req.connection_info();
+    let address = if trust_forwarded_for {
+        info.realip_remote_addr()
+    } else {
+        info.peer_addr()
+    };
+    let address = address.unwrap_or("unknown");
+    match address.parse::<std::net::SocketAddr>() {
+        Ok(socket) => socket.ip().to_string(),
+        Err(_) => address.to_string(),
+    }
+}
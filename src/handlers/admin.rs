@@ -0,0 +1,938 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use bytes::Bytes;
+use chrono::{Duration, Utc};
+use futures_util::StreamExt;
+use std::sync::atomic::Ordering;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use validator::Validate;
+
+use crate::models::{
+    download_progress, AgentListResponse, AgentStatus, CacheExportQuery, CacheExportRecord,
+    CacheFlushResponse, CacheImportResponse, CacheInvalidationResponse, CacheStatsHistory,
+    CacheStatsHistoryQuery, CacheStatsPoint, CacheStatsResponse, CacheTierHits, Cursor,
+    DiagnosticsResponse, ErrorResponse, InflightListResponse, ModelCompareQuery, ModelComparison,
+    ModelOutcomeStats, ModelReloadRequest, ModelStatusResponse, ModelSwitchRequest,
+    ModelSwitchResponse, PageQuery, ProviderCapture, ProviderCaptureResponse, RoutingReport,
+    RoutingReportQuery, RoutingTierStats, TemplateVersion, TopicReport, TopicReportQuery,
+    TopicStats,
+};
+use crate::repositories::ModelOutcomeRecord;
+use crate::services::{AgentService, DEFAULT_TEMPLATE_NAMESPACE};
+use crate::utils::secrets_match;
+use crate::AppState;
+
+const DEFAULT_REPORT_HOURS: i64 = 24 * 7;
+/// Longest look-back accepted by the reports; larger values would overflow
+/// the timestamp arithmetic.
+const MAX_REPORT_HOURS: i64 = 24 * 366;
+const DEFAULT_CACHE_HISTORY_HOURS: i64 = 24;
+const DEFAULT_CACHE_HISTORY_BUCKET_MINUTES: i64 = 60;
+const DEFAULT_AGENT_PAGE_SIZE: usize = 50;
+const MAX_AGENT_PAGE_SIZE: usize = 500;
+/// Entries read from the durable tier per query while exporting.
+const EXPORT_PAGE_SIZE: usize = 500;
+/// Longest import line accepted; larger lines are not cache entries this
+/// service wrote.
+const MAX_IMPORT_LINE_BYTES: usize = 32 * 1024 * 1024;
+/// Failures listed in an import response; the rest are only counted.
+const MAX_IMPORT_ERRORS: usize = 20;
+
+/// Returns an error response unless the request carries the configured admin token.
+pub(crate) fn authorize(state: &AppState, http_req: &HttpRequest) -> Option<HttpResponse> {
+    let Some(expected) = state.config.security.admin_token.as_deref() else {
+        return Some(HttpResponse::Forbidden().json(ErrorResponse::new(
+            "Admin API is disabled; set ADMIN_API_TOKEN to enable it",
+        )));
+    };
+
+    let provided = http_req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if !provided.is_some_and(|provided| secrets_match(provided, expected)) {
+        return Some(HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid admin token")));
+    }
+    None
+}
+
+pub async fn invalidate_cache_tag(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+
+    let tag = path.into_inner();
+    match state.cache_service.invalidate_tag(&tag).await {
+        Ok(removed) => {
+            tracing::info!(
+                tag = %tag,
+                memory = removed.memory,
+                redis = removed.redis,
+                sqlite = removed.sqlite,
+                "Invalidated cache tag"
+            );
+            Ok(HttpResponse::Ok().json(CacheInvalidationResponse {
+                tag,
+                memory_removed: removed.memory,
+                redis_removed: removed.redis,
+                sqlite_removed: removed.sqlite,
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Cache invalidation error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to invalidate cache tag",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+/// Empties the memory tier of every instance, e.g. after a bad answer was
+/// fixed in Redis or the durable tier directly.
+pub async fn flush_memory_cache(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let memory_removed = state.cache_service.flush_memory().await;
+    tracing::info!(memory_removed, "Flushed memory cache");
+    Ok(HttpResponse::Ok().json(CacheFlushResponse { memory_removed }))
+}
+
+pub async fn cache_stats(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let stats = state.cache_service.stats();
+    Ok(HttpResponse::Ok().json(CacheStatsResponse {
+        total_requests: stats.total_requests.load(Ordering::Relaxed),
+        memory_hits: stats.memory_hits.load(Ordering::Relaxed),
+        redis_hits: stats.redis_hits.load(Ordering::Relaxed),
+        durable_hits: stats.sqlite_hits.load(Ordering::Relaxed),
+        stale_hits: stats.stale_hits.load(Ordering::Relaxed),
+        pending_writes: state.cache_service.pending_writes(),
+        dropped_writes: stats.dropped_writes.load(Ordering::Relaxed),
+        tiers: state.cache_service.tier_statuses(),
+    }))
+}
+
+/// Recorded cache hit rates per tier over time, from the snapshots taken
+/// every `CACHE_STATS_FLUSH_INTERVAL_SECS`.
+pub async fn cache_stats_history(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<CacheStatsHistoryQuery>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+
+    let hours = query
+        .since_hours
+        .unwrap_or(DEFAULT_CACHE_HISTORY_HOURS)
+        .clamp(1, MAX_REPORT_HOURS);
+    let since = Utc::now() - Duration::hours(hours);
+    let bucket_seconds = query
+        .bucket_minutes
+        .unwrap_or(DEFAULT_CACHE_HISTORY_BUCKET_MINUTES)
+        .clamp(1, MAX_REPORT_HOURS * 60)
+        * 60;
+    match state
+        .cache_service
+        .stats_history(since, bucket_seconds)
+        .await
+    {
+        Ok(buckets) => Ok(HttpResponse::Ok().json(CacheStatsHistory {
+            since,
+            bucket_seconds,
+            buckets: buckets
+                .into_iter()
+                .map(|bucket| {
+                    let sample = bucket.sample;
+                    let rate = |hits: u64| hits as f64 / sample.requests.max(1) as f64;
+                    let hits = sample.memory_hits + sample.redis_hits + sample.durable_hits;
+                    CacheStatsPoint {
+                        start: bucket.start,
+                        requests: sample.requests,
+                        hits,
+                        hit_rate: rate(hits),
+                        stale_hits: sample.stale_hits,
+                        dropped_writes: sample.dropped_writes,
+                        tiers: [
+                            ("memory", sample.memory_hits),
+                            ("redis", sample.redis_hits),
+                            ("durable", sample.durable_hits),
+                        ]
+                        .into_iter()
+                        .map(|(tier, hits)| CacheTierHits {
+                            tier: tier.to_string(),
+                            hits,
+                            hit_rate: rate(hits),
+                        })
+                        .collect(),
+                    }
+                })
+                .collect(),
+        })),
+        Err(e) => Ok(
+            HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                "Cache statistics history unavailable",
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+/// Takes a cache tier out of service, e.g. Redis during maintenance, until it
+/// is enabled again or the service restarts.
+pub async fn disable_cache_tier(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    set_cache_tier(state, http_req, path.into_inner(), false)
+}
+
+pub async fn enable_cache_tier(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    set_cache_tier(state, http_req, path.into_inner(), true)
+}
+
+fn set_cache_tier(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    tier: String,
+    enabled: bool,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    if !state.cache_service.set_tier_enabled(&tier, enabled) {
+        return Ok(HttpResponse::NotFound().json(ErrorResponse::with_details(
+            "Unknown cache tier",
+            format!("{:?} is not one of the configured CACHE_TIERS", tier),
+        )));
+    }
+    tracing::warn!(tier = %tier, enabled, "Cache tier state changed by an operator");
+    Ok(HttpResponse::Ok().json(state.cache_service.tier_statuses()))
+}
+
+/// Streams live durable-tier entries as JSONL, one [`CacheExportRecord`] per
+/// line, for offline analysis or for seeding another instance.
+pub async fn export_cache(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<CacheExportQuery>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let CacheExportQuery { tag, limit } = query.into_inner();
+    let limit = limit.unwrap_or(usize::MAX);
+
+    // Fetch the first page up front so an unavailable tier is a JSON error,
+    // not a truncated stream.
+    let cache = state.cache_service.clone();
+    let mut page = match cache
+        .export_page(None, tag.clone(), EXPORT_PAGE_SIZE.min(limit))
+        .await
+    {
+        Ok(page) => page,
+        Err(e) => {
+            return Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Cache export failed",
+                    e.to_string(),
+                )),
+            );
+        }
+    };
+
+    let (tx, rx) = mpsc::channel::<Bytes>(32);
+    tokio::spawn(async move {
+        let mut sent = 0;
+        loop {
+            let full_page = page.len() == EXPORT_PAGE_SIZE;
+            let last_key = page.last().map(|record| record.key.clone());
+            for record in &page {
+                let Ok(mut line) = serde_json::to_vec(record) else {
+                    continue;
+                };
+                line.push(b'\n');
+                if tx.send(Bytes::from(line)).await.is_err() {
+                    return;
+                }
+                sent += 1;
+            }
+            if !full_page || sent >= limit {
+                return;
+            }
+            page = match cache
+                .export_page(last_key, tag.clone(), EXPORT_PAGE_SIZE.min(limit - sent))
+                .await
+            {
+                Ok(page) => page,
+                Err(e) => {
+                    tracing::error!("Cache export stopped after {} entries: {:?}", sent, e);
+                    return;
+                }
+            };
+        }
+    });
+
+    let stream = ReceiverStream::new(rx).map(Ok::<Bytes, std::io::Error>);
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            actix_web::http::header::CONTENT_TYPE,
+            "application/x-ndjson",
+        ))
+        .streaming(stream))
+}
+
+/// Loads a JSONL export into Redis and the durable tier. Entries keep their
+/// tags and expiry; already expired ones are skipped.
+pub async fn import_cache(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    mut payload: web::Payload,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+
+    let mut summary = CacheImportResponse {
+        imported: 0,
+        expired: 0,
+        failed: 0,
+        errors: Vec::new(),
+    };
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut line_number = 0;
+    loop {
+        let chunk = payload.next().await.transpose()?;
+        let finished = chunk.is_none();
+        if let Some(chunk) = chunk {
+            buffer.extend_from_slice(&chunk);
+        }
+
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            line_number += 1;
+            import_line(&state, &line, line_number, &mut summary).await;
+        }
+        if buffer.len() > MAX_IMPORT_LINE_BYTES {
+            return Ok(
+                HttpResponse::PayloadTooLarge().json(ErrorResponse::with_details(
+                    "Import line too long",
+                    format!(
+                        "Line {} exceeds {} bytes",
+                        line_number + 1,
+                        MAX_IMPORT_LINE_BYTES
+                    ),
+                )),
+            );
+        }
+        if finished {
+            if !buffer.is_empty() {
+                line_number += 1;
+                import_line(&state, &buffer, line_number, &mut summary).await;
+            }
+            break;
+        }
+    }
+
+    tracing::info!(
+        imported = summary.imported,
+        expired = summary.expired,
+        failed = summary.failed,
+        "Imported cache entries"
+    );
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+async fn import_line(
+    state: &AppState,
+    line: &[u8],
+    line_number: usize,
+    summary: &mut CacheImportResponse,
+) {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return;
+    }
+    let result = match serde_json::from_slice::<CacheExportRecord>(line) {
+        Ok(record) => state.cache_service.import(&record).await,
+        Err(e) => Err(e.into()),
+    };
+    match result {
+        Ok(true) => summary.imported += 1,
+        Ok(false) => summary.expired += 1,
+        Err(e) => {
+            summary.failed += 1;
+            if summary.errors.len() < MAX_IMPORT_ERRORS {
+                summary.errors.push(format!("line {}: {}", line_number, e));
+            }
+        }
+    }
+}
+
+/// Cache-key versions of every prompt template, plus the default namespace.
+pub async fn list_template_versions(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let namespaces = &state.cache_namespaces;
+    let versions: Vec<TemplateVersion> = std::iter::once(None)
+        .chain(state.ai_service.templates().names().into_iter().map(Some))
+        .map(|template| TemplateVersion {
+            template: template.unwrap_or(DEFAULT_TEMPLATE_NAMESPACE).to_string(),
+            version: namespaces.template_version(template),
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(versions))
+}
+
+/// Starts a new cache namespace for a template after its prompt changed, so
+/// answers generated with the old prompt stop being served.
+pub async fn bump_template_version(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let template = path.into_inner().to_ascii_lowercase();
+    let template = (template != DEFAULT_TEMPLATE_NAMESPACE).then_some(template);
+    if let Some(name) = template.as_deref() {
+        if state.ai_service.templates().get(name).is_none() {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse::with_details(
+                "Unknown template",
+                format!(
+                    "Available templates: {}, {}",
+                    DEFAULT_TEMPLATE_NAMESPACE,
+                    state.ai_service.templates().names().join(", ")
+                ),
+            )));
+        }
+    }
+
+    match state.cache_namespaces.bump(template.as_deref()).await {
+        Ok(version) => {
+            let template = template.unwrap_or_else(|| DEFAULT_TEMPLATE_NAMESPACE.to_string());
+            tracing::info!(template = %template, version, "Bumped template cache version");
+            Ok(HttpResponse::Ok().json(TemplateVersion { template, version }))
+        }
+        Err(e) => {
+            tracing::error!("Template version bump failed: {:?}", e);
+            Ok(
+                HttpResponse::InternalServerError().json(ErrorResponse::with_details(
+                    "Failed to bump template version",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+pub async fn routing_report(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<RoutingReportQuery>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+
+    let hours = query
+        .since_hours
+        .unwrap_or(DEFAULT_REPORT_HOURS)
+        .clamp(1, MAX_REPORT_HOURS);
+    let since = Utc::now() - Duration::hours(hours);
+    match state.routing_metrics.stats_since(since).await {
+        Ok(records) => Ok(HttpResponse::Ok().json(RoutingReport {
+            since,
+            medium_threshold: state.config.ai.complexity_medium_threshold,
+            high_threshold: state.config.ai.complexity_high_threshold,
+            tiers: records
+                .into_iter()
+                .map(|record| {
+                    let total = record.total.max(1) as f64;
+                    RoutingTierStats {
+                        negative_feedback_rate: record.negative_feedback as f64 / total,
+                        escalation_rate: record.escalated as f64 / total,
+                        complexity: record.complexity,
+                        forced: record.forced,
+                        total: record.total,
+                        negative_feedback: record.negative_feedback,
+                        escalated: record.escalated,
+                        avg_message_chars: record.avg_message_chars,
+                    }
+                })
+                .collect(),
+        })),
+        Err(e) => {
+            tracing::error!("Routing report error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to build routing report",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+pub async fn topic_report(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<TopicReportQuery>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+
+    let hours = query
+        .since_hours
+        .unwrap_or(DEFAULT_REPORT_HOURS)
+        .clamp(1, MAX_REPORT_HOURS);
+    let since = Utc::now() - Duration::hours(hours);
+    match state.topics.counts_since(since).await {
+        Ok(records) => {
+            let conversations: u64 = records.iter().map(|record| record.conversations).sum();
+            Ok(HttpResponse::Ok().json(TopicReport {
+                since,
+                conversations,
+                topics: records
+                    .into_iter()
+                    .map(|record| TopicStats {
+                        share: record.conversations as f64 / conversations.max(1) as f64,
+                        escalation_rate: record.escalated as f64
+                            / record.conversations.max(1) as f64,
+                        topic: record.topic,
+                        conversations: record.conversations,
+                        escalated: record.escalated,
+                    })
+                    .collect(),
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Topic report error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to build topic report",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+pub async fn compare_models(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<ModelCompareQuery>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let (a, b) = (query.a.trim(), query.b.trim());
+    if a.is_empty() || b.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Both `a` and `b` model names are required",
+        )));
+    }
+
+    let hours = query
+        .since_hours
+        .unwrap_or(DEFAULT_REPORT_HOURS)
+        .clamp(1, MAX_REPORT_HOURS);
+    let since = Utc::now() - Duration::hours(hours);
+    let outcomes = futures_util::future::try_join(
+        state.routing_metrics.model_outcomes_since(a, since),
+        state.routing_metrics.model_outcomes_since(b, since),
+    )
+    .await;
+    match outcomes {
+        Ok((outcome_a, outcome_b)) => Ok(HttpResponse::Ok().json(ModelComparison {
+            since,
+            a: model_outcome_stats(a, outcome_a),
+            b: model_outcome_stats(b, outcome_b),
+        })),
+        Err(e) => {
+            tracing::error!("Model comparison error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to build model comparison",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+fn model_outcome_stats(model: &str, record: ModelOutcomeRecord) -> ModelOutcomeStats {
+    // Nearest-rank percentile over the ascending durations.
+    let percentile = |p: usize| {
+        let len = record.durations_ms.len();
+        (len > 0).then(|| record.durations_ms[((len * p).div_ceil(100)).clamp(1, len) - 1])
+    };
+    let answers = record.answers.max(1) as f64;
+    let ratings = record.positive_ratings + record.negative_ratings;
+    ModelOutcomeStats {
+        model: model.to_string(),
+        answers: record.answers,
+        latency_p50_ms: percentile(50),
+        latency_p90_ms: percentile(90),
+        latency_p99_ms: percentile(99),
+        positive_ratings: record.positive_ratings,
+        negative_ratings: record.negative_ratings,
+        feedback_score: (ratings > 0).then(|| record.positive_ratings as f64 / ratings as f64),
+        negative_feedback_rate: record.negative_feedback as f64 / answers,
+        escalation_rate: record.escalated as f64 / answers,
+        total_cost_usd: record.cost_usd,
+        cost_per_answer_usd: (record.costed_answers > 0)
+            .then(|| record.cost_usd / record.costed_answers as f64),
+    }
+}
+
+pub async fn get_provider_captures(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    if !state.provider_capture.is_enabled() {
+        return Ok(HttpResponse::NotFound().json(ErrorResponse::new(
+            "Provider capture is disabled; set PROVIDER_CAPTURE_ENABLED=true",
+        )));
+    }
+
+    let request_id = path.into_inner();
+    match state.provider_capture.get(&request_id).await {
+        Ok(records) if records.is_empty() => Ok(
+            HttpResponse::NotFound().json(ErrorResponse::new("No captures for this request id"))
+        ),
+        Ok(records) => Ok(HttpResponse::Ok().json(ProviderCaptureResponse {
+            request_id,
+            captures: records
+                .into_iter()
+                .map(|record| ProviderCapture {
+                    provider: record.provider,
+                    endpoint: record.endpoint,
+                    status: record.status,
+                    error: record.error,
+                    duration_ms: record.duration_ms,
+                    request: serde_json::from_str(&record.request_json)
+                        .unwrap_or(serde_json::Value::String(record.request_json)),
+                    response: record.response_json.map(|json| {
+                        serde_json::from_str(&json).unwrap_or(serde_json::Value::String(json))
+                    }),
+                    created_at: record.created_at,
+                })
+                .collect(),
+        })),
+        Err(e) => {
+            tracing::error!("Provider capture lookup error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to load provider captures",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+/// Current OpenRouter spend against the daily and monthly caps.
+pub async fn cloud_spend(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+
+    match state.spend_service.status().await {
+        Ok(status) => Ok(HttpResponse::Ok().json(status)),
+        Err(e) => {
+            tracing::error!("Cloud spend lookup error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to load cloud spend",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+/// Installed agents, most recently seen first by default, with whether each is
+/// still reporting and whether it runs an outdated version.
+pub async fn list_agents(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<PageQuery>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let page = match query.resolve(DEFAULT_AGENT_PAGE_SIZE, MAX_AGENT_PAGE_SIZE) {
+        Ok(page) => page,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(e))),
+    };
+
+    let latest_version = state.agent_service.latest_version();
+    match state.agent_service.list(page.clone()).await {
+        Ok(mut records) => {
+            let page = page.finish(&mut records, |record| Cursor {
+                timestamp: record.last_seen.timestamp(),
+                id: record.report.agent_id.clone(),
+            });
+            let agents = records
+                .into_iter()
+                .map(|record| AgentStatus {
+                    connected: state.agent_service.is_connected(record.last_seen),
+                    outdated: AgentService::is_outdated(
+                        &record.report.version,
+                        latest_version.as_deref(),
+                    ),
+                    agent_id: record.report.agent_id,
+                    version: record.report.version,
+                    os: record.report.os,
+                    os_version: record.report.os_version,
+                    hostname: record.report.hostname,
+                    health: record.report.health,
+                    message: record.report.message,
+                    first_seen: record.first_seen,
+                    last_seen: record.last_seen,
+                    heartbeats: record.heartbeats,
+                })
+                .collect();
+            Ok(HttpResponse::Ok().json(AgentListResponse {
+                agents,
+                latest_version,
+                page,
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Agent listing error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to list agents",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+/// `GET /api/admin/model/status`: the serving model and any weight
+/// downloads in progress, with their rate and ETA.
+pub async fn model_status(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    // Only a load or a switch holds the model lock; a switch replaces a loaded model.
+    let model_loaded = match state.ai_model.try_read() {
+        Ok(model) => model.is_ready(),
+        Err(_) => state.ai_service.model_switch_in_progress(),
+    };
+    Ok(HttpResponse::Ok().json(ModelStatusResponse {
+        model_name: state.ai_service.model_name(),
+        model_loaded,
+        model_switch_in_progress: state.ai_service.model_switch_in_progress(),
+        downloads: download_progress(),
+    }))
+}
+
+/// `GET /api/admin/diagnostics`: supervised background tasks and load.
+pub async fn diagnostics(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    // Only a load or a switch holds the model lock; a switch replaces a loaded model.
+    let model_loaded = match state.ai_model.try_read() {
+        Ok(model) => model.is_ready(),
+        Err(_) => state.ai_service.model_switch_in_progress(),
+    };
+    Ok(HttpResponse::Ok().json(DiagnosticsResponse {
+        uptime_seconds: state.start_time.elapsed().as_secs(),
+        model_name: state.ai_service.model_name(),
+        model_loaded,
+        model_switch_in_progress: state.ai_service.model_switch_in_progress(),
+        embedding_model: state.embeddings.model_name().map(str::to_string),
+        embedding_model_loaded: state.embeddings.is_ready(),
+        queued_generations: state.ai_service.queued_generations(),
+        inference_workers: state.ai_service.inference_pool().workers(),
+        inference_queued: state.ai_service.inference_pool().queued(),
+        pending_cache_writes: state.cache_service.pending_writes(),
+        dropped_cache_writes: state
+            .cache_service
+            .stats()
+            .dropped_writes
+            .load(Ordering::Relaxed),
+        knowledge_base: state.knowledge.status(),
+        background_tasks: state.tasks.snapshot(),
+    }))
+}
+
+/// Switches the default local model without downtime: the new weights load
+/// in the background while the current model keeps answering.
+pub async fn switch_model(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    payload: web::Json<ModelSwitchRequest>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let req = payload.into_inner();
+    if let Err(e) = req.validate() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Invalid request",
+            format!("Validation error: {}", e),
+        )));
+    }
+    let model_path = req.model_path.filter(|p| !p.trim().is_empty());
+
+    let current_model = state.ai_service.model_name();
+    if req.model_name == current_model && model_path.is_none() {
+        return Ok(HttpResponse::Ok().json(ModelSwitchResponse {
+            requested_model: current_model.clone(),
+            current_model,
+        }));
+    }
+    Ok(start_model_switch(&state, req.model_name, model_path))
+}
+
+/// Loads the default local model again, or the named one, from disk and
+/// swaps it in once ready. Unlike a switch, naming the serving model reloads
+/// it, e.g. after its weights were replaced in place.
+pub async fn reload_model(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    payload: Option<web::Json<ModelReloadRequest>>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let req = payload.map(web::Json::into_inner).unwrap_or_default();
+    if let Err(e) = req.validate() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Invalid request",
+            format!("Validation error: {}", e),
+        )));
+    }
+    let model_path = req.model_path.filter(|p| !p.trim().is_empty());
+    let (model_name, model_path) = match req.model_name.filter(|name| !name.trim().is_empty()) {
+        Some(model_name) => (model_name, model_path),
+        // Reloading the serving model reads it from where it was loaded.
+        None => (
+            state.ai_service.model_name(),
+            model_path.or_else(|| state.ai_service.model_path()),
+        ),
+    };
+    Ok(start_model_switch(&state, model_name, model_path))
+}
+
+/// Starts loading `model_name` as the `model_switch` task; `409` while
+/// another load is running.
+fn start_model_switch(
+    state: &AppState,
+    model_name: String,
+    model_path: Option<String>,
+) -> HttpResponse {
+    if let Some(backend) = state.ai_service.local_backend() {
+        return HttpResponse::Conflict().json(ErrorResponse::with_details(
+            "Local generations are served by a model server",
+            format!(
+                "LOCAL_BACKEND={} loads its own model; restart it to switch",
+                backend.kind()
+            ),
+        ));
+    }
+    if !state.ai_service.begin_model_switch() {
+        return HttpResponse::Conflict()
+            .json(ErrorResponse::new("A model switch is already in progress"));
+    }
+
+    tracing::info!(model = %model_name, "Loading standby model");
+    let current_model = state.ai_service.model_name();
+    let ai_service = state.ai_service.clone();
+    let requested_model = model_name.clone();
+    state.tasks.spawn("model_switch", move |handle| {
+        let ai_service = ai_service.clone();
+        let model_name = model_name.clone();
+        let model_path = model_path.clone();
+        async move {
+            ai_service
+                .switch_model(&model_name, model_path.as_deref())
+                .await?;
+            handle.ran();
+            Ok(())
+        }
+    });
+
+    HttpResponse::Accepted().json(ModelSwitchResponse {
+        current_model,
+        requested_model,
+    })
+}
+
+pub async fn list_inflight(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    Ok(HttpResponse::Ok().json(InflightListResponse {
+        generations: state.ai_service.inflight().snapshot(),
+    }))
+}
+
+/// Stops a running generation; its caller gets a `cancelled` error.
+pub async fn cancel_inflight(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let id = path.into_inner();
+    if !state.ai_service.inflight().cancel(&id) {
+        return Ok(
+            HttpResponse::NotFound().json(ErrorResponse::new("No running generation with this id"))
+        );
+    }
+    tracing::info!(generation = %id, "Cancellation requested by an operator");
+    Ok(HttpResponse::Accepted().finish())
+}
@@ -0,0 +1,50 @@
+use actix_web::HttpResponse;
+
+use crate::models::{ErrorResponse, PromptTooLong};
+use crate::services::{DeadlineExceeded, GenerationCancelled, Overloaded};
+
+/// The 429 returned when generation capacity and its wait queue are exhausted.
+pub(crate) fn overloaded(rejection: &Overloaded) -> HttpResponse {
+    let mut body = ErrorResponse::with_code("Server is busy, retry shortly", "overloaded");
+    body.details = Some(rejection.to_string());
+    HttpResponse::TooManyRequests()
+        .insert_header((
+            actix_web::http::header::RETRY_AFTER,
+            rejection.retry_after_secs.to_string(),
+        ))
+        .json(body)
+}
+
+/// Maps a failed generation to a 429 when it was refused admission.
+pub(crate) fn rejected_by_admission(error: &anyhow::Error) -> Option<HttpResponse> {
+    error.downcast_ref::<Overloaded>().map(overloaded)
+}
+
+/// Maps a generation an operator cancelled through the admin API to a 503.
+pub(crate) fn cancelled_by_operator(error: &anyhow::Error) -> Option<HttpResponse> {
+    error
+        .downcast_ref::<GenerationCancelled>()
+        .map(|cancelled| {
+            let mut body = ErrorResponse::with_code("Generation was cancelled", "cancelled");
+            body.details = Some(cancelled.to_string());
+            HttpResponse::ServiceUnavailable().json(body)
+        })
+}
+
+/// Maps a generation that outlived the caller's `X-Request-Timeout-Ms` to a 504.
+pub(crate) fn past_deadline(error: &anyhow::Error) -> Option<HttpResponse> {
+    error.downcast_ref::<DeadlineExceeded>().map(|exceeded| {
+        let mut body = ErrorResponse::with_code("Request deadline exceeded", "deadline_exceeded");
+        body.details = Some(exceeded.to_string());
+        HttpResponse::GatewayTimeout().json(body)
+    })
+}
+
+/// Maps a prompt refused for overflowing the context window to a 413.
+pub(crate) fn prompt_too_long(error: &anyhow::Error) -> Option<HttpResponse> {
+    error.downcast_ref::<PromptTooLong>().map(|too_long| {
+        let mut body = ErrorResponse::with_code("Prompt is too long", "prompt_too_long");
+        body.details = Some(too_long.to_string());
+        HttpResponse::PayloadTooLarge().json(body)
+    })
+}
@@ -0,0 +1,56 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::Utc;
+use validator::Validate;
+
+use crate::models::{AgentHeartbeat, AgentHeartbeatResponse, ErrorResponse};
+use crate::services::AgentService;
+use crate::utils::secrets_match;
+use crate::AppState;
+
+/// Records an installed agent's version and health, and tells it whether an
+/// update is available.
+pub async fn agent_heartbeat(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    payload: web::Json<AgentHeartbeat>,
+) -> Result<HttpResponse> {
+    let Some(expected) = state.config.agents.token.as_deref() else {
+        return Ok(HttpResponse::Forbidden().json(ErrorResponse::new(
+            "Agent heartbeats are disabled; set AGENT_TOKEN to enable them",
+        )));
+    };
+    let provided = http_req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if !provided.is_some_and(|provided| secrets_match(provided, expected)) {
+        return Ok(HttpResponse::Unauthorized().json(ErrorResponse::new("Invalid agent token")));
+    }
+
+    let heartbeat = payload.into_inner();
+    if let Err(e) = heartbeat.validate() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Invalid request",
+            format!("Validation error: {}", e),
+        )));
+    }
+
+    let version = heartbeat.version.clone();
+    if let Err(e) = state.agent_service.record(heartbeat).await {
+        tracing::error!("Agent heartbeat error: {:?}", e);
+        return Ok(
+            HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                "Failed to record heartbeat",
+                e.to_string(),
+            )),
+        );
+    }
+
+    let latest_version = state.agent_service.latest_version();
+    Ok(HttpResponse::Ok().json(AgentHeartbeatResponse {
+        received_at: Utc::now(),
+        update_available: AgentService::is_outdated(&version, latest_version.as_deref()),
+        latest_version,
+    }))
+}
@@ -1,22 +1,67 @@
+use actix_multipart::Multipart;
 use actix_web::{web, HttpRequest, HttpResponse, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
 use futures_util::StreamExt;
-use bytes::Bytes;
+use tokio::time::{Duration, Instant};
+use tracing::Instrument;
 use uuid::Uuid;
 use validator::Validate;
-use tokio::sync::mpsc;
-use tokio::time::{sleep, Duration};
-use tokio_stream::wrappers::ReceiverStream;
 
-use crate::models::{ChatRequest, ChatResponse, ErrorResponse};
-use crate::services::Complexity;
-use crate::utils::cache_key;
+use crate::handlers::{
+    blocked_by_policy, cancelled_by_operator, check_access, key_tenant, negotiate_encoder,
+    ollama_chat, past_deadline, policy_violation, prompt_too_long, rejected_by_admission,
+    stream_response, word_chunks, Access, StreamSummary,
+};
+use crate::middleware::Screened;
+use crate::models::{
+    ChatPayload, ChatRequest, ChatResponse, ContinueRequest, ErrorResponse, ImageAttachment,
+    RegenerateRequest, RequestContext, RoutingHint, StreamPollQuery, StreamPollResponse,
+    StreamTicket, StreamTransport, MAX_IMAGES, MAX_IMAGE_BYTES,
+};
+use crate::services::{CacheSource, IdempotencyClaim, DEFAULT_TEMPLATE_NAMESPACE};
+use crate::utils::{cache_key, detect_language, is_time_sensitive, normalize_message};
 use crate::AppState;
 
+const DEFAULT_POLL_WAIT_MS: u64 = 10_000;
+const MAX_POLL_WAIT_MS: u64 = 25_000;
+/// Sent as the user turn when resuming an interrupted answer.
+const CONTINUE_PROMPT: &str = "Your previous answer was cut off. Continue it exactly where it \
+stopped, without repeating what was already written and without any preamble.";
+
+/// Where a chat answer is cached. Answers to other questions with the same
+/// `scope` (every key part but the message) may stand in for it.
+#[derive(Debug, Clone, Default)]
+struct ChatCacheKey {
+    key: String,
+    scope: String,
+    /// The normalized message.
+    question: String,
+}
+
 pub async fn chat(
     state: web::Data<AppState>,
     http_req: HttpRequest,
-    req: web::Json<ChatRequest>,
+    mut ctx: RequestContext,
+    payload: Screened<ChatPayload>,
 ) -> Result<HttpResponse> {
+    let access = match check_access(&state, &http_req, true) {
+        Ok(access) => access,
+        Err(denied) => return Ok(denied),
+    };
+    let mut req = match payload.into_inner() {
+        ChatPayload::Ollama(_) if !state.config.security.endpoint_group_enabled("compat") => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse::new("Endpoint not found")));
+        }
+        ChatPayload::Ollama(ollama_req) => {
+            return ollama_chat(state, ctx, ollama_req, access).await
+        }
+        ChatPayload::Native(req) => req,
+    };
+    let idempotency_key = match idempotency_key(&http_req) {
+        Ok(key) => key.map(|key| format!("chat:{}", key)),
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(e))),
+    };
+
     // Validate request
     if let Err(e) = req.validate() {
         return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
@@ -24,24 +69,124 @@ pub async fn chat(
             format!("Validation error: {}", e),
         )));
     }
+    // Images need the cloud model, constraints the local one.
+    if req.has_images() && req.constraint.is_some() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Output constraints cannot be combined with images",
+        )));
+    }
+    if access == Access::Trial && req.has_images() {
+        return Ok(HttpResponse::Forbidden().json(ErrorResponse::with_code(
+            "Image input requires an API key",
+            "api_key_required",
+        )));
+    }
+    access.restrict(&state, &mut ctx, &mut req);
+
+    if let Some(conversation_id) = req.conversation_id {
+        let tenant = key_tenant(&state, &http_req);
+        if let Err(detected) = state
+            .loop_guard
+            .check(conversation_id, tenant, &req.message)
+        {
+            tracing::warn!(conversation_id = %conversation_id, "Refused looping conversation: {}", detected);
+            let mut body = ErrorResponse::with_code(
+                "Conversation paused: possible automated loop",
+                "loop_detected",
+            );
+            body.details = Some(detected.to_string());
+            return Ok(HttpResponse::TooManyRequests()
+                .insert_header((actix_web::http::header::RETRY_AFTER, "60"))
+                .json(body));
+        }
+    }
+
+    if let Some(template) = req.template.as_deref() {
+        if state.ai_service.templates().get(template).is_none() {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+                "Unknown template",
+                format!(
+                    "Available templates: {}",
+                    state.ai_service.templates().names().join(", ")
+                ),
+            )));
+        }
+    }
+
+    if let Some(adapter) = req.adapter.as_deref() {
+        let registry = state.ai_service.models();
+        if !registry.has_adapter(adapter) {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+                "Unknown adapter",
+                format!("Available adapters: {}", registry.adapters().join(", ")),
+            )));
+        }
+        if req.model.is_some() {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+                "Invalid request",
+                "adapter applies to the default local model and cannot be combined with model",
+            )));
+        }
+    }
 
     let conversation_id = req.conversation_id.unwrap_or_else(Uuid::new_v4);
     let model_name = req
         .model
         .clone()
-        .unwrap_or_else(|| state.config.ai.model_name.clone());
+        .unwrap_or_else(|| state.ai_service.model_name());
     let temperature = req.temperature.unwrap_or(state.config.ai.temperature);
     let max_tokens = req.max_tokens.unwrap_or(state.config.ai.max_tokens);
 
-    let cache_key = cache_key(&[
-        &req.message,
-        &model_name,
-        &temperature.to_string(),
-        &max_tokens.to_string(),
-    ]);
+    let template = req.template.as_deref();
+    let cache_namespace = format!(
+        "{}|{}@{}|{}",
+        model_name,
+        template
+            .unwrap_or(DEFAULT_TEMPLATE_NAMESPACE)
+            .to_ascii_lowercase(),
+        state.cache_namespaces.template_version(template),
+        state.config.ai.prompt_version
+    );
+    let question = normalize_message(&req.message, &state.config.cache.key_normalization);
+    let system_prompt = req.system_prompt.as_deref().unwrap_or("");
+    let temperature = temperature.to_string();
+    let max_tokens = max_tokens.to_string();
+    let options = format!(
+        "{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+        req.adapter,
+        req.stop,
+        req.top_k,
+        req.repetition_penalty,
+        req.frequency_penalty,
+        req.presence_penalty,
+        req.forced_complexity(),
+        req.language,
+        req.constraint,
+        req.search.unwrap_or_default(),
+        ctx.flags.local_only
+    );
+    let cache_key = ChatCacheKey {
+        key: cache_key(&[
+            &cache_namespace,
+            &question,
+            system_prompt,
+            &temperature,
+            &max_tokens,
+            &options,
+        ]),
+        scope: cache_key(&[
+            &cache_namespace,
+            system_prompt,
+            &temperature,
+            &max_tokens,
+            &options,
+        ]),
+        question,
+    };
 
     let cache_bypass = req.cache_bypass.unwrap_or(false);
     let wants_stream = req.stream.unwrap_or(false)
+        || req.stream_format.is_some()
         || http_req
             .headers()
             .get(actix_web::http::header::ACCEPT)
@@ -52,77 +197,448 @@ pub async fn chat(
                     || value.contains("application/jsonl")
             })
             .unwrap_or(false);
-    let use_cache = !cache_bypass && rand::random::<f32>() < state.config.cache.cache_probability;
+    // Deterministic runs must exercise generation every time, so they skip the
+    // cache entirely.
+    let deterministic = req.deterministic.unwrap_or(state.config.ai.deterministic);
+    let use_cache = !cache_bypass && !deterministic;
+    // Follow-ups depend on earlier turns, so only opening messages are cacheable;
+    // tool-using requests are steps of a client-side agent loop and never cached.
+    // Answers about images are not cached either.
+    let time_sensitive = is_time_sensitive(&req.message);
+    let time_sensitive_ttl = state.config.cache.time_sensitive_ttl_seconds;
+    if time_sensitive && time_sensitive_ttl > 0 {
+        // Short-lived entries only, so a release or advisory answer is
+        // refreshed within minutes.
+        req.cache_ttl_seconds = Some(
+            req.cache_ttl_seconds
+                .map_or(time_sensitive_ttl, |ttl| ttl.min(time_sensitive_ttl)),
+        );
+    }
+    let use_cache = use_cache
+        && !(time_sensitive && time_sensitive_ttl == 0)
+        && req.tools.is_none()
+        && req.tool_results.is_none()
+        && !req.has_images()
+        && !(req.conversation_id.is_some()
+            && state
+                .conversation_service
+                .has_history(conversation_id)
+                .await);
 
-    if use_cache {
-        if let Some((cached, source)) = state.cache_service.get(&cache_key).await {
-            if let Ok(mut cached_response) = serde_json::from_value::<ChatResponse>(cached) {
-                cached_response.cache_hit = true;
-                cached_response.cache_source = Some(source.as_str().to_string());
-                cached_response.conversation_id = conversation_id;
-                cached_response.timestamp = chrono::Utc::now();
-                if wants_stream {
-                    return Ok(stream_text_response(
-                        &http_req,
-                        cached_response.response.clone(),
-                        model_name.clone(),
-                        true,
-                        cached_response.cache_source.clone(),
-                        conversation_id,
-                    ));
+    if req.stream_transport.unwrap_or_default() == StreamTransport::Longpoll {
+        let token = state.stream_service.create().await;
+        let ticket = StreamTicket {
+            token: token.clone(),
+            poll_url: format!("/api/chat/stream/{}", token),
+            conversation_id,
+        };
+        let state = state.clone();
+        let span = ctx.span.clone();
+        tokio::spawn(
+            async move {
+                let result = resolve_idempotent(
+                    &state,
+                    &ctx,
+                    &req,
+                    idempotency_key.as_deref(),
+                    &cache_key,
+                    use_cache,
+                    conversation_id,
+                )
+                .await;
+                match result {
+                    Ok(chat_response) => {
+                        for chunk in word_chunks(&chat_response.response) {
+                            state.stream_service.append(&token, chunk).await;
+                        }
+                        let metadata = serde_json::json!({
+                            "model": model_name,
+                            "cache_hit": chat_response.cache_hit,
+                            "cache_source": chat_response.cache_source,
+                            "cache_stale": chat_response.cache_stale,
+                            "conversation_id": conversation_id,
+                            "tool_calls": chat_response.tool_calls,
+                            "request_id": chat_response.request_id,
+                            "response_hash": chat_response.response_hash,
+                            "partial": chat_response.partial,
+                            "search_mode": chat_response.search_mode,
+                            "searched": chat_response.searched,
+                            "provenance": chat_response.provenance,
+                        });
+                        state.stream_service.finish(&token, Some(metadata)).await;
+                    }
+                    Err(e) => {
+                        tracing::error!("Chat error: {:?}", e);
+                        state.stream_service.fail(&token, e.to_string()).await;
+                    }
                 }
-                return respond_chat(http_req, cached_response);
             }
+            .instrument(span),
+        );
+        return Ok(HttpResponse::Accepted().json(ticket));
+    }
+
+    let result = resolve_idempotent(
+        &state,
+        &ctx,
+        &req,
+        idempotency_key.as_deref(),
+        &cache_key,
+        use_cache,
+        conversation_id,
+    )
+    .await;
+    match result {
+        Ok(chat_response) => {
+            // Tool calls are structured, so they are always returned as a JSON body.
+            if wants_stream && chat_response.tool_calls.is_empty() {
+                let encoder = negotiate_encoder(&http_req, req.stream_format);
+                let summary = StreamSummary {
+                    model: model_name.clone(),
+                    conversation_id: Some(conversation_id),
+                    cache_hit: chat_response.cache_hit,
+                    cache_source: chat_response.cache_source.clone(),
+                    cache_stale: chat_response.cache_stale,
+                    response_hash: chat_response.response_hash.clone(),
+                    partial: chat_response.partial,
+                    total_duration: None,
+                };
+                return Ok(stream_response(encoder, chat_response.response, summary));
+            }
+            respond_chat(http_req, chat_response)
+        }
+        Err(e) => {
+            if let Some(blocked) = blocked_by_policy(&e) {
+                return Ok(blocked);
+            }
+            if let Some(rejected) = rejected_by_admission(&e) {
+                return Ok(rejected);
+            }
+            if let Some(cancelled) = cancelled_by_operator(&e) {
+                return Ok(cancelled);
+            }
+            if let Some(timed_out) = past_deadline(&e) {
+                return Ok(timed_out);
+            }
+            if let Some(too_long) = prompt_too_long(&e) {
+                return Ok(too_long);
+            }
+            tracing::error!("Chat error: {:?}", e);
+            Ok(
+                HttpResponse::InternalServerError().json(ErrorResponse::with_details(
+                    "Failed to process chat request",
+                    e.to_string(),
+                )),
+            )
         }
     }
+}
 
-    let complexity = state.ai_service.analyze_complexity(&req).await;
-    let response = match complexity {
-        Complexity::Low => state.ai_service.local_model_generate(&req).await,
-        Complexity::Medium => {
-            let search_results = state.ai_service.search(&req.message).await;
-            match search_results {
-                Ok(results) => state.ai_service.enrich_and_generate(&req, &results).await,
-                Err(err) => Err(err),
+/// Multipart variant of `POST /api/chat` for screenshots: a `request` part with
+/// the JSON chat request (or a plain `message` part) plus image file parts.
+pub async fn chat_with_images(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    ctx: RequestContext,
+    mut payload: Multipart,
+) -> Result<HttpResponse> {
+    if let Err(denied) = check_access(&state, &http_req, false) {
+        return Ok(denied);
+    }
+    let mut req: Option<ChatRequest> = None;
+    let mut message: Option<String> = None;
+    let mut images = Vec::new();
+
+    while let Some(field) = payload.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            Err(e) => {
+                return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+                    "Invalid multipart body",
+                    e.to_string(),
+                )))
             }
+        };
+        let name = field
+            .content_disposition()
+            .get_name()
+            .unwrap_or("")
+            .to_string();
+        let media_type = field
+            .content_type()
+            .map(|mime| mime.essence_str().to_string());
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+                        "Invalid multipart body",
+                        e.to_string(),
+                    )))
+                }
+            };
+            if bytes.len() + chunk.len() > MAX_IMAGE_BYTES {
+                return Ok(
+                    HttpResponse::PayloadTooLarge().json(ErrorResponse::with_details(
+                        "Multipart part too large",
+                        format!("Parts are limited to {} bytes", MAX_IMAGE_BYTES),
+                    )),
+                );
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        match name.as_str() {
+            "request" => match serde_json::from_slice(&bytes) {
+                Ok(parsed) => req = Some(parsed),
+                Err(e) => {
+                    return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+                        "Invalid request part",
+                        e.to_string(),
+                    )))
+                }
+            },
+            "message" => message = Some(String::from_utf8_lossy(&bytes).into_owned()),
+            _ => match media_type {
+                Some(_) if images.len() >= MAX_IMAGES => {
+                    return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+                        "Too many images",
+                        format!("At most {} images per request", MAX_IMAGES),
+                    )))
+                }
+                Some(media_type) if media_type.starts_with("image/") => {
+                    images.push(ImageAttachment {
+                        media_type,
+                        data: STANDARD.encode(&bytes),
+                    })
+                }
+                _ => {
+                    return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+                        "Unexpected multipart part",
+                        format!("Part {:?} is neither the request nor an image", name),
+                    )))
+                }
+            },
+        }
+    }
+
+    let mut req = req.unwrap_or_default();
+    if let Some(message) = message {
+        req.message = message;
+    }
+    req.images.get_or_insert_with(Vec::new).extend(images);
+    // Screened here, since a multipart body skips the extractor.
+    let mut payload = ChatPayload::Native(req);
+    if let Err(violation) = state.guardrails.screen_request(&mut payload) {
+        return Ok(policy_violation(&violation));
+    }
+    chat(state, http_req, ctx, Screened(payload)).await
+}
+
+/// Re-runs the last user turn of a conversation with optional overrides. The
+/// previous answer is replaced and the cache is never consulted.
+pub async fn regenerate_chat(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    ctx: RequestContext,
+    path: web::Path<Uuid>,
+    payload: Option<web::Json<RegenerateRequest>>,
+) -> Result<HttpResponse> {
+    if let Err(denied) = check_access(&state, &http_req, false) {
+        return Ok(denied);
+    }
+    let conversation_id = path.into_inner();
+    let overrides = payload.map(|p| p.into_inner()).unwrap_or_default();
+    if let Err(e) = overrides.validate() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Invalid request",
+            format!("Validation error: {}", e),
+        )));
+    }
+
+    let removed = match state
+        .conversation_service
+        .pop_last_turn(conversation_id)
+        .await
+    {
+        Ok(removed) => removed,
+        Err(e) => {
+            tracing::error!("Regenerate lookup error: {:?}", e);
+            return Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to load conversation",
+                    e.to_string(),
+                )),
+            );
         }
-        Complexity::High => {
-            let search_results = state.ai_service.search(&req.message).await;
-            match search_results {
-                Ok(results) => state.ai_service.cloud_model_generate(&req, &results).await,
-                Err(err) => Err(err),
+    };
+    let Some(user_message) = removed.first().map(|m| m.content.clone()) else {
+        return Ok(HttpResponse::NotFound().json(ErrorResponse::new(
+            "Conversation has no user message to regenerate",
+        )));
+    };
+
+    let req = ChatRequest {
+        message: user_message,
+        conversation_id: Some(conversation_id),
+        model: overrides.model,
+        temperature: overrides.temperature,
+        max_tokens: overrides.max_tokens,
+        routing: overrides.routing,
+        cache_bypass: Some(true),
+        ..Default::default()
+    };
+
+    match resolve_chat(
+        &state,
+        &ctx,
+        &req,
+        &ChatCacheKey::default(),
+        false,
+        conversation_id,
+    )
+    .await
+    {
+        Ok(chat_response) => respond_chat(http_req, chat_response),
+        Err(e) => {
+            tracing::error!("Regenerate error: {:?}", e);
+            // Put the previous exchange back so a failed retry loses nothing.
+            let previous_answer = removed
+                .iter()
+                .skip(1)
+                .find(|m| m.role == "assistant")
+                .map(|m| m.content.as_str())
+                .unwrap_or("");
+            state
+                .conversation_service
+                .record_turn(conversation_id, &req.message, previous_answer)
+                .await;
+            if let Some(blocked) = blocked_by_policy(&e) {
+                return Ok(blocked);
+            }
+            if let Some(rejected) = rejected_by_admission(&e) {
+                return Ok(rejected);
+            }
+            if let Some(cancelled) = cancelled_by_operator(&e) {
+                return Ok(cancelled);
+            }
+            if let Some(timed_out) = past_deadline(&e) {
+                return Ok(timed_out);
             }
+            if let Some(too_long) = prompt_too_long(&e) {
+                return Ok(too_long);
+            }
+            Ok(
+                HttpResponse::InternalServerError().json(ErrorResponse::with_details(
+                    "Failed to regenerate response",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+/// Resumes the interrupted last answer of a conversation. The continuation is
+/// appended to the stored partial text and the combined answer is returned.
+pub async fn continue_chat(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    ctx: RequestContext,
+    payload: web::Json<ContinueRequest>,
+) -> Result<HttpResponse> {
+    if let Err(denied) = check_access(&state, &http_req, false) {
+        return Ok(denied);
+    }
+    let req = payload.into_inner();
+    if let Err(e) = req.validate() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Invalid request",
+            format!("Validation error: {}", e),
+        )));
+    }
+    let conversation_id = req.conversation_id;
+
+    let interrupted = match state
+        .conversation_service
+        .interrupted_answer(conversation_id)
+        .await
+    {
+        Ok(Some(message)) => message,
+        Ok(None) => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse::with_code(
+                "Conversation has no interrupted answer to continue",
+                "not_interrupted",
+            )))
         }
+        Err(e) => {
+            tracing::error!("Continue lookup error: {:?}", e);
+            return Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to load conversation",
+                    e.to_string(),
+                )),
+            );
+        }
+    };
+
+    // Partial answers only come from the local model, so it finishes them too.
+    let chat_req = ChatRequest {
+        message: CONTINUE_PROMPT.to_string(),
+        conversation_id: Some(conversation_id),
+        max_tokens: req.max_tokens,
+        // The prompt above is English; keep going in the answer's own language.
+        language: detect_language(&interrupted.content).map(str::to_string),
+        routing: Some(RoutingHint::Local),
+        cache_bypass: Some(true),
+        ..Default::default()
     };
 
-    match response {
+    match state.ai_service.generate(&ctx, &chat_req).await {
         Ok(mut chat_response) => {
-            chat_response.conversation_id = conversation_id;
-            chat_response.cache_hit = false;
-            chat_response.cache_source = None;
-            let value = serde_json::to_value(&chat_response).unwrap_or_else(|_| {
-                serde_json::json!({ "response": chat_response.response })
-            });
-            if use_cache {
-                let _ = state.cache_service.set(&cache_key, &value).await;
-            }
-            if wants_stream {
-                return Ok(stream_text_response(
-                    &http_req,
-                    chat_response.response.clone(),
-                    model_name.clone(),
-                    false,
-                    None,
+            if let Err(violation) = state.guardrails.screen_answer(&mut chat_response) {
+                return Ok(policy_violation(&violation));
+            }
+            let combined = join_continuation(&interrupted.content, &chat_response.response);
+            if let Err(e) = state
+                .conversation_service
+                .resume_answer(
                     conversation_id,
-                ));
+                    interrupted.id,
+                    &combined,
+                    chat_response.partial_reason.as_deref(),
+                )
+                .await
+            {
+                tracing::warn!("Failed to store resumed answer: {}", e);
             }
+            chat_response.response_hash = Some(cache_key(&[&combined]));
+            chat_response.response = combined;
+            chat_response.conversation_id = conversation_id;
+            chat_response.request_id = ctx.request_id;
             respond_chat(http_req, chat_response)
         }
         Err(e) => {
-            tracing::error!("Chat error: {:?}", e);
+            if let Some(blocked) = blocked_by_policy(&e) {
+                return Ok(blocked);
+            }
+            if let Some(rejected) = rejected_by_admission(&e) {
+                return Ok(rejected);
+            }
+            if let Some(cancelled) = cancelled_by_operator(&e) {
+                return Ok(cancelled);
+            }
+            if let Some(timed_out) = past_deadline(&e) {
+                return Ok(timed_out);
+            }
+            if let Some(too_long) = prompt_too_long(&e) {
+                return Ok(too_long);
+            }
+            tracing::error!("Continue error: {:?}", e);
             Ok(
                 HttpResponse::InternalServerError().json(ErrorResponse::with_details(
-                    "Failed to process chat request",
+                    "Failed to continue response",
                     e.to_string(),
                 )),
             )
@@ -130,6 +646,377 @@ pub async fn chat(
     }
 }
 
+/// Appends a continuation to the text it resumes, adding a space only where
+/// the model did not start with punctuation.
+fn join_continuation(partial: &str, continuation: &str) -> String {
+    let continuation = continuation.trim_start();
+    if continuation.is_empty() {
+        return partial.to_string();
+    }
+    let separator = match continuation.chars().next() {
+        Some(c) if c.is_ascii_punctuation() => "",
+        _ if partial.ends_with(char::is_whitespace) => "",
+        _ => " ",
+    };
+    format!("{}{}{}", partial, separator, continuation)
+}
+
+pub async fn poll_chat_stream(
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<StreamPollQuery>,
+) -> Result<HttpResponse> {
+    let token = path.into_inner();
+    let wait = Duration::from_millis(
+        query
+            .wait_ms
+            .unwrap_or(DEFAULT_POLL_WAIT_MS)
+            .min(MAX_POLL_WAIT_MS),
+    );
+
+    match state.stream_service.poll(&token, query.offset, wait).await {
+        Some(snapshot) => Ok(HttpResponse::Ok().json(StreamPollResponse {
+            token,
+            chunks: snapshot.chunks,
+            next_offset: snapshot.next_offset,
+            done: snapshot.done,
+            error: snapshot.error,
+            metadata: snapshot.metadata,
+        })),
+        None => Ok(
+            HttpResponse::NotFound().json(ErrorResponse::new("Unknown or expired stream token"))
+        ),
+    }
+}
+
+/// Runs [`resolve_chat`] once per idempotency key; retries with the same key
+/// receive the stored response instead of generating (and recording) it again.
+async fn resolve_idempotent(
+    state: &web::Data<AppState>,
+    ctx: &RequestContext,
+    req: &ChatRequest,
+    idempotency_key: Option<&str>,
+    cache_key: &ChatCacheKey,
+    use_cache: bool,
+    conversation_id: Uuid,
+) -> anyhow::Result<ChatResponse> {
+    let Some(key) = idempotency_key else {
+        return resolve_chat(state, ctx, req, cache_key, use_cache, conversation_id).await;
+    };
+    let guard = match state.cache_service.claim_idempotency(ctx, key).await {
+        IdempotencyClaim::Replay(value) => return Ok(serde_json::from_value(value)?),
+        IdempotencyClaim::Acquired(guard) => guard,
+    };
+
+    // Detached, so a retry after a dropped connection still gets this answer.
+    let (state, ctx, req) = (state.clone(), ctx.clone(), req.clone());
+    let cache_key = cache_key.clone();
+    let span = ctx.span.clone();
+    tokio::spawn(
+        async move {
+            let chat_response =
+                resolve_chat(&state, &ctx, &req, &cache_key, use_cache, conversation_id).await?;
+            guard.complete(&serde_json::to_value(&chat_response)?).await;
+            Ok::<_, anyhow::Error>(chat_response)
+        }
+        .instrument(span),
+    )
+    .await?
+}
+
+/// Produces the response for a chat request and records the turn in the
+/// conversation history.
+async fn resolve_chat(
+    state: &AppState,
+    ctx: &RequestContext,
+    req: &ChatRequest,
+    cache_key: &ChatCacheKey,
+    use_cache: bool,
+    conversation_id: Uuid,
+) -> anyhow::Result<ChatResponse> {
+    let started = Instant::now();
+    let mut chat_response =
+        cached_or_generate(state, ctx, req, cache_key, use_cache, conversation_id).await?;
+    let duration_ms = started.elapsed().as_millis() as u64;
+    // Cached answers are screened too, so rule changes apply to them immediately.
+    state.guardrails.screen_answer(&mut chat_response)?;
+    state
+        .loop_guard
+        .record_answer(conversation_id, &chat_response.response);
+    chat_response.request_id = ctx.request_id.clone();
+    // Snippets are executed per request and never cached, so output always reflects a real run.
+    if req.execute_code == Some(true) && state.sandbox_service.is_enabled() {
+        chat_response.code_executions = state
+            .sandbox_service
+            .execute_snippets(&chat_response.response)
+            .await;
+    }
+    match chat_response.partial_reason.as_deref() {
+        Some(reason) => {
+            state
+                .conversation_service
+                .record_partial_turn(
+                    conversation_id,
+                    &req.message,
+                    &chat_response.response,
+                    reason,
+                )
+                .await
+        }
+        None => {
+            state
+                .conversation_service
+                .record_turn(conversation_id, &req.message, &chat_response.response)
+                .await
+        }
+    }
+    // Cached and shared answers were already counted when they were generated.
+    let reused = chat_response.cache_hit || chat_response.coalesced;
+    if let (false, Some(complexity)) = (reused, chat_response.complexity) {
+        state
+            .routing_metrics
+            .record(
+                conversation_id,
+                complexity,
+                req.forced_complexity().is_some(),
+                req.message.chars().count(),
+                &chat_response,
+                duration_ms,
+            )
+            .await;
+    }
+    state
+        .handoff_service
+        .evaluate(conversation_id, &req.message, &chat_response);
+    Ok(chat_response)
+}
+
+/// Serves the response from cache when allowed, otherwise generates it and
+/// populates the cache.
+async fn cached_or_generate(
+    state: &AppState,
+    ctx: &RequestContext,
+    req: &ChatRequest,
+    cache_key: &ChatCacheKey,
+    use_cache: bool,
+    conversation_id: Uuid,
+) -> anyhow::Result<ChatResponse> {
+    if use_cache {
+        if let Some((cached, source)) = state.cache_service.get(&cache_key.key).await {
+            if let Some(cached_response) = from_cache(cached, source, conversation_id) {
+                return Ok(cached_response);
+            }
+        }
+        if let Some((stale, source)) = state.cache_service.get_stale(&cache_key.key).await {
+            if let Some(mut stale_response) = from_cache(stale, source, conversation_id) {
+                stale_response.cache_stale = true;
+                refresh_in_background(state, ctx, req, cache_key, conversation_id);
+                return Ok(stale_response);
+            }
+        }
+        if let Some(similar_response) = similar_from_cache(state, cache_key, conversation_id).await
+        {
+            return Ok(similar_response);
+        }
+    }
+
+    generate_and_store(state, ctx, req, cache_key, use_cache, conversation_id).await
+}
+
+/// The cached answer to an earlier question in the same scope. Fuzzy matching
+/// picks up to `max_similar_results` candidates cheaply; the best of them must
+/// then score `similarity_threshold` by embedding, or by the fuzzy ratio
+/// itself while no embedding model is loaded.
+async fn similar_from_cache(
+    state: &AppState,
+    cache_key: &ChatCacheKey,
+    conversation_id: Uuid,
+) -> Option<ChatResponse> {
+    let settings = &state.config.cache;
+    if !settings.similar_lookup {
+        return None;
+    }
+    let candidates = state
+        .cache_service
+        .similar_candidates(&cache_key.scope, &cache_key.key, &cache_key.question)
+        .await;
+    if candidates.is_empty() {
+        return None;
+    }
+    let questions: Vec<String> = candidates.iter().map(|c| c.question.clone()).collect();
+    let scores = match state
+        .embeddings
+        .similarities(&cache_key.question, &questions)
+        .await
+    {
+        Some(scores) => scores,
+        None => candidates.iter().map(|c| c.ratio).collect(),
+    };
+    let mut ranked: Vec<_> = candidates
+        .into_iter()
+        .zip(scores)
+        .filter(|(_, score)| *score >= settings.similarity_threshold)
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    for (candidate, score) in ranked {
+        // The entry may have expired or been evicted since it was indexed.
+        if let Some((cached, source)) = state.cache_service.get(&candidate.key).await {
+            if let Some(mut response) = from_cache(cached, source, conversation_id) {
+                response.cache_similarity = Some(score);
+                return Some(response);
+            }
+        }
+    }
+    None
+}
+
+fn from_cache(
+    cached: serde_json::Value,
+    source: CacheSource,
+    conversation_id: Uuid,
+) -> Option<ChatResponse> {
+    let mut cached_response = serde_json::from_value::<ChatResponse>(cached).ok()?;
+    cached_response.cache_hit = true;
+    cached_response.cache_source = Some(source.as_str().to_string());
+    if let Some(provenance) = cached_response.provenance.as_mut() {
+        provenance.cache_tier = cached_response.cache_source.clone();
+    }
+    cached_response.conversation_id = conversation_id;
+    cached_response.timestamp = chrono::Utc::now();
+    Some(cached_response)
+}
+
+/// Regenerates a stale entry after its old answer was served, unless another
+/// request is already doing so.
+fn refresh_in_background(
+    state: &AppState,
+    ctx: &RequestContext,
+    req: &ChatRequest,
+    cache_key: &ChatCacheKey,
+    conversation_id: Uuid,
+) {
+    let Some(guard) = state.cache_service.begin_refresh(&cache_key.key) else {
+        return;
+    };
+    let state = state.clone();
+    // The caller's request id already names the request that got the stale answer.
+    let ctx = ctx.detached();
+    let req = req.clone();
+    let cache_key = cache_key.clone();
+    let span = ctx.span.clone();
+    tokio::spawn(
+        async move {
+            let _guard = guard;
+            let refreshed =
+                generate_and_store(&state, &ctx, &req, &cache_key, true, conversation_id).await;
+            if let Err(e) = refreshed {
+                tracing::warn!("Refreshing a stale cache entry failed: {:?}", e);
+            }
+        }
+        .instrument(span),
+    );
+}
+
+/// Generates the response and, when allowed, stores it in the cache.
+async fn generate_and_store(
+    state: &AppState,
+    ctx: &RequestContext,
+    req: &ChatRequest,
+    cache_key: &ChatCacheKey,
+    use_cache: bool,
+    conversation_id: Uuid,
+) -> anyhow::Result<ChatResponse> {
+    // Concurrent identical cacheable requests share one generation.
+    let mut chat_response = if use_cache {
+        state
+            .ai_service
+            .generate_coalesced(ctx, req, &cache_key.key)
+            .await?
+    } else {
+        state.ai_service.generate(ctx, req).await?
+    };
+    chat_response.conversation_id = conversation_id;
+    chat_response.cache_hit = false;
+    chat_response.cache_source = None;
+    // The parameter shadows the hashing helper here.
+    let response_hash = crate::utils::cache_key(&[&chat_response.response]);
+    chat_response.response_hash = Some(response_hash.clone());
+    let value = serde_json::to_value(&chat_response)
+        .unwrap_or_else(|_| serde_json::json!({ "response": chat_response.response }));
+    // Only the request that ran the generation stores it.
+    if use_cache
+        && !chat_response.coalesced
+        && is_cacheable(&chat_response)
+        && req.cache_ttl_seconds != Some(0)
+        && state.cache_service.should_store(chat_response.route)
+    {
+        let stored = state
+            .cache_service
+            .set(
+                &cache_key.key,
+                &value,
+                &cache_tags(state, req, conversation_id, &response_hash),
+                req.cache_ttl_seconds,
+            )
+            .await;
+        if stored.is_ok() && state.config.cache.similar_lookup {
+            state.cache_service.remember_question(
+                &cache_key.scope,
+                &cache_key.question,
+                &cache_key.key,
+            );
+        }
+    }
+    Ok(chat_response)
+}
+
+/// Partial answers are never cached, so a retry gets a complete one; neither
+/// are degraded or empty ones, which would otherwise be served for the whole TTL.
+fn is_cacheable(chat_response: &ChatResponse) -> bool {
+    chat_response.cacheable
+        && !chat_response.partial
+        && !(chat_response.response.trim().is_empty() && chat_response.tool_calls.is_empty())
+}
+
+/// Tags that let an admin invalidate answers after a model, prompt or
+/// knowledge-base change, redaction evict answers derived from a conversation,
+/// and negative feedback evict one answer.
+fn cache_tags(
+    state: &AppState,
+    req: &ChatRequest,
+    conversation_id: Uuid,
+    response_hash: &str,
+) -> Vec<String> {
+    let model_name = req
+        .model
+        .clone()
+        .unwrap_or_else(|| state.ai_service.model_name());
+    let mut tags = vec![
+        format!("model:{}", model_name),
+        format!("prompt:{}", state.config.ai.prompt_version),
+        format!("conversation:{}", conversation_id),
+        format!("response:{}", response_hash),
+    ];
+    if let Some(snapshot) = state.ai_service.knowledge_base_snapshot() {
+        tags.push(format!("kb:{}", snapshot));
+    }
+    tags
+}
+
+/// The caller's `Idempotency-Key`, if any. Errors are meant for the client.
+pub(crate) fn idempotency_key(
+    http_req: &HttpRequest,
+) -> std::result::Result<Option<String>, String> {
+    let Some(value) = http_req.headers().get("idempotency-key") else {
+        return Ok(None);
+    };
+    let key = value.to_str().map(str::trim).unwrap_or("");
+    if key.is_empty() || key.len() > 255 || !key.chars().all(|c| c.is_ascii_graphic()) {
+        return Err("Idempotency-Key must be 1-255 visible ASCII characters".to_string());
+    }
+    Ok(Some(key.to_string()))
+}
+
 fn respond_chat(http_req: HttpRequest, chat_response: ChatResponse) -> Result<HttpResponse> {
     let accept = http_req
         .headers()
@@ -144,52 +1031,3 @@ fn respond_chat(http_req: HttpRequest, chat_response: ChatResponse) -> Result<Ht
 
     Ok(HttpResponse::Ok().json(chat_response))
 }
-
-fn stream_text_response(
-    _http_req: &HttpRequest,
-    response: String,
-    model_name: String,
-    cache_hit: bool,
-    cache_source: Option<String>,
-    conversation_id: Uuid,
-) -> HttpResponse {
-    let (tx, rx) = mpsc::channel::<Bytes>(32);
-    tokio::spawn(async move {
-        let words: Vec<&str> = response.split_whitespace().collect();
-        for (index, word) in words.iter().enumerate() {
-            let token = if index == 0 {
-                (*word).to_string()
-            } else {
-                format!(" {}", word)
-            };
-            let payload = serde_json::json!({
-                "model": model_name,
-                "created_at": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Nanos, true),
-                "response": token,
-                "done": false
-            });
-            let line = format!("{}\n", payload);
-            if tx.send(Bytes::from(line)).await.is_err() {
-                return;
-            }
-            sleep(Duration::from_millis(60)).await;
-        }
-
-        let done_payload = serde_json::json!({
-            "model": model_name,
-            "created_at": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Nanos, true),
-            "response": "",
-            "done": true,
-            "cache_hit": cache_hit,
-            "cache_source": cache_source,
-            "conversation_id": conversation_id,
-        });
-        let line = format!("{}\n", done_payload);
-        let _ = tx.send(Bytes::from(line)).await;
-    });
-
-    let stream = ReceiverStream::new(rx).map(Ok::<Bytes, std::io::Error>);
-    HttpResponse::Ok()
-        .insert_header((actix_web::http::header::CONTENT_TYPE, "application/x-ndjson"))
-        .streaming(stream)
-}
@@ -0,0 +1,276 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::Utc;
+use regex::RegexBuilder;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::handlers::authorize;
+use crate::models::{
+    ConversationArchivesResponse, ConversationDetail, ConversationListResponse, Cursor,
+    ErrorResponse, FeedbackRating, FeedbackRequest, PageQuery, RedactionRequest, RedactionResponse,
+};
+use crate::services::RedactionPlan;
+use crate::AppState;
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+/// Compiled-size cap for caller-supplied redaction patterns.
+const MAX_PATTERN_SIZE: usize = 1 << 20;
+
+pub async fn get_conversation(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let conversation_id = path.into_inner();
+
+    match state.conversation_service.get(conversation_id).await {
+        Ok(Some((record, messages))) => Ok(HttpResponse::Ok().json(ConversationDetail {
+            summary: record.into(),
+            messages: messages.into_iter().map(Into::into).collect(),
+        })),
+        Ok(None) => Ok(HttpResponse::NotFound().json(ErrorResponse::new("Conversation not found"))),
+        Err(e) => {
+            tracing::error!("Conversation lookup error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to load conversation",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+/// Older turns moved out of the conversation by `CONVERSATION_MAX_MESSAGES`
+/// or `CONVERSATION_MAX_MESSAGE_AGE_DAYS`, paged by archive time.
+pub async fn get_conversation_archives(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<PageQuery>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let conversation_id = path.into_inner();
+    let page = match query.resolve(DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE) {
+        Ok(page) => page,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(e))),
+    };
+
+    match state
+        .conversation_service
+        .archives(conversation_id, page.clone())
+        .await
+    {
+        Ok(mut archives) => {
+            let page = page.finish(&mut archives, |archive| Cursor {
+                timestamp: archive.archived_at.timestamp(),
+                id: archive.id.to_string(),
+            });
+            Ok(HttpResponse::Ok().json(ConversationArchivesResponse {
+                conversation_id: conversation_id.to_string(),
+                archives: archives.into_iter().map(Into::into).collect(),
+                page,
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Conversation archive lookup error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to load conversation archives",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+pub async fn list_conversations(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<PageQuery>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let page = match query.resolve(DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE) {
+        Ok(page) => page,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(e))),
+    };
+
+    match state.conversation_service.list(page.clone()).await {
+        Ok(mut records) => {
+            let page = page.finish(&mut records, |record| Cursor {
+                timestamp: record.updated_at.timestamp(),
+                id: record.conversation_id.clone(),
+            });
+            Ok(HttpResponse::Ok().json(ConversationListResponse {
+                conversations: records.into_iter().map(Into::into).collect(),
+                page,
+            }))
+        }
+        Err(e) => {
+            tracing::error!("Conversation listing error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to list conversations",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+/// Records user feedback on the latest answer of a conversation. It feeds
+/// the routing report, so it takes the admin token like the report does.
+pub async fn submit_feedback(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<FeedbackRequest>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let conversation_id = path.into_inner();
+    if payload.rating == FeedbackRating::Positive {
+        return Ok(HttpResponse::Accepted().finish());
+    }
+
+    match state
+        .routing_metrics
+        .mark_negative_feedback(conversation_id)
+        .await
+    {
+        Ok(true) => Ok(HttpResponse::Accepted().finish()),
+        Ok(false) => Ok(HttpResponse::NotFound().json(ErrorResponse::new(
+            "No answer recorded for this conversation",
+        ))),
+        Err(e) => {
+            tracing::error!("Feedback error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to record feedback",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+/// Masks or removes messages and pattern matches from a stored conversation,
+/// dropping its rolling summary and any cached answers derived from it.
+/// Irreversible, so it takes the admin token.
+pub async fn redact_conversation(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<RedactionRequest>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let conversation_id = path.into_inner();
+    let req = payload.into_inner();
+    if let Err(e) = req.validate() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Invalid request",
+            format!("Validation error: {}", e),
+        )));
+    }
+    if req.ranges.is_empty() && req.patterns.is_empty() && !req.builtin_patterns {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Specify ranges, patterns or builtin_patterns",
+        )));
+    }
+    if req.ranges.iter().any(|range| range.start >= range.end) {
+        return Ok(
+            HttpResponse::BadRequest().json(ErrorResponse::new("Each range needs start < end"))
+        );
+    }
+
+    let mut patterns = Vec::with_capacity(req.patterns.len());
+    for pattern in &req.patterns {
+        match RegexBuilder::new(pattern)
+            .size_limit(MAX_PATTERN_SIZE)
+            .build()
+        {
+            Ok(regex) => patterns.push(regex),
+            Err(e) => {
+                return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+                    "Invalid pattern",
+                    e.to_string(),
+                )))
+            }
+        }
+    }
+
+    let plan = RedactionPlan {
+        ranges: req
+            .ranges
+            .iter()
+            .map(|range| range.start..range.end)
+            .collect(),
+        mode: req.mode,
+        patterns,
+        builtin_patterns: req.builtin_patterns,
+        reason: req.reason,
+    };
+    let outcome = match state
+        .conversation_service
+        .redact(conversation_id, plan)
+        .await
+    {
+        Ok(Some(outcome)) => outcome,
+        Ok(None) => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse::new("Conversation not found")))
+        }
+        Err(e) => {
+            tracing::error!("Conversation redaction error: {:?}", e);
+            return Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to redact conversation",
+                    e.to_string(),
+                )),
+            );
+        }
+    };
+
+    // The stored history is already clean; a cache failure is reported but not fatal.
+    let cache_entries_removed = match state
+        .cache_service
+        .invalidate_tag(&format!("conversation:{}", conversation_id))
+        .await
+    {
+        Ok(removed) => removed.memory.max(removed.redis).max(removed.sqlite),
+        Err(e) => {
+            tracing::warn!(
+                "Failed to evict cached answers for {}: {}",
+                conversation_id,
+                e
+            );
+            0
+        }
+    };
+
+    tracing::info!(
+        conversation_id = %conversation_id,
+        removed = outcome.removed,
+        masked = outcome.masked,
+        scrubbed = outcome.scrubbed,
+        "Conversation redacted"
+    );
+    Ok(HttpResponse::Ok().json(RedactionResponse {
+        conversation_id,
+        messages_removed: outcome.removed,
+        messages_masked: outcome.masked,
+        messages_scrubbed: outcome.scrubbed,
+        cache_entries_removed,
+        redacted_at: Utc::now(),
+    }))
+}
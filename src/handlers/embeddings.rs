@@ -0,0 +1,126 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+use crate::handlers::{check_access, overloaded};
+use crate::models::{
+    EmbeddingData, EmbeddingRequest, EmbeddingResponse, EmbeddingUsage, EmbeddingVector,
+    ErrorResponse,
+};
+use crate::AppState;
+
+/// Texts accepted in one request.
+const MAX_EMBEDDING_INPUTS: usize = 256;
+/// Longest text accepted; the encoder only reads its first 256 tokens anyway.
+const MAX_EMBEDDING_INPUT_CHARS: usize = 32_000;
+
+/// `POST /api/embeddings`: OpenAI-compatible sentence embeddings from the
+/// local `EMBEDDING_MODEL`.
+pub async fn create_embeddings(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<EmbeddingRequest>,
+) -> Result<HttpResponse> {
+    if let Err(denied) = check_access(&state, &http_req, false) {
+        return Ok(denied);
+    }
+    let req = req.into_inner();
+
+    let Some(model_name) = state.embeddings.model_name().map(str::to_string) else {
+        return Ok(HttpResponse::NotFound().json(ErrorResponse::new(
+            "No embedding model is configured; set EMBEDDING_MODEL",
+        )));
+    };
+    if req
+        .model
+        .as_deref()
+        .is_some_and(|model| model != model_name)
+    {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Unknown embedding model",
+            format!("Embeddings are served by {}", model_name),
+        )));
+    }
+    let base64 = match req.encoding_format.as_deref() {
+        None | Some("float") => false,
+        Some("base64") => true,
+        Some(other) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+                "Invalid request",
+                format!(
+                    "Unknown encoding_format {:?}, expected float or base64",
+                    other
+                ),
+            )));
+        }
+    };
+
+    let texts = req.input.into_texts();
+    if texts.is_empty() || texts.len() > MAX_EMBEDDING_INPUTS {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Invalid request",
+            format!("input must hold 1 to {} texts", MAX_EMBEDDING_INPUTS),
+        )));
+    }
+    if texts
+        .iter()
+        .any(|text| text.is_empty() || text.chars().count() > MAX_EMBEDDING_INPUT_CHARS)
+    {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Invalid request",
+            format!(
+                "Each input must be 1 to {} characters long",
+                MAX_EMBEDDING_INPUT_CHARS
+            ),
+        )));
+    }
+
+    // Encoding shares the CPU with generation, so it waits for the same slots.
+    let _permit = match state.ai_service.admit().await {
+        Ok(permit) => permit,
+        Err(rejection) => return Ok(overloaded(&rejection)),
+    };
+    let (vectors, tokens) = match state.embeddings.embed_counted(texts).await {
+        Some(Ok(embedded)) => embedded,
+        Some(Err(e)) => {
+            tracing::error!("Embedding failed: {:?}", e);
+            return Ok(
+                HttpResponse::InternalServerError().json(ErrorResponse::with_details(
+                    "Failed to compute embeddings",
+                    e.to_string(),
+                )),
+            );
+        }
+        None => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(ErrorResponse::new("Embedding model is still loading")));
+        }
+    };
+
+    let data = vectors
+        .into_iter()
+        .enumerate()
+        .map(|(index, vector)| EmbeddingData {
+            object: "embedding".to_string(),
+            index,
+            embedding: if base64 {
+                let bytes: Vec<u8> = vector
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect();
+                EmbeddingVector::Base64(STANDARD.encode(bytes))
+            } else {
+                EmbeddingVector::Float(vector)
+            },
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(EmbeddingResponse {
+        object: "list".to_string(),
+        data,
+        model: model_name,
+        usage: EmbeddingUsage {
+            prompt_tokens: tokens,
+            total_tokens: tokens,
+        },
+    }))
+}
@@ -0,0 +1,147 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use validator::Validate;
+
+use crate::handlers::{authorize, caller_id, check_access};
+use crate::models::{
+    ErrorResponse, FeedbackRating, FeedbackReceipt, FeedbackStats, FeedbackStatsQuery,
+    FeedbackSubmission, ResponseFeedbackStats,
+};
+use crate::AppState;
+
+const DEFAULT_STATS_HOURS: i64 = 24 * 7;
+/// Longest look-back accepted; larger values would overflow the timestamp
+/// arithmetic.
+const MAX_STATS_HOURS: i64 = 24 * 366;
+const DEFAULT_STATS_LIMIT: usize = 10;
+const MAX_STATS_LIMIT: usize = 100;
+
+/// Stores a rating of one answer and evicts it from the cache once enough
+/// distinct callers have rated it negatively.
+pub async fn record_feedback(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    payload: web::Json<FeedbackSubmission>,
+) -> Result<HttpResponse> {
+    if let Err(denied) = check_access(&state, &http_req, false) {
+        return Ok(denied);
+    }
+    let req = payload.into_inner();
+    if let Err(e) = req.validate() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Invalid request",
+            format!("Validation error: {}", e),
+        )));
+    }
+
+    let negative_feedback = match state
+        .feedback_service
+        .submit(
+            req.conversation_id,
+            &req.response_hash,
+            req.rating,
+            req.comment,
+            caller_id(&state, &http_req),
+        )
+        .await
+    {
+        Ok(count) => count,
+        Err(e) => {
+            tracing::error!("Feedback error: {:?}", e);
+            return Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to record feedback",
+                    e.to_string(),
+                )),
+            );
+        }
+    };
+
+    let mut evicted = false;
+    if req.rating == FeedbackRating::Negative {
+        // Routing outcomes are tracked per conversation; a missing decision is fine here.
+        if let Err(e) = state
+            .routing_metrics
+            .mark_negative_feedback(req.conversation_id)
+            .await
+        {
+            tracing::warn!("Failed to flag routing feedback: {}", e);
+        }
+
+        // Only the submission that reaches the threshold evicts, so later ones
+        // don't keep clearing a regenerated answer with the same text.
+        let threshold = state.config.cache.feedback_eviction_threshold;
+        if threshold > 0 && negative_feedback == threshold {
+            let tag = format!("response:{}", req.response_hash);
+            match state.cache_service.invalidate_tag(&tag).await {
+                Ok(removed) => {
+                    evicted = removed.memory.max(removed.redis).max(removed.sqlite) > 0;
+                    tracing::info!(
+                        response_hash = %req.response_hash,
+                        negative_feedback,
+                        evicted,
+                        "Evicted negatively rated answer"
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to evict rated answer {}: {}", req.response_hash, e)
+                }
+            }
+        }
+    }
+
+    Ok(HttpResponse::Accepted().json(FeedbackReceipt {
+        response_hash: req.response_hash,
+        negative_feedback,
+        evicted,
+    }))
+}
+
+/// Rating totals and the worst-rated answers across every caller, so it
+/// takes the admin token.
+pub async fn feedback_stats(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<FeedbackStatsQuery>,
+) -> Result<HttpResponse> {
+    if let Some(denied) = authorize(&state, &http_req) {
+        return Ok(denied);
+    }
+    let hours = query
+        .since_hours
+        .unwrap_or(DEFAULT_STATS_HOURS)
+        .clamp(1, MAX_STATS_HOURS);
+    let since = Utc::now() - Duration::hours(hours);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_STATS_LIMIT)
+        .clamp(1, MAX_STATS_LIMIT);
+
+    match state.feedback_service.stats_since(since, limit).await {
+        Ok((totals, most_negative)) => Ok(HttpResponse::Ok().json(FeedbackStats {
+            since,
+            total: totals.total,
+            positive: totals.positive,
+            negative: totals.negative,
+            with_comment: totals.with_comment,
+            negative_rate: totals.negative as f64 / totals.total.max(1) as f64,
+            most_negative: most_negative
+                .into_iter()
+                .map(|record| ResponseFeedbackStats {
+                    response_hash: record.response_hash,
+                    positive: record.positive,
+                    negative: record.negative,
+                })
+                .collect(),
+        })),
+        Err(e) => {
+            tracing::error!("Feedback stats error: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "Failed to build feedback stats",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
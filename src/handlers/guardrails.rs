@@ -0,0 +1,27 @@
+use actix_web::HttpResponse;
+
+use crate::models::ErrorResponse;
+use crate::services::{GuardrailStage, GuardrailViolation};
+
+/// The 422 returned when a request or an answer is blocked by a guardrail rule.
+pub(crate) fn policy_violation(violation: &GuardrailViolation) -> HttpResponse {
+    let error = match violation.stage {
+        GuardrailStage::Input => "Request blocked by content policy",
+        GuardrailStage::Output => "Response blocked by content policy",
+    };
+    let mut body = ErrorResponse::with_code(error, violation.policy_code.clone());
+    body.details = Some(
+        violation
+            .message
+            .clone()
+            .unwrap_or_else(|| format!("Matched the {} policy", violation.category)),
+    );
+    HttpResponse::UnprocessableEntity().json(body)
+}
+
+/// Maps a failed generation to a 422 when a guardrail blocked its answer.
+pub(crate) fn blocked_by_policy(error: &anyhow::Error) -> Option<HttpResponse> {
+    error
+        .downcast_ref::<GuardrailViolation>()
+        .map(policy_violation)
+}
@@ -2,32 +2,72 @@ use actix_web::{web, HttpResponse, Result};
 use chrono::{Utc, Duration};
 use std::time::Instant;
 
-use crate::models::{HealthResponse, ErrorResponse};
+use crate::models::{
+    CapabilitiesResponse, CloudSpendStatus, EndpointGroup, ErrorResponse, HealthResponse,
+};
+use crate::routes::api::ENDPOINT_GROUP_ROUTES;
 use crate::AppState;
 
+/// Spend and cap state, when OpenRouter accounting is enabled.
+async fn spend_status(state: &AppState) -> Option<CloudSpendStatus> {
+    if !state.spend_service.is_enabled() {
+        return None;
+    }
+    match state.spend_service.status().await {
+        Ok(status) => Some(status),
+        Err(e) => {
+            tracing::warn!("Failed to read OpenRouter spend: {}", e);
+            None
+        }
+    }
+}
+
+/// Whether the local model is loaded, whether it was unloaded for idleness,
+/// and the device it runs on. A model server is asked instead when
+/// `LOCAL_BACKEND` names one.
+async fn local_model_status(state: &AppState) -> (bool, bool, String) {
+    if let Some(backend) = state.ai_service.local_backend() {
+        return (backend.is_ready().await, false, "remote".to_string());
+    }
+    let model = state.ai_model.read().await;
+    (
+        model.is_ready(),
+        model.is_idle_unloaded(),
+        model.device_name(),
+    )
+}
+
 pub async fn health_check(state: web::Data<AppState>) -> Result<HttpResponse> {
     let uptime = state.start_time.elapsed().as_secs();
-    let model_loaded = state.ai_model.read().await.is_ready();
+    let (model_loaded, idle_unloaded, device) = local_model_status(&state).await;
 
+    // A model unloaded for idleness loads again on the next request.
+    let healthy = model_loaded || idle_unloaded;
     let response = HealthResponse {
-        status: if model_loaded { "healthy" } else { "initializing" }.to_string(),
+        status: if healthy { "healthy" } else { "initializing" }.to_string(),
         model_loaded,
+        device,
         uptime_seconds: uptime,
         version: env!("CARGO_PKG_VERSION").to_string(),
+        cloud_spend: spend_status(&state).await,
+        queued_generations: state.ai_service.queued_generations(),
     };
 
     Ok(HttpResponse::Ok().json(response))
 }
 
 pub async fn ready_check(state: web::Data<AppState>) -> Result<HttpResponse> {
-    let model_loaded = state.ai_model.read().await.is_ready();
+    let (model_loaded, idle_unloaded, device) = local_model_status(&state).await;
 
-    if model_loaded {
+    if model_loaded || idle_unloaded {
         Ok(HttpResponse::Ok().json(HealthResponse {
             status: "ready".to_string(),
-            model_loaded: true,
+            model_loaded,
+            device,
             uptime_seconds: state.start_time.elapsed().as_secs(),
             version: env!("CARGO_PKG_VERSION").to_string(),
+            cloud_spend: None,
+            queued_generations: state.ai_service.queued_generations(),
         }))
     } else {
         Ok(HttpResponse::ServiceUnavailable().json(ErrorResponse::new(
@@ -36,6 +76,25 @@ pub async fn ready_check(state: web::Data<AppState>) -> Result<HttpResponse> {
     }
 }
 
+/// Lists the endpoint groups this instance serves, so clients can tell a
+/// disabled feature from a missing one.
+pub async fn capabilities(state: web::Data<AppState>) -> Result<HttpResponse> {
+    let security = &state.config.security;
+    let endpoint_groups = ENDPOINT_GROUP_ROUTES
+        .iter()
+        .filter(|(name, _)| security.endpoint_group_enabled(name))
+        .map(|(name, routes)| EndpointGroup {
+            name: name.to_string(),
+            routes: routes.iter().map(|route| route.to_string()).collect(),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(CapabilitiesResponse {
+        endpoint_groups,
+        disabled_endpoint_groups: security.disabled_endpoint_groups.clone(),
+    }))
+}
+
 pub async fn not_found() -> Result<HttpResponse> {
     Ok(HttpResponse::NotFound().json(ErrorResponse::new(
         "Endpoint not found"
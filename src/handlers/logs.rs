@@ -1,16 +1,28 @@
-use actix_web::{web, HttpResponse, Result};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
 use validator::Validate;
 use chrono::Utc;
+use std::time::Instant;
 
+use crate::handlers::{
+    cancelled_by_operator, check_access, overloaded, policy_violation, prompt_too_long,
+};
+use crate::middleware::Screened;
 use crate::models::{
-    LogAnalysisRequest, LogAnalysisResponse, ErrorResponse
+    ErrorResponse, LogAnalysisRequest, LogAnalysisResponse, LogAnalysisTimings, ModelUnavailable,
+    PartialGeneration, RequestContext, Route,
 };
+use crate::utils::{cache_key, LOG_DIGEST_VERSION};
 use crate::AppState;
 
 pub async fn analyze_logs(
     state: web::Data<AppState>,
-    req: web::Json<LogAnalysisRequest>,
+    http_req: HttpRequest,
+    ctx: RequestContext,
+    req: Screened<LogAnalysisRequest>,
 ) -> Result<HttpResponse> {
+    if let Err(denied) = check_access(&state, &http_req, false) {
+        return Ok(denied);
+    }
     // Validate request
     if let Err(e) = req.validate() {
         return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
@@ -19,58 +31,181 @@ pub async fn analyze_logs(
         )));
     }
 
-    // Get mutable reference to AI model
-    let mut ai_model = state.ai_model.write().await;
+    // Reuse the parsed form of uploads seen before
+    let started = Instant::now();
+    let stored = match state.log_store.digest(&req.logs).await {
+        Ok(stored) => stored,
+        Err(e) => {
+            tracing::error!("Log parsing error: {:?}", e);
+            return Ok(
+                HttpResponse::InternalServerError().json(ErrorResponse::with_details(
+                    "Failed to analyze logs",
+                    e.to_string(),
+                )),
+            );
+        }
+    };
+    let parse_ms = started.elapsed().as_millis() as u64;
+
+    let policy = &state.config.cache.log_analysis;
+    let model_name = state.ai_service.model_name();
+    let logs_key = if policy.match_similar {
+        stored.digest.signature()
+    } else {
+        stored.content_hash.clone()
+    };
+    let entry_key = cache_key(&[
+        "log_analysis",
+        &model_name,
+        &state.config.ai.prompt_version,
+        LOG_DIGEST_VERSION,
+        &logs_key,
+        req.context.as_deref().unwrap_or(""),
+    ]);
+    if policy.enabled {
+        if let Some((value, _)) = state.cache_service.get(&entry_key).await {
+            if let Ok(mut cached) = serde_json::from_value::<LogAnalysisResponse>(value) {
+                // Re-screened so entries stored before a guardrail change are held to it.
+                if let Err(violation) = state.guardrails.screen_answer(&mut cached) {
+                    return Ok(policy_violation(&violation));
+                }
+                cached.cache_hit = true;
+                cached.timestamp = Utc::now();
+                cached.log_hash = Some(stored.content_hash);
+                cached.digest_reused = stored.reused;
+                cached.timings = Some(LogAnalysisTimings {
+                    parse_ms,
+                    inference_ms: 0,
+                    total_ms: started.elapsed().as_millis() as u64,
+                });
+                return Ok(HttpResponse::Ok().json(cached));
+            }
+        }
+    }
+
+    let queued_at = Instant::now();
+    let _permit = match state.ai_service.admit().await {
+        Ok(permit) => permit,
+        Err(rejection) => return Ok(overloaded(&rejection)),
+    };
+    let queue_wait = queued_at.elapsed();
+
+    let digest = stored.digest.render();
+    let context = req.context.clone();
 
     // Process the log analysis request
-    match ai_model.analyze_logs(&req.logs, req.context.clone()).await {
-        Ok(analysis) => {
-            // Extract structured information from the analysis
-            let issues: Vec<String> = analysis
-                .lines()
-                .filter(|line| line.to_lowercase().contains("issue") ||
-                               line.to_lowercase().contains("error"))
-                .take(5)
-                .map(|s| s.trim().to_string())
-                .collect();
+    let inference_started = Instant::now();
+    let analyzed = state
+        .ai_service
+        .analyze_logs(&ctx, queue_wait, digest, context)
+        .await;
+    if let Some(too_long) = analyzed.as_ref().err().and_then(prompt_too_long) {
+        return Ok(too_long);
+    }
+    if let Some(cancelled) = analyzed.as_ref().err().and_then(cancelled_by_operator) {
+        return Ok(cancelled);
+    }
+    let (mut analysis, partial_reason) =
+        match analyzed {
+            Ok(analysis) => (analysis, None),
+            Err(e) if e.is::<ModelUnavailable>() => {
+                tracing::error!("Failed to load the AI model: {:?}", e);
+                return Ok(
+                    HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                        "AI model is unavailable",
+                        e.to_string(),
+                    )),
+                );
+            }
+            // A long analysis cut short is still worth returning.
+            Err(e) => match e.downcast::<PartialGeneration>() {
+                Ok(partial) => {
+                    tracing::warn!("Returning partial log analysis: {}", partial.reason);
+                    (partial.text, Some(partial.reason))
+                }
+                Err(e) => {
+                    tracing::error!("Log analysis error: {:?}", e);
+                    return Ok(HttpResponse::InternalServerError().json(
+                        ErrorResponse::with_details("Failed to analyze logs", e.to_string()),
+                    ));
+                }
+            },
+        };
+    let inference_ms = inference_started.elapsed().as_millis() as u64;
 
-            let recommendations: Vec<String> = analysis
-                .lines()
-                .filter(|line| line.to_lowercase().contains("recommend") ||
-                               line.to_lowercase().contains("suggest"))
-                .take(5)
-                .map(|s| s.trim().to_string())
-                .collect();
+    if let Err(violation) = state.guardrails.screen_answer(&mut analysis) {
+        return Ok(policy_violation(&violation));
+    }
 
-            let severity = if analysis.to_lowercase().contains("critical") {
-                "critical".to_string()
-            } else if analysis.to_lowercase().contains("error") {
-                "high".to_string()
-            } else if analysis.to_lowercase().contains("warning") {
-                "medium".to_string()
-            } else {
-                "low".to_string()
-            };
+    // Extract structured information from the analysis
+    let issues: Vec<String> = analysis
+        .lines()
+        .filter(|line| {
+            line.to_lowercase().contains("issue") || line.to_lowercase().contains("error")
+        })
+        .take(5)
+        .map(|s| s.trim().to_string())
+        .collect();
 
-            let confidence = if analysis.len() > 500 { 0.8 } else { 0.6 };
+    let recommendations: Vec<String> = analysis
+        .lines()
+        .filter(|line| {
+            line.to_lowercase().contains("recommend") || line.to_lowercase().contains("suggest")
+        })
+        .take(5)
+        .map(|s| s.trim().to_string())
+        .collect();
 
-            let response = LogAnalysisResponse {
-                analysis,
-                issues,
-                recommendations,
-                severity,
-                confidence,
-                timestamp: Utc::now(),
-            };
+    let severity = if analysis.to_lowercase().contains("critical") {
+        "critical".to_string()
+    } else if analysis.to_lowercase().contains("error") {
+        "high".to_string()
+    } else if analysis.to_lowercase().contains("warning") {
+        "medium".to_string()
+    } else {
+        "low".to_string()
+    };
 
-            Ok(HttpResponse::Ok().json(response))
-        }
-        Err(e) => {
-            tracing::error!("Log analysis error: {:?}", e);
-            Ok(HttpResponse::InternalServerError().json(ErrorResponse::with_details(
-                "Failed to analyze logs",
-                e.to_string()
-            )))
+    let confidence = match (&partial_reason, analysis.len() > 500) {
+        (Some(_), _) => 0.4,
+        (None, true) => 0.8,
+        (None, false) => 0.6,
+    };
+
+    let response = LogAnalysisResponse {
+        analysis,
+        issues,
+        recommendations,
+        severity,
+        confidence,
+        timestamp: Utc::now(),
+        log_hash: Some(stored.content_hash),
+        digest_reused: stored.reused,
+        timings: Some(LogAnalysisTimings {
+            parse_ms,
+            inference_ms,
+            total_ms: started.elapsed().as_millis() as u64,
+        }),
+        partial: partial_reason.is_some(),
+        partial_reason,
+        cache_hit: false,
+    };
+
+    // Partial analyses are never stored, so a retry gets a complete one. Analyses
+    // come from the local model, so `CACHE_WRITE_POLICY=cloud` stores none.
+    if policy.enabled && !response.partial && state.cache_service.should_store(Some(Route::Local)) {
+        if let Ok(value) = serde_json::to_value(&response) {
+            let tags = [
+                "endpoint:log_analysis".to_string(),
+                format!("model:{}", model_name),
+                format!("prompt:{}", state.config.ai.prompt_version),
+            ];
+            let _ = state
+                .cache_service
+                .set(&entry_key, &value, &tags, Some(policy.ttl_seconds))
+                .await;
         }
     }
+
+    Ok(HttpResponse::Ok().json(response))
 }
@@ -1,10 +1,34 @@
+pub mod access;
+pub mod admin;
+pub mod admission;
+pub mod agents;
 pub mod chat;
+pub mod conversations;
+pub mod embeddings;
+pub mod feedback;
+pub mod guardrails;
 pub mod health;
 pub mod logs;
+pub mod models;
+pub mod ollama;
 pub mod scripts;
+pub mod streaming;
+pub mod ui;
 
+pub use access::*;
+pub use admin::*;
+pub use admission::*;
+pub use agents::*;
 pub use chat::*;
+pub use conversations::*;
+pub use embeddings::*;
+pub use feedback::*;
+pub use guardrails::*;
 pub use health::*;
 pub use logs::*;
+pub use models::*;
+pub use ollama::*;
 pub use scripts::*;
+pub use streaming::*;
+pub use ui::*;
 
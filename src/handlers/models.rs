@@ -0,0 +1,96 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use validator::Validate;
+
+use crate::handlers::check_access;
+use crate::models::{
+    ErrorResponse, ModelListQuery, ModelListResponse, TokenizeRequest, TokenizeResponse,
+};
+use crate::AppState;
+
+/// `GET /api/models`: local models a chat request can select, and optionally
+/// OpenRouter's catalog, for client model pickers.
+pub async fn list_models(
+    state: web::Data<AppState>,
+    query: web::Query<ModelListQuery>,
+) -> Result<HttpResponse> {
+    let (cloud, cloud_error) = if query.include_cloud {
+        match state.ai_service.cloud_models().await {
+            Ok(models) => (Some(models), None),
+            Err(e) => {
+                tracing::warn!("Failed to read the OpenRouter model catalog: {}", e);
+                (None, Some(e.to_string()))
+            }
+        }
+    } else {
+        (None, None)
+    };
+
+    Ok(HttpResponse::Ok().json(ModelListResponse {
+        local: state.ai_service.local_models(),
+        default_cloud_model: state.ai_service.default_cloud_model(),
+        cloud,
+        cloud_error,
+    }))
+}
+
+/// `POST /api/tokenize`: token count of `text` under a local model's
+/// tokenizer, so clients can check a prompt or log fits before sending it.
+pub async fn tokenize(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    req: web::Json<TokenizeRequest>,
+) -> Result<HttpResponse> {
+    if let Err(denied) = check_access(&state, &http_req, false) {
+        return Ok(denied);
+    }
+    if let Err(e) = req.validate() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Invalid request",
+            format!("Validation error: {}", e),
+        )));
+    }
+    let req = req.into_inner();
+
+    let models = state.ai_service.models();
+    let Some(model) = models.get(req.model.as_deref()) else {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Unknown local model",
+            format!("Local models: {}", models.names().join(", ")),
+        )));
+    };
+    let model_name = match req.model.as_deref() {
+        Some(name) => models.model_name(name).unwrap_or(name).to_string(),
+        None => state.ai_service.model_name(),
+    };
+
+    let model = model.read_owned().await;
+    let text = req.text;
+    // Long logs take a while to encode; keep it off the async workers.
+    let counted = tokio::task::spawn_blocking(move || match model.tokenize(&text) {
+        Some(ids) => (ids.len(), false, Some(ids), model.context_length()),
+        None => (
+            model.count_tokens(&text),
+            true,
+            None,
+            model.context_length(),
+        ),
+    })
+    .await;
+    let (token_count, estimated, ids, context_length) = match counted {
+        Ok(counted) => counted,
+        Err(e) => {
+            tracing::error!("Tokenization failed: {}", e);
+            return Ok(HttpResponse::InternalServerError()
+                .json(ErrorResponse::new("Failed to tokenize text")));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(TokenizeResponse {
+        model: model_name,
+        token_count,
+        estimated,
+        context_length,
+        fits: token_count <= context_length,
+        ids: ids.filter(|_| req.include_ids),
+    }))
+}
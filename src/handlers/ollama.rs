@@ -0,0 +1,277 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{SecondsFormat, Utc};
+use std::time::Instant;
+use validator::Validate;
+
+use crate::handlers::{
+    cancelled_by_operator, check_access, past_deadline, policy_violation, prompt_too_long,
+    rejected_by_admission, stream_response, Access, OllamaChunkEncoder, StreamSummary,
+};
+use crate::middleware::Screened;
+use crate::models::{
+    ChatRequest, ErrorResponse, OllamaChatRequest, OllamaChatResponse, OllamaGenerateRequest,
+    OllamaGenerateResponse, OllamaMessage, OllamaModelDetails, OllamaModelTag, OllamaOptions,
+    OllamaTagsResponse, RequestContext,
+};
+use crate::AppState;
+
+/// Version reported on `/api/version`; Ollama clients gate features on it.
+const OLLAMA_COMPAT_VERSION: &str = "0.1.32";
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum OllamaEndpoint {
+    Generate,
+    Chat,
+}
+
+pub async fn ollama_generate(
+    state: web::Data<AppState>,
+    http_req: HttpRequest,
+    ctx: RequestContext,
+    req: Screened<OllamaGenerateRequest>,
+) -> Result<HttpResponse> {
+    let access = match check_access(&state, &http_req, false) {
+        Ok(access) => access,
+        Err(denied) => return Ok(denied),
+    };
+    let req = req.into_inner();
+
+    run_ollama(
+        state,
+        ctx,
+        access,
+        OllamaEndpoint::Generate,
+        &req.model,
+        req.system,
+        req.prompt,
+        req.stream,
+        req.options.as_ref(),
+    )
+    .await
+}
+
+/// Ollama-shaped `/api/chat`; dispatched from the chat handler when the body has `messages`.
+pub async fn ollama_chat(
+    state: web::Data<AppState>,
+    ctx: RequestContext,
+    req: OllamaChatRequest,
+    access: Access,
+) -> Result<HttpResponse> {
+    let system_prompt = req
+        .messages
+        .iter()
+        .filter(|message| message.role == "system")
+        .map(|message| message.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let message = flatten_messages(&req.messages);
+    run_ollama(
+        state,
+        ctx,
+        access,
+        OllamaEndpoint::Chat,
+        &req.model,
+        Some(system_prompt),
+        message,
+        req.stream,
+        req.options.as_ref(),
+    )
+    .await
+}
+
+pub async fn ollama_tags(state: web::Data<AppState>) -> Result<HttpResponse> {
+    let ai = &state.config.ai;
+    let quantization_level = match (ai.quantized, ai.quantization_bits) {
+        (true, Some(bits)) => format!("Q{}", bits),
+        _ => "F16".to_string(),
+    };
+
+    let model_name = state.ai_service.model_name();
+    let tag = OllamaModelTag {
+        name: model_name.clone(),
+        model: model_name.clone(),
+        modified_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        size: 0,
+        digest: format!("{:x}", md5::compute(model_name.as_bytes())),
+        details: OllamaModelDetails {
+            format: if ai.model_format == "gguf" {
+                "gguf"
+            } else {
+                "safetensors"
+            }
+            .to_string(),
+            family: "llama".to_string(),
+            parameter_size: parameter_size(&model_name),
+            quantization_level,
+        },
+    };
+
+    Ok(HttpResponse::Ok().json(OllamaTagsResponse { models: vec![tag] }))
+}
+
+pub async fn ollama_version() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "version": OLLAMA_COMPAT_VERSION })))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_ollama(
+    state: web::Data<AppState>,
+    mut ctx: RequestContext,
+    access: Access,
+    endpoint: OllamaEndpoint,
+    model: &str,
+    system_prompt: Option<String>,
+    message: String,
+    stream: Option<bool>,
+    options: Option<&OllamaOptions>,
+) -> Result<HttpResponse> {
+    let started = Instant::now();
+    let requested_model = model.trim_end_matches(":latest");
+    let local_model = state.ai_service.model_name();
+    let model_name = if requested_model.is_empty() {
+        local_model.clone()
+    } else {
+        requested_model.to_string()
+    };
+
+    let mut chat_req = ChatRequest {
+        message,
+        // The local model is implied; only foreign names are forwarded to the cloud path.
+        model: (model_name != local_model).then(|| model_name.clone()),
+        system_prompt: system_prompt.filter(|s| !s.trim().is_empty()),
+        temperature: options.and_then(|o| o.temperature),
+        max_tokens: options
+            .and_then(|o| o.num_predict)
+            .filter(|n| *n > 0)
+            .map(|n| n as usize),
+        stop: options.and_then(|o| o.stop.clone()),
+        top_k: options.and_then(|o| o.top_k),
+        repetition_penalty: options.and_then(|o| o.repeat_penalty),
+        frequency_penalty: options.and_then(|o| o.frequency_penalty),
+        presence_penalty: options.and_then(|o| o.presence_penalty),
+        stream,
+        ..Default::default()
+    };
+    access.restrict(&state, &mut ctx, &mut chat_req);
+
+    if let Err(e) = chat_req.validate() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Invalid request",
+            format!("Validation error: {}", e),
+        )));
+    }
+
+    match state.ai_service.generate(&ctx, &chat_req).await {
+        Ok(mut chat_response) => {
+            if let Err(violation) = state.guardrails.screen_answer(&mut chat_response) {
+                return Ok(policy_violation(&violation));
+            }
+            let total_duration = started.elapsed().as_nanos() as u64;
+            if stream.unwrap_or(true) {
+                let summary = StreamSummary {
+                    model: model_name,
+                    total_duration: Some(total_duration),
+                    ..Default::default()
+                };
+                Ok(stream_response(
+                    Box::new(OllamaChunkEncoder { endpoint }),
+                    chat_response.response,
+                    summary,
+                ))
+            } else {
+                Ok(HttpResponse::Ok().json(ollama_chunk(
+                    endpoint,
+                    &model_name,
+                    chat_response.response,
+                    true,
+                    Some(total_duration),
+                )))
+            }
+        }
+        Err(e) => {
+            if let Some(rejected) = rejected_by_admission(&e) {
+                return Ok(rejected);
+            }
+            if let Some(cancelled) = cancelled_by_operator(&e) {
+                return Ok(cancelled);
+            }
+            if let Some(timed_out) = past_deadline(&e) {
+                return Ok(timed_out);
+            }
+            if let Some(too_long) = prompt_too_long(&e) {
+                return Ok(too_long);
+            }
+            tracing::error!("Ollama {:?} error: {:?}", endpoint, e);
+            Ok(
+                HttpResponse::InternalServerError().json(ErrorResponse::with_details(
+                    "Failed to process request",
+                    e.to_string(),
+                )),
+            )
+        }
+    }
+}
+
+/// Folds the non-system messages into a single prompt; system messages are
+/// passed separately as the system prompt.
+fn flatten_messages(messages: &[OllamaMessage]) -> String {
+    let turns: Vec<&OllamaMessage> = messages
+        .iter()
+        .filter(|message| message.role != "system")
+        .collect();
+    if let [only] = turns.as_slice() {
+        return only.content.clone();
+    }
+
+    turns
+        .iter()
+        .map(|message| format!("{}: {}", message.role, message.content))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+pub(crate) fn ollama_chunk(
+    endpoint: OllamaEndpoint,
+    model: &str,
+    content: String,
+    done: bool,
+    total_duration: Option<u64>,
+) -> serde_json::Value {
+    let created_at = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true);
+    let done_reason = done.then(|| "stop".to_string());
+    let value = match endpoint {
+        OllamaEndpoint::Generate => serde_json::to_value(OllamaGenerateResponse {
+            model: model.to_string(),
+            created_at,
+            response: content,
+            done,
+            done_reason,
+            total_duration,
+        }),
+        OllamaEndpoint::Chat => serde_json::to_value(OllamaChatResponse {
+            model: model.to_string(),
+            created_at,
+            message: OllamaMessage {
+                role: "assistant".to_string(),
+                content,
+            },
+            done,
+            done_reason,
+            total_duration,
+        }),
+    };
+    value.unwrap_or_default()
+}
+
+/// Best-effort parameter size from names like `TinyLlama-1.1B-Chat`.
+fn parameter_size(model_name: &str) -> String {
+    model_name
+        .split(|c: char| c == '-' || c == '/' || c == '_')
+        .find(|part| {
+            part.len() > 1
+                && part.ends_with(['B', 'b'])
+                && part[..part.len() - 1].parse::<f32>().is_ok()
+        })
+        .map(|part| part.to_uppercase())
+        .unwrap_or_else(|| "unknown".to_string())
+}
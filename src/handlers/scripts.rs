@@ -1,16 +1,32 @@
-use actix_web::{web, HttpResponse, Result};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
 use validator::Validate;
 use chrono::Utc;
+use std::time::Instant;
 
+use crate::handlers::{
+    cancelled_by_operator, check_access, idempotency_key, overloaded, policy_violation,
+    prompt_too_long,
+};
+use crate::middleware::Screened;
 use crate::models::{
-    ScriptGenerationRequest, ScriptResponse, ErrorResponse, Environment, ScriptLanguage
+    Environment, ErrorResponse, ModelUnavailable, RequestContext, Route, ScriptGenerationRequest,
+    ScriptLanguage, ScriptResponse,
+};
+use crate::services::IdempotencyClaim;
+use crate::utils::{
+    cache_key, normalize_message, script_locale, DEFAULT_SCRIPT_LOCALE, SCRIPT_LOCALES,
 };
 use crate::AppState;
 
 pub async fn generate_script(
     state: web::Data<AppState>,
-    req: web::Json<ScriptGenerationRequest>,
+    http_req: HttpRequest,
+    ctx: RequestContext,
+    req: Screened<ScriptGenerationRequest>,
 ) -> Result<HttpResponse> {
+    if let Err(denied) = check_access(&state, &http_req, false) {
+        return Ok(denied);
+    }
     // Validate request
     if let Err(e) = req.validate() {
         return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
@@ -32,12 +48,98 @@ pub async fn generate_script(
         ScriptLanguage::Powershell => "powershell",
     };
 
-    // Get mutable reference to AI model
-    let mut ai_model = state.ai_model.write().await;
+    // An explicit locale must be supported; `Accept-Language` only applies when it is.
+    let locale_tag = req.locale.as_deref().unwrap_or_else(|| {
+        ctx.locale
+            .as_deref()
+            .filter(|tag| script_locale(tag).is_some())
+            .unwrap_or(DEFAULT_SCRIPT_LOCALE)
+    });
+    let Some(locale) = script_locale(locale_tag) else {
+        let supported: Vec<&str> = SCRIPT_LOCALES.iter().map(|locale| locale.code).collect();
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse::with_details(
+            "Unsupported locale",
+            format!("Supported locales: {}", supported.join(", ")),
+        )));
+    };
+
+    let guard = match idempotency_key(&http_req) {
+        Ok(Some(key)) => {
+            let key = format!("script:{}", key);
+            match state.cache_service.claim_idempotency(&ctx, &key).await {
+                IdempotencyClaim::Replay(value) => return Ok(HttpResponse::Ok().json(value)),
+                IdempotencyClaim::Acquired(guard) => Some(guard),
+            }
+        }
+        Ok(None) => None,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(ErrorResponse::new(e))),
+    };
+
+    let policy = &state.config.cache.scripts;
+    let model_name = state.ai_service.model_name();
+    let requirement = if policy.match_similar {
+        normalize_message(&req.requirement, &state.config.cache.key_normalization)
+    } else {
+        req.requirement.clone()
+    };
+    let entry_key = cache_key(&[
+        "script",
+        &model_name,
+        &state.config.ai.prompt_version,
+        environment_str,
+        language_str,
+        locale.code,
+        &requirement,
+    ]);
+    if policy.enabled {
+        if let Some((value, _)) = state.cache_service.get(&entry_key).await {
+            if let Ok(mut cached) = serde_json::from_value::<ScriptResponse>(value) {
+                // Re-screened so entries stored before a guardrail change are held to it.
+                if let Err(violation) = state.guardrails.screen_answer(&mut cached) {
+                    return Ok(policy_violation(&violation));
+                }
+                cached.cache_hit = true;
+                cached.timestamp = Utc::now();
+                if let (Some(guard), Ok(value)) = (guard, serde_json::to_value(&cached)) {
+                    guard.complete(&value).await;
+                }
+                return Ok(HttpResponse::Ok().json(cached));
+            }
+        }
+    }
+
+    let queued_at = Instant::now();
+    let _permit = match state.ai_service.admit().await {
+        Ok(permit) => permit,
+        Err(rejection) => return Ok(overloaded(&rejection)),
+    };
+    let queue_wait = queued_at.elapsed();
+
+    let generated = state
+        .ai_service
+        .generate_script(
+            &ctx,
+            queue_wait,
+            req.requirement.clone(),
+            environment_str,
+            language_str,
+            locale,
+        )
+        .await;
+    if let Some(too_long) = generated.as_ref().err().and_then(prompt_too_long) {
+        return Ok(too_long);
+    }
+    if let Some(cancelled) = generated.as_ref().err().and_then(cancelled_by_operator) {
+        return Ok(cancelled);
+    }
 
     // Process the script generation request
-    match ai_model.generate_script(&req.requirement, environment_str, language_str).await {
-        Ok(script_content) => {
+    match generated {
+        Ok(mut script_content) => {
+            if let Err(violation) = state.guardrails.screen_answer(&mut script_content) {
+                return Ok(policy_violation(&violation));
+            }
+
             // Parse the response to extract script, explanation, and warnings
             let parts: Vec<&str> = script_content.split("\n\n").collect();
 
@@ -64,11 +166,40 @@ pub async fn generate_script(
                 environment: environment_str.to_string(),
                 explanation,
                 safety_warnings,
+                locale: locale.code.to_string(),
                 timestamp: Utc::now(),
+                cache_hit: false,
             };
 
+            // Scripts come from the local model, so `CACHE_WRITE_POLICY=cloud` stores none.
+            if policy.enabled && state.cache_service.should_store(Some(Route::Local)) {
+                if let Ok(value) = serde_json::to_value(&response) {
+                    let tags = [
+                        "endpoint:scripts".to_string(),
+                        format!("model:{}", model_name),
+                        format!("prompt:{}", state.config.ai.prompt_version),
+                    ];
+                    let _ = state
+                        .cache_service
+                        .set(&entry_key, &value, &tags, Some(policy.ttl_seconds))
+                        .await;
+                }
+            }
+
+            if let (Some(guard), Ok(value)) = (guard, serde_json::to_value(&response)) {
+                guard.complete(&value).await;
+            }
             Ok(HttpResponse::Ok().json(response))
         }
+        Err(e) if e.is::<ModelUnavailable>() => {
+            tracing::error!("Failed to load the AI model: {:?}", e);
+            Ok(
+                HttpResponse::ServiceUnavailable().json(ErrorResponse::with_details(
+                    "AI model is unavailable",
+                    e.to_string(),
+                )),
+            )
+        }
         Err(e) => {
             tracing::error!("Script generation error: {:?}", e);
             Ok(HttpResponse::InternalServerError().json(ErrorResponse::with_details(
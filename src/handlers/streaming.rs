@@ -0,0 +1,267 @@
+use actix_web::{HttpRequest, HttpResponse};
+use bytes::Bytes;
+use chrono::{SecondsFormat, Utc};
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration};
+use tokio_stream::wrappers::ReceiverStream;
+use uuid::Uuid;
+
+use crate::handlers::{ollama_chunk, OllamaEndpoint};
+use crate::models::StreamFormat;
+
+/// Pause between streamed tokens.
+const TOKEN_INTERVAL: Duration = Duration::from_millis(60);
+
+/// What is known about an answer once its last token has been sent.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StreamSummary {
+    pub model: String,
+    pub conversation_id: Option<Uuid>,
+    pub cache_hit: bool,
+    pub cache_source: Option<String>,
+    /// Served past its TTL while a fresh answer is generated.
+    pub cache_stale: bool,
+    pub response_hash: Option<String>,
+    /// Generation stopped early; the streamed answer is incomplete.
+    pub partial: bool,
+    /// Nanoseconds, as reported by Ollama.
+    pub total_duration: Option<u64>,
+}
+
+/// One wire format for streamed answers. Adding a format means implementing
+/// this trait; chunking, pacing and the HTTP response are shared.
+pub(crate) trait StreamEncoder: Send + 'static {
+    fn content_type(&self) -> &'static str;
+
+    fn token(&mut self, summary: &StreamSummary, token: &str) -> Bytes;
+
+    /// Frames sent after the last token.
+    fn finish(&mut self, summary: &StreamSummary) -> Vec<Bytes>;
+}
+
+fn ndjson_frame(payload: &Value) -> Bytes {
+    Bytes::from(format!("{}\n", payload))
+}
+
+fn sse_frame(payload: &Value) -> Bytes {
+    Bytes::from(format!("data: {}\n\n", payload))
+}
+
+const SSE_DONE: Bytes = Bytes::from_static(b"data: [DONE]\n\n");
+
+fn created_at() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+fn native_token(summary: &StreamSummary, token: &str) -> Value {
+    json!({
+        "model": summary.model,
+        "created_at": created_at(),
+        "response": token,
+        "done": false
+    })
+}
+
+fn native_done(summary: &StreamSummary) -> Value {
+    json!({
+        "model": summary.model,
+        "created_at": created_at(),
+        "response": "",
+        "done": true,
+        "cache_hit": summary.cache_hit,
+        "cache_source": summary.cache_source,
+        "cache_stale": summary.cache_stale,
+        "response_hash": summary.response_hash,
+        "conversation_id": summary.conversation_id,
+        "partial": summary.partial,
+    })
+}
+
+/// Native chunks, one JSON object per line.
+pub(crate) struct NdjsonEncoder;
+
+impl StreamEncoder for NdjsonEncoder {
+    fn content_type(&self) -> &'static str {
+        "application/x-ndjson"
+    }
+
+    fn token(&mut self, summary: &StreamSummary, token: &str) -> Bytes {
+        ndjson_frame(&native_token(summary, token))
+    }
+
+    fn finish(&mut self, summary: &StreamSummary) -> Vec<Bytes> {
+        vec![ndjson_frame(&native_done(summary))]
+    }
+}
+
+/// Native chunks as server-sent events, closed by `[DONE]`.
+pub(crate) struct SseEncoder;
+
+impl StreamEncoder for SseEncoder {
+    fn content_type(&self) -> &'static str {
+        "text/event-stream"
+    }
+
+    fn token(&mut self, summary: &StreamSummary, token: &str) -> Bytes {
+        sse_frame(&native_token(summary, token))
+    }
+
+    fn finish(&mut self, summary: &StreamSummary) -> Vec<Bytes> {
+        vec![sse_frame(&native_done(summary)), SSE_DONE]
+    }
+}
+
+/// `chat.completion.chunk` server-sent events, as OpenAI-compatible clients expect.
+pub(crate) struct OpenAiChunkEncoder {
+    id: String,
+    created: i64,
+    sent_role: bool,
+}
+
+impl OpenAiChunkEncoder {
+    pub(crate) fn new() -> Self {
+        Self {
+            id: format!("chatcmpl-{}", Uuid::new_v4().simple()),
+            created: Utc::now().timestamp(),
+            sent_role: false,
+        }
+    }
+
+    fn chunk(&self, summary: &StreamSummary, delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": summary.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        })
+    }
+}
+
+impl StreamEncoder for OpenAiChunkEncoder {
+    fn content_type(&self) -> &'static str {
+        "text/event-stream"
+    }
+
+    fn token(&mut self, summary: &StreamSummary, token: &str) -> Bytes {
+        // The role is announced once, on the first delta.
+        let delta = if self.sent_role {
+            json!({"content": token})
+        } else {
+            self.sent_role = true;
+            json!({"role": "assistant", "content": token})
+        };
+        sse_frame(&self.chunk(summary, delta, None))
+    }
+
+    fn finish(&mut self, summary: &StreamSummary) -> Vec<Bytes> {
+        let finish_reason = if summary.partial { "length" } else { "stop" };
+        vec![
+            sse_frame(&self.chunk(summary, json!({}), Some(finish_reason))),
+            SSE_DONE,
+        ]
+    }
+}
+
+/// Ollama `/api/generate` or `/api/chat` chunks, one JSON object per line.
+pub(crate) struct OllamaChunkEncoder {
+    pub endpoint: OllamaEndpoint,
+}
+
+impl StreamEncoder for OllamaChunkEncoder {
+    fn content_type(&self) -> &'static str {
+        "application/x-ndjson"
+    }
+
+    fn token(&mut self, summary: &StreamSummary, token: &str) -> Bytes {
+        ndjson_frame(&ollama_chunk(
+            self.endpoint,
+            &summary.model,
+            token.to_string(),
+            false,
+            None,
+        ))
+    }
+
+    fn finish(&mut self, summary: &StreamSummary) -> Vec<Bytes> {
+        vec![ndjson_frame(&ollama_chunk(
+            self.endpoint,
+            &summary.model,
+            String::new(),
+            true,
+            summary.total_duration,
+        ))]
+    }
+}
+
+/// The encoder for a native chat stream: the requested `stream_format`, else
+/// SSE when the `Accept` header asks for it, else NDJSON.
+pub(crate) fn negotiate_encoder(
+    http_req: &HttpRequest,
+    requested: Option<StreamFormat>,
+) -> Box<dyn StreamEncoder> {
+    let format = requested.unwrap_or_else(|| {
+        let accept = http_req
+            .headers()
+            .get(actix_web::http::header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        if accept.contains("text/event-stream") {
+            StreamFormat::Sse
+        } else {
+            StreamFormat::Ndjson
+        }
+    });
+    match format {
+        StreamFormat::Ndjson => Box::new(NdjsonEncoder),
+        StreamFormat::Sse => Box::new(SseEncoder),
+        StreamFormat::Openai => Box::new(OpenAiChunkEncoder::new()),
+    }
+}
+
+/// Splits a response into whitespace-delimited stream chunks, keeping the
+/// separating space on every chunk after the first.
+pub(crate) fn word_chunks(response: &str) -> Vec<String> {
+    response
+        .split_whitespace()
+        .enumerate()
+        .map(|(index, word)| {
+            if index == 0 {
+                word.to_string()
+            } else {
+                format!(" {}", word)
+            }
+        })
+        .collect()
+}
+
+/// Streams a finished answer token by token in the encoder's wire format.
+pub(crate) fn stream_response(
+    mut encoder: Box<dyn StreamEncoder>,
+    response: String,
+    summary: StreamSummary,
+) -> HttpResponse {
+    let content_type = encoder.content_type();
+    let (tx, rx) = mpsc::channel::<Bytes>(32);
+    tokio::spawn(async move {
+        for token in word_chunks(&response) {
+            if tx.send(encoder.token(&summary, &token)).await.is_err() {
+                return;
+            }
+            sleep(TOKEN_INTERVAL).await;
+        }
+        for frame in encoder.finish(&summary) {
+            if tx.send(frame).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    let stream = ReceiverStream::new(rx).map(Ok::<Bytes, std::io::Error>);
+    HttpResponse::Ok()
+        .insert_header((actix_web::http::header::CONTENT_TYPE, content_type))
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "no-cache"))
+        .streaming(stream)
+}
@@ -0,0 +1,11 @@
+use actix_web::{HttpResponse, Result};
+
+/// Self-contained playground page; no external assets so it works offline.
+const INDEX_HTML: &[u8] = include_bytes!("../../static/ui/index.html");
+
+pub async fn ui_index() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "no-cache"))
+        .body(INDEX_HTML))
+}
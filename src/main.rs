@@ -10,7 +10,7 @@ mod utils;
 use actix_cors::Cors;
 use actix_web::{middleware::Logger, web, App, HttpServer};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tracing::{error, info};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -18,14 +18,38 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use config::{CacheSettings, Config};
 use handlers::health::not_found;
 use models::AIModel;
-use routes::api;
-use services::{AIService, CacheService};
+use routes::{api, ui};
+use services::{
+    AIService, AgentService, AlertService, CacheNamespaceService, CacheService,
+    ConversationService, EmbeddingService, FeedbackService, GuardrailService, HandoffService,
+    HttpClients, KnowledgeService, LogStoreService, LoopGuardService, ProviderCaptureService,
+    RoutingMetricsService, SandboxService, SpendService, StreamService, TaskRegistry, TopicService,
+    TrialLimiter,
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub ai_model: Arc<RwLock<AIModel>>,
     pub ai_service: AIService,
     pub cache_service: CacheService,
+    pub cache_namespaces: CacheNamespaceService,
+    pub stream_service: StreamService,
+    pub conversation_service: ConversationService,
+    pub sandbox_service: SandboxService,
+    pub handoff_service: HandoffService,
+    pub routing_metrics: RoutingMetricsService,
+    pub feedback_service: FeedbackService,
+    pub provider_capture: ProviderCaptureService,
+    pub spend_service: SpendService,
+    pub agent_service: AgentService,
+    pub guardrails: GuardrailService,
+    pub loop_guard: LoopGuardService,
+    pub log_store: LogStoreService,
+    pub topics: TopicService,
+    pub knowledge: KnowledgeService,
+    pub embeddings: EmbeddingService,
+    pub trial_limiter: TrialLimiter,
+    pub tasks: TaskRegistry,
     pub config: Config,
     pub start_time: Instant,
 }
@@ -57,6 +81,14 @@ async fn main() -> std::io::Result<()> {
         config.server.port
     );
 
+    let http_clients = match HttpClients::new(&config.outbound) {
+        Ok(clients) => clients,
+        Err(e) => {
+            error!("Failed to configure outbound HTTP clients: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
     // Initialize AI model
     let ai_model = Arc::new(RwLock::new(AIModel::new(config.ai.clone())));
     let cache_service = match CacheService::new(config.cache.clone()).await {
@@ -71,26 +103,142 @@ async fn main() -> std::io::Result<()> {
             CacheService::new(fallback).await.expect("cache service")
         }
     };
-    let ai_service = AIService::new(ai_model.clone(), config.ai.clone(), config.openrouter.clone());
+    let conversation_service = ConversationService::new(&config.conversations);
+    let provider_capture = ProviderCaptureService::new(&config.provider_capture);
+    let spend_service = match SpendService::new(&config.openrouter) {
+        Ok(service) => service,
+        Err(e) => {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+    };
+    let embeddings = EmbeddingService::new(&config.ai);
+    let knowledge = KnowledgeService::new(
+        &config.knowledge_base,
+        http_clients.clone(),
+        embeddings.clone(),
+    );
+    let ai_service = AIService::new(
+        ai_model.clone(),
+        config.ai.clone(),
+        config.openrouter.clone(),
+        conversation_service.clone(),
+        provider_capture.clone(),
+        spend_service.clone(),
+        http_clients.clone(),
+        knowledge.clone(),
+        embeddings.clone(),
+    );
+    let routing_metrics = RoutingMetricsService::new(&config.conversations);
+    let handoff_service = HandoffService::new(
+        config.handoff.clone(),
+        conversation_service.clone(),
+        routing_metrics.clone(),
+        http_clients.clone(),
+    );
+
+    // Answers must not repeat the service's own instructions.
+    let protected_prompts: Vec<String> = std::iter::once(config.ai.system_prompt.clone())
+        .chain(ai_service.templates().prompts().map(str::to_string))
+        .collect();
 
     let state = AppState {
         ai_model: ai_model.clone(),
         ai_service,
         cache_service,
+        cache_namespaces: CacheNamespaceService::new(&config.cache),
+        stream_service: StreamService::default(),
+        conversation_service,
+        sandbox_service: SandboxService::new(config.sandbox.clone()),
+        handoff_service,
+        routing_metrics,
+        feedback_service: FeedbackService::new(&config.conversations),
+        provider_capture,
+        spend_service,
+        agent_service: AgentService::new(&config.agents),
+        guardrails: GuardrailService::new(&config.guardrails, &protected_prompts),
+        loop_guard: LoopGuardService::new(config.loop_guard.clone()),
+        log_store: LogStoreService::new(&config.log_store),
+        topics: TopicService::new(&config.conversations),
+        knowledge,
+        embeddings,
+        trial_limiter: TrialLimiter::new(config.trial.clone()),
+        tasks: TaskRegistry::default(),
         config: config.clone(),
         start_time: Instant::now(),
     };
 
-    // Start model loading in background
-    let model_loader = state.ai_model.clone();
-    tokio::spawn(async move {
-        info!("Starting background model loading...");
-        if let Err(e) = model_loader.write().await.load_model().await {
-            error!("Failed to load AI model: {}", e);
+    AlertService::new(
+        config.alerts.clone(),
+        state.cache_service.clone(),
+        state.ai_model.clone(),
+        http_clients,
+    )
+    .spawn(&state.tasks);
+    state.cache_service.spawn_writer(&state.tasks);
+    state.cache_service.spawn_cleanup(&state.tasks);
+    state.cache_service.spawn_stats_recorder(&state.tasks);
+    state
+        .cache_service
+        .spawn_invalidation_listener(&state.tasks);
+    state.cache_namespaces.spawn(&state.tasks);
+    state.topics.spawn(&state.tasks);
+    state.conversation_service.spawn(&state.tasks);
+    state.knowledge.spawn(&state.tasks);
+
+    // Start model loading in background; a model server loads its own.
+    if let Some(backend) = state.ai_service.local_backend() {
+        info!(
+            "Local generations are served by LOCAL_BACKEND={}",
+            backend.kind()
+        );
+    } else {
+        let model_loader = state.ai_model.clone();
+        state.tasks.spawn("model_loader", move |handle| {
+            let model_loader = model_loader.clone();
+            async move {
+                info!("Starting background model loading...");
+                model_loader
+                    .write()
+                    .await
+                    .load_model()
+                    .await
+                    .map_err(|e| e.context("Failed to load AI model"))?;
+                handle.ran();
+                Ok(())
+            }
+        });
+
+        if config.ai.model_idle_unload_secs > 0 {
+            state.ai_service.models().spawn_idle_unloader(
+                &state.tasks,
+                Duration::from_secs(config.ai.model_idle_unload_secs),
+            );
         }
-    });
+    }
+
+    if state.embeddings.is_enabled() {
+        let embeddings = state.embeddings.clone();
+        state.tasks.spawn("embedding_loader", move |handle| {
+            let embeddings = embeddings.clone();
+            async move {
+                embeddings
+                    .load()
+                    .await
+                    .map_err(|e| e.context("Failed to load embedding model"))?;
+                handle.ran();
+                Ok(())
+            }
+        });
+    }
+
+    let ui_enabled = config.server.ui_enabled;
+    if ui_enabled {
+        info!("Playground UI enabled at /ui");
+    }
 
     // Create HTTP server
+    let security = config.security.clone();
     let server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
@@ -100,9 +248,15 @@ async fn main() -> std::io::Result<()> {
 
         App::new()
             .app_data(web::Data::new(state.clone()))
+            .wrap(middleware::RequestContextMiddleware)
             .wrap(cors)
             .wrap(Logger::default())
-            .service(api::config())
+            .service(api::config(&security))
+            .configure(|cfg| {
+                if ui_enabled {
+                    cfg.service(ui::config());
+                }
+            })
             .default_service(web::route().to(not_found))
     })
     .bind(format!("{}:{}", config.server.host, config.server.port))?;
@@ -113,5 +267,9 @@ async fn main() -> std::io::Result<()> {
     );
 
     // Run the server
-    server.workers(config.server.workers).run().await
+    server
+        .workers(config.server.workers)
+        .worker_max_blocking_threads(config.server.blocking_threads)
+        .run()
+        .await
 }
@@ -0,0 +1,60 @@
+use actix_web::{dev::Payload, error, web, Error, FromRequest, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use std::ops::{Deref, DerefMut};
+
+use crate::handlers::policy_violation;
+use crate::services::GuardedText;
+use crate::AppState;
+
+/// JSON body of a generation route, screened by the input guardrails before
+/// the handler runs. Generation routes take their body through this
+/// extractor, so none can skip the check. A blocked body is answered with the
+/// 422 from [`policy_violation`].
+pub struct Screened<T>(pub T);
+
+impl<T> Screened<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Screened<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Screened<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> FromRequest for Screened<T>
+where
+    T: DeserializeOwned + GuardedText + 'static,
+{
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let body = web::Json::<T>::from_request(req, payload);
+        let state = req.app_data::<web::Data<AppState>>().cloned();
+        Box::pin(async move {
+            let Some(state) = state else {
+                return Err(error::ErrorInternalServerError(
+                    "Guardrails are not configured",
+                ));
+            };
+            let mut body = body.await?.into_inner();
+            if let Err(violation) = state.guardrails.screen_request(&mut body) {
+                let response = policy_violation(&violation);
+                return Err(error::InternalError::from_response(violation, response).into());
+            }
+            Ok(Screened(body))
+        })
+    }
+}
@@ -1,3 +1,7 @@
 pub mod cors;
+pub mod guardrails;
+pub mod request_context;
 
 pub use cors::*;
+pub use guardrails::*;
+pub use request_context::*;
@@ -0,0 +1,154 @@
+use actix_web::{
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue, ACCEPT_LANGUAGE, AUTHORIZATION},
+    Error, FromRequest, HttpMessage, HttpRequest,
+};
+use futures_util::future::{ok, ready, LocalBoxFuture, Ready};
+use ring::digest::{digest, SHA256};
+use std::rc::Rc;
+use tokio::time::{Duration, Instant};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::models::{RequestContext, RequestFlags};
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Builds the [`RequestContext`] for every request, stores it in the request
+/// extensions for handlers to extract, runs the handler inside its span and
+/// echoes the request id in `X-Request-Id`.
+pub struct RequestContextMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestContextMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestContextMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestContextMiddlewareService {
+            service: Rc::new(service),
+        })
+    }
+}
+
+pub struct RequestContextMiddlewareService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestContextMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let context = build_context(req.request());
+        let span = context.span.clone();
+        let request_id = context.request_id.clone();
+        req.extensions_mut().insert(context);
+        let service = self.service.clone();
+
+        Box::pin(
+            async move {
+                let mut res = service.call(req).await?;
+                if let Some(value) = request_id.and_then(|id| HeaderValue::from_str(&id).ok()) {
+                    res.headers_mut()
+                        .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                }
+                Ok(res)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+/// Handlers take the context built by [`RequestContextMiddleware`]; without
+/// the middleware it is built from the request on the spot.
+impl FromRequest for RequestContext {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let context = req.extensions().get::<RequestContext>().cloned();
+        ready(Ok(context.unwrap_or_else(|| build_context(req))))
+    }
+}
+
+fn build_context(req: &HttpRequest) -> RequestContext {
+    let headers = req.headers();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+
+    let request_id = header(REQUEST_ID_HEADER)
+        .filter(|id| {
+            id.len() <= 128
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    let api_key = header(AUTHORIZATION.as_str())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| header("x-api-key"))
+        .map(str::trim);
+    let tenant = header("x-tenant-id")
+        .filter(|tenant| tenant.len() <= 128)
+        .map(str::to_string);
+    let locale = header(ACCEPT_LANGUAGE.as_str())
+        .and_then(|v| v.split(',').next())
+        .and_then(|v| v.split(';').next())
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty() && *tag != "*")
+        .map(str::to_string);
+    let deadline = header("x-request-timeout-ms")
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|ms| *ms > 0)
+        .map(|ms| Instant::now() + Duration::from_millis(ms));
+
+    let mut context = RequestContext {
+        request_id: Some(request_id),
+        api_key_id: api_key.map(key_fingerprint),
+        tenant,
+        locale,
+        flags: RequestFlags::default(),
+        deadline,
+        span: tracing::Span::none(),
+    };
+    context.span = tracing::info_span!(
+        "request",
+        request_id = context.request_id.as_deref().unwrap_or(""),
+        tenant = context.tenant.as_deref(),
+        api_key = context.api_key_id.as_deref(),
+    );
+    context
+}
+
+/// First 12 hex digits of the key's SHA-256: enough to tell callers apart in
+/// logs and scoped keys without exposing the key.
+fn key_fingerprint(key: &str) -> String {
+    digest(&SHA256, key.as_bytes())
+        .as_ref()
+        .iter()
+        .take(6)
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -0,0 +1,81 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::models::PageInfo;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentHealth {
+    Healthy,
+    Degraded,
+    Unhealthy,
+}
+
+impl AgentHealth {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AgentHealth::Healthy => "healthy",
+            AgentHealth::Degraded => "degraded",
+            AgentHealth::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+/// Periodic report from an installed selfcare agent.
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct AgentHeartbeat {
+    /// Stable per-installation id chosen by the agent.
+    #[validate(length(min = 1, max = 128))]
+    pub agent_id: String,
+    #[validate(length(min = 1, max = 64))]
+    pub version: String,
+    #[validate(length(min = 1, max = 64))]
+    pub os: String,
+    #[validate(length(max = 128))]
+    pub os_version: Option<String>,
+    #[validate(length(max = 255))]
+    pub hostname: Option<String>,
+    pub health: AgentHealth,
+    /// Detail for a degraded or unhealthy agent.
+    #[validate(length(max = 1000))]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentHeartbeatResponse {
+    pub received_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_version: Option<String>,
+    pub update_available: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStatus {
+    pub agent_id: String,
+    pub version: String,
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    pub health: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub heartbeats: u64,
+    /// Reported within the configured offline window.
+    pub connected: bool,
+    /// Runs a version older than `latest_version`.
+    pub outdated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentListResponse {
+    pub agents: Vec<AgentStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_version: Option<String>,
+    #[serde(flatten)]
+    pub page: PageInfo,
+}
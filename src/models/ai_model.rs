@@ -0,0 +1,1222 @@
+use anyhow::{anyhow, bail, Context, Result};
+use candle_core::quantized::gguf_file;
+use candle_core::{DType, Device, DeviceLocation, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::generation::{LogitsProcessor, Sampling};
+use candle_transformers::models::llama::{
+    Cache, Config as LlamaModelConfig, Llama, LlamaConfig, LlamaEosToks,
+};
+use candle_transformers::models::quantized_llama::ModelWeights as QuantizedLlama;
+use regex_automata::util::primitives::StateID;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use tokenizers::Tokenizer;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::config::{AiConfig, LoraAdapterSettings};
+use crate::models::{
+    condense_request, download_model, merge_lora_adapters, ChatContext, ConversationMessage,
+    DownloadRequest, KvCache, NativeLlama, PromptBudget, PromptFit, Speculation,
+};
+use crate::utils::{
+    estimate_tokens, format_transcript, generate_chat_prompt, generate_log_analysis_prompt,
+    generate_script_prompt, generate_summary_prompt, OutputGrammar, ScriptLocale,
+};
+
+const LOG_ANALYSIS_MAX_TOKENS: usize = 1024;
+const SCRIPT_MAX_TOKENS: usize = 1536;
+pub const SUMMARY_MAX_TOKENS: usize = 256;
+/// How many trailing tokens the repetition penalty looks at.
+const REPEAT_LAST_N: usize = 64;
+/// Most likely tokens kept per step under an output constraint.
+const CONSTRAINED_CANDIDATES: usize = 64;
+
+/// Sampling controls for a single generation.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationParams {
+    pub temperature: f32,
+    pub max_tokens: usize,
+    pub top_k: Option<usize>,
+    pub stop: Vec<String>,
+    pub repetition_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    /// Fixed sampler seed for reproducible output; random when unset.
+    pub seed: Option<u64>,
+    /// Only tokens that keep the output within this grammar are sampled.
+    pub constraint: Option<Arc<OutputGrammar>>,
+    /// Generation stops once this turns true.
+    pub cancel: Option<watch::Receiver<bool>>,
+}
+
+impl GenerationParams {
+    pub fn new(temperature: f32, max_tokens: usize) -> Self {
+        Self {
+            temperature,
+            max_tokens,
+            ..Default::default()
+        }
+    }
+}
+
+/// A generation stopped early (error or timeout) after producing some output.
+#[derive(Debug, Clone)]
+pub struct PartialGeneration {
+    pub text: String,
+    pub reason: String,
+}
+
+impl fmt::Display for PartialGeneration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Generation interrupted after partial output: {}",
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for PartialGeneration {}
+
+/// The local model could not be loaded to serve a generation; holds the
+/// load error.
+#[derive(Debug)]
+pub struct ModelUnavailable(pub String);
+
+impl fmt::Display for ModelUnavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ModelUnavailable {}
+
+/// Prompt and sampling for a log analysis, whichever backend runs it.
+pub fn log_analysis_request(
+    config: &AiConfig,
+    logs: &str,
+    context: Option<String>,
+) -> (String, GenerationParams) {
+    let params = GenerationParams {
+        seed: default_seed(config),
+        ..GenerationParams::new(
+            config.temperature,
+            LOG_ANALYSIS_MAX_TOKENS.min(config.max_tokens),
+        )
+    };
+    (generate_log_analysis_prompt(logs, context), params)
+}
+
+/// Prompt and sampling for a script generation, whichever backend runs it.
+pub fn script_request(
+    config: &AiConfig,
+    requirement: &str,
+    environment: &str,
+    language: &str,
+    locale: &ScriptLocale,
+) -> (String, GenerationParams) {
+    let params = GenerationParams {
+        seed: default_seed(config),
+        ..GenerationParams::new(config.temperature, SCRIPT_MAX_TOKENS.min(config.max_tokens))
+    };
+    (
+        generate_script_prompt(requirement, environment, language, locale),
+        params,
+    )
+}
+
+/// Seed for generations outside a chat request, set when deterministic mode is on.
+fn default_seed(config: &AiConfig) -> Option<u64> {
+    config.deterministic.then_some(config.deterministic_seed)
+}
+
+enum Weights {
+    Safetensors {
+        model: Llama,
+        config: LlamaModelConfig,
+        dtype: DType,
+    },
+    /// Quantized weights keep their KV cache inside the model. Clones share
+    /// the weights, so each generation runs on its own clone.
+    Gguf(QuantizedLlama),
+    /// Safetensors weights with a draft model for speculative decoding.
+    Speculative {
+        target: NativeLlama,
+        draft: NativeLlama,
+    },
+}
+
+impl Weights {
+    fn start(&self, device: &Device) -> Result<Forward<'_>> {
+        Ok(match self {
+            Self::Safetensors {
+                model,
+                config,
+                dtype,
+            } => Forward::Safetensors(model, Cache::new(true, *dtype, config, device)?),
+            Self::Gguf(model) => Forward::Gguf(model.clone()),
+            Self::Speculative { target, .. } => Forward::Native(target, target.new_cache()),
+        })
+    }
+}
+
+/// The weights and KV cache of one generation.
+enum Forward<'a> {
+    Safetensors(&'a Llama, Cache),
+    Gguf(QuantizedLlama),
+    Native(&'a NativeLlama, KvCache),
+}
+
+impl Forward<'_> {
+    /// Logits for the last position of `input`, which starts at `index_pos`.
+    fn forward(&mut self, input: &Tensor, index_pos: usize) -> Result<Tensor> {
+        Ok(match self {
+            Self::Safetensors(model, cache) => model.forward(input, index_pos, cache)?,
+            // Position 0 starts a new sequence and clears the cached keys and values.
+            Self::Gguf(model) => model.forward(input, index_pos)?,
+            // The cache knows the position.
+            Self::Native(model, cache) => {
+                let input = input.squeeze(0)?.to_vec1::<u32>()?;
+                model.forward(&input, cache, 1)?
+            }
+        })
+    }
+}
+
+/// Weight files found for a model.
+pub(crate) enum WeightFiles {
+    Safetensors(Vec<PathBuf>),
+    Gguf(PathBuf),
+}
+
+impl WeightFiles {
+    pub(crate) fn paths(&self) -> &[PathBuf] {
+        match self {
+            Self::Safetensors(files) => files,
+            Self::Gguf(file) => std::slice::from_ref(file),
+        }
+    }
+}
+
+struct LoadedModel {
+    weights: Weights,
+    tokenizer: Tokenizer,
+    eos_tokens: Vec<u32>,
+    /// Text of each token, built on the first constrained generation.
+    pieces: OnceLock<Vec<String>>,
+}
+
+/// Weights are only read while generating: each call builds its own KV cache
+/// and sampler, so generations can run in parallel behind a read lock.
+pub struct AIModel {
+    config: AiConfig,
+    device: Device,
+    loaded: Option<LoadedModel>,
+    /// Start of the latest generation, or the load.
+    last_used: Mutex<Instant>,
+    /// Weights were dropped after sitting idle and load again on next use.
+    idle_unloaded: bool,
+}
+
+/// Weights taken out of a model by [`AIModel::unload`]; dropping them frees
+/// the memory, which can take a while for large weights.
+pub struct UnloadedWeights {
+    _weights: Option<LoadedModel>,
+}
+
+impl AIModel {
+    pub fn new(config: AiConfig) -> Self {
+        let device = inference_device(&config.device).clone();
+        Self {
+            config,
+            device,
+            loaded: None,
+            last_used: Mutex::new(Instant::now()),
+            idle_unloaded: false,
+        }
+    }
+
+    /// Whether the weights were unloaded for idleness, so the model should
+    /// be loaded again rather than reported as down.
+    pub fn is_idle_unloaded(&self) -> bool {
+        self.idle_unloaded
+    }
+
+    /// Time since the latest generation started, or since the load.
+    pub fn idle_for(&self) -> Duration {
+        self.last_used
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .elapsed()
+    }
+
+    /// Takes the weights out of memory; the next [`AIModel::load_model`]
+    /// reads them again.
+    pub fn unload(&mut self) -> UnloadedWeights {
+        let weights = self.loaded.take();
+        if weights.is_some() {
+            self.idle_unloaded = true;
+            info!(
+                "Unloaded AI model {} after {:?} idle",
+                self.config.model_name,
+                self.idle_for()
+            );
+        }
+        UnloadedWeights { _weights: weights }
+    }
+
+    /// Device generations run on, as `cpu`, `cuda:N` or `metal:N`.
+    pub fn device_name(&self) -> String {
+        device_name(&self.device)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.loaded.is_some()
+    }
+
+    pub fn model_name(&self) -> &str {
+        &self.config.model_name
+    }
+
+    /// Precision the weights are loaded in: `gguf` for quantized weights,
+    /// otherwise `f32` on the CPU and `bf16` on a GPU.
+    pub fn precision(&self) -> &'static str {
+        let gguf = match &self.loaded {
+            Some(loaded) => matches!(loaded.weights, Weights::Gguf(_)),
+            None => self.config.model_format == "gguf",
+        };
+        if gguf {
+            "gguf"
+        } else if self.device.is_cpu() {
+            "f32"
+        } else {
+            "bf16"
+        }
+    }
+
+    pub async fn load_model(&mut self) -> Result<()> {
+        if self.loaded.is_some() {
+            return Ok(());
+        }
+
+        let model_dir = match self.resolve_model_dir() {
+            Ok(dir) => dir,
+            Err(e)
+                if self.config.model_auto_download
+                    && self.model_path().is_none()
+                    && self.config.model_format == "gguf" =>
+            {
+                return Err(e.context("GGUF weights are not downloaded; place them in MODEL_PATH"));
+            }
+            Err(e) if self.config.model_auto_download && self.model_path().is_none() => {
+                info!("{}; downloading from the Hugging Face Hub", e);
+                self.download_model().await?
+            }
+            Err(e) => return Err(e),
+        };
+        info!(
+            "Loading AI model {} from {}",
+            self.config.model_name,
+            model_dir.display()
+        );
+
+        let draft_dir = self.draft_model_dir().await;
+        let loaded = match locate_weights(&model_dir, &self.config.model_format)? {
+            WeightFiles::Safetensors(files) => {
+                self.load_safetensors(&model_dir, &files, draft_dir.as_deref())?
+            }
+            WeightFiles::Gguf(file) => {
+                if draft_dir.is_some() {
+                    warn!("Speculative decoding needs safetensors weights; draft model ignored");
+                }
+                self.load_gguf(&file)?
+            }
+        };
+        self.loaded = Some(loaded);
+        self.idle_unloaded = false;
+        *self
+            .last_used
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+        info!("AI model loaded successfully");
+        Ok(())
+    }
+
+    fn load_safetensors(
+        &self,
+        model_dir: &Path,
+        weights: &[PathBuf],
+        draft_dir: Option<&Path>,
+    ) -> Result<LoadedModel> {
+        if self.config.quantized {
+            warn!("Quantized loading is not available for safetensors weights; loading full precision");
+        }
+
+        let tokenizer = load_tokenizer(model_dir)?;
+
+        let config_bytes = fs::read(model_dir.join("config.json"))
+            .with_context(|| format!("Failed to read model config in {}", model_dir.display()))?;
+        let llama_config: LlamaConfig = serde_json::from_slice(&config_bytes)?;
+        let config = llama_config.into_config(false);
+
+        let dtype = if self.device.is_cpu() {
+            DType::F32
+        } else {
+            DType::BF16
+        };
+        let adapters = self.merged_adapters()?;
+        let vb = if adapters.is_empty() {
+            unsafe { VarBuilder::from_mmaped_safetensors(weights, dtype, &self.device)? }
+        } else {
+            // Merging rewrites weights, so they are read into memory instead of mapped.
+            let mut tensors = HashMap::new();
+            for file in weights {
+                tensors.extend(candle_core::safetensors::load(file, &self.device)?);
+            }
+            merge_lora_adapters(&mut tensors, &adapters, &self.device)?;
+            VarBuilder::from_tensors(tensors, dtype, &self.device)
+        };
+        let eos_tokens = match &config.eos_token_id {
+            Some(LlamaEosToks::Single(id)) => vec![*id],
+            Some(LlamaEosToks::Multiple(ids)) => ids.clone(),
+            None => tokenizer.token_to_id("</s>").into_iter().collect(),
+        };
+
+        // The draft only speeds generation up, so the model serves without one it cannot use.
+        let draft = draft_dir.and_then(|dir| match self.load_draft(dir, &config, dtype) {
+            Ok(draft) => Some(draft),
+            Err(e) => {
+                warn!("Speculative decoding is off: {:#}", e);
+                None
+            }
+        });
+        let weights = match draft {
+            Some(draft) => Weights::Speculative {
+                target: NativeLlama::load(vb, &config)?,
+                draft,
+            },
+            None => Weights::Safetensors {
+                model: Llama::load(vb, &config)?,
+                config,
+                dtype,
+            },
+        };
+
+        Ok(LoadedModel {
+            weights,
+            tokenizer,
+            eos_tokens,
+            pieces: OnceLock::new(),
+        })
+    }
+
+    /// Directory of the draft model's weights, when one is configured and
+    /// can be found or downloaded.
+    async fn draft_model_dir(&self) -> Option<PathBuf> {
+        let path = self.config.draft_model_path.clone();
+        let name = self
+            .config
+            .draft_model_name
+            .clone()
+            .or_else(|| path.clone())?;
+        let draft = AIModel::new(AiConfig {
+            model_name: name,
+            model_path: path,
+            model_format: "safetensors".to_string(),
+            draft_model_name: None,
+            draft_model_path: None,
+            lora_merge: Vec::new(),
+            ..self.config.clone()
+        });
+        let dir = match draft.resolve_model_dir() {
+            Err(e) if self.config.model_auto_download && draft.model_path().is_none() => {
+                info!(
+                    "{}; downloading the draft model from the Hugging Face Hub",
+                    e
+                );
+                draft.download_model().await
+            }
+            resolved => resolved,
+        };
+        match dir {
+            Ok(dir) => Some(dir),
+            Err(e) => {
+                warn!("Speculative decoding is off: {:#}", e);
+                None
+            }
+        }
+    }
+
+    /// The draft model in `dir`, checked against the main model's `config`.
+    fn load_draft(
+        &self,
+        dir: &Path,
+        config: &LlamaModelConfig,
+        dtype: DType,
+    ) -> Result<NativeLlama> {
+        let config_bytes = fs::read(dir.join("config.json"))
+            .with_context(|| format!("Failed to read draft model config in {}", dir.display()))?;
+        let draft_config: LlamaConfig = serde_json::from_slice(&config_bytes)?;
+        let draft_config = draft_config.into_config(false);
+        if draft_config.vocab_size != config.vocab_size {
+            bail!(
+                "the draft model has {} tokens in its vocabulary and the main model {}",
+                draft_config.vocab_size,
+                config.vocab_size
+            );
+        }
+        let files = weight_files(dir)?;
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&files, dtype, &self.device)? };
+        let draft = NativeLlama::load(vb, &draft_config)?;
+        if draft.max_positions() < self.config.context_length {
+            bail!(
+                "the draft model handles {} positions, fewer than CONTEXT_LENGTH {}",
+                draft.max_positions(),
+                self.config.context_length
+            );
+        }
+        info!(
+            "Loaded draft model from {} for speculative decoding ({} tokens per step)",
+            dir.display(),
+            self.config.speculative_tokens
+        );
+        Ok(draft)
+    }
+
+    /// Adapters named in `lora_merge`, in order.
+    fn merged_adapters(&self) -> Result<Vec<LoraAdapterSettings>> {
+        self.config
+            .lora_merge
+            .iter()
+            .map(|name| {
+                self.config
+                    .lora_adapters
+                    .iter()
+                    .find(|adapter| &adapter.name == name)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("Unknown LoRA adapter {}", name))
+            })
+            .collect()
+    }
+
+    /// Loads a llama.cpp-format file. GGUF embeds its own vocabulary, but the
+    /// `tokenizer.json` of the original model must sit next to the file.
+    fn load_gguf(&self, file: &Path) -> Result<LoadedModel> {
+        if !self.config.lora_merge.is_empty() {
+            bail!("LoRA adapters can only be merged into safetensors weights, not GGUF");
+        }
+        let model_dir = file.parent().unwrap_or(Path::new("."));
+        let tokenizer = load_tokenizer(model_dir)?;
+
+        let mut reader = fs::File::open(file)
+            .with_context(|| format!("Failed to open GGUF weights {}", file.display()))?;
+        let content = gguf_file::Content::read(&mut reader)
+            .map_err(|e| e.with_path(file))
+            .with_context(|| format!("Failed to read GGUF header of {}", file.display()))?;
+        let eos_token = content
+            .metadata
+            .get("tokenizer.ggml.eos_token_id")
+            .and_then(|value| value.to_u32().ok());
+        let model = QuantizedLlama::from_gguf(content, &mut reader, &self.device)?;
+
+        let eos_tokens = match eos_token {
+            Some(id) => vec![id],
+            None => tokenizer.token_to_id("</s>").into_iter().collect(),
+        };
+
+        Ok(LoadedModel {
+            weights: Weights::Gguf(model),
+            tokenizer,
+            eos_tokens,
+            pieces: OnceLock::new(),
+        })
+    }
+
+    pub async fn chat_with_params(
+        &self,
+        system_prompt: &str,
+        message: &str,
+        conversation_id: Option<String>,
+        history: &ChatContext,
+        params: &GenerationParams,
+        language: Option<&str>,
+    ) -> Result<String> {
+        let prompt =
+            generate_chat_prompt(system_prompt, message, conversation_id, history, language);
+        self.generate(&prompt, params)
+    }
+
+    /// Room for the input of a prompt rendered as `frame` without it, with
+    /// `max_new_tokens` left for the answer.
+    pub fn prompt_budget(&self, frame: &str, max_new_tokens: usize) -> PromptBudget {
+        PromptBudget::new(
+            self.config.context_length,
+            self.count_tokens(frame),
+            max_new_tokens,
+        )
+    }
+
+    /// `input` as is when it fits in `budget`, otherwise cut down or
+    /// condensed as `PROMPT_OVERFLOW` says.
+    pub fn fit_input(
+        &self,
+        input: String,
+        budget: &PromptBudget,
+        seed: Option<u64>,
+    ) -> Result<String> {
+        let count_tokens = |text: &str| self.count_tokens(text);
+        match budget.plan(&input, &self.config.prompt_overflow, &count_tokens)? {
+            PromptFit::Fits => Ok(input),
+            PromptFit::Truncated(text) => Ok(text),
+            PromptFit::Summarize(chunks) => {
+                let mut summaries = Vec::with_capacity(chunks.len());
+                for chunk in &chunks {
+                    let (prompt, params) = condense_request(chunk, seed);
+                    summaries.push(match self.generate(&prompt, &params) {
+                        Ok(text) => text,
+                        // A condensed piece cut short still stands in for it.
+                        Err(e) => e.downcast::<PartialGeneration>()?.text,
+                    });
+                }
+                Ok(budget.join_summaries(&summaries, &count_tokens))
+            }
+        }
+    }
+
+    /// Folds `messages` into `previous_summary` and returns the new summary.
+    pub fn summarize(
+        &self,
+        previous_summary: Option<&str>,
+        messages: &[ConversationMessage],
+        seed: Option<u64>,
+    ) -> Result<String> {
+        let prompt = generate_summary_prompt(previous_summary, &format_transcript(messages));
+        let params = GenerationParams {
+            seed,
+            ..GenerationParams::new(0.2, SUMMARY_MAX_TOKENS)
+        };
+        self.generate(&prompt, &params)
+    }
+
+    /// Token count under the loaded tokenizer, or a ~4 chars/token estimate before load.
+    pub fn count_tokens(&self, text: &str) -> usize {
+        self.tokenize(text)
+            .map(|ids| ids.len())
+            .unwrap_or_else(|| estimate_tokens(text))
+    }
+
+    /// Token ids of `text` without special tokens; `None` before load.
+    pub fn tokenize(&self, text: &str) -> Option<Vec<u32>> {
+        self.loaded
+            .as_ref()
+            .and_then(|loaded| loaded.tokenizer.encode(text, false).ok())
+            .map(|encoding| encoding.get_ids().to_vec())
+    }
+
+    pub fn context_length(&self) -> usize {
+        self.config.context_length
+    }
+
+    pub async fn analyze_logs(
+        &self,
+        logs: String,
+        context: Option<String>,
+        cancel: Option<watch::Receiver<bool>>,
+    ) -> Result<String> {
+        let (frame, mut params) = log_analysis_request(&self.config, "", context.clone());
+        params.cancel = cancel;
+        let budget = self.prompt_budget(&frame, params.max_tokens);
+        let logs = self.fit_input(logs, &budget, params.seed)?;
+        let prompt = generate_log_analysis_prompt(&logs, context);
+        self.generate(&prompt, &params)
+    }
+
+    pub async fn generate_script(
+        &self,
+        requirement: String,
+        environment: &str,
+        language: &str,
+        locale: &ScriptLocale,
+        cancel: Option<watch::Receiver<bool>>,
+    ) -> Result<String> {
+        let (frame, mut params) = script_request(&self.config, "", environment, language, locale);
+        params.cancel = cancel;
+        let budget = self.prompt_budget(&frame, params.max_tokens);
+        let requirement = self.fit_input(requirement, &budget, params.seed)?;
+        let prompt = generate_script_prompt(&requirement, environment, language, locale);
+        self.generate(&prompt, &params)
+    }
+
+    fn generate(&self, prompt: &str, params: &GenerationParams) -> Result<String> {
+        let loaded = self
+            .loaded
+            .as_ref()
+            .ok_or_else(|| anyhow!("Model not loaded"))?;
+        *self
+            .last_used
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Instant::now();
+
+        let mut tokens = loaded
+            .tokenizer
+            .encode(prompt, true)
+            .map_err(|e| anyhow!("Tokenization error: {}", e))?
+            .get_ids()
+            .to_vec();
+        let prompt_len = tokens.len();
+        // Output constraints are checked token by token in the loop below.
+        if let (Weights::Speculative { target, draft }, None) =
+            (&loaded.weights, &params.constraint)
+        {
+            return self.generate_speculative(loaded, target, draft, tokens, params);
+        }
+
+        let mut forward = loaded.weights.start(&self.device)?;
+        let top_p = self.config.top_p as f64;
+        let sampling = if params.temperature <= 0.0 {
+            Sampling::ArgMax
+        } else {
+            let temperature = params.temperature as f64;
+            match params.top_k {
+                Some(k) => Sampling::TopKThenTopP {
+                    k,
+                    p: top_p,
+                    temperature,
+                },
+                None => Sampling::TopP {
+                    p: top_p,
+                    temperature,
+                },
+            }
+        };
+        let mut logits_processor =
+            LogitsProcessor::from_sampling(params.seed.unwrap_or_else(rand::random), sampling);
+
+        let grammar = params.constraint.as_deref();
+        let pieces = match grammar {
+            Some(_) => token_pieces(loaded)?,
+            None => &[],
+        };
+        let mut grammar_state = grammar.map(OutputGrammar::start);
+
+        let mut index_pos = 0;
+        let mut sample_next =
+            |tokens: &[u32], step: usize, state: Option<StateID>| -> Result<u32> {
+                let context_size = if step > 0 { 1 } else { tokens.len() };
+                let context = &tokens[tokens.len() - context_size..];
+                let input = Tensor::new(context, &self.device)?.unsqueeze(0)?;
+                let logits = forward.forward(&input, index_pos)?;
+                let logits = logits.squeeze(0)?.to_dtype(DType::F32)?;
+                index_pos += context.len();
+                let mut logits = apply_penalties(logits, &tokens[prompt_len..], params)?;
+                if let (Some(grammar), Some(state)) = (grammar, state) {
+                    logits = constrain_logits(&logits, grammar, state, pieces, &loaded.eos_tokens)?;
+                }
+                Ok(logits_processor.sample(&logits)?)
+            };
+
+        let timeout = self.config.generation_timeout_secs;
+        let deadline = (timeout > 0).then(|| Instant::now() + Duration::from_secs(timeout));
+        let mut interrupted = None;
+        for step in 0..params.max_tokens {
+            if tokens.len() >= self.config.context_length {
+                break;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                interrupted = Some(format!("generation exceeded {}s", timeout));
+                break;
+            }
+            if params
+                .cancel
+                .as_ref()
+                .is_some_and(|cancel| *cancel.borrow())
+            {
+                interrupted = Some("cancelled by an operator".to_string());
+                break;
+            }
+            let next_token = match sample_next(&tokens, step, grammar_state) {
+                Ok(token) => token,
+                // Keep what was produced so far rather than failing the whole answer.
+                Err(e) if tokens.len() > prompt_len => {
+                    warn!(
+                        "Generation failed after {} tokens: {}",
+                        tokens.len() - prompt_len,
+                        e
+                    );
+                    interrupted = Some(e.to_string());
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            if loaded.eos_tokens.contains(&next_token) {
+                break;
+            }
+            if let (Some(grammar), Some(state)) = (grammar, grammar_state) {
+                grammar_state = grammar.advance(state, &pieces[next_token as usize]);
+            }
+            tokens.push(next_token);
+
+            if !params.stop.is_empty() {
+                let text = decode(&loaded.tokenizer, &tokens[prompt_len..])?;
+                if find_stop(&text, &params.stop).is_some() {
+                    break;
+                }
+            }
+        }
+
+        if let (Some(grammar), Some(state), None) = (grammar, grammar_state, &interrupted) {
+            if !grammar.accepts(state) {
+                return Err(anyhow!(
+                    "Output did not complete the constraint within {} tokens",
+                    params.max_tokens
+                ));
+            }
+        }
+        finish_generation(
+            &loaded.tokenizer,
+            &tokens[prompt_len..],
+            &params.stop,
+            interrupted,
+        )
+    }
+
+    /// [`AIModel::generate`] with the draft model proposing tokens that
+    /// `target` verifies, several per forward pass of the main model.
+    fn generate_speculative(
+        &self,
+        loaded: &LoadedModel,
+        target: &NativeLlama,
+        draft: &NativeLlama,
+        mut tokens: Vec<u32>,
+        params: &GenerationParams,
+    ) -> Result<String> {
+        let prompt_len = tokens.len();
+        let context_length = self.config.context_length;
+        let mut speculation = Speculation::new(
+            target,
+            draft,
+            self.config.speculative_tokens,
+            params,
+            self.config.top_p as f64,
+        );
+
+        let timeout = self.config.generation_timeout_secs;
+        let deadline = (timeout > 0).then(|| Instant::now() + Duration::from_secs(timeout));
+        let mut interrupted = None;
+        'generation: while tokens.len() - prompt_len < params.max_tokens
+            && tokens.len() < context_length
+        {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                interrupted = Some(format!("generation exceeded {}s", timeout));
+                break;
+            }
+            if params
+                .cancel
+                .as_ref()
+                .is_some_and(|cancel| *cancel.borrow())
+            {
+                interrupted = Some("cancelled by an operator".to_string());
+                break;
+            }
+            let limit = (params.max_tokens - (tokens.len() - prompt_len))
+                .min(context_length - tokens.len());
+            let proposed = match speculation.step(&tokens, prompt_len, params, limit) {
+                Ok(proposed) => proposed,
+                // Keep what was produced so far rather than failing the whole answer.
+                Err(e) if tokens.len() > prompt_len => {
+                    warn!(
+                        "Generation failed after {} tokens: {}",
+                        tokens.len() - prompt_len,
+                        e
+                    );
+                    interrupted = Some(e.to_string());
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            for token in proposed {
+                if loaded.eos_tokens.contains(&token) {
+                    break 'generation;
+                }
+                tokens.push(token);
+                if !params.stop.is_empty() {
+                    let text = decode(&loaded.tokenizer, &tokens[prompt_len..])?;
+                    if find_stop(&text, &params.stop).is_some() {
+                        break 'generation;
+                    }
+                }
+            }
+        }
+
+        finish_generation(
+            &loaded.tokenizer,
+            &tokens[prompt_len..],
+            &params.stop,
+            interrupted,
+        )
+    }
+
+    fn model_path(&self) -> Option<&str> {
+        self.config
+            .model_path
+            .as_deref()
+            .filter(|p| !p.trim().is_empty())
+    }
+
+    fn cache_location(&self) -> (PathBuf, String) {
+        hf_cache_location(&self.config, &self.config.model_name)
+    }
+
+    async fn download_model(&self) -> Result<PathBuf> {
+        let (cache_dir, repo_name) = self.cache_location();
+        let repo_dir = cache_dir.join("hub").join(repo_name);
+        download_model(DownloadRequest {
+            model_name: &self.config.model_name,
+            repo_dir: &repo_dir,
+            token: self.config.huggingface_token.as_deref(),
+            min_free_bytes: self.config.model_min_free_disk_mb * 1024 * 1024,
+            max_concurrent: self.config.model_max_concurrent_downloads,
+        })
+        .await
+        .with_context(|| format!("Failed to download model {}", self.config.model_name))
+    }
+
+    fn resolve_model_dir(&self) -> Result<PathBuf> {
+        if let Some(path) = self.model_path() {
+            return Ok(expand_home(path));
+        }
+
+        let (cache_dir, repo_name) = self.cache_location();
+        if let Some(snapshot) = cached_snapshot(&cache_dir, &repo_name) {
+            return Ok(snapshot);
+        }
+
+        Err(anyhow!(
+            "Model {} not found in {}; set MODEL_PATH or MODEL_AUTO_DOWNLOAD=true",
+            self.config.model_name,
+            cache_dir.display()
+        ))
+    }
+}
+
+/// The device `DEVICE` names, opened once and shared by every model. A
+/// device that cannot be opened, e.g. in a build without the `cuda` or
+/// `metal` feature, falls back to the CPU.
+fn inference_device(name: &str) -> &'static Device {
+    static DEVICE: OnceLock<Device> = OnceLock::new();
+    DEVICE.get_or_init(|| {
+        let (kind, ordinal) = match name.split_once(':') {
+            Some((kind, ordinal)) => (kind, ordinal.parse().unwrap_or(0)),
+            None => (name, 0),
+        };
+        let opened = match kind {
+            "cpu" => Ok(Device::Cpu),
+            "cuda" => Device::new_cuda(ordinal),
+            "metal" => Device::new_metal(ordinal),
+            _ => Ok(Device::cuda_if_available(0)
+                .ok()
+                .filter(|device| device.is_cuda())
+                .or_else(|| Device::metal_if_available(0).ok())
+                .unwrap_or(Device::Cpu)),
+        };
+        let device = opened.unwrap_or_else(|e| {
+            warn!("Device {} is unavailable ({}); running on the CPU", name, e);
+            Device::Cpu
+        });
+        info!("AI model will run on device: {}", device_name(&device));
+        device
+    })
+}
+
+fn device_name(device: &Device) -> String {
+    match device.location() {
+        DeviceLocation::Cpu => "cpu".to_string(),
+        DeviceLocation::Cuda { gpu_id } => format!("cuda:{}", gpu_id),
+        DeviceLocation::Metal { gpu_id } => format!("metal:{}", gpu_id),
+    }
+}
+
+fn load_tokenizer(model_dir: &Path) -> Result<Tokenizer> {
+    Tokenizer::from_file(model_dir.join("tokenizer.json")).map_err(|e| {
+        anyhow!(
+            "Failed to load tokenizer from {}: {}",
+            model_dir.display(),
+            e
+        )
+    })
+}
+
+fn decode(tokenizer: &Tokenizer, tokens: &[u32]) -> Result<String> {
+    tokenizer
+        .decode(tokens, true)
+        .map_err(|e| anyhow!("Decoding error: {}", e))
+}
+
+/// Text each token adds when appended to a sequence, indexed by token id.
+fn token_pieces(loaded: &LoadedModel) -> Result<&[String]> {
+    if let Some(pieces) = loaded.pieces.get() {
+        return Ok(pieces);
+    }
+    // Decoding after an anchor keeps the leading space SentencePiece tokens carry.
+    let anchor = loaded
+        .tokenizer
+        .encode("a", false)
+        .map_err(|e| anyhow!("Tokenization error: {}", e))?
+        .get_ids()
+        .last()
+        .copied()
+        .ok_or_else(|| anyhow!("Tokenizer produced no anchor token"))?;
+    let prefix = decode(&loaded.tokenizer, &[anchor])?;
+    let vocab_size = loaded.tokenizer.get_vocab_size(true) as u32;
+    let pieces = (0..vocab_size)
+        .map(|id| {
+            decode(&loaded.tokenizer, &[anchor, id])
+                .ok()
+                .and_then(|text| text.strip_prefix(prefix.as_str()).map(str::to_string))
+                .unwrap_or_default()
+        })
+        .collect();
+    Ok(loaded.pieces.get_or_init(|| pieces))
+}
+
+/// Masks every token that would take the output outside `grammar`, keeping the
+/// `CONSTRAINED_CANDIDATES` most likely ones that stay inside it.
+fn constrain_logits(
+    logits: &Tensor,
+    grammar: &OutputGrammar,
+    state: StateID,
+    pieces: &[String],
+    eos_tokens: &[u32],
+) -> Result<Tensor> {
+    let values = logits.to_vec1::<f32>()?;
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_unstable_by(|a, b| values[*b].total_cmp(&values[*a]));
+
+    let mut masked = vec![f32::NEG_INFINITY; values.len()];
+    let mut allowed = 0;
+    for index in order {
+        let fits = if eos_tokens.contains(&(index as u32)) {
+            grammar.accepts(state)
+        } else {
+            pieces
+                .get(index)
+                .is_some_and(|piece| !piece.is_empty() && grammar.advance(state, piece).is_some())
+        };
+        if fits {
+            masked[index] = values[index];
+            allowed += 1;
+            if allowed == CONSTRAINED_CANDIDATES {
+                break;
+            }
+        }
+    }
+    if allowed == 0 {
+        return Err(anyhow!(
+            "No token can continue the output within the constraint"
+        ));
+    }
+    Ok(Tensor::new(masked, logits.device())?)
+}
+
+/// The generated text up to any stop sequence, or a [`PartialGeneration`]
+/// when `interrupted` says why generation stopped early.
+fn finish_generation(
+    tokenizer: &Tokenizer,
+    generated: &[u32],
+    stop: &[String],
+    interrupted: Option<String>,
+) -> Result<String> {
+    let mut text = decode(tokenizer, generated)?;
+    if let Some(index) = find_stop(&text, stop) {
+        text.truncate(index);
+    }
+    if let Some(reason) = interrupted {
+        let text = text.trim().to_string();
+        if text.is_empty() {
+            return Err(anyhow!(
+                "Generation interrupted before any output: {}",
+                reason
+            ));
+        }
+        return Err(PartialGeneration { text, reason }.into());
+    }
+    Ok(text.trim().to_string())
+}
+
+/// Byte offset of the earliest stop sequence in `text`.
+fn find_stop(text: &str, stop: &[String]) -> Option<usize> {
+    stop.iter()
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| text.find(s.as_str()))
+        .min()
+}
+
+/// Applies the repetition penalty (over the last `REPEAT_LAST_N` tokens) and the
+/// OpenAI-style frequency/presence penalties (over all generated tokens).
+pub(crate) fn apply_penalties(
+    logits: Tensor,
+    generated: &[u32],
+    params: &GenerationParams,
+) -> Result<Tensor> {
+    let mut logits = logits;
+    if let Some(penalty) = params.repetition_penalty.filter(|p| *p != 1.0) {
+        let start = generated.len().saturating_sub(REPEAT_LAST_N);
+        logits = candle_transformers::utils::apply_repeat_penalty(
+            &logits,
+            penalty,
+            &generated[start..],
+        )?;
+    }
+
+    let frequency = params.frequency_penalty.unwrap_or(0.0);
+    let presence = params.presence_penalty.unwrap_or(0.0);
+    if (frequency == 0.0 && presence == 0.0) || generated.is_empty() {
+        return Ok(logits);
+    }
+
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for token in generated {
+        *counts.entry(*token).or_default() += 1;
+    }
+    let device = logits.device().clone();
+    let mut values = logits.to_vec1::<f32>()?;
+    for (token, count) in counts {
+        if let Some(value) = values.get_mut(token as usize) {
+            *value -= frequency * count as f32 + presence;
+        }
+    }
+    Ok(Tensor::new(values, &device)?)
+}
+
+/// Total size of the weight files for `config`'s model, when they are on disk.
+pub(crate) fn local_weights_size(config: &AiConfig) -> Option<u64> {
+    let model_dir = match config
+        .model_path
+        .as_deref()
+        .filter(|p| !p.trim().is_empty())
+    {
+        Some(path) => expand_home(path),
+        None => {
+            let (cache_dir, repo_name) = hf_cache_location(config, &config.model_name);
+            cached_snapshot(&cache_dir, &repo_name)?
+        }
+    };
+    let files = locate_weights(&model_dir, &config.model_format).ok()?;
+    files
+        .paths()
+        .iter()
+        .map(|file| fs::metadata(file).map(|metadata| metadata.len()).ok())
+        .sum()
+}
+
+/// Hugging Face cache root and the `models--org--name` directory name for `model_name`.
+pub(crate) fn hf_cache_location(config: &AiConfig, model_name: &str) -> (PathBuf, String) {
+    let cache_dir = config
+        .huggingface_cache_dir
+        .as_deref()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or("~/.cache/huggingface");
+    let repo_name = format!("models--{}", model_name.replace('/', "--"));
+    (expand_home(cache_dir), repo_name)
+}
+
+/// Snapshot directory of a completed download in the cache, in either the
+/// legacy or the `hub/` layout.
+pub(crate) fn cached_snapshot(cache_dir: &Path, repo_name: &str) -> Option<PathBuf> {
+    [
+        cache_dir.join(repo_name),
+        cache_dir.join("hub").join(repo_name),
+    ]
+    .into_iter()
+    .find_map(|repo_dir| {
+        let revision = fs::read_to_string(repo_dir.join("refs").join("main")).ok()?;
+        Some(repo_dir.join("snapshots").join(revision.trim()))
+    })
+}
+
+/// Weights at `path`, which is a model directory or, for GGUF, the file
+/// itself. `auto` takes a `.gguf` path or a directory without safetensors
+/// weights as GGUF.
+pub(crate) fn locate_weights(path: &Path, format: &str) -> Result<WeightFiles> {
+    let is_gguf_file = |path: &Path| {
+        path.extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("gguf"))
+    };
+    if path.is_file() {
+        return match format {
+            "safetensors" => Err(anyhow!(
+                "{} is a file; MODEL_PATH must be a directory for safetensors weights",
+                path.display()
+            )),
+            _ if format == "gguf" || is_gguf_file(path) => {
+                Ok(WeightFiles::Gguf(path.to_path_buf()))
+            }
+            _ => Err(anyhow!("{} is not a GGUF file", path.display())),
+        };
+    }
+
+    if format != "gguf" {
+        match weight_files(path) {
+            Ok(files) => return Ok(WeightFiles::Safetensors(files)),
+            Err(e) if format == "safetensors" => return Err(e),
+            Err(_) => {}
+        }
+    }
+
+    let mut gguf: Vec<PathBuf> = fs::read_dir(path)
+        .with_context(|| format!("Failed to read model directory {}", path.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|file| file.is_file() && is_gguf_file(file))
+        .collect();
+    gguf.sort();
+    match gguf.len() {
+        0 => Err(anyhow!(
+            "No safetensors or GGUF weights found in {}",
+            path.display()
+        )),
+        1 => Ok(WeightFiles::Gguf(gguf.remove(0))),
+        _ => Err(anyhow!(
+            "Several GGUF files in {}; set MODEL_PATH to the one to load",
+            path.display()
+        )),
+    }
+}
+
+pub(crate) fn weight_files(model_dir: &Path) -> Result<Vec<PathBuf>> {
+    let index_path = model_dir.join("model.safetensors.index.json");
+    if index_path.exists() {
+        let index: serde_json::Value = serde_json::from_slice(&fs::read(&index_path)?)?;
+        let files: BTreeSet<String> = index
+            .get("weight_map")
+            .and_then(|map| map.as_object())
+            .map(|map| {
+                map.values()
+                    .filter_map(|file| file.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default();
+        return Ok(files.into_iter().map(|file| model_dir.join(file)).collect());
+    }
+
+    let single = model_dir.join("model.safetensors");
+    if single.exists() {
+        return Ok(vec![single]);
+    }
+
+    Err(anyhow!(
+        "No safetensors weights found in {}",
+        model_dir.display()
+    ))
+}
+
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::PageInfo;
+use crate::repositories::{ArchiveRecord, ConversationRecord, MessageRecord};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationMessage {
+    pub role: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    /// Set when this answer is partial; resume it with `POST /api/chat/continue`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interrupted: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationSummary {
+    pub conversation_id: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub message_count: u64,
+    pub escalated: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalated_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalation_reason: Option<String>,
+}
+
+/// Prior conversation context rendered into a chat prompt.
+#[derive(Debug, Clone, Default)]
+pub struct ChatContext {
+    /// Rolling summary of turns that no longer fit in the context window.
+    pub summary: Option<String>,
+    /// Most recent turns, oldest first.
+    pub turns: Vec<ConversationMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationDetail {
+    #[serde(flatten)]
+    pub summary: ConversationSummary,
+    pub messages: Vec<ConversationMessage>,
+}
+
+/// Turns moved out of a conversation by the message count or age limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationArchive {
+    pub archived_at: DateTime<Utc>,
+    /// The rolling summary when the turns were archived.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub messages: Vec<ConversationMessage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationArchivesResponse {
+    pub conversation_id: String,
+    pub archives: Vec<ConversationArchive>,
+    #[serde(flatten)]
+    pub page: PageInfo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationListResponse {
+    pub conversations: Vec<ConversationSummary>,
+    #[serde(flatten)]
+    pub page: PageInfo,
+}
+
+impl From<ConversationRecord> for ConversationSummary {
+    fn from(record: ConversationRecord) -> Self {
+        Self {
+            conversation_id: record.conversation_id,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+            message_count: record.message_count,
+            escalated: record.escalated_at.is_some(),
+            escalated_at: record.escalated_at,
+            escalation_reason: record.escalation_reason,
+        }
+    }
+}
+
+impl From<MessageRecord> for ConversationMessage {
+    fn from(record: MessageRecord) -> Self {
+        Self {
+            role: record.role,
+            content: record.content,
+            created_at: record.created_at,
+            interrupted: record.interrupted,
+        }
+    }
+}
+
+impl From<ArchiveRecord> for ConversationArchive {
+    fn from(record: ArchiveRecord) -> Self {
+        Self {
+            archived_at: record.archived_at,
+            summary: record.summary,
+            messages: record.messages.into_iter().map(Into::into).collect(),
+        }
+    }
+}
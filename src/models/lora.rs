@@ -0,0 +1,102 @@
+use anyhow::{anyhow, bail, Context, Result};
+use candle_core::{DType, Device, Tensor};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+use crate::config::LoraAdapterSettings;
+
+/// Prefix PEFT puts before the base model's tensor names.
+const PEFT_PREFIX: &str = "base_model.model.";
+
+/// The part of a PEFT `adapter_config.json` the merge needs.
+#[derive(Debug, Deserialize)]
+struct AdapterConfig {
+    r: usize,
+    lora_alpha: f64,
+}
+
+/// Merges each adapter into `weights`, in order: every targeted matrix `W`
+/// becomes `W + scale * (lora_alpha / r) * B·A`. Adapters are PEFT
+/// directories holding `adapter_config.json` and `adapter_model.safetensors`.
+pub fn merge_lora_adapters(
+    weights: &mut HashMap<String, Tensor>,
+    adapters: &[LoraAdapterSettings],
+    device: &Device,
+) -> Result<()> {
+    for adapter in adapters {
+        let merged = merge_adapter(weights, adapter, device)
+            .with_context(|| format!("Failed to merge LoRA adapter {}", adapter.name))?;
+        info!(
+            "Merged LoRA adapter {} into {} weight matrices",
+            adapter.name, merged
+        );
+    }
+    Ok(())
+}
+
+fn merge_adapter(
+    weights: &mut HashMap<String, Tensor>,
+    adapter: &LoraAdapterSettings,
+    device: &Device,
+) -> Result<usize> {
+    let dir = Path::new(&adapter.path);
+    let config_bytes = fs::read(dir.join("adapter_config.json"))
+        .with_context(|| format!("Failed to read adapter_config.json in {}", dir.display()))?;
+    let config: AdapterConfig = serde_json::from_slice(&config_bytes)?;
+    if config.r == 0 {
+        bail!("adapter_config.json has r = 0");
+    }
+    let scaling = adapter.scale as f64 * config.lora_alpha / config.r as f64;
+
+    let file = dir.join("adapter_model.safetensors");
+    let tensors = candle_core::safetensors::load(&file, device)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+
+    let mut merged = 0;
+    for (name, lora_a) in &tensors {
+        let Some(target) = base_weight_name(name, "lora_A") else {
+            continue;
+        };
+        let b_name = name.replace("lora_A", "lora_B");
+        let lora_b = tensors
+            .get(&b_name)
+            .ok_or_else(|| anyhow!("{} has no matching {}", name, b_name))?;
+        let base = weights
+            .get(&target)
+            .ok_or_else(|| anyhow!("The base model has no weight {} for {}", target, name))?;
+
+        // B (out × r) · A (r × in) has the shape of the base matrix (out × in).
+        let delta = lora_b
+            .to_dtype(DType::F32)?
+            .matmul(&lora_a.to_dtype(DType::F32)?)?
+            .affine(scaling, 0.0)?;
+        if delta.dims() != base.dims() {
+            bail!(
+                "{} has shape {:?}, but the base weight {} has {:?}",
+                name,
+                delta.dims(),
+                target,
+                base.dims()
+            );
+        }
+        let updated = (base.to_dtype(DType::F32)? + delta)?.to_dtype(base.dtype())?;
+        weights.insert(target, updated);
+        merged += 1;
+    }
+    if merged == 0 {
+        bail!("{} holds no lora_A/lora_B weight pairs", file.display());
+    }
+    Ok(merged)
+}
+
+/// Base tensor name for a PEFT LoRA tensor such as
+/// `base_model.model.model.layers.0.self_attn.q_proj.lora_A.weight`
+/// (or `...lora_A.default.weight`).
+fn base_weight_name(name: &str, part: &str) -> Option<String> {
+    let name = name.strip_prefix(PEFT_PREFIX).unwrap_or(name);
+    let (module, rest) = name.split_once(&format!(".{}.", part))?;
+    matches!(rest, "weight" | "default.weight").then(|| format!("{}.weight", module))
+}
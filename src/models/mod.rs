@@ -0,0 +1,27 @@
+pub mod agent;
+pub mod ai_model;
+pub mod conversation;
+pub mod lora;
+pub mod model_download;
+pub mod ollama;
+pub mod pagination;
+pub mod prompt_budget;
+pub mod request_context;
+pub mod requests;
+pub mod responses;
+pub mod speculative;
+pub mod tools;
+
+pub use agent::*;
+pub use ai_model::*;
+pub use conversation::*;
+pub use lora::*;
+pub use model_download::*;
+pub use ollama::*;
+pub use pagination::*;
+pub use prompt_budget::*;
+pub use request_context::*;
+pub use requests::*;
+pub use responses::*;
+pub use speculative::*;
+pub use tools::*;
@@ -0,0 +1,442 @@
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::StreamExt;
+use ring::digest::{Context as DigestContext, SHA256};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex as AsyncMutex, Semaphore};
+use tracing::{info, warn};
+
+use crate::models::ModelDownloadStatus;
+use crate::utils::available_bytes;
+
+const HUB_URL: &str = "https://huggingface.co";
+const REVISION: &str = "main";
+/// A lock file older than this is assumed to belong to a crashed process.
+const STALE_LOCK_AFTER: Duration = Duration::from_secs(6 * 3600);
+const LOCK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct DownloadRequest<'a> {
+    pub model_name: &'a str,
+    /// `models--org--name` directory inside the Hugging Face cache.
+    pub repo_dir: &'a Path,
+    pub token: Option<&'a str>,
+    pub min_free_bytes: u64,
+    pub max_concurrent: usize,
+}
+
+/// Downloads a model snapshot into the Hugging Face cache layout and returns the
+/// snapshot directory. Concurrent downloads of the same model are serialized within
+/// the process and, through a lock file, across processes sharing the cache.
+pub async fn download_model(request: DownloadRequest<'_>) -> Result<PathBuf> {
+    let model_lock = model_lock(request.model_name);
+    let _in_process = model_lock.lock().await;
+
+    tokio::fs::create_dir_all(request.repo_dir).await?;
+    let _cross_process = DownloadLock::acquire(request.repo_dir.join(".download.lock")).await?;
+
+    // Another task or process may have finished while we waited.
+    let ref_path = request.repo_dir.join("refs").join(REVISION);
+    let snapshot = request.repo_dir.join("snapshots").join(REVISION);
+    if ref_path.exists() {
+        return Ok(snapshot);
+    }
+    tokio::fs::create_dir_all(&snapshot).await?;
+
+    let client = reqwest::Client::new();
+    let hub = Hub {
+        client: &client,
+        model_name: request.model_name,
+        token: request.token,
+    };
+
+    let checksums = match hub.checksums().await {
+        Ok(checksums) => checksums,
+        Err(e) => {
+            warn!(
+                "Downloading {} without checksum verification: {}",
+                request.model_name, e
+            );
+            HashMap::new()
+        }
+    };
+
+    for file in ["config.json", "tokenizer.json"] {
+        hub.download(file, &snapshot.join(file), None).await?;
+    }
+
+    let weights = match hub.fetch_json("model.safetensors.index.json").await? {
+        Some(index) => {
+            let index_path = snapshot.join("model.safetensors.index.json");
+            tokio::fs::write(&index_path, serde_json::to_vec(&index)?).await?;
+            index
+                .get("weight_map")
+                .and_then(|map| map.as_object())
+                .map(|map| {
+                    map.values()
+                        .filter_map(|file| file.as_str().map(str::to_string))
+                        .collect::<BTreeSet<_>>()
+                })
+                .unwrap_or_default()
+                .into_iter()
+                .collect::<Vec<_>>()
+        }
+        None => vec!["model.safetensors".to_string()],
+    };
+
+    let mut total_bytes = 0;
+    for file in &weights {
+        total_bytes += hub.size(file).await?;
+    }
+    ensure_free_space(&snapshot, total_bytes, request.min_free_bytes)?;
+
+    let progress = ProgressEntry::start(request.model_name, total_bytes, weights.len());
+    let slots = download_slots(request.max_concurrent);
+    futures_util::future::try_join_all(weights.iter().map(|file| {
+        let (checksums, hub, snapshot, progress) = (&checksums, &hub, &snapshot, &progress);
+        let model_name = request.model_name;
+        async move {
+            let _permit = slots.acquire().await?;
+            let checksum = checksums.get(file).map(String::as_str);
+            if checksum.is_none() {
+                warn!("No checksum published for {}/{}", model_name, file);
+            }
+            hub.download(file, &snapshot.join(file), Some((progress, checksum)))
+                .await?;
+            progress.file_done();
+            Ok::<_, anyhow::Error>(())
+        }
+    }))
+    .await?;
+    drop(progress);
+
+    // Written last: its presence marks the snapshot as complete.
+    tokio::fs::create_dir_all(ref_path.parent().unwrap_or(request.repo_dir)).await?;
+    tokio::fs::write(&ref_path, REVISION).await?;
+    info!(
+        "Downloaded {} ({} weight files)",
+        request.model_name,
+        weights.len()
+    );
+    Ok(snapshot)
+}
+
+struct Hub<'a> {
+    client: &'a reqwest::Client,
+    model_name: &'a str,
+    token: Option<&'a str>,
+}
+
+impl Hub<'_> {
+    fn url(&self, file: &str) -> String {
+        format!(
+            "{}/{}/resolve/{}/{}",
+            HUB_URL, self.model_name, REVISION, file
+        )
+    }
+
+    fn get(&self, file: &str) -> reqwest::RequestBuilder {
+        let builder = self.client.get(self.url(file));
+        match self.token {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        }
+    }
+
+    async fn fetch_json(&self, file: &str) -> Result<Option<serde_json::Value>> {
+        let response = self.get(file).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.json().await?))
+    }
+
+    async fn size(&self, file: &str) -> Result<u64> {
+        let url = self.url(file);
+        let builder = self.client.head(&url);
+        let builder = match self.token {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        };
+        let response = builder.send().await?.error_for_status()?;
+        response
+            .content_length()
+            .filter(|length| *length > 0)
+            .ok_or_else(|| anyhow!("Hub did not report a size for {}", url))
+    }
+
+    /// SHA-256 of each LFS file in the repository, by file name.
+    async fn checksums(&self) -> Result<HashMap<String, String>> {
+        let url = format!(
+            "{}/api/models/{}/revision/{}?blobs=true",
+            HUB_URL, self.model_name, REVISION
+        );
+        let builder = self.client.get(&url);
+        let builder = match self.token {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        };
+        let info: serde_json::Value = builder.send().await?.error_for_status()?.json().await?;
+        Ok(info
+            .get("siblings")
+            .and_then(|siblings| siblings.as_array())
+            .map(|siblings| {
+                siblings
+                    .iter()
+                    .filter_map(|sibling| {
+                        let name = sibling.get("rfilename")?.as_str()?;
+                        let sha256 = sibling.get("lfs")?.get("sha256")?.as_str()?;
+                        Some((name.to_string(), sha256.to_lowercase()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Streams the file into `<dest>.part` and renames it into place, so readers
+    /// never observe a partially written file. A weight file is counted in
+    /// `tracked`'s progress and, when a checksum is known, only renamed into
+    /// place once its SHA-256 matches.
+    async fn download(
+        &self,
+        file: &str,
+        dest: &Path,
+        tracked: Option<(&ProgressEntry, Option<&str>)>,
+    ) -> Result<()> {
+        if dest.exists() {
+            // Left by an earlier, interrupted download; verified back then.
+            if let (Some((progress, _)), Ok(metadata)) = (tracked, std::fs::metadata(dest)) {
+                progress.add_bytes(metadata.len());
+            }
+            return Ok(());
+        }
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        info!("Downloading {}/{}", self.model_name, file);
+        let response = self.get(file).send().await?.error_for_status()?;
+        let partial = dest.with_extension("part");
+        let mut out = tokio::fs::File::create(&partial)
+            .await
+            .with_context(|| format!("Failed to create {}", partial.display()))?;
+        let mut body = response.bytes_stream();
+        let mut hasher = DigestContext::new(&SHA256);
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            out.write_all(&chunk).await?;
+            hasher.update(&chunk);
+            if let Some((progress, _)) = tracked {
+                progress.add_bytes(chunk.len() as u64);
+            }
+        }
+        out.flush().await?;
+        drop(out);
+
+        if let Some((_, Some(expected))) = tracked {
+            let actual: String = hasher
+                .finish()
+                .as_ref()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            if actual != expected {
+                let _ = tokio::fs::remove_file(&partial).await;
+                bail!(
+                    "Checksum mismatch for {}/{}: expected {}, got {}",
+                    self.model_name,
+                    file,
+                    expected,
+                    actual
+                );
+            }
+        }
+        tokio::fs::rename(&partial, dest).await?;
+        Ok(())
+    }
+}
+
+struct Progress {
+    total_bytes: u64,
+    downloaded_bytes: u64,
+    files: usize,
+    files_done: usize,
+    started: Instant,
+}
+
+fn progress_registry() -> &'static Mutex<HashMap<String, Progress>> {
+    static PROGRESS: OnceLock<Mutex<HashMap<String, Progress>>> = OnceLock::new();
+    PROGRESS.get_or_init(Default::default)
+}
+
+/// A download's entry in the progress registry, removed when the download
+/// ends either way.
+struct ProgressEntry {
+    model_name: String,
+}
+
+impl ProgressEntry {
+    fn start(model_name: &str, total_bytes: u64, files: usize) -> Self {
+        progress_registry()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                model_name.to_string(),
+                Progress {
+                    total_bytes,
+                    downloaded_bytes: 0,
+                    files,
+                    files_done: 0,
+                    started: Instant::now(),
+                },
+            );
+        Self {
+            model_name: model_name.to_string(),
+        }
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut Progress)) {
+        let mut registry = progress_registry()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(progress) = registry.get_mut(&self.model_name) {
+            apply(progress);
+        }
+    }
+
+    fn add_bytes(&self, bytes: u64) {
+        self.update(|progress| progress.downloaded_bytes += bytes);
+    }
+
+    fn file_done(&self) {
+        self.update(|progress| progress.files_done += 1);
+    }
+}
+
+impl Drop for ProgressEntry {
+    fn drop(&mut self) {
+        progress_registry()
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.model_name);
+    }
+}
+
+/// Weight downloads in progress in this process, by model name.
+pub fn download_progress() -> Vec<ModelDownloadStatus> {
+    let registry = progress_registry()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let mut downloads: Vec<ModelDownloadStatus> = registry
+        .iter()
+        .map(|(model_name, progress)| {
+            let elapsed = progress.started.elapsed().as_secs_f64();
+            let bytes_per_sec = if elapsed > 0.0 {
+                progress.downloaded_bytes as f64 / elapsed
+            } else {
+                0.0
+            };
+            let remaining = progress
+                .total_bytes
+                .saturating_sub(progress.downloaded_bytes);
+            ModelDownloadStatus {
+                model_name: model_name.clone(),
+                downloaded_bytes: progress.downloaded_bytes,
+                total_bytes: progress.total_bytes,
+                files: progress.files,
+                files_done: progress.files_done,
+                bytes_per_sec: bytes_per_sec as u64,
+                eta_seconds: (bytes_per_sec > 0.0)
+                    .then(|| (remaining as f64 / bytes_per_sec).ceil() as u64),
+            }
+        })
+        .collect();
+    downloads.sort_by(|a, b| a.model_name.cmp(&b.model_name));
+    downloads
+}
+
+fn model_lock(model_name: &str) -> Arc<AsyncMutex<()>> {
+    static LOCKS: OnceLock<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>> = OnceLock::new();
+    let mut locks = LOCKS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    locks.entry(model_name.to_string()).or_default().clone()
+}
+
+/// Process-wide limit on parallel weight downloads; sized by the first caller.
+fn download_slots(max_concurrent: usize) -> &'static Semaphore {
+    static SLOTS: OnceLock<Semaphore> = OnceLock::new();
+    SLOTS.get_or_init(|| Semaphore::new(max_concurrent.max(1)))
+}
+
+fn ensure_free_space(dir: &Path, needed_bytes: u64, min_free_bytes: u64) -> Result<()> {
+    let Some(available) = available_bytes(dir)? else {
+        warn!("Free disk space check is not supported on this platform");
+        return Ok(());
+    };
+    if available < needed_bytes.saturating_add(min_free_bytes) {
+        bail!(
+            "Not enough disk space in {}: model needs {} MB, {} MB available, {} MB must stay free",
+            dir.display(),
+            needed_bytes / (1024 * 1024),
+            available / (1024 * 1024),
+            min_free_bytes / (1024 * 1024)
+        );
+    }
+    Ok(())
+}
+
+/// Lock file held while a snapshot is being downloaded; removed on drop.
+struct DownloadLock {
+    path: PathBuf,
+}
+
+impl DownloadLock {
+    async fn acquire(path: PathBuf) -> Result<Self> {
+        let mut warned = false;
+        loop {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(_) => return Ok(Self { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if is_stale(&path) {
+                        warn!("Removing stale download lock {}", path.display());
+                        let _ = std::fs::remove_file(&path);
+                        continue;
+                    }
+                    if !warned {
+                        info!(
+                            "Waiting for another process to finish downloading ({})",
+                            path.display()
+                        );
+                        warned = true;
+                    }
+                    tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create {}", path.display()))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for DownloadLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn is_stale(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > STALE_LOCK_AFTER)
+}
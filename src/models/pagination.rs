@@ -0,0 +1,120 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
+/// Query-string parameters shared by list endpoints.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PageQuery {
+    pub limit: Option<usize>,
+    /// Opaque `next_cursor` from the previous page.
+    pub cursor: Option<String>,
+    pub sort: Option<SortOrder>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl PageQuery {
+    /// Validates the query against an endpoint's limits. Errors are meant for the client.
+    pub fn resolve(&self, default_limit: usize, max_limit: usize) -> Result<Page, String> {
+        let cursor = match self.cursor.as_deref().filter(|c| !c.is_empty()) {
+            Some(raw) => Some(Cursor::decode(raw).ok_or("Invalid cursor")?),
+            None => None,
+        };
+        if let (Some(since), Some(until)) = (self.since, self.until) {
+            if since > until {
+                return Err("`since` must not be after `until`".to_string());
+            }
+        }
+
+        Ok(Page {
+            limit: self.limit.unwrap_or(default_limit).clamp(1, max_limit),
+            cursor,
+            sort: self.sort.unwrap_or_default(),
+            since: self.since,
+            until: self.until,
+        })
+    }
+}
+
+/// A validated page request; `limit` is always bounded.
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub limit: usize,
+    pub cursor: Option<Cursor>,
+    pub sort: SortOrder,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Page {
+    /// Trims the extra row fetched past `limit` and derives the cursor for the next page.
+    pub fn finish<T>(&self, items: &mut Vec<T>, cursor_of: impl Fn(&T) -> Cursor) -> PageInfo {
+        let next_cursor = if items.len() > self.limit {
+            items.truncate(self.limit);
+            items.last().map(|item| cursor_of(item).encode())
+        } else {
+            None
+        };
+        PageInfo {
+            limit: self.limit,
+            sort: self.sort,
+            next_cursor,
+        }
+    }
+}
+
+/// Keyset position: the sort timestamp and a unique id to break ties.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub timestamp: i64,
+    pub id: String,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        format!("{}:{}", self.timestamp, self.id)
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    pub fn decode(raw: &str) -> Option<Self> {
+        if raw.len() % 2 != 0 {
+            return None;
+        }
+        let bytes = (0..raw.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(raw.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let text = String::from_utf8(bytes).ok()?;
+        let (timestamp, id) = text.split_once(':')?;
+        Some(Self {
+            timestamp: timestamp.parse().ok()?,
+            id: id.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageInfo {
+    pub limit: usize,
+    pub sort: SortOrder,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
@@ -0,0 +1,230 @@
+use anyhow::Result;
+use std::fmt;
+use tracing::warn;
+
+use crate::models::{GenerationParams, SUMMARY_MAX_TOKENS};
+use crate::utils::generate_condense_prompt;
+
+/// Room left for the tokens a tokenizer adds around a prompt, such as BOS.
+const SPECIAL_TOKEN_MARGIN: usize = 8;
+/// Least room worth cutting an input down to; with less, the prompt is refused.
+const MIN_INPUT_TOKENS: usize = 32;
+/// Most pieces `summarize` condenses; a longer input loses its middle first.
+const MAX_SUMMARY_CHUNKS: usize = 8;
+const HEAD_OMITTED: &str = "[... earlier input omitted to fit the context window ...]\n";
+
+/// A prompt that would not fit in the context window with its answer, and
+/// was refused by `PROMPT_OVERFLOW=reject` or could not be cut down.
+#[derive(Debug)]
+pub struct PromptTooLong {
+    /// Tokens the prompt and its answer need.
+    pub tokens: usize,
+    pub context_length: usize,
+}
+
+impl fmt::Display for PromptTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The prompt and its answer need {} tokens, but the context window holds {}",
+            self.tokens, self.context_length
+        )
+    }
+}
+
+impl std::error::Error for PromptTooLong {}
+
+/// What [`PromptBudget::plan`] decided for an input.
+pub enum PromptFit {
+    Fits,
+    Truncated(String),
+    /// Pieces to condense one by one and pass to [`PromptBudget::join_summaries`].
+    Summarize(Vec<String>),
+}
+
+/// The room in the context window for the variable input of a prompt (a
+/// chat message, logs, a script requirement): what is left after the rest
+/// of the prompt and the answer.
+#[derive(Debug, Clone, Copy)]
+pub struct PromptBudget {
+    context_length: usize,
+    reserved: usize,
+}
+
+impl PromptBudget {
+    /// `frame_tokens` counts the prompt rendered with an empty input.
+    pub fn new(context_length: usize, frame_tokens: usize, max_new_tokens: usize) -> Self {
+        Self {
+            context_length,
+            reserved: frame_tokens + max_new_tokens + SPECIAL_TOKEN_MARGIN,
+        }
+    }
+
+    pub fn input_tokens(&self) -> usize {
+        self.context_length.saturating_sub(self.reserved)
+    }
+
+    /// How `input` is brought within the budget under `overflow`, one of
+    /// `PROMPT_OVERFLOW_STRATEGIES`. Fails with [`PromptTooLong`] for
+    /// `reject`, or when the rest of the prompt leaves no room to cut to.
+    pub fn plan(
+        &self,
+        input: &str,
+        overflow: &str,
+        count_tokens: &dyn Fn(&str) -> usize,
+    ) -> Result<PromptFit> {
+        let tokens = count_tokens(input);
+        let room = self.input_tokens();
+        if tokens <= room {
+            return Ok(PromptFit::Fits);
+        }
+        let too_long = PromptTooLong {
+            tokens: self.reserved + tokens,
+            context_length: self.context_length,
+        };
+        if overflow == "reject" || room < MIN_INPUT_TOKENS {
+            return Err(too_long.into());
+        }
+        warn!("{}; applying PROMPT_OVERFLOW={}", too_long, overflow);
+        Ok(match overflow {
+            "truncate_head" => PromptFit::Truncated(truncate_head(input, room, count_tokens)),
+            "summarize" => PromptFit::Summarize(self.summary_chunks(input, count_tokens)),
+            _ => PromptFit::Truncated(truncate_middle(input, room, count_tokens)),
+        })
+    }
+
+    /// The condensed pieces joined in order, losing their middle if they
+    /// still do not fit.
+    pub fn join_summaries(
+        &self,
+        summaries: &[String],
+        count_tokens: &dyn Fn(&str) -> usize,
+    ) -> String {
+        let joined = summaries
+            .iter()
+            .map(|summary| summary.trim())
+            .filter(|summary| !summary.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        truncate_middle(&joined, self.input_tokens(), count_tokens)
+    }
+
+    /// `input` split on line breaks into pieces that each fit one condense prompt.
+    fn summary_chunks(&self, input: &str, count_tokens: &dyn Fn(&str) -> usize) -> Vec<String> {
+        let frame = count_tokens(&generate_condense_prompt(""));
+        let budget = self
+            .context_length
+            .saturating_sub(frame + SUMMARY_MAX_TOKENS + SPECIAL_TOKEN_MARGIN)
+            .max(MIN_INPUT_TOKENS);
+        let input = truncate_middle(input, budget * MAX_SUMMARY_CHUNKS, count_tokens);
+
+        let mut chunks = Vec::new();
+        let mut current = String::new();
+        let mut used = 0;
+        for line in input.lines() {
+            let mut line = line.to_string();
+            let mut cost = count_tokens(&line);
+            if cost > budget {
+                line = truncate_middle(&line, budget, count_tokens);
+                cost = count_tokens(&line);
+            }
+            // One more for the line break.
+            cost += 1;
+            if used + cost > budget && !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+                used = 0;
+            }
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(&line);
+            used += cost;
+        }
+        if !current.is_empty() {
+            chunks.push(current);
+        }
+        chunks
+    }
+}
+
+/// Prompt and sampling that condense one piece of an input for `summarize`.
+pub fn condense_request(chunk: &str, seed: Option<u64>) -> (String, GenerationParams) {
+    let params = GenerationParams {
+        seed,
+        ..GenerationParams::new(0.2, SUMMARY_MAX_TOKENS)
+    };
+    (generate_condense_prompt(chunk), params)
+}
+
+/// The end of `text` within `budget` tokens, starting on a whole line where
+/// that loses little.
+pub fn truncate_head(text: &str, budget: usize, count_tokens: &dyn Fn(&str) -> usize) -> String {
+    shrink(text, budget, count_tokens, |text, keep| {
+        let kept = &text[byte_offset(text, text.chars().count() - keep)..];
+        let kept = match kept.find('\n') {
+            Some(index) if index < kept.len() / 2 => &kept[index + 1..],
+            _ => kept,
+        };
+        format!("{}{}", HEAD_OMITTED, kept)
+    })
+}
+
+/// The start and end of `text` within `budget` tokens, with a note of what
+/// was left out between them. Cuts fall on line breaks where that loses
+/// little, so log lines stay whole.
+pub fn truncate_middle(text: &str, budget: usize, count_tokens: &dyn Fn(&str) -> usize) -> String {
+    shrink(text, budget, count_tokens, |text, keep| {
+        let chars = text.chars().count();
+        let head = &text[..byte_offset(text, keep / 2)];
+        let head = match head.rfind('\n') {
+            Some(index) if index >= head.len() / 2 => &head[..index],
+            _ => head,
+        };
+        let tail = &text[byte_offset(text, chars - (keep - keep / 2))..];
+        let tail = match tail.find('\n') {
+            Some(index) if index < tail.len() / 2 => &tail[index + 1..],
+            _ => tail,
+        };
+        let omitted = &text[head.len()..text.len() - tail.len()];
+        let note = match omitted.matches('\n').count() {
+            lines if lines > 1 => format!("{} lines", lines - 1),
+            _ => format!("{} characters", omitted.chars().count()),
+        };
+        format!(
+            "{}\n[... {} omitted to fit the context window ...]\n{}",
+            head, note, tail
+        )
+    })
+}
+
+/// Cuts `text` with `cut`, which keeps about the given number of
+/// characters, until it fits in `budget` tokens.
+fn shrink(
+    text: &str,
+    budget: usize,
+    count_tokens: &dyn Fn(&str) -> usize,
+    cut: impl Fn(&str, usize) -> String,
+) -> String {
+    let mut tokens = count_tokens(text);
+    if tokens <= budget {
+        return text.to_string();
+    }
+    let mut keep = text.chars().count();
+    loop {
+        // Scale by the characters per token seen so far, a little under so
+        // it settles in a few rounds.
+        keep = (keep * budget / tokens.max(1) * 9 / 10).min(keep.saturating_sub(1));
+        let cut_text = cut(text, keep);
+        tokens = count_tokens(&cut_text);
+        if tokens <= budget || keep == 0 {
+            return cut_text;
+        }
+    }
+}
+
+/// Byte offset of the `chars`-th character of `text`.
+fn byte_offset(text: &str, chars: usize) -> usize {
+    text.char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(index, _)| index)
+}
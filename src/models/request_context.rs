@@ -0,0 +1,73 @@
+use tokio::time::{Duration, Instant};
+
+/// Values resolved once per request by
+/// [`RequestContextMiddleware`](crate::middleware::RequestContextMiddleware)
+/// and passed down to services, instead of each handler re-reading headers.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// From a well-formed `X-Request-Id`, otherwise generated. `None` for work
+    /// not done on behalf of a caller, such as refreshing a stale cache entry.
+    pub request_id: Option<String>,
+    /// Short fingerprint of the API key the caller presented, never the key.
+    pub api_key_id: Option<String>,
+    /// From `X-Tenant-Id`.
+    pub tenant: Option<String>,
+    /// First language of `Accept-Language`, e.g. `pt-BR`.
+    pub locale: Option<String>,
+    pub flags: RequestFlags,
+    /// When the caller stops waiting, from `X-Request-Timeout-Ms`.
+    pub deadline: Option<Instant>,
+    /// Span the request is handled in; detached work is instrumented with it.
+    pub span: tracing::Span,
+}
+
+/// Restrictions decided for the request after it arrived.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RequestFlags {
+    /// Never route to the cloud model; set for trial callers.
+    pub local_only: bool,
+}
+
+impl Default for RequestContext {
+    fn default() -> Self {
+        Self {
+            request_id: None,
+            api_key_id: None,
+            tenant: None,
+            locale: None,
+            flags: RequestFlags::default(),
+            deadline: None,
+            span: tracing::Span::none(),
+        }
+    }
+}
+
+impl RequestContext {
+    /// A context for work that outlives the request, such as refreshing a
+    /// stale cache entry: same caller and flags, but no request id to
+    /// attribute it to and no deadline.
+    pub fn detached(&self) -> Self {
+        Self {
+            request_id: None,
+            deadline: None,
+            ..self.clone()
+        }
+    }
+
+    /// Time left before the deadline; zero once it has passed.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Namespaces a client-chosen key, such as an `Idempotency-Key`, by the
+    /// caller, so two callers picking the same key never see each other's data.
+    pub fn caller_scoped(&self, key: &str) -> String {
+        format!(
+            "{}:{}:{}",
+            self.tenant.as_deref().unwrap_or("-"),
+            self.api_key_id.as_deref().unwrap_or("-"),
+            key
+        )
+    }
+}
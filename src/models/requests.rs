@@ -0,0 +1,538 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, OnceLock};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::models::{OllamaChatRequest, ToolDefinition, ToolResult};
+use crate::utils::{language_name, OutputGrammar};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamTransport {
+    /// Chunks are written to the open HTTP response (NDJSON or SSE).
+    #[default]
+    Chunked,
+    /// The response is buffered and read via `GET /api/chat/stream/{token}`.
+    Longpoll,
+}
+
+/// Shape the answer must take, enforced while sampling: `{"regex": "yes|no"}`
+/// or `{"grammar": "root ::= ..."}` with a non-recursive GBNF-style grammar.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputConstraint {
+    Regex(String),
+    Grammar(String),
+}
+
+/// Wire format of a chunked chat stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamFormat {
+    /// Native chunks, one JSON object per line.
+    Ndjson,
+    /// Native chunks as server-sent events.
+    Sse,
+    /// OpenAI `chat.completion.chunk` server-sent events.
+    Openai,
+}
+
+/// Routing tier for a chat request: local model, local model with search
+/// context, or the cloud model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Complexity {
+    Low,
+    Medium,
+    High,
+}
+
+impl Complexity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Complexity::Low => "low",
+            Complexity::Medium => "medium",
+            Complexity::High => "high",
+        }
+    }
+}
+
+/// Caller-selected generation path; `auto` keeps the complexity heuristic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoutingHint {
+    Local,
+    Enriched,
+    Cloud,
+    Auto,
+}
+
+/// Whether answers are enriched with web search results; `auto` searches for
+/// Medium and High complexity requests only.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    Off,
+    #[default]
+    Auto,
+    Force,
+}
+
+impl RoutingHint {
+    /// The tier this hint pins, or `None` for `auto`.
+    pub fn complexity(&self) -> Option<Complexity> {
+        match self {
+            RoutingHint::Local => Some(Complexity::Low),
+            RoutingHint::Enriched => Some(Complexity::Medium),
+            RoutingHint::Cloud => Some(Complexity::High),
+            RoutingHint::Auto => None,
+        }
+    }
+}
+
+/// Path that actually produced an answer, after fallbacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Route {
+    Local,
+    Enriched,
+    Cloud,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "compile_constraint"))]
+pub struct ChatRequest {
+    #[validate(length(min = 1, max = 32000))]
+    pub message: String,
+    pub conversation_id: Option<Uuid>,
+    pub model: Option<String>,
+    /// A `LORA_ADAPTERS` name; the default local model answers with that
+    /// adapter merged in.
+    #[validate(length(max = 64))]
+    pub adapter: Option<String>,
+    #[validate(length(max = 8000))]
+    pub system_prompt: Option<String>,
+    /// Named prompt template; ignored when `system_prompt` is set.
+    #[validate(length(max = 64))]
+    pub template: Option<String>,
+    /// Language to answer in (BCP 47 tag, ISO 639-3 code or English name);
+    /// detected from the message when unset.
+    #[validate(custom = "validate_language")]
+    pub language: Option<String>,
+    #[validate(range(min = 0.0, max = 2.0))]
+    pub temperature: Option<f32>,
+    #[validate(range(min = 1, max = 8192))]
+    pub max_tokens: Option<usize>,
+    /// Generation stops before the first occurrence of any of these strings.
+    #[validate(length(max = 4))]
+    pub stop: Option<Vec<String>>,
+    #[validate(range(min = 1, max = 1000))]
+    pub top_k: Option<usize>,
+    #[validate(range(min = 0.1, max = 2.0))]
+    pub repetition_penalty: Option<f32>,
+    #[validate(range(min = -2.0, max = 2.0))]
+    pub frequency_penalty: Option<f32>,
+    #[validate(range(min = -2.0, max = 2.0))]
+    pub presence_penalty: Option<f32>,
+    pub cache_bypass: Option<bool>,
+    /// How long this answer stays cached; 0 keeps it out of the cache.
+    #[validate(range(max = 2592000))]
+    pub cache_ttl_seconds: Option<u64>,
+    pub stream: Option<bool>,
+    pub strict_grounding: Option<bool>,
+    pub stream_transport: Option<StreamTransport>,
+    /// Chunked stream format; negotiated from `Accept` when unset. Setting it implies `stream`.
+    pub stream_format: Option<StreamFormat>,
+    pub execute_code: Option<bool>,
+    /// Fixes the sampler seed and skips the cache so repeated runs match.
+    pub deterministic: Option<bool>,
+    /// Skips complexity analysis and routes to this tier (for experiments).
+    pub force_complexity: Option<Complexity>,
+    /// Forces the local, enriched or cloud path; `force_complexity` takes precedence.
+    pub routing: Option<RoutingHint>,
+    /// Overrides whether web search results are added to the prompt.
+    pub search: Option<SearchMode>,
+    /// Functions the model may call instead of answering directly.
+    #[validate(length(max = 32))]
+    pub tools: Option<Vec<ToolDefinition>>,
+    /// Passed through to providers with native tool calling (`"auto"`, `"none"`, ...).
+    pub tool_choice: Option<serde_json::Value>,
+    /// Outputs of tool calls returned by a previous response.
+    pub tool_results: Option<Vec<ToolResult>>,
+    /// Images to describe or analyze; routes the request to the vision model.
+    #[validate(custom = "validate_images")]
+    pub images: Option<Vec<ImageAttachment>>,
+    /// Overrides `OPENROUTER_REDACT_PII`; `false` sends the request to the cloud verbatim.
+    pub redact_pii: Option<bool>,
+    /// Restricts the answer to a regex or grammar; pins the request to the local model.
+    #[validate(custom = "validate_constraint")]
+    pub constraint: Option<OutputConstraint>,
+    /// `constraint` compiled during validation, reused by generation.
+    #[serde(skip)]
+    pub(crate) compiled_constraint: OnceLock<Arc<OutputGrammar>>,
+}
+
+pub const MAX_IMAGES: usize = 4;
+/// Largest decoded image accepted.
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+pub const IMAGE_MEDIA_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
+/// Longest regex or grammar accepted as an output constraint.
+pub const MAX_CONSTRAINT_LEN: usize = 4096;
+
+/// An image sent with a chat message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageAttachment {
+    /// One of [`IMAGE_MEDIA_TYPES`].
+    pub media_type: String,
+    /// Base64-encoded image bytes, without a `data:` prefix.
+    pub data: String,
+}
+
+impl ImageAttachment {
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+fn validate_images(images: &[ImageAttachment]) -> Result<(), ValidationError> {
+    if images.len() > MAX_IMAGES {
+        return Err(ValidationError::new("too_many_images"));
+    }
+    for image in images {
+        if !IMAGE_MEDIA_TYPES.contains(&image.media_type.as_str()) {
+            return Err(ValidationError::new("unsupported_image_type"));
+        }
+        match STANDARD.decode(image.data.as_bytes()) {
+            Ok(bytes) if bytes.is_empty() => return Err(ValidationError::new("empty_image")),
+            Ok(bytes) if bytes.len() > MAX_IMAGE_BYTES => {
+                return Err(ValidationError::new("image_too_large"))
+            }
+            Ok(_) => {}
+            Err(_) => return Err(ValidationError::new("invalid_image_base64")),
+        }
+    }
+    Ok(())
+}
+
+fn validate_constraint(constraint: &OutputConstraint) -> Result<(), ValidationError> {
+    let source = match constraint {
+        OutputConstraint::Regex(source) | OutputConstraint::Grammar(source) => source,
+    };
+    if source.trim().is_empty() || source.len() > MAX_CONSTRAINT_LEN {
+        return Err(ValidationError::new("constraint_length"));
+    }
+    Ok(())
+}
+
+fn compile_constraint(req: &ChatRequest) -> Result<(), ValidationError> {
+    match req.output_grammar() {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let mut error = ValidationError::new("invalid_constraint");
+            error.message = Some(format!("{:#}", e).into());
+            Err(error)
+        }
+    }
+}
+
+fn validate_language(language: &str) -> Result<(), ValidationError> {
+    match language_name(language) {
+        Some(_) => Ok(()),
+        None => Err(ValidationError::new("unknown_language")),
+    }
+}
+
+impl ChatRequest {
+    /// The tier pinned by `force_complexity` or a non-`auto` routing hint.
+    pub fn forced_complexity(&self) -> Option<Complexity> {
+        self.force_complexity
+            .or_else(|| self.routing.and_then(|hint| hint.complexity()))
+    }
+
+    /// Only the cloud path can read images.
+    pub fn has_images(&self) -> bool {
+        self.images
+            .as_ref()
+            .is_some_and(|images| !images.is_empty())
+    }
+
+    /// The compiled `constraint`, built on first use and shared by clones of
+    /// this request.
+    pub fn output_grammar(&self) -> anyhow::Result<Option<Arc<OutputGrammar>>> {
+        let Some(constraint) = &self.constraint else {
+            return Ok(None);
+        };
+        if let Some(grammar) = self.compiled_constraint.get() {
+            return Ok(Some(grammar.clone()));
+        }
+        let grammar = Arc::new(OutputGrammar::compile(constraint)?);
+        Ok(Some(
+            self.compiled_constraint.get_or_init(|| grammar).clone(),
+        ))
+    }
+}
+
+/// Overrides for `POST /api/chat/{conversation_id}/regenerate`; all optional.
+#[derive(Debug, Clone, Default, Deserialize, Validate)]
+pub struct RegenerateRequest {
+    pub model: Option<String>,
+    #[validate(range(min = 0.0, max = 2.0))]
+    pub temperature: Option<f32>,
+    #[validate(range(min = 1, max = 8192))]
+    pub max_tokens: Option<usize>,
+    pub routing: Option<RoutingHint>,
+}
+
+/// Body of `POST /api/chat/continue`.
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct ContinueRequest {
+    pub conversation_id: Uuid,
+    #[validate(range(min = 1, max = 8192))]
+    pub max_tokens: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StreamPollQuery {
+    #[serde(default)]
+    pub offset: usize,
+    pub wait_ms: Option<u64>,
+}
+
+/// Body accepted by `POST /api/chat`: the native request or an Ollama chat
+/// request. Native is tried first; an Ollama body has no `message`, so it
+/// falls through to the second variant.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ChatPayload {
+    Native(ChatRequest),
+    Ollama(OllamaChatRequest),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct LogAnalysisRequest {
+    #[validate(length(min = 1, max = 500000))]
+    pub logs: String,
+    pub context: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    Linux,
+    Windows,
+    MacOS,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptLanguage {
+    Bash,
+    Python,
+    Powershell,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ScriptGenerationRequest {
+    #[validate(length(min = 1, max = 4000))]
+    pub requirement: String,
+    pub environment: Environment,
+    pub language: ScriptLanguage,
+    /// BCP 47 tag (e.g. `es`, `pt-BR`) for comments and the explanation; code is not translated.
+    #[validate(length(max = 35))]
+    pub locale: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedbackRating {
+    Positive,
+    Negative,
+}
+
+impl FeedbackRating {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedbackRating::Positive => "positive",
+            FeedbackRating::Negative => "negative",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackRequest {
+    pub rating: FeedbackRating,
+}
+
+/// Rating of one answer, identified by the `response_hash` returned with it.
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct FeedbackSubmission {
+    pub conversation_id: Uuid,
+    #[validate(length(min = 1, max = 64))]
+    pub response_hash: String,
+    pub rating: FeedbackRating,
+    #[validate(length(max = 2000))]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeedbackStatsQuery {
+    /// Look-back window; defaults to one week.
+    pub since_hours: Option<i64>,
+    /// Number of worst-rated answers to list; defaults to 10.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactionMode {
+    /// Replace the selected messages' content with a placeholder.
+    #[default]
+    Mask,
+    /// Delete the selected messages.
+    Remove,
+}
+
+impl RedactionMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RedactionMode::Mask => "mask",
+            RedactionMode::Remove => "remove",
+        }
+    }
+}
+
+/// Messages `start..end` by position in the conversation, zero-based.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MessageRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Validate)]
+pub struct RedactionRequest {
+    /// Applied according to `mode`.
+    #[serde(default)]
+    #[validate(length(max = 100))]
+    pub ranges: Vec<MessageRange>,
+    /// Regexes whose matches are masked in every remaining message.
+    #[serde(default)]
+    #[validate(length(max = 20))]
+    pub patterns: Vec<String>,
+    /// Also mask the built-in credential and PII patterns.
+    #[serde(default)]
+    pub builtin_patterns: bool,
+    #[serde(default)]
+    pub mode: RedactionMode,
+    #[validate(length(max = 500))]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RoutingReportQuery {
+    /// Look-back window; defaults to one week.
+    pub since_hours: Option<i64>,
+}
+
+/// Query for `GET /api/models`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelListQuery {
+    /// Also list OpenRouter's models, from a catalog cached for an hour.
+    #[serde(default)]
+    pub include_cloud: bool,
+}
+
+/// `input` of `POST /api/embeddings`: one text or several.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl EmbeddingInput {
+    pub fn into_texts(self) -> Vec<String> {
+        match self {
+            Self::One(text) => vec![text],
+            Self::Many(texts) => texts,
+        }
+    }
+}
+
+/// Body of `POST /api/embeddings`, in OpenAI's shape.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddingRequest {
+    pub input: EmbeddingInput,
+    /// Must name the configured `EMBEDDING_MODEL` when set.
+    pub model: Option<String>,
+    /// `float` (default) or `base64` of little-endian `f32`s.
+    pub encoding_format: Option<String>,
+}
+
+/// Body of `POST /api/tokenize`.
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct TokenizeRequest {
+    #[validate(length(max = 500000))]
+    pub text: String,
+    /// A `LOCAL_MODELS` name; the default local model when unset.
+    pub model: Option<String>,
+    /// Also return the token ids.
+    #[serde(default)]
+    pub include_ids: bool,
+}
+
+/// Body of `POST /api/admin/models/switch`.
+#[derive(Debug, Clone, Deserialize, Validate)]
+pub struct ModelSwitchRequest {
+    /// Hugging Face model id, also used to find or download the weights.
+    #[validate(length(min = 1, max = 200))]
+    pub model_name: String,
+    /// Local weights directory; the Hugging Face cache is used when unset.
+    #[validate(length(max = 4096))]
+    pub model_path: Option<String>,
+}
+
+/// Body of `POST /api/admin/model/reload`; empty reloads the serving model.
+#[derive(Debug, Clone, Default, Deserialize, Validate)]
+pub struct ModelReloadRequest {
+    /// Hugging Face model id; the serving model when unset.
+    #[validate(length(min = 1, max = 200))]
+    pub model_name: Option<String>,
+    /// Local weights directory; the Hugging Face cache is used when unset.
+    #[validate(length(max = 4096))]
+    pub model_path: Option<String>,
+}
+
+/// Query for `GET /api/admin/cache/export`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheExportQuery {
+    /// Only entries carrying this tag, e.g. `model:<name>`.
+    pub tag: Option<String>,
+    /// Stop after this many entries; unlimited when absent.
+    pub limit: Option<usize>,
+}
+
+/// Query for `GET /api/admin/cache/stats/history`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheStatsHistoryQuery {
+    /// Look-back window; defaults to one day.
+    pub since_hours: Option<i64>,
+    /// Bucket width; defaults to one hour.
+    pub bucket_minutes: Option<i64>,
+}
+
+/// Query for `GET /api/admin/topics`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TopicReportQuery {
+    /// Look-back window; defaults to one week.
+    pub since_hours: Option<i64>,
+}
+
+/// Query for `GET /api/admin/models/compare`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelCompareQuery {
+    pub a: String,
+    pub b: String,
+    /// Look-back window; defaults to one week.
+    pub since_hours: Option<i64>,
+}
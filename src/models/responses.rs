@@ -0,0 +1,768 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use crate::models::{Complexity, Route, SearchMode, ToolCall};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatResponse {
+    pub response: String,
+    pub conversation_id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub cache_hit: bool,
+    #[serde(default)]
+    pub cache_source: Option<String>,
+    /// True when a cached answer past its TTL was served while a fresh one is
+    /// generated in the background.
+    #[serde(default)]
+    pub cache_stale: bool,
+    /// Set when the answer was cached for a similar earlier question rather
+    /// than this one: how similar the two questions scored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_similarity: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grounding: Option<GroundingReport>,
+    /// Search results that looked like prompt injection, when any did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub injection: Option<InjectionReport>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub code_executions: Vec<CodeExecution>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub complexity: Option<Complexity>,
+    /// How `complexity` was scored; absent when the tier was forced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub complexity_score: Option<ComplexityScore>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route: Option<Route>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Identifies this answer text when submitting feedback.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_hash: Option<String>,
+    /// Model that generated the answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Provider cost of generating the answer; recorded for reports, never returned.
+    #[serde(skip)]
+    pub cost_usd: Option<f64>,
+    /// True when generation stopped early and `response` holds only the output so far.
+    #[serde(default)]
+    pub partial: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_reason: Option<String>,
+    /// Search mode applied to the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_mode: Option<SearchMode>,
+    /// True when web search ran for this answer.
+    #[serde(default)]
+    pub searched: bool,
+    /// True when the answer was generated for an identical request that was
+    /// already in flight, rather than for this one.
+    #[serde(default)]
+    pub coalesced: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// False for degraded answers that must never be cached: a fallback from
+    /// the requested route, a failed search or a placeholder reply.
+    #[serde(skip)]
+    pub cacheable: bool,
+}
+
+impl ChatResponse {
+    pub fn new(response: String, conversation_id: Uuid) -> Self {
+        Self {
+            response,
+            conversation_id,
+            timestamp: Utc::now(),
+            cache_hit: false,
+            cache_source: None,
+            cache_stale: false,
+            cache_similarity: None,
+            grounding: None,
+            injection: None,
+            code_executions: Vec::new(),
+            complexity: None,
+            complexity_score: None,
+            route: None,
+            tool_calls: Vec::new(),
+            request_id: None,
+            response_hash: None,
+            model: None,
+            cost_usd: None,
+            partial: false,
+            partial_reason: None,
+            search_mode: None,
+            searched: false,
+            coalesced: false,
+            provenance: None,
+            cacheable: true,
+        }
+    }
+}
+
+/// How the router scored a chat message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComplexityScore {
+    /// Length in characters plus the weight of each signal found; compared
+    /// with the thresholds.
+    pub score: usize,
+    /// Share of `score` by signal: `length`, `stack_trace`, `code_blocks`,
+    /// `questions` and `language`.
+    pub signals: BTreeMap<String, usize>,
+    pub medium_threshold: usize,
+    pub high_threshold: usize,
+    /// Detected language of the message, when not English.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Probability that the message needs a stronger tier, from the
+    /// configured classifier; when set it decides the tier instead of `score`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub classifier_probability: Option<f32>,
+}
+
+/// How an answer was produced, for auditing a wrong answer after the fact.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Provenance {
+    pub route: Option<Route>,
+    pub model: Option<String>,
+    pub prompt_version: String,
+    /// Knowledge-base snapshot the deployment was serving.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub knowledge_base_snapshot: Option<String>,
+    /// Tier the answer was served from; `None` when it was generated for this request.
+    #[serde(default)]
+    pub cache_tier: Option<String>,
+    #[serde(default)]
+    pub search_providers: Vec<String>,
+    /// Retrieved documents given to the model, by URL.
+    #[serde(default)]
+    pub document_ids: Vec<String>,
+}
+
+/// Per-sentence support of an answer by the retrieved sources.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroundingReport {
+    pub supported_ratio: f32,
+    pub strict: bool,
+    pub sentences: Vec<GroundingSentence>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroundingSentence {
+    pub text: String,
+    pub score: f32,
+    pub supported: bool,
+    pub source_url: Option<String>,
+}
+
+/// Search results screened out of, or marked in, an enriched prompt because
+/// they read like instructions to the model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InjectionReport {
+    /// `strip` when the results were left out of the prompt, `flag` when they
+    /// were kept and marked as untrusted.
+    pub action: String,
+    pub sources: Vec<SuspiciousSource>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SuspiciousSource {
+    pub title: String,
+    pub url: String,
+    /// Heuristics that matched, such as `instruction_override` or `role_marker`.
+    pub signals: Vec<String>,
+    /// Probability of injection from the configured classifier.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub classifier_probability: Option<f32>,
+}
+
+/// Result of running a snippet from the answer in the sandbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeExecution {
+    pub language: String,
+    pub code: String,
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    pub duration_ms: u64,
+}
+
+/// A configured cache tier and whether it is currently serving.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheTierStatus {
+    pub name: String,
+    /// False while an operator has taken the tier out of service.
+    pub enabled: bool,
+    /// New answers are stored in the tier (unless it is disabled).
+    pub write: bool,
+    /// The backing store was reachable at startup; always true for memory.
+    pub connected: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheStatsResponse {
+    pub total_requests: u64,
+    pub memory_hits: u64,
+    pub redis_hits: u64,
+    /// Hits on the durable tier, SQLite or Postgres.
+    pub durable_hits: u64,
+    /// Expired entries served under `CACHE_STALE_WHILE_REVALIDATE_SECONDS`.
+    pub stale_hits: u64,
+    pub pending_writes: usize,
+    pub dropped_writes: u64,
+    /// Configured tiers in lookup order.
+    pub tiers: Vec<CacheTierStatus>,
+}
+
+/// `GET /api/admin/cache/stats/history`: recorded cache lookups and hits over time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheStatsHistory {
+    pub since: DateTime<Utc>,
+    pub bucket_seconds: i64,
+    /// Oldest first; periods without lookups or recorded snapshots are omitted.
+    pub buckets: Vec<CacheStatsPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheStatsPoint {
+    pub start: DateTime<Utc>,
+    pub requests: u64,
+    pub hits: u64,
+    /// Share of lookups answered by any tier.
+    pub hit_rate: f64,
+    pub stale_hits: u64,
+    pub dropped_writes: u64,
+    /// In lookup order: `memory`, `redis`, `durable`.
+    pub tiers: Vec<CacheTierHits>,
+}
+
+/// Lookups one tier answered; `hit_rate` is their share of all lookups, so
+/// the tiers' rates add up to the overall rate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheTierHits {
+    pub tier: String,
+    pub hits: u64,
+    pub hit_rate: f64,
+}
+
+/// The cache-key version of a prompt template; `default` covers requests
+/// without a template.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateVersion {
+    pub template: String,
+    pub version: u64,
+}
+
+/// One line of a cache export, and of the matching import.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheExportRecord {
+    pub key: String,
+    /// The cached response, decompressed.
+    pub value: serde_json::Value,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    #[serde(default)]
+    pub hits: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheImportResponse {
+    pub imported: u64,
+    /// Entries whose `expires_at` had already passed.
+    pub expired: u64,
+    pub failed: u64,
+    /// The first few failures, with their line numbers.
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheFlushResponse {
+    /// Entries dropped from this instance's memory tier; other instances
+    /// clear theirs when the flush reaches them over Redis.
+    pub memory_removed: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheInvalidationResponse {
+    pub tag: String,
+    pub memory_removed: u64,
+    pub redis_removed: u64,
+    pub sqlite_removed: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingReport {
+    pub since: DateTime<Utc>,
+    pub medium_threshold: usize,
+    pub high_threshold: usize,
+    pub tiers: Vec<RoutingTierStats>,
+}
+
+/// `GET /api/admin/topics`: what labeled conversations active in the window were about.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopicReport {
+    pub since: DateTime<Utc>,
+    pub conversations: u64,
+    pub topics: Vec<TopicStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopicStats {
+    pub topic: String,
+    pub conversations: u64,
+    /// Share of the window's labeled conversations.
+    pub share: f64,
+    pub escalated: u64,
+    pub escalation_rate: f64,
+}
+
+/// Outcomes for answers routed to one tier; `forced` separates `force_complexity`/`routing` overrides.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingTierStats {
+    pub complexity: String,
+    pub forced: bool,
+    pub total: u64,
+    pub negative_feedback: u64,
+    pub escalated: u64,
+    pub negative_feedback_rate: f64,
+    pub escalation_rate: f64,
+    pub avg_message_chars: f64,
+}
+
+/// Side-by-side outcomes of two models over the same window.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelComparison {
+    pub since: DateTime<Utc>,
+    pub a: ModelOutcomeStats,
+    pub b: ModelOutcomeStats,
+}
+
+/// Generated (non-cached) answers of one model. Latency covers the whole
+/// generation path, including search for enriched and cloud answers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelOutcomeStats {
+    pub model: String,
+    pub answers: u64,
+    pub latency_p50_ms: Option<u64>,
+    pub latency_p90_ms: Option<u64>,
+    pub latency_p99_ms: Option<u64>,
+    pub positive_ratings: u64,
+    pub negative_ratings: u64,
+    /// Share of positive per-answer ratings; `None` without ratings.
+    pub feedback_score: Option<f64>,
+    pub negative_feedback_rate: f64,
+    pub escalation_rate: f64,
+    pub total_cost_usd: f64,
+    /// Average over answers with a known cost; `None` for local models.
+    pub cost_per_answer_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackReceipt {
+    pub response_hash: String,
+    pub negative_feedback: u64,
+    /// Whether this submission evicted the answer from the cache.
+    pub evicted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedbackStats {
+    pub since: DateTime<Utc>,
+    pub total: u64,
+    pub positive: u64,
+    pub negative: u64,
+    pub with_comment: u64,
+    pub negative_rate: f64,
+    /// Answers with the most negative ratings in the window, worst first.
+    pub most_negative: Vec<ResponseFeedbackStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseFeedbackStats {
+    pub response_hash: String,
+    pub positive: u64,
+    pub negative: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderCapture {
+    pub provider: String,
+    pub endpoint: String,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    pub request: serde_json::Value,
+    pub response: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderCaptureResponse {
+    pub request_id: String,
+    pub captures: Vec<ProviderCapture>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionResponse {
+    pub conversation_id: Uuid,
+    pub messages_removed: usize,
+    pub messages_masked: usize,
+    /// Messages in which a pattern matched.
+    pub messages_scrubbed: usize,
+    pub cache_entries_removed: u64,
+    pub redacted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamTicket {
+    pub token: String,
+    pub poll_url: String,
+    pub conversation_id: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamPollResponse {
+    pub token: String,
+    pub chunks: Vec<String>,
+    pub next_offset: usize,
+    pub done: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogAnalysisResponse {
+    pub analysis: String,
+    pub issues: Vec<String>,
+    pub recommendations: Vec<String>,
+    pub severity: String,
+    pub confidence: f32,
+    pub timestamp: DateTime<Utc>,
+    /// Content hash of the uploaded logs; identical uploads share it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_hash: Option<String>,
+    /// True when the parsed logs were reused from an earlier upload.
+    #[serde(default)]
+    pub digest_reused: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<LogAnalysisTimings>,
+    /// True when generation stopped early and `analysis` is incomplete.
+    #[serde(default)]
+    pub partial: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_reason: Option<String>,
+    /// True when served from the log-analysis cache.
+    #[serde(default)]
+    pub cache_hit: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogAnalysisTimings {
+    /// Parsing, or the store lookup when the digest was reused.
+    pub parse_ms: u64,
+    pub inference_ms: u64,
+    pub total_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptResponse {
+    pub script: String,
+    pub language: String,
+    pub environment: String,
+    pub explanation: String,
+    pub safety_warnings: Vec<String>,
+    pub locale: String,
+    pub timestamp: DateTime<Utc>,
+    /// True when served from the script cache.
+    #[serde(default)]
+    pub cache_hit: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthResponse {
+    pub status: String,
+    pub model_loaded: bool,
+    /// Device local generations run on: `cpu`, `cuda:N` or `metal:N`, or
+    /// `remote` when `LOCAL_BACKEND` names a model server.
+    pub device: String,
+    pub uptime_seconds: u64,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cloud_spend: Option<CloudSpendStatus>,
+    /// Requests waiting for a generation slot.
+    #[serde(default)]
+    pub queued_generations: usize,
+}
+
+/// Endpoint groups mounted on this instance, from `GET /api/capabilities`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapabilitiesResponse {
+    pub endpoint_groups: Vec<EndpointGroup>,
+    /// Groups switched off with `DISABLED_ENDPOINT_GROUPS`.
+    pub disabled_endpoint_groups: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointGroup {
+    pub name: String,
+    pub routes: Vec<String>,
+}
+
+/// Lifecycle of a supervised background task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Running,
+    /// Panicked and waiting out its backoff before the next start.
+    Restarting,
+    Finished,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundTaskStatus {
+    pub name: String,
+    pub state: TaskState,
+    /// When the current (or last) run started.
+    pub started_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    pub restarts: u32,
+}
+
+/// A local model a chat request can select, as listed by `GET /api/models`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalModelInfo {
+    /// Value for `model` in a chat request; unset for the default model,
+    /// which answers when `model` is omitted.
+    pub name: Option<String>,
+    /// Hugging Face model id.
+    pub model_name: String,
+    pub default: bool,
+    /// Size of the weight files; unset while they are not on disk.
+    pub size_bytes: Option<u64>,
+    pub quantization: String,
+    pub loaded: bool,
+}
+
+/// A model from OpenRouter's catalog, usable as `model` on the cloud path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloudModelInfo {
+    pub id: String,
+    pub name: String,
+    pub context_length: Option<u64>,
+    pub prompt_price_per_mtok: Option<f64>,
+    pub completion_price_per_mtok: Option<f64>,
+}
+
+/// `POST /api/embeddings`, in OpenAI's shape.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingResponse {
+    /// Always `list`.
+    pub object: String,
+    pub data: Vec<EmbeddingData>,
+    pub model: String,
+    pub usage: EmbeddingUsage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingData {
+    /// Always `embedding`.
+    pub object: String,
+    /// Position of the text in the request's `input`.
+    pub index: usize,
+    pub embedding: EmbeddingVector,
+}
+
+/// Floats, or base64 when requested with `encoding_format: "base64"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EmbeddingVector {
+    Float(Vec<f32>),
+    Base64(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingUsage {
+    pub prompt_tokens: usize,
+    pub total_tokens: usize,
+}
+
+/// `POST /api/tokenize`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenizeResponse {
+    pub model: String,
+    pub token_count: usize,
+    /// The model is not loaded yet, so the count assumes ~4 characters per token.
+    pub estimated: bool,
+    pub context_length: usize,
+    /// Whether the text alone fits within `context_length`.
+    pub fits: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ids: Option<Vec<u32>>,
+}
+
+/// `GET /api/models`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelListResponse {
+    pub local: Vec<LocalModelInfo>,
+    /// Used on the cloud path when a request names no model.
+    pub default_cloud_model: Option<String>,
+    /// Present when requested with `include_cloud=true` and the catalog could be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cloud: Option<Vec<CloudModelInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cloud_error: Option<String>,
+}
+
+/// A weight download in progress, from `GET /api/admin/model/status`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelDownloadStatus {
+    pub model_name: String,
+    pub downloaded_bytes: u64,
+    pub total_bytes: u64,
+    /// Weight files in the download, and how many are complete and verified.
+    pub files: usize,
+    pub files_done: usize,
+    /// Average rate since the download started.
+    pub bytes_per_sec: u64,
+    /// Unset until the first bytes arrive.
+    pub eta_seconds: Option<u64>,
+}
+
+/// `GET /api/admin/model/status`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelStatusResponse {
+    pub model_name: String,
+    pub model_loaded: bool,
+    pub model_switch_in_progress: bool,
+    /// Weight downloads in progress, including standby and embedding models.
+    pub downloads: Vec<ModelDownloadStatus>,
+}
+
+/// `POST /api/admin/models/switch` and `/api/admin/model/reload`; the new
+/// model is loaded in the background.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelSwitchResponse {
+    pub current_model: String,
+    pub requested_model: String,
+}
+
+/// A generation that is currently running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InflightGeneration {
+    /// Identifier for `POST /api/admin/inflight/{id}/cancel`.
+    pub id: String,
+    pub request_id: Option<String>,
+    /// Path and model taken so far; unset until routing has picked them.
+    pub route: Option<Route>,
+    pub model: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub elapsed_ms: u64,
+    /// Time spent waiting for a generation slot before starting.
+    pub queue_wait_ms: u64,
+    /// Cancellation was requested and the generation is winding down.
+    pub cancelling: bool,
+}
+
+/// `GET /api/admin/inflight`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InflightListResponse {
+    pub generations: Vec<InflightGeneration>,
+}
+
+/// `GET /api/admin/diagnostics`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticsResponse {
+    pub uptime_seconds: u64,
+    pub model_name: String,
+    pub model_loaded: bool,
+    pub model_switch_in_progress: bool,
+    /// Sentence encoder used for semantic ranking; absent when not configured.
+    pub embedding_model: Option<String>,
+    pub embedding_model_loaded: bool,
+    pub queued_generations: usize,
+    pub inference_workers: usize,
+    /// Admitted generations waiting for a free inference worker.
+    pub inference_queued: usize,
+    pub pending_cache_writes: usize,
+    pub dropped_cache_writes: u64,
+    pub knowledge_base: KnowledgeSyncStatus,
+    pub background_tasks: Vec<BackgroundTaskStatus>,
+}
+
+/// Knowledge-base index in use and the outcome of the latest sync.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KnowledgeSyncStatus {
+    pub enabled: bool,
+    pub active_version: Option<String>,
+    pub documents: usize,
+    pub chunks: usize,
+    pub syncing: bool,
+    pub last_sync_at: Option<DateTime<Utc>>,
+    pub last_success_at: Option<DateTime<Utc>>,
+    /// Documents added, changed or removed by the last successful sync.
+    pub last_changed_documents: usize,
+    pub last_error: Option<String>,
+}
+
+/// OpenRouter spend for the current UTC day and month against the configured caps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CloudSpendStatus {
+    pub daily_spend_usd: f64,
+    pub daily_cap_usd: Option<f64>,
+    pub daily_requests: u64,
+    pub monthly_spend_usd: f64,
+    pub monthly_cap_usd: Option<f64>,
+    pub monthly_requests: u64,
+    /// High-complexity requests are served locally while a cap is reached.
+    pub capped: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub details: Option<String>,
+    /// Machine-readable reason for errors clients are expected to handle.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ErrorResponse {
+    pub fn new(error: impl Into<String>) -> Self {
+        Self {
+            error: error.into(),
+            details: None,
+            code: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    pub fn with_details(error: impl Into<String>, details: impl Into<String>) -> Self {
+        Self {
+            error: error.into(),
+            details: Some(details.into()),
+            code: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    pub fn with_code(error: impl Into<String>, code: impl Into<String>) -> Self {
+        Self {
+            code: Some(code.into()),
+            ..Self::new(error)
+        }
+    }
+}
@@ -0,0 +1,494 @@
+use anyhow::Result;
+use candle_core::{DType, Device, IndexOp, Tensor};
+use candle_nn::{
+    embedding, linear_no_bias, rms_norm, Embedding, Linear, Module, RmsNorm, VarBuilder,
+};
+use candle_transformers::models::llama::Config;
+use candle_transformers::utils::repeat_kv;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::models::ai_model::apply_penalties;
+use crate::models::GenerationParams;
+
+/// A Llama-family model run here rather than by candle's `Llama`, whose
+/// forward pass only returns the logits of the last position and whose KV
+/// cache cannot be rolled back. Speculative decoding needs both: the main
+/// model scores every drafted token in one pass, and rejected tokens are
+/// dropped from both caches.
+pub(crate) struct NativeLlama {
+    embed_tokens: Embedding,
+    blocks: Vec<Block>,
+    norm: RmsNorm,
+    lm_head: Linear,
+    cos: Tensor,
+    sin: Tensor,
+}
+
+struct Block {
+    input_layernorm: RmsNorm,
+    attention: Attention,
+    post_attention_layernorm: RmsNorm,
+    mlp: Mlp,
+}
+
+struct Attention {
+    q_proj: Linear,
+    k_proj: Linear,
+    v_proj: Linear,
+    o_proj: Linear,
+    heads: usize,
+    kv_heads: usize,
+    head_dim: usize,
+}
+
+struct Mlp {
+    gate_proj: Linear,
+    up_proj: Linear,
+    down_proj: Linear,
+}
+
+/// Keys and values of one sequence, per layer.
+pub(crate) struct KvCache {
+    layers: Vec<Option<(Tensor, Tensor)>>,
+}
+
+impl KvCache {
+    /// Positions cached.
+    fn len(&self) -> usize {
+        self.layers
+            .first()
+            .and_then(|layer| layer.as_ref())
+            .map_or(0, |(k, _)| k.dims()[2])
+    }
+
+    /// Forgets every position from `len` on.
+    fn truncate(&mut self, len: usize) -> Result<()> {
+        for (k, v) in self.layers.iter_mut().flatten() {
+            if k.dims()[2] > len {
+                *k = k.narrow(2, 0, len)?;
+                *v = v.narrow(2, 0, len)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NativeLlama {
+    pub(crate) fn load(vb: VarBuilder, config: &Config) -> Result<Self> {
+        let embed_tokens = embedding(
+            config.vocab_size,
+            config.hidden_size,
+            vb.pp("model.embed_tokens"),
+        )?;
+        let lm_head = if config.tie_word_embeddings {
+            Linear::new(embed_tokens.embeddings().clone(), None)
+        } else {
+            linear_no_bias(config.hidden_size, config.vocab_size, vb.pp("lm_head"))?
+        };
+        let norm = rms_norm(config.hidden_size, config.rms_norm_eps, vb.pp("model.norm"))?;
+        let blocks = (0..config.num_hidden_layers)
+            .map(|layer| Block::load(vb.pp(format!("model.layers.{}", layer)), config))
+            .collect::<Result<Vec<_>>>()?;
+        let head_dim = config.hidden_size / config.num_attention_heads;
+        let (cos, sin) = rotary_tables(config, head_dim, vb.dtype(), vb.device())?;
+        Ok(Self {
+            embed_tokens,
+            blocks,
+            norm,
+            lm_head,
+            cos,
+            sin,
+        })
+    }
+
+    /// Longest sequence the rotary tables cover.
+    pub(crate) fn max_positions(&self) -> usize {
+        self.cos.dims()[0]
+    }
+
+    pub(crate) fn new_cache(&self) -> KvCache {
+        KvCache {
+            layers: vec![None; self.blocks.len()],
+        }
+    }
+
+    /// Logits (`last` × vocabulary, in F32) for the last `last` positions of
+    /// `input`, which continues the sequence held in `cache`. Earlier
+    /// positions only fill the cache, so a long prompt never gets a logits row
+    /// per token.
+    pub(crate) fn forward(
+        &self,
+        input: &[u32],
+        cache: &mut KvCache,
+        last: usize,
+    ) -> Result<Tensor> {
+        let device = self.embed_tokens.embeddings().device();
+        let offset = cache.len();
+        let seq_len = input.len();
+        let cos = self.cos.narrow(0, offset, seq_len)?;
+        let sin = self.sin.narrow(0, offset, seq_len)?;
+        let mask = (seq_len > 1)
+            .then(|| causal_mask(seq_len, offset, device))
+            .transpose()?;
+
+        let mut x = self
+            .embed_tokens
+            .forward(&Tensor::new(input, device)?.unsqueeze(0)?)?;
+        for (block, kv) in self.blocks.iter().zip(cache.layers.iter_mut()) {
+            x = block.forward(&x, &cos, &sin, mask.as_ref(), kv)?;
+        }
+        let rows = last.clamp(1, seq_len);
+        let x = self.norm.forward(&x.narrow(1, seq_len - rows, rows)?)?;
+        Ok(self.lm_head.forward(&x)?.squeeze(0)?.to_dtype(DType::F32)?)
+    }
+}
+
+impl Block {
+    fn load(vb: VarBuilder, config: &Config) -> Result<Self> {
+        let hidden = config.hidden_size;
+        let head_dim = hidden / config.num_attention_heads;
+        let kv_size = config.num_key_value_heads * head_dim;
+        let attn = vb.pp("self_attn");
+        let mlp = vb.pp("mlp");
+        Ok(Self {
+            input_layernorm: rms_norm(hidden, config.rms_norm_eps, vb.pp("input_layernorm"))?,
+            attention: Attention {
+                q_proj: linear_no_bias(hidden, hidden, attn.pp("q_proj"))?,
+                k_proj: linear_no_bias(hidden, kv_size, attn.pp("k_proj"))?,
+                v_proj: linear_no_bias(hidden, kv_size, attn.pp("v_proj"))?,
+                o_proj: linear_no_bias(hidden, hidden, attn.pp("o_proj"))?,
+                heads: config.num_attention_heads,
+                kv_heads: config.num_key_value_heads,
+                head_dim,
+            },
+            post_attention_layernorm: rms_norm(
+                hidden,
+                config.rms_norm_eps,
+                vb.pp("post_attention_layernorm"),
+            )?,
+            mlp: Mlp {
+                gate_proj: linear_no_bias(hidden, config.intermediate_size, mlp.pp("gate_proj"))?,
+                up_proj: linear_no_bias(hidden, config.intermediate_size, mlp.pp("up_proj"))?,
+                down_proj: linear_no_bias(config.intermediate_size, hidden, mlp.pp("down_proj"))?,
+            },
+        })
+    }
+
+    fn forward(
+        &self,
+        x: &Tensor,
+        cos: &Tensor,
+        sin: &Tensor,
+        mask: Option<&Tensor>,
+        kv: &mut Option<(Tensor, Tensor)>,
+    ) -> Result<Tensor> {
+        let attended =
+            self.attention
+                .forward(&self.input_layernorm.forward(x)?, cos, sin, mask, kv)?;
+        let x = (attended + x)?;
+        let mixed = self
+            .mlp
+            .forward(&self.post_attention_layernorm.forward(&x)?)?;
+        Ok((mixed + x)?)
+    }
+}
+
+impl Attention {
+    fn forward(
+        &self,
+        x: &Tensor,
+        cos: &Tensor,
+        sin: &Tensor,
+        mask: Option<&Tensor>,
+        kv: &mut Option<(Tensor, Tensor)>,
+    ) -> Result<Tensor> {
+        let (batch, seq_len, _) = x.dims3()?;
+        let heads = |proj: &Linear, count: usize| -> Result<Tensor> {
+            Ok(proj
+                .forward(x)?
+                .reshape((batch, seq_len, count, self.head_dim))?
+                .transpose(1, 2)?
+                .contiguous()?)
+        };
+        let q = candle_nn::rotary_emb::rope(&heads(&self.q_proj, self.heads)?, cos, sin)?;
+        let k = candle_nn::rotary_emb::rope(&heads(&self.k_proj, self.kv_heads)?, cos, sin)?;
+        let v = heads(&self.v_proj, self.kv_heads)?;
+
+        let (k, v) = match kv.take() {
+            Some((cached_k, cached_v)) => (
+                Tensor::cat(&[&cached_k, &k], 2)?,
+                Tensor::cat(&[&cached_v, &v], 2)?,
+            ),
+            None => (k, v),
+        };
+        *kv = Some((k.clone(), v.clone()));
+
+        let n_rep = self.heads / self.kv_heads;
+        let dtype = q.dtype();
+        // Attention in F32 keeps BF16 weights from losing the softmax.
+        let q = q.to_dtype(DType::F32)?;
+        let k = repeat_kv(k, n_rep)?.to_dtype(DType::F32)?;
+        let v = repeat_kv(v, n_rep)?.to_dtype(DType::F32)?.contiguous()?;
+        let scores = (q.matmul(&k.t()?)? / (self.head_dim as f64).sqrt())?;
+        let scores = match mask {
+            Some(mask) => scores.broadcast_add(mask)?,
+            None => scores,
+        };
+        let weights = candle_nn::ops::softmax_last_dim(&scores)?;
+        let y = weights
+            .matmul(&v)?
+            .to_dtype(dtype)?
+            .transpose(1, 2)?
+            .reshape((batch, seq_len, self.heads * self.head_dim))?;
+        Ok(self.o_proj.forward(&y)?)
+    }
+}
+
+impl Mlp {
+    fn forward(&self, x: &Tensor) -> Result<Tensor> {
+        let gate = candle_nn::ops::silu(&self.gate_proj.forward(x)?)?;
+        Ok(self
+            .down_proj
+            .forward(&(gate * self.up_proj.forward(x)?)?)?)
+    }
+}
+
+/// Additive mask letting the `seq_len` new positions, which follow `offset`
+/// cached ones, attend to everything up to themselves.
+fn causal_mask(seq_len: usize, offset: usize, device: &Device) -> Result<Tensor> {
+    let total = offset + seq_len;
+    let mask: Vec<f32> = (0..seq_len)
+        .flat_map(|i| {
+            (0..total).map(move |j| {
+                if j > offset + i {
+                    f32::NEG_INFINITY
+                } else {
+                    0.0
+                }
+            })
+        })
+        .collect();
+    Ok(Tensor::from_vec(mask, (seq_len, total), device)?)
+}
+
+/// Rotary embedding tables for every position, with Llama 3's frequency
+/// scaling when the config has one.
+fn rotary_tables(
+    config: &Config,
+    head_dim: usize,
+    dtype: DType,
+    device: &Device,
+) -> Result<(Tensor, Tensor)> {
+    let mut inv_freq: Vec<f32> = (0..head_dim)
+        .step_by(2)
+        .map(|i| 1.0 / config.rope_theta.powf(i as f32 / head_dim as f32))
+        .collect();
+    if let Some(scaling) = &config.rope_scaling {
+        let original = scaling.original_max_position_embeddings as f32;
+        let low_freq_wavelen = original / scaling.low_freq_factor;
+        let high_freq_wavelen = original / scaling.high_freq_factor;
+        for freq in &mut inv_freq {
+            let wavelen = 2.0 * std::f32::consts::PI / *freq;
+            if wavelen > low_freq_wavelen {
+                *freq /= scaling.factor;
+            } else if wavelen >= high_freq_wavelen {
+                let smooth = (original / wavelen - scaling.low_freq_factor)
+                    / (scaling.high_freq_factor - scaling.low_freq_factor);
+                *freq = (1.0 - smooth) * *freq / scaling.factor + smooth * *freq;
+            }
+        }
+    }
+    let half = inv_freq.len();
+    let inv_freq = Tensor::from_vec(inv_freq, (1, half), device)?;
+    let positions = Tensor::arange(0u32, config.max_position_embeddings as u32, device)?
+        .to_dtype(DType::F32)?
+        .reshape((config.max_position_embeddings, 1))?;
+    let freqs = positions.matmul(&inv_freq)?;
+    Ok((freqs.cos()?.to_dtype(dtype)?, freqs.sin()?.to_dtype(dtype)?))
+}
+
+/// Temperature, top-k and top-p applied to logits as an explicit
+/// distribution, which acceptance compares between the two models.
+struct Sampler {
+    temperature: f64,
+    top_k: Option<usize>,
+    top_p: f64,
+    rng: StdRng,
+}
+
+impl Sampler {
+    fn distribution(&self, logits: &Tensor) -> Result<Vec<f32>> {
+        let logits = logits.to_vec1::<f32>()?;
+        let mut probs = vec![0.0; logits.len()];
+        if self.temperature <= 0.0 {
+            let best = logits
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map_or(0, |(index, _)| index);
+            probs[best] = 1.0;
+            return Ok(probs);
+        }
+
+        let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let temperature = self.temperature as f32;
+        for (prob, logit) in probs.iter_mut().zip(&logits) {
+            *prob = ((logit - max) / temperature).exp();
+        }
+        let mut order: Vec<usize> = (0..probs.len()).collect();
+        order.sort_unstable_by(|a, b| probs[*b].total_cmp(&probs[*a]));
+        let mut keep = self.top_k.unwrap_or(order.len()).clamp(1, order.len());
+        if self.top_p < 1.0 {
+            let total: f32 = order[..keep].iter().map(|&index| probs[index]).sum();
+            let mut cumulative = 0.0;
+            for (rank, &index) in order[..keep].iter().enumerate() {
+                cumulative += probs[index] / total;
+                if cumulative >= self.top_p as f32 {
+                    keep = rank + 1;
+                    break;
+                }
+            }
+        }
+        for &index in &order[keep..] {
+            probs[index] = 0.0;
+        }
+        let total: f32 = probs.iter().sum();
+        for prob in &mut probs {
+            *prob /= total;
+        }
+        Ok(probs)
+    }
+
+    /// A token drawn from `weights`, which need not sum to one.
+    fn sample(&mut self, weights: &[f32]) -> u32 {
+        let total: f32 = weights.iter().sum();
+        let mut target = self.rng.gen::<f32>() * total;
+        let mut last = 0;
+        for (index, &weight) in weights.iter().enumerate() {
+            if weight <= 0.0 {
+                continue;
+            }
+            if target < weight {
+                return index as u32;
+            }
+            target -= weight;
+            last = index;
+        }
+        last as u32
+    }
+}
+
+/// One generation's speculative decoding: each step the draft model proposes
+/// a few tokens, the main model scores them all in one forward pass, and the
+/// longest prefix it agrees with is kept along with one token of its own.
+/// Rejection sampling keeps the output distributed as the main model alone
+/// would sample it.
+pub(crate) struct Speculation<'a> {
+    target: &'a NativeLlama,
+    draft: &'a NativeLlama,
+    target_cache: KvCache,
+    draft_cache: KvCache,
+    sampler: Sampler,
+    lookahead: usize,
+}
+
+impl<'a> Speculation<'a> {
+    pub(crate) fn new(
+        target: &'a NativeLlama,
+        draft: &'a NativeLlama,
+        lookahead: usize,
+        params: &GenerationParams,
+        top_p: f64,
+    ) -> Self {
+        Self {
+            target,
+            draft,
+            target_cache: target.new_cache(),
+            draft_cache: draft.new_cache(),
+            sampler: Sampler {
+                temperature: params.temperature as f64,
+                top_k: params.top_k,
+                top_p,
+                rng: StdRng::seed_from_u64(params.seed.unwrap_or_else(rand::random)),
+            },
+            lookahead,
+        }
+    }
+
+    /// Up to `limit` tokens following `tokens`, of which the generated ones
+    /// start at `prompt_len`. Always at least one.
+    pub(crate) fn step(
+        &mut self,
+        tokens: &[u32],
+        prompt_len: usize,
+        params: &GenerationParams,
+        limit: usize,
+    ) -> Result<Vec<u32>> {
+        let lookahead = self.lookahead.min(limit.saturating_sub(1));
+        let mut drafted = Vec::with_capacity(lookahead);
+        let mut draft_probs = Vec::with_capacity(lookahead);
+        for _ in 0..lookahead {
+            let input: Vec<u32> = tokens
+                .iter()
+                .chain(&drafted)
+                .skip(self.draft_cache.len())
+                .copied()
+                .collect();
+            let logits = self.draft.forward(&input, &mut self.draft_cache, 1)?;
+            let probs = self.sampler.distribution(&logits.i(0)?)?;
+            drafted.push(self.sampler.sample(&probs));
+            draft_probs.push(probs);
+        }
+
+        let input: Vec<u32> = tokens[self.target_cache.len()..]
+            .iter()
+            .chain(&drafted)
+            .copied()
+            .collect();
+        // Row 0 scores the token after the last one in `tokens`, and each
+        // later row the token after the drafted one before it.
+        let logits = self
+            .target
+            .forward(&input, &mut self.target_cache, drafted.len() + 1)?;
+
+        let mut generated = tokens[prompt_len..].to_vec();
+        let mut accepted = Vec::with_capacity(drafted.len() + 1);
+        let proposals = drafted.iter().zip(&draft_probs).map(Some).chain([None]);
+        for (position, proposal) in proposals.enumerate() {
+            let row = apply_penalties(logits.i(position)?, &generated, params)?;
+            let probs = self.sampler.distribution(&row)?;
+            let Some((&token, token_draft_probs)) = proposal else {
+                // Every drafted token held up; the main model adds one more.
+                accepted.push(self.sampler.sample(&probs));
+                break;
+            };
+            let draft_prob = token_draft_probs[token as usize];
+            let target_prob = probs[token as usize];
+            if self.sampler.rng.gen::<f32>() < target_prob / draft_prob {
+                accepted.push(token);
+                generated.push(token);
+                continue;
+            }
+            // Rejected: sample from where the main model outweighs the draft.
+            let residual: Vec<f32> = probs
+                .iter()
+                .zip(token_draft_probs)
+                .map(|(target, draft)| (target - draft).max(0.0))
+                .collect();
+            let replacement = if residual.iter().any(|&weight| weight > 0.0) {
+                self.sampler.sample(&residual)
+            } else {
+                self.sampler.sample(&probs)
+            };
+            accepted.push(replacement);
+            break;
+        }
+
+        // Both caches keep every kept token but the newest, which the next step feeds.
+        let kept = tokens.len() + accepted.len() - 1;
+        self.target_cache.truncate(kept)?;
+        self.draft_cache.truncate(kept)?;
+        Ok(accepted)
+    }
+}
@@ -0,0 +1,136 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, params_from_iter, Connection};
+
+use crate::models::Page;
+use crate::repositories::{enable_wal, ensure_intact, keyset_clause, ReadPool};
+use std::fs;
+use std::path::PathBuf;
+
+/// Fields of one heartbeat, as stored.
+#[derive(Debug, Clone)]
+pub struct AgentReport {
+    pub agent_id: String,
+    pub version: String,
+    pub os: String,
+    pub os_version: Option<String>,
+    pub hostname: Option<String>,
+    pub health: String,
+    pub message: Option<String>,
+}
+
+/// Latest known state of an agent.
+#[derive(Debug, Clone)]
+pub struct AgentRecord {
+    pub report: AgentReport,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub heartbeats: u64,
+}
+
+#[derive(Clone)]
+pub struct AgentRepo {
+    path: PathBuf,
+    reader: ReadPool,
+}
+
+impl AgentRepo {
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Failed to create agent store directory: {}",
+                    parent.display()
+                )
+            })?;
+        }
+        ensure_intact(&path)?;
+        let reader = ReadPool::new(path.clone(), 2);
+        let repo = Self { path, reader };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        enable_wal(&conn)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS agents (
+                agent_id TEXT PRIMARY KEY,
+                version TEXT NOT NULL,
+                os TEXT NOT NULL,
+                os_version TEXT,
+                hostname TEXT,
+                health TEXT NOT NULL,
+                message TEXT,
+                first_seen INTEGER NOT NULL,
+                last_seen INTEGER NOT NULL,
+                heartbeats INTEGER NOT NULL DEFAULT 1
+            );
+            CREATE INDEX IF NOT EXISTS idx_agents_last_seen
+                ON agents(last_seen, agent_id);",
+        )?;
+        Ok(())
+    }
+
+    /// Replaces the agent's reported state and bumps its heartbeat count.
+    pub fn upsert(&self, report: &AgentReport) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        let now = Utc::now().timestamp();
+        conn.execute(
+            "INSERT INTO agents (agent_id, version, os, os_version, hostname, health, message, first_seen, last_seen)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)
+             ON CONFLICT(agent_id) DO UPDATE SET
+                version = excluded.version,
+                os = excluded.os,
+                os_version = excluded.os_version,
+                hostname = excluded.hostname,
+                health = excluded.health,
+                message = excluded.message,
+                last_seen = excluded.last_seen,
+                heartbeats = heartbeats + 1",
+            params![
+                report.agent_id,
+                report.version,
+                report.os,
+                report.os_version,
+                report.hostname,
+                report.health,
+                report.message,
+                now
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Agents ordered by when they last reported.
+    pub fn list(&self, page: &Page) -> Result<Vec<AgentRecord>> {
+        self.reader.with(|conn| {
+            let (clause, values) = keyset_clause(page, "last_seen", "agent_id");
+            let mut stmt = conn.prepare(&format!(
+                "SELECT agent_id, version, os, os_version, hostname, health, message,
+                        first_seen, last_seen, heartbeats
+                 FROM agents{}",
+                clause
+            ))?;
+            let rows = stmt.query_map(params_from_iter(values), |row| {
+                Ok(AgentRecord {
+                    report: AgentReport {
+                        agent_id: row.get(0)?,
+                        version: row.get(1)?,
+                        os: row.get(2)?,
+                        os_version: row.get(3)?,
+                        hostname: row.get(4)?,
+                        health: row.get(5)?,
+                        message: row.get(6)?,
+                    },
+                    first_seen: DateTime::<Utc>::from_timestamp(row.get(7)?, 0).unwrap_or_default(),
+                    last_seen: DateTime::<Utc>::from_timestamp(row.get(8)?, 0).unwrap_or_default(),
+                    heartbeats: row.get::<_, i64>(9)? as u64,
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+        })
+    }
+}
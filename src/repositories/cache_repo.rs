@@ -1,9 +1,15 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Duration, Utc};
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::repositories::ensure_intact;
+use crate::utils::{
+    lsh_buckets, minhash_signature, minhash_similarity, signature_from_bytes, signature_to_bytes,
+};
+
 #[derive(Debug, Clone)]
 pub struct CacheRecord {
     pub key: String,
@@ -13,6 +19,65 @@ pub struct CacheRecord {
     pub hits: u64,
 }
 
+/// A live entry together with its tags, as listed for export.
+#[derive(Debug, Clone)]
+pub struct TaggedCacheRecord {
+    pub record: CacheRecord,
+    pub tags: Vec<String>,
+}
+
+/// Operations of the durable cache tier, implemented by the SQLite and Postgres stores.
+/// Calls block, so async callers run them on the blocking pool.
+pub trait CacheStore: Send + Sync {
+    /// The live entry for `key`, counting the hit.
+    fn get(&self, key: &str) -> Result<Option<CacheRecord>>;
+
+    /// Stores the entry for `ttl_seconds`, or the configured number of days.
+    fn set(
+        &self,
+        key: &str,
+        value_json: &str,
+        tags: &[String],
+        ttl_seconds: Option<u64>,
+    ) -> Result<()>;
+
+    /// Deletes every entry carrying `tag` and returns the removed keys.
+    fn invalidate_tag(&self, tag: &str) -> Result<Vec<String>>;
+
+    /// An entry that expired less than `max_stale_seconds` ago, without
+    /// counting a hit.
+    fn get_stale(&self, key: &str, max_stale_seconds: u64) -> Result<Option<CacheRecord>>;
+
+    /// Deletes entries that expired more than `retain_seconds` ago.
+    fn cleanup_expired(&self, retain_seconds: u64) -> Result<u64>;
+
+    /// Up to `limit` live entries with keys after `after`, in key order,
+    /// optionally only those tagged `tag`. Does not count as hits.
+    fn list(
+        &self,
+        after: Option<&str>,
+        tag: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<TaggedCacheRecord>>;
+
+    /// Indexes `question` as the one answered by the entry at `key`, for
+    /// [`CacheStore::similar_questions`]. Stores without an index ignore it.
+    fn index_question(&self, _scope: &str, _question: &str, _key: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Up to `limit` live entries in `scope` whose indexed question shares an
+    /// LSH bucket with `question`, as `(key, question)`, most similar first.
+    fn similar_questions(
+        &self,
+        _scope: &str,
+        _question: &str,
+        _limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        Ok(Vec::new())
+    }
+}
+
 #[derive(Clone)]
 pub struct CacheRepo {
     path: PathBuf,
@@ -28,6 +93,7 @@ impl CacheRepo {
                 format!("Failed to create sqlite cache directory: {}", parent.display())
             })?;
         }
+        ensure_intact(&path)?;
         let repo = Self {
             path,
             ttl_days: ttl_days as i64,
@@ -48,16 +114,64 @@ impl CacheRepo {
                 hits INTEGER NOT NULL DEFAULT 0
             );
             CREATE INDEX IF NOT EXISTS idx_ai_cache_expires ON ai_cache(expires_at);
-            CREATE TABLE IF NOT EXISTS cache_stats (
-                metric TEXT PRIMARY KEY,
-                value INTEGER NOT NULL,
-                updated_at INTEGER NOT NULL
-            );",
+            CREATE TABLE IF NOT EXISTS ai_cache_tags (
+                cache_key TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (cache_key, tag)
+            );
+            CREATE INDEX IF NOT EXISTS idx_ai_cache_tags_tag ON ai_cache_tags(tag);
+            CREATE TABLE IF NOT EXISTS ai_cache_questions (
+                cache_key TEXT PRIMARY KEY,
+                scope TEXT NOT NULL,
+                question TEXT NOT NULL,
+                minhash BLOB NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS ai_cache_lsh (
+                scope TEXT NOT NULL,
+                band INTEGER NOT NULL,
+                bucket INTEGER NOT NULL,
+                cache_key TEXT NOT NULL,
+                PRIMARY KEY (scope, band, bucket, cache_key)
+            );
+            CREATE INDEX IF NOT EXISTS idx_ai_cache_lsh_key ON ai_cache_lsh(cache_key);",
         )?;
         Ok(())
     }
 
-    pub fn get(&self, key: &str) -> Result<Option<CacheRecord>> {
+    fn cleanup_if_needed(&self) -> Result<()> {
+        if self.max_size_bytes == 0 {
+            return Ok(());
+        }
+        let size = self.db_size()?;
+        if size <= self.max_size_bytes {
+            return Ok(());
+        }
+
+        let conn = Connection::open(&self.path)?;
+        conn.execute_batch(
+            "DELETE FROM ai_cache
+             WHERE cache_key IN (
+                SELECT cache_key FROM ai_cache ORDER BY created_at ASC LIMIT 500
+             );
+             DELETE FROM ai_cache_tags
+             WHERE cache_key NOT IN (SELECT cache_key FROM ai_cache);",
+        )?;
+        delete_orphaned_questions(&conn)?;
+        conn.execute_batch("VACUUM;")?;
+        Ok(())
+    }
+
+    fn db_size(&self) -> Result<u64> {
+        if !Path::new(&self.path).exists() {
+            return Ok(0);
+        }
+        let metadata = fs::metadata(&self.path)?;
+        Ok(metadata.len())
+    }
+}
+
+impl CacheStore for CacheRepo {
+    fn get(&self, key: &str) -> Result<Option<CacheRecord>> {
         let conn = Connection::open(&self.path)?;
         let now = Utc::now().timestamp();
         let mut stmt = conn.prepare(
@@ -97,12 +211,22 @@ impl CacheRepo {
         }
     }
 
-    pub fn set(&self, key: &str, value_json: &str) -> Result<()> {
-        let conn = Connection::open(&self.path)?;
+    fn set(
+        &self,
+        key: &str,
+        value_json: &str,
+        tags: &[String],
+        ttl_seconds: Option<u64>,
+    ) -> Result<()> {
+        let mut conn = Connection::open(&self.path)?;
         let now = Utc::now();
-        let expires_at = now + Duration::days(self.ttl_days);
+        let expires_at = match ttl_seconds {
+            Some(ttl_seconds) => now + Duration::seconds(ttl_seconds as i64),
+            None => now + Duration::days(self.ttl_days),
+        };
 
-        conn.execute(
+        let tx = conn.transaction()?;
+        tx.execute(
             "INSERT INTO ai_cache (cache_key, response_json, created_at, expires_at, hits)
              VALUES (?1, ?2, ?3, ?4, 0)
              ON CONFLICT(cache_key) DO UPDATE SET
@@ -116,42 +240,221 @@ impl CacheRepo {
                 expires_at.timestamp()
             ],
         )?;
+        tx.execute(
+            "DELETE FROM ai_cache_tags WHERE cache_key = ?1",
+            params![key],
+        )?;
+        for tag in tags {
+            tx.execute(
+                "INSERT OR IGNORE INTO ai_cache_tags (cache_key, tag) VALUES (?1, ?2)",
+                params![key, tag],
+            )?;
+        }
+        tx.commit()?;
         self.cleanup_if_needed()?;
         Ok(())
     }
 
-    pub fn cleanup_expired(&self) -> Result<u64> {
+    fn invalidate_tag(&self, tag: &str) -> Result<Vec<String>> {
+        let mut conn = Connection::open(&self.path)?;
+        let tx = conn.transaction()?;
+        let keys = {
+            let mut stmt = tx.prepare(
+                "SELECT t.cache_key FROM ai_cache_tags t
+                 JOIN ai_cache c ON c.cache_key = t.cache_key
+                 WHERE t.tag = ?1",
+            )?;
+            let rows = stmt.query_map(params![tag], |row| row.get::<_, String>(0))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        tx.execute(
+            "DELETE FROM ai_cache
+             WHERE cache_key IN (SELECT cache_key FROM ai_cache_tags WHERE tag = ?1)",
+            params![tag],
+        )?;
+        tx.execute(
+            "DELETE FROM ai_cache_tags
+             WHERE cache_key IN (SELECT cache_key FROM ai_cache_tags WHERE tag = ?1)",
+            params![tag],
+        )?;
+        delete_orphaned_questions(&tx)?;
+        tx.commit()?;
+        Ok(keys)
+    }
+
+    fn get_stale(&self, key: &str, max_stale_seconds: u64) -> Result<Option<CacheRecord>> {
         let conn = Connection::open(&self.path)?;
         let now = Utc::now().timestamp();
-        let rows = conn.execute("DELETE FROM ai_cache WHERE expires_at <= ?1", params![now])?;
+        let record = conn
+            .query_row(
+                "SELECT cache_key, response_json, created_at, expires_at, hits
+                 FROM ai_cache
+                 WHERE cache_key = ?1 AND expires_at <= ?2 AND expires_at > ?3",
+                params![key, now, now - max_stale_seconds as i64],
+                |row| {
+                    Ok(CacheRecord {
+                        key: row.get(0)?,
+                        value_json: row.get(1)?,
+                        created_at: timestamp(row.get(2)?),
+                        expires_at: timestamp(row.get(3)?),
+                        hits: row.get::<_, i64>(4)? as u64,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(record)
+    }
+
+    fn cleanup_expired(&self, retain_seconds: u64) -> Result<u64> {
+        let conn = Connection::open(&self.path)?;
+        let cutoff = Utc::now().timestamp() - retain_seconds as i64;
+        let rows = conn.execute(
+            "DELETE FROM ai_cache WHERE expires_at <= ?1",
+            params![cutoff],
+        )?;
+        conn.execute(
+            "DELETE FROM ai_cache_tags WHERE cache_key NOT IN (SELECT cache_key FROM ai_cache)",
+            [],
+        )?;
+        delete_orphaned_questions(&conn)?;
         Ok(rows as u64)
     }
 
-    fn cleanup_if_needed(&self) -> Result<()> {
-        if self.max_size_bytes == 0 {
-            return Ok(());
-        }
-        let size = self.db_size()?;
-        if size <= self.max_size_bytes {
-            return Ok(());
+    fn list(
+        &self,
+        after: Option<&str>,
+        tag: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<TaggedCacheRecord>> {
+        let conn = Connection::open(&self.path)?;
+        let records = {
+            let mut stmt = conn.prepare(
+                "SELECT c.cache_key, c.response_json, c.created_at, c.expires_at, c.hits
+                 FROM ai_cache c
+                 WHERE c.expires_at > ?1 AND c.cache_key > ?2
+                   AND (?3 IS NULL OR EXISTS (
+                        SELECT 1 FROM ai_cache_tags t
+                        WHERE t.cache_key = c.cache_key AND t.tag = ?3))
+                 ORDER BY c.cache_key
+                 LIMIT ?4",
+            )?;
+            let rows = stmt.query_map(
+                params![
+                    Utc::now().timestamp(),
+                    after.unwrap_or(""),
+                    tag,
+                    limit as i64
+                ],
+                |row| {
+                    Ok(CacheRecord {
+                        key: row.get(0)?,
+                        value_json: row.get(1)?,
+                        created_at: timestamp(row.get(2)?),
+                        expires_at: timestamp(row.get(3)?),
+                        hits: row.get::<_, i64>(4)? as u64,
+                    })
+                },
+            )?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+
+        let mut stmt =
+            conn.prepare("SELECT tag FROM ai_cache_tags WHERE cache_key = ?1 ORDER BY tag")?;
+        let mut tagged = Vec::with_capacity(records.len());
+        for record in records {
+            let tags = stmt
+                .query_map(params![record.key], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            tagged.push(TaggedCacheRecord { record, tags });
         }
+        Ok(tagged)
+    }
 
-        let conn = Connection::open(&self.path)?;
-        conn.execute_batch(
-            "DELETE FROM ai_cache
-             WHERE cache_key IN (
-                SELECT cache_key FROM ai_cache ORDER BY created_at ASC LIMIT 500
-             );",
+    fn index_question(&self, scope: &str, question: &str, key: &str) -> Result<()> {
+        let signature = minhash_signature(question);
+        let mut conn = Connection::open(&self.path)?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO ai_cache_questions (cache_key, scope, question, minhash)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(cache_key) DO UPDATE SET
+                scope = excluded.scope,
+                question = excluded.question,
+                minhash = excluded.minhash",
+            params![key, scope, question, signature_to_bytes(&signature)],
         )?;
-        conn.execute_batch("VACUUM;")?;
+        tx.execute(
+            "DELETE FROM ai_cache_lsh WHERE cache_key = ?1",
+            params![key],
+        )?;
+        for (band, bucket) in lsh_buckets(&signature) {
+            tx.execute(
+                "INSERT OR IGNORE INTO ai_cache_lsh (scope, band, bucket, cache_key)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![scope, band as i64, bucket, key],
+            )?;
+        }
+        tx.commit()?;
         Ok(())
     }
 
-    fn db_size(&self) -> Result<u64> {
-        if !Path::new(&self.path).exists() {
-            return Ok(0);
+    /// Looks up one bucket per band rather than scanning the questions.
+    fn similar_questions(
+        &self,
+        scope: &str,
+        question: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>> {
+        let signature = minhash_signature(question);
+        let conn = Connection::open(&self.path)?;
+        let mut stmt = conn.prepare(
+            "SELECT q.cache_key, q.question, q.minhash
+             FROM ai_cache_lsh l
+             JOIN ai_cache_questions q ON q.cache_key = l.cache_key
+             JOIN ai_cache c ON c.cache_key = l.cache_key
+             WHERE l.scope = ?1 AND l.band = ?2 AND l.bucket = ?3 AND c.expires_at > ?4",
+        )?;
+        let now = Utc::now().timestamp();
+        let mut candidates: HashMap<String, (String, f32)> = HashMap::new();
+        for (band, bucket) in lsh_buckets(&signature) {
+            let rows = stmt.query_map(params![scope, band as i64, bucket, now], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Vec<u8>>(2)?,
+                ))
+            })?;
+            for row in rows {
+                let (key, question, minhash) = row?;
+                candidates.entry(key).or_insert_with(|| {
+                    let similarity =
+                        minhash_similarity(&signature, &signature_from_bytes(&minhash));
+                    (question, similarity)
+                });
+            }
         }
-        let metadata = fs::metadata(&self.path)?;
-        Ok(metadata.len())
+
+        let mut ranked: Vec<_> = candidates.into_iter().collect();
+        ranked.sort_by(|a, b| b.1 .1.total_cmp(&a.1 .1));
+        ranked.truncate(limit);
+        Ok(ranked
+            .into_iter()
+            .map(|(key, (question, _))| (key, question))
+            .collect())
     }
 }
+
+/// Drops question-index rows whose entry is gone.
+fn delete_orphaned_questions(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "DELETE FROM ai_cache_questions
+         WHERE cache_key NOT IN (SELECT cache_key FROM ai_cache);
+         DELETE FROM ai_cache_lsh
+         WHERE cache_key NOT IN (SELECT cache_key FROM ai_cache);",
+    )?;
+    Ok(())
+}
+
+fn timestamp(seconds: i64) -> DateTime<Utc> {
+    DateTime::<Utc>::from_timestamp(seconds, 0).unwrap_or_default()
+}
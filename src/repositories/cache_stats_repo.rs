@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::repositories::{enable_wal, ensure_intact};
+
+/// Cache counters over one period: lookups and the hits each tier answered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStatsSample {
+    pub requests: u64,
+    pub memory_hits: u64,
+    pub redis_hits: u64,
+    pub durable_hits: u64,
+    pub stale_hits: u64,
+    pub dropped_writes: u64,
+}
+
+impl CacheStatsSample {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Samples summed over a bucket starting at `start`.
+#[derive(Debug, Clone)]
+pub struct CacheStatsBucket {
+    pub start: DateTime<Utc>,
+    pub sample: CacheStatsSample,
+}
+
+/// Periodic snapshots of the cache counters, stored beside the SQLite cache
+/// so hit rates survive restarts.
+#[derive(Clone)]
+pub struct CacheStatsRepo {
+    path: PathBuf,
+}
+
+impl CacheStatsRepo {
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create cache directory: {}", parent.display())
+            })?;
+        }
+        ensure_intact(&path)?;
+        let repo = Self { path };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        enable_wal(&conn)?;
+        // `cache_stats` held one running value per metric and was never written.
+        conn.execute_batch(
+            "DROP TABLE IF EXISTS cache_stats;
+            CREATE TABLE IF NOT EXISTS cache_stats_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                recorded_at INTEGER NOT NULL,
+                requests INTEGER NOT NULL,
+                memory_hits INTEGER NOT NULL,
+                redis_hits INTEGER NOT NULL,
+                durable_hits INTEGER NOT NULL,
+                stale_hits INTEGER NOT NULL,
+                dropped_writes INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_cache_stats_history_recorded
+                ON cache_stats_history(recorded_at);",
+        )?;
+        Ok(())
+    }
+
+    /// Stores the counters accumulated since the previous sample.
+    pub fn record(&self, recorded_at: DateTime<Utc>, sample: &CacheStatsSample) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute(
+            "INSERT INTO cache_stats_history (
+                recorded_at, requests, memory_hits, redis_hits, durable_hits, stale_hits,
+                dropped_writes
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                recorded_at.timestamp(),
+                sample.requests as i64,
+                sample.memory_hits as i64,
+                sample.redis_hits as i64,
+                sample.durable_hits as i64,
+                sample.stale_hits as i64,
+                sample.dropped_writes as i64,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Samples recorded since `since`, summed into buckets of `bucket_seconds`
+    /// aligned to the epoch, oldest first. Empty buckets are omitted.
+    pub fn history(
+        &self,
+        since: DateTime<Utc>,
+        bucket_seconds: i64,
+    ) -> Result<Vec<CacheStatsBucket>> {
+        let conn = Connection::open(&self.path)?;
+        let mut stmt = conn.prepare(
+            "SELECT (recorded_at / ?2) * ?2 AS bucket, SUM(requests), SUM(memory_hits),
+                    SUM(redis_hits), SUM(durable_hits), SUM(stale_hits), SUM(dropped_writes)
+             FROM cache_stats_history
+             WHERE recorded_at >= ?1
+             GROUP BY bucket
+             ORDER BY bucket",
+        )?;
+        let rows = stmt.query_map(params![since.timestamp(), bucket_seconds.max(1)], |row| {
+            Ok(CacheStatsBucket {
+                start: DateTime::<Utc>::from_timestamp(row.get(0)?, 0).unwrap_or_default(),
+                sample: CacheStatsSample {
+                    requests: row.get::<_, i64>(1)? as u64,
+                    memory_hits: row.get::<_, i64>(2)? as u64,
+                    redis_hits: row.get::<_, i64>(3)? as u64,
+                    durable_hits: row.get::<_, i64>(4)? as u64,
+                    stale_hits: row.get::<_, i64>(5)? as u64,
+                    dropped_writes: row.get::<_, i64>(6)? as u64,
+                },
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Deletes samples recorded before `before` and returns how many.
+    pub fn prune(&self, before: DateTime<Utc>) -> Result<u64> {
+        let conn = Connection::open(&self.path)?;
+        let removed = conn.execute(
+            "DELETE FROM cache_stats_history WHERE recorded_at < ?1",
+            params![before.timestamp()],
+        )?;
+        Ok(removed as u64)
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+
+use crate::repositories::{enable_wal, ensure_intact, ReadPool};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct CaptureRecord {
+    pub request_id: String,
+    pub provider: String,
+    pub endpoint: String,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    pub request_json: String,
+    pub response_json: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+pub struct CaptureRepo {
+    path: PathBuf,
+    reader: ReadPool,
+}
+
+impl CaptureRepo {
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Failed to create provider capture directory: {}",
+                    parent.display()
+                )
+            })?;
+        }
+        ensure_intact(&path)?;
+        let reader = ReadPool::new(path.clone(), 2);
+        let repo = Self { path, reader };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        enable_wal(&conn)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS provider_captures (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                request_id TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                endpoint TEXT NOT NULL,
+                status INTEGER,
+                error TEXT,
+                duration_ms INTEGER NOT NULL,
+                request_json TEXT NOT NULL,
+                response_json TEXT,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_provider_captures_request
+                ON provider_captures(request_id);
+            CREATE INDEX IF NOT EXISTS idx_provider_captures_created
+                ON provider_captures(created_at);",
+        )?;
+        Ok(())
+    }
+
+    pub fn insert(&self, record: &CaptureRecord) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute(
+            "INSERT INTO provider_captures
+                (request_id, provider, endpoint, status, error, duration_ms,
+                 request_json, response_json, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                record.request_id,
+                record.provider,
+                record.endpoint,
+                record.status,
+                record.error,
+                record.duration_ms as i64,
+                record.request_json,
+                record.response_json,
+                record.created_at.timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn by_request_id(&self, request_id: &str) -> Result<Vec<CaptureRecord>> {
+        self.reader
+            .with(|conn| Self::query_request(conn, request_id))
+    }
+
+    fn query_request(conn: &Connection, request_id: &str) -> Result<Vec<CaptureRecord>> {
+        let mut stmt = conn.prepare(
+            "SELECT request_id, provider, endpoint, status, error, duration_ms,
+                    request_json, response_json, created_at
+             FROM provider_captures
+             WHERE request_id = ?1
+             ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map(params![request_id], |row| {
+            Ok(CaptureRecord {
+                request_id: row.get(0)?,
+                provider: row.get(1)?,
+                endpoint: row.get(2)?,
+                status: row.get(3)?,
+                error: row.get(4)?,
+                duration_ms: row.get::<_, i64>(5)? as u64,
+                request_json: row.get(6)?,
+                response_json: row.get(7)?,
+                created_at: DateTime::<Utc>::from_timestamp(row.get(8)?, 0).unwrap_or_default(),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    pub fn purge_before(&self, cutoff: DateTime<Utc>) -> Result<u64> {
+        let conn = Connection::open(&self.path)?;
+        let rows = conn.execute(
+            "DELETE FROM provider_captures WHERE created_at < ?1",
+            params![cutoff.timestamp()],
+        )?;
+        Ok(rows as u64)
+    }
+}
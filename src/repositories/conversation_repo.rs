@@ -0,0 +1,638 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::models::Page;
+use crate::repositories::{enable_wal, ensure_intact, has_column, keyset_clause};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct ConversationRecord {
+    pub conversation_id: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub message_count: u64,
+    pub escalated_at: Option<DateTime<Utc>>,
+    pub escalation_reason: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MessageRecord {
+    pub id: i64,
+    pub role: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    /// Why generation stopped early; set on assistant messages holding a partial answer.
+    pub interrupted: Option<String>,
+}
+
+/// Audit entry for a redaction; counts only, so the redacted text is not kept.
+#[derive(Debug, Clone)]
+pub struct RedactionAudit {
+    pub mode: String,
+    pub reason: Option<String>,
+    pub messages_removed: usize,
+    pub messages_masked: usize,
+    pub messages_scrubbed: usize,
+    pub pattern_count: usize,
+}
+
+/// Older turns moved out of a conversation, with the rolling summary as it
+/// stood when they were archived.
+#[derive(Debug, Clone)]
+pub struct ArchiveRecord {
+    pub id: i64,
+    pub archived_at: DateTime<Utc>,
+    pub summary: Option<String>,
+    pub messages: Vec<MessageRecord>,
+}
+
+/// A message as stored in an archive's compressed transcript.
+#[derive(Serialize, Deserialize)]
+struct ArchivedMessage {
+    id: i64,
+    role: String,
+    content: String,
+    created_at: i64,
+    interrupted: Option<String>,
+}
+
+const ARCHIVE_COMPRESSION_LEVEL: i32 = 9;
+
+#[derive(Clone)]
+pub struct ConversationRepo {
+    path: PathBuf,
+}
+
+impl ConversationRepo {
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Failed to create conversation store directory: {}",
+                    parent.display()
+                )
+            })?;
+        }
+        ensure_intact(&path)?;
+        let repo = Self { path };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        enable_wal(&conn)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS conversations (
+                conversation_id TEXT PRIMARY KEY,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                message_count INTEGER NOT NULL DEFAULT 0,
+                escalated_at INTEGER,
+                escalation_reason TEXT,
+                summary TEXT,
+                summarized_count INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE INDEX IF NOT EXISTS idx_conversations_updated ON conversations(updated_at);
+            CREATE TABLE IF NOT EXISTS conversation_messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                conversation_id TEXT NOT NULL,
+                role TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_conversation_messages_conversation
+                ON conversation_messages(conversation_id, id);
+            CREATE TABLE IF NOT EXISTS conversation_redactions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                conversation_id TEXT NOT NULL,
+                mode TEXT NOT NULL,
+                reason TEXT,
+                messages_removed INTEGER NOT NULL,
+                messages_masked INTEGER NOT NULL,
+                messages_scrubbed INTEGER NOT NULL,
+                pattern_count INTEGER NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_conversation_redactions_conversation
+                ON conversation_redactions(conversation_id);
+            CREATE TABLE IF NOT EXISTS conversation_archives (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                conversation_id TEXT NOT NULL,
+                archived_at INTEGER NOT NULL,
+                message_count INTEGER NOT NULL,
+                first_message_at INTEGER NOT NULL,
+                last_message_at INTEGER NOT NULL,
+                summary TEXT,
+                transcript BLOB NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_conversation_archives_conversation
+                ON conversation_archives(conversation_id, id);",
+        )?;
+        // Stores created by older versions lack these columns.
+        for (column, definition) in [
+            ("escalated_at", "INTEGER"),
+            ("escalation_reason", "TEXT"),
+            ("summary", "TEXT"),
+            ("summarized_count", "INTEGER NOT NULL DEFAULT 0"),
+        ] {
+            if !has_column(&conn, "conversations", column)? {
+                conn.execute_batch(&format!(
+                    "ALTER TABLE conversations ADD COLUMN {} {}",
+                    column, definition
+                ))?;
+            }
+        }
+        if !has_column(&conn, "conversation_messages", "interrupted")? {
+            conn.execute_batch("ALTER TABLE conversation_messages ADD COLUMN interrupted TEXT")?;
+        }
+        Ok(())
+    }
+
+    /// Appends a user/assistant exchange, creating the conversation on first use.
+    /// `interrupted` marks the assistant message as a partial answer.
+    pub fn append_turn(
+        &self,
+        conversation_id: &str,
+        user: &str,
+        assistant: &str,
+        interrupted: Option<&str>,
+    ) -> Result<()> {
+        let mut conn = Connection::open(&self.path)?;
+        let now = Utc::now().timestamp();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO conversations (conversation_id, created_at, updated_at, message_count)
+             VALUES (?1, ?2, ?2, 0)
+             ON CONFLICT(conversation_id) DO NOTHING",
+            params![conversation_id, now],
+        )?;
+        for (role, content, interrupted) in
+            [("user", user, None), ("assistant", assistant, interrupted)]
+        {
+            tx.execute(
+                "INSERT INTO conversation_messages
+                    (conversation_id, role, content, created_at, interrupted)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![conversation_id, role, content, now, interrupted],
+            )?;
+        }
+        tx.execute(
+            "UPDATE conversations
+             SET updated_at = ?1, message_count = message_count + 2
+             WHERE conversation_id = ?2",
+            params![now, conversation_id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Removes the newest user message and the replies after it, returning them oldest first.
+    pub fn pop_last_turn(&self, conversation_id: &str) -> Result<Vec<MessageRecord>> {
+        let mut conn = Connection::open(&self.path)?;
+        let tx = conn.transaction()?;
+        let last_user: Option<i64> = tx.query_row(
+            "SELECT MAX(id) FROM conversation_messages
+                 WHERE conversation_id = ?1 AND role = 'user'",
+            params![conversation_id],
+            |row| row.get(0),
+        )?;
+        let Some(last_user) = last_user else {
+            return Ok(Vec::new());
+        };
+
+        let removed = {
+            let mut stmt = tx.prepare(
+                "SELECT id, role, content, created_at, interrupted FROM conversation_messages
+                 WHERE conversation_id = ?1 AND id >= ?2
+                 ORDER BY id ASC",
+            )?;
+            let rows = stmt.query_map(params![conversation_id, last_user], row_to_message)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        tx.execute(
+            "DELETE FROM conversation_messages WHERE conversation_id = ?1 AND id >= ?2",
+            params![conversation_id, last_user],
+        )?;
+        tx.execute(
+            "UPDATE conversations
+             SET message_count = MAX(message_count - ?1, 0),
+                 summarized_count = MIN(summarized_count, MAX(message_count - ?1, 0))
+             WHERE conversation_id = ?2",
+            params![removed.len() as i64, conversation_id],
+        )?;
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Deletes and rewrites messages in one transaction, passes every archived
+    /// message through `scrub`, drops the rolling summaries (they may quote
+    /// redacted text) and records the audit entry. Patterns are not stored.
+    /// Returns the number of archived messages `scrub` changed.
+    pub fn apply_redaction(
+        &self,
+        conversation_id: &str,
+        removals: &[i64],
+        rewrites: &[(i64, String)],
+        scrub: impl Fn(&str) -> String,
+        audit: &RedactionAudit,
+    ) -> Result<usize> {
+        let mut conn = Connection::open(&self.path)?;
+        let now = Utc::now().timestamp();
+        let tx = conn.transaction()?;
+        for id in removals {
+            tx.execute(
+                "DELETE FROM conversation_messages WHERE conversation_id = ?1 AND id = ?2",
+                params![conversation_id, id],
+            )?;
+        }
+        for (id, content) in rewrites {
+            tx.execute(
+                "UPDATE conversation_messages SET content = ?1 WHERE conversation_id = ?2 AND id = ?3",
+                params![content, conversation_id, id],
+            )?;
+        }
+        tx.execute(
+            "UPDATE conversations
+             SET message_count = MAX(message_count - ?1, 0), summary = NULL, summarized_count = 0
+             WHERE conversation_id = ?2",
+            params![removals.len() as i64, conversation_id],
+        )?;
+        let archives = {
+            let mut stmt = tx.prepare(
+                "SELECT id, transcript FROM conversation_archives WHERE conversation_id = ?1",
+            )?;
+            let rows = stmt.query_map(params![conversation_id], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        let mut archived_scrubbed = 0;
+        for (archive_id, transcript) in archives {
+            let mut messages = decode_transcript(&transcript)?;
+            for message in &mut messages {
+                let content = scrub(&message.content);
+                if content != message.content {
+                    message.content = content;
+                    archived_scrubbed += 1;
+                }
+            }
+            tx.execute(
+                "UPDATE conversation_archives SET summary = NULL, transcript = ?1 WHERE id = ?2",
+                params![encode_transcript(&messages)?, archive_id],
+            )?;
+        }
+        tx.execute(
+            "INSERT INTO conversation_redactions
+                (conversation_id, mode, reason, messages_removed, messages_masked,
+                 messages_scrubbed, pattern_count, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                conversation_id,
+                audit.mode,
+                audit.reason,
+                audit.messages_removed as i64,
+                audit.messages_masked as i64,
+                (audit.messages_scrubbed + archived_scrubbed) as i64,
+                audit.pattern_count as i64,
+                now
+            ],
+        )?;
+        tx.commit()?;
+        Ok(archived_scrubbed)
+    }
+
+    /// The newest message when it is an interrupted assistant answer.
+    pub fn interrupted_answer(&self, conversation_id: &str) -> Result<Option<MessageRecord>> {
+        let conn = Connection::open(&self.path)?;
+        let record = conn
+            .query_row(
+                "SELECT id, role, content, created_at, interrupted FROM conversation_messages
+                 WHERE conversation_id = ?1
+                 ORDER BY id DESC LIMIT 1",
+                params![conversation_id],
+                row_to_message,
+            )
+            .optional()?;
+        Ok(record.filter(|m| m.role == "assistant" && m.interrupted.is_some()))
+    }
+
+    /// Replaces a partial answer with its continuation; `interrupted` stays set
+    /// when the continuation was cut short as well.
+    pub fn resume_answer(
+        &self,
+        conversation_id: &str,
+        message_id: i64,
+        content: &str,
+        interrupted: Option<&str>,
+    ) -> Result<bool> {
+        let conn = Connection::open(&self.path)?;
+        let now = Utc::now().timestamp();
+        let updated = conn.execute(
+            "UPDATE conversation_messages SET content = ?1, interrupted = ?2
+             WHERE conversation_id = ?3 AND id = ?4",
+            params![content, interrupted, conversation_id, message_id],
+        )?;
+        conn.execute(
+            "UPDATE conversations SET updated_at = ?1 WHERE conversation_id = ?2",
+            params![now, conversation_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Marks the conversation as escalated. Returns `false` when it already was.
+    pub fn mark_escalated(&self, conversation_id: &str, reason: &str) -> Result<bool> {
+        let conn = Connection::open(&self.path)?;
+        let now = Utc::now().timestamp();
+        conn.execute(
+            "INSERT INTO conversations (conversation_id, created_at, updated_at, message_count)
+             VALUES (?1, ?2, ?2, 0)
+             ON CONFLICT(conversation_id) DO NOTHING",
+            params![conversation_id, now],
+        )?;
+        let changed = conn.execute(
+            "UPDATE conversations
+             SET escalated_at = ?1, escalation_reason = ?2
+             WHERE conversation_id = ?3 AND escalated_at IS NULL",
+            params![now, reason, conversation_id],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// The rolling summary and how many leading messages it covers.
+    pub fn summary(&self, conversation_id: &str) -> Result<(Option<String>, usize)> {
+        let conn = Connection::open(&self.path)?;
+        let summary = conn
+            .query_row(
+                "SELECT summary, summarized_count FROM conversations WHERE conversation_id = ?1",
+                params![conversation_id],
+                |row| {
+                    Ok((
+                        row.get::<_, Option<String>>(0)?,
+                        row.get::<_, i64>(1)? as usize,
+                    ))
+                },
+            )
+            .optional()?;
+        Ok(summary.unwrap_or((None, 0)))
+    }
+
+    pub fn set_summary(
+        &self,
+        conversation_id: &str,
+        summary: &str,
+        summarized_count: usize,
+    ) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute(
+            "UPDATE conversations SET summary = ?1, summarized_count = ?2
+             WHERE conversation_id = ?3",
+            params![summary, summarized_count as i64, conversation_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&self, conversation_id: &str) -> Result<Option<ConversationRecord>> {
+        let conn = Connection::open(&self.path)?;
+        let record = conn
+            .query_row(
+                "SELECT conversation_id, created_at, updated_at, message_count, escalated_at, escalation_reason
+                 FROM conversations WHERE conversation_id = ?1",
+                params![conversation_id],
+                row_to_conversation,
+            )
+            .optional()?;
+        Ok(record)
+    }
+
+    pub fn messages(&self, conversation_id: &str) -> Result<Vec<MessageRecord>> {
+        let conn = Connection::open(&self.path)?;
+        let mut stmt = conn.prepare(
+            "SELECT id, role, content, created_at, interrupted
+             FROM conversation_messages
+             WHERE conversation_id = ?1
+             ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map(params![conversation_id], row_to_message)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Conversations holding more than `max_messages` messages (when not 0) or
+    /// messages created before `older_than`, at most `limit` of them.
+    pub fn over_limit(
+        &self,
+        max_messages: usize,
+        older_than: Option<DateTime<Utc>>,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        let conn = Connection::open(&self.path)?;
+        let mut stmt = conn.prepare(
+            "SELECT conversation_id FROM conversations c
+             WHERE (?1 > 0 AND c.message_count > ?1)
+                OR (?2 IS NOT NULL AND EXISTS (
+                    SELECT 1 FROM conversation_messages m
+                    WHERE m.conversation_id = c.conversation_id AND m.created_at < ?2))
+             LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                max_messages as i64,
+                older_than.map(|t| t.timestamp()),
+                limit as i64
+            ],
+            |row| row.get::<_, String>(0),
+        )?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Moves the oldest messages beyond `max_messages`, and those created
+    /// before `older_than`, into one compressed archive record. Whole turns
+    /// are moved, so the kept history starts with a user message. The rolling
+    /// summary stays on the conversation and is copied into the archive.
+    /// Returns the number of messages archived.
+    pub fn archive_oldest(
+        &self,
+        conversation_id: &str,
+        max_messages: usize,
+        older_than: Option<DateTime<Utc>>,
+    ) -> Result<usize> {
+        let mut conn = Connection::open(&self.path)?;
+        let tx = conn.transaction()?;
+        let messages = {
+            let mut stmt = tx.prepare(
+                "SELECT id, role, content, created_at, interrupted
+                 FROM conversation_messages
+                 WHERE conversation_id = ?1
+                 ORDER BY id ASC",
+            )?;
+            let rows = stmt.query_map(params![conversation_id], row_to_message)?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+
+        let mut split = if max_messages > 0 {
+            messages.len().saturating_sub(max_messages)
+        } else {
+            0
+        };
+        if let Some(older_than) = older_than {
+            split = split.max(
+                messages
+                    .iter()
+                    .take_while(|m| m.created_at < older_than)
+                    .count(),
+            );
+        }
+        while split < messages.len() && messages[split].role != "user" {
+            split += 1;
+        }
+        if split == 0 {
+            return Ok(0);
+        }
+        let archived = &messages[..split];
+
+        let summary: Option<String> = tx
+            .query_row(
+                "SELECT summary FROM conversations WHERE conversation_id = ?1",
+                params![conversation_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        let transcript: Vec<ArchivedMessage> = archived
+            .iter()
+            .map(|m| ArchivedMessage {
+                id: m.id,
+                role: m.role.clone(),
+                content: m.content.clone(),
+                created_at: m.created_at.timestamp(),
+                interrupted: m.interrupted.clone(),
+            })
+            .collect();
+        let compressed = encode_transcript(&transcript)?;
+        tx.execute(
+            "INSERT INTO conversation_archives
+                (conversation_id, archived_at, message_count, first_message_at,
+                 last_message_at, summary, transcript)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                conversation_id,
+                Utc::now().timestamp(),
+                archived.len() as i64,
+                archived[0].created_at.timestamp(),
+                archived[archived.len() - 1].created_at.timestamp(),
+                summary,
+                compressed
+            ],
+        )?;
+        tx.execute(
+            "DELETE FROM conversation_messages WHERE conversation_id = ?1 AND id <= ?2",
+            params![conversation_id, archived[archived.len() - 1].id],
+        )?;
+        tx.execute(
+            "UPDATE conversations
+             SET message_count = MAX(message_count - ?1, 0),
+                 summarized_count = MAX(summarized_count - ?1, 0)
+             WHERE conversation_id = ?2",
+            params![archived.len() as i64, conversation_id],
+        )?;
+        tx.commit()?;
+        Ok(archived.len())
+    }
+
+    /// Archived turns of a conversation, filtered and paged by `page` on the
+    /// archive time.
+    pub fn archives(&self, conversation_id: &str, page: &Page) -> Result<Vec<ArchiveRecord>> {
+        let conn = Connection::open(&self.path)?;
+        let (clause, mut values) = keyset_clause(page, "archived_at", "id");
+        values.push(Value::Text(conversation_id.to_string()));
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, archived_at, summary, transcript
+             FROM (SELECT * FROM conversation_archives WHERE conversation_id = ?{}){}",
+            values.len(),
+            clause
+        ))?;
+        let rows = stmt.query_map(params_from_iter(values), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Vec<u8>>(3)?,
+            ))
+        })?;
+        let mut archives = Vec::new();
+        for row in rows {
+            let (id, archived_at, summary, transcript) = row?;
+            archives.push(ArchiveRecord {
+                id,
+                archived_at: timestamp_to_datetime(archived_at),
+                summary,
+                messages: decode_transcript(&transcript)?
+                    .into_iter()
+                    .map(|m| MessageRecord {
+                        id: m.id,
+                        role: m.role,
+                        content: m.content,
+                        created_at: timestamp_to_datetime(m.created_at),
+                        interrupted: m.interrupted,
+                    })
+                    .collect(),
+            });
+        }
+        Ok(archives)
+    }
+
+    /// Lists conversations ordered by last update, filtered and paged by `page`.
+    pub fn list(&self, page: &Page) -> Result<Vec<ConversationRecord>> {
+        let conn = Connection::open(&self.path)?;
+        let (clause, values) = keyset_clause(page, "updated_at", "conversation_id");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT conversation_id, created_at, updated_at, message_count, escalated_at, escalation_reason
+             FROM conversations{}",
+            clause
+        ))?;
+        let rows = stmt.query_map(params_from_iter(values), row_to_conversation)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+}
+
+fn row_to_conversation(row: &rusqlite::Row<'_>) -> rusqlite::Result<ConversationRecord> {
+    Ok(ConversationRecord {
+        conversation_id: row.get(0)?,
+        created_at: timestamp_to_datetime(row.get(1)?),
+        updated_at: timestamp_to_datetime(row.get(2)?),
+        message_count: row.get::<_, i64>(3)? as u64,
+        escalated_at: row.get::<_, Option<i64>>(4)?.map(timestamp_to_datetime),
+        escalation_reason: row.get(5)?,
+    })
+}
+
+fn row_to_message(row: &rusqlite::Row<'_>) -> rusqlite::Result<MessageRecord> {
+    Ok(MessageRecord {
+        id: row.get(0)?,
+        role: row.get(1)?,
+        content: row.get(2)?,
+        created_at: timestamp_to_datetime(row.get(3)?),
+        interrupted: row.get(4)?,
+    })
+}
+
+fn encode_transcript(messages: &[ArchivedMessage]) -> Result<Vec<u8>> {
+    Ok(zstd::encode_all(
+        serde_json::to_vec(messages)?.as_slice(),
+        ARCHIVE_COMPRESSION_LEVEL,
+    )?)
+}
+
+fn decode_transcript(transcript: &[u8]) -> Result<Vec<ArchivedMessage>> {
+    let json = zstd::decode_all(transcript).context("Corrupt conversation archive")?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+fn timestamp_to_datetime(timestamp: i64) -> DateTime<Utc> {
+    DateTime::<Utc>::from_timestamp(timestamp, 0).unwrap_or_default()
+}
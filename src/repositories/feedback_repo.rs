@@ -0,0 +1,150 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+
+use crate::repositories::{enable_wal, ensure_intact, has_column, ReadPool};
+use std::fs;
+use std::path::PathBuf;
+
+/// Feedback counts for one response across all submissions.
+#[derive(Debug, Clone)]
+pub struct ResponseFeedbackRecord {
+    pub response_hash: String,
+    pub positive: u64,
+    pub negative: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FeedbackTotalsRecord {
+    pub total: u64,
+    pub positive: u64,
+    pub negative: u64,
+    pub with_comment: u64,
+}
+
+#[derive(Clone)]
+pub struct FeedbackRepo {
+    path: PathBuf,
+    reporting: ReadPool,
+}
+
+impl FeedbackRepo {
+    /// `reporting` serves the stats queries; writes always go to `path`.
+    pub fn new(path: impl Into<PathBuf>, reporting: ReadPool) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Failed to create feedback store directory: {}",
+                    parent.display()
+                )
+            })?;
+        }
+        ensure_intact(&path)?;
+        let repo = Self { path, reporting };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        enable_wal(&conn)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS response_feedback (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                conversation_id TEXT NOT NULL,
+                response_hash TEXT NOT NULL,
+                rating TEXT NOT NULL,
+                comment TEXT,
+                caller TEXT,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_response_feedback_hash
+                ON response_feedback(response_hash);
+            CREATE INDEX IF NOT EXISTS idx_response_feedback_created
+                ON response_feedback(created_at);",
+        )?;
+        // Stores created by older versions lack the caller.
+        if !has_column(&conn, "response_feedback", "caller")? {
+            conn.execute_batch("ALTER TABLE response_feedback ADD COLUMN caller TEXT")?;
+        }
+        Ok(())
+    }
+
+    /// Stores the submission and returns how many distinct callers have rated
+    /// the response negatively, including this one. Rows stored before callers
+    /// were recorded count once each.
+    pub fn insert(
+        &self,
+        conversation_id: &str,
+        response_hash: &str,
+        rating: &str,
+        comment: Option<&str>,
+        caller: &str,
+    ) -> Result<u64> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute(
+            "INSERT INTO response_feedback (conversation_id, response_hash, rating, comment, caller, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![conversation_id, response_hash, rating, comment, caller, Utc::now().timestamp()],
+        )?;
+        let negative: i64 = conn.query_row(
+            "SELECT COUNT(DISTINCT COALESCE(caller, 'row:' || id)) FROM response_feedback
+             WHERE response_hash = ?1 AND rating = 'negative'",
+            params![response_hash],
+            |row| row.get(0),
+        )?;
+        Ok(negative as u64)
+    }
+
+    pub fn totals_since(&self, since: DateTime<Utc>) -> Result<FeedbackTotalsRecord> {
+        self.reporting.with(|conn| {
+            Ok(conn.query_row(
+                "SELECT COUNT(*),
+                        COALESCE(SUM(rating = 'positive'), 0),
+                        COALESCE(SUM(rating = 'negative'), 0),
+                        COALESCE(SUM(comment IS NOT NULL AND comment != ''), 0)
+                 FROM response_feedback
+                 WHERE created_at >= ?1",
+                params![since.timestamp()],
+                |row| {
+                    Ok(FeedbackTotalsRecord {
+                        total: row.get::<_, i64>(0)? as u64,
+                        positive: row.get::<_, i64>(1)? as u64,
+                        negative: row.get::<_, i64>(2)? as u64,
+                        with_comment: row.get::<_, i64>(3)? as u64,
+                    })
+                },
+            )?)
+        })
+    }
+
+    /// Responses with the most negative ratings since `since`, worst first.
+    pub fn most_negative_since(
+        &self,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<ResponseFeedbackRecord>> {
+        self.reporting.with(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT response_hash,
+                        SUM(rating = 'positive'),
+                        SUM(rating = 'negative') AS negative
+                 FROM response_feedback
+                 WHERE created_at >= ?1
+                 GROUP BY response_hash
+                 HAVING negative > 0
+                 ORDER BY negative DESC, response_hash
+                 LIMIT ?2",
+            )?;
+            let rows = stmt.query_map(params![since.timestamp(), limit as i64], |row| {
+                Ok(ResponseFeedbackRecord {
+                    response_hash: row.get(0)?,
+                    positive: row.get::<_, i64>(1)? as u64,
+                    negative: row.get::<_, i64>(2)? as u64,
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+        })
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::{Connection, ErrorCode, OpenFlags};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// Findings from `quick_check` included in the startup diagnostic.
+const MAX_REPORTED_PROBLEMS: usize = 5;
+
+/// Runs `PRAGMA quick_check` on the SQLite file at `path` before a store
+/// creates its schema. A corrupt file, typically left by an unclean shutdown,
+/// is moved aside with its WAL and shared-memory files so the store starts
+/// over on an empty database instead of failing every query. Each file is
+/// checked once per process, since several stores share one file.
+pub fn ensure_intact(path: &Path) -> Result<()> {
+    static CHECKED: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+    let mut checked = CHECKED
+        .get_or_init(|| Mutex::new(HashSet::new()))
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if checked.contains(path) {
+        return Ok(());
+    }
+    if path.exists() {
+        if let Some(problems) = find_corruption(path)? {
+            let moved_to = move_aside(path)?;
+            tracing::error!(
+                path = %path.display(),
+                moved_to = %moved_to.display(),
+                problems = %problems.join("; "),
+                "SQLite database failed its integrity check; moved it aside and starting \
+                 with an empty database. Data in the moved file is no longer used."
+            );
+        }
+    }
+    checked.insert(path.to_path_buf());
+    Ok(())
+}
+
+/// Like [`ensure_intact`], for stores whose data must never be silently
+/// replaced, such as the spend ledger that enforces the cost caps: a corrupt
+/// file is left where it is and an error is returned, so startup stops.
+pub fn require_intact(path: &Path) -> Result<()> {
+    if path.exists() {
+        if let Some(problems) = find_corruption(path)? {
+            anyhow::bail!(
+                "SQLite database {} failed its integrity check ({}); restore it from a backup \
+                 or salvage it with `sqlite3 .recover` before starting",
+                path.display(),
+                problems.join("; ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The problems `quick_check` reported, or `None` when the file is healthy.
+/// Errors that do not indicate corruption, such as permissions, are returned.
+fn find_corruption(path: &Path) -> Result<Option<Vec<String>>> {
+    let check = || -> rusqlite::Result<Vec<String>> {
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        let mut stmt = conn.prepare("PRAGMA quick_check")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.collect()
+    };
+    match check() {
+        Ok(rows) if rows.len() == 1 && rows[0] == "ok" => Ok(None),
+        Ok(mut rows) => {
+            rows.truncate(MAX_REPORTED_PROBLEMS);
+            Ok(Some(rows))
+        }
+        Err(rusqlite::Error::SqliteFailure(e, message))
+            if matches!(e.code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase) =>
+        {
+            Ok(Some(vec![message.unwrap_or_else(|| e.to_string())]))
+        }
+        Err(e) => {
+            Err(e).with_context(|| format!("Failed to check SQLite database: {}", path.display()))
+        }
+    }
+}
+
+/// Renames the database and its `-wal` and `-shm` files to
+/// `<name>.corrupt-<timestamp>` and returns the database's new path.
+fn move_aside(path: &Path) -> Result<PathBuf> {
+    let suffix = format!(".corrupt-{}", Utc::now().format("%Y%m%dT%H%M%S"));
+    let mut moved_to = path.as_os_str().to_owned();
+    moved_to.push(&suffix);
+    let moved_to = PathBuf::from(moved_to);
+    fs::rename(path, &moved_to)
+        .with_context(|| format!("Failed to move corrupt database aside: {}", path.display()))?;
+    for sidecar in ["-wal", "-shm"] {
+        let mut from = path.as_os_str().to_owned();
+        from.push(sidecar);
+        let from = PathBuf::from(from);
+        if from.exists() {
+            let mut to = moved_to.as_os_str().to_owned();
+            to.push(sidecar);
+            fs::rename(&from, PathBuf::from(to)).with_context(|| {
+                format!("Failed to move corrupt database aside: {}", from.display())
+            })?;
+        }
+    }
+    Ok(moved_to)
+}
@@ -0,0 +1,232 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::repositories::{enable_wal, ensure_intact, has_column};
+
+/// A synced document as stored in one index version.
+#[derive(Debug, Clone)]
+pub struct KnowledgeDocumentRecord {
+    pub doc_id: String,
+    pub title: String,
+    pub content_hash: String,
+    pub chunks: Vec<KnowledgeChunkRecord>,
+}
+
+#[derive(Debug, Clone)]
+pub struct KnowledgeChunkRecord {
+    pub text: String,
+    /// Hashed term vector, always present.
+    pub vector: Vec<f32>,
+    /// Sentence embedding from the version's `embedding_model`, if any.
+    pub embedding: Option<Vec<f32>>,
+}
+
+/// An index version as loaded from the store.
+#[derive(Debug, Clone)]
+pub struct KnowledgeVersionRecord {
+    pub version: String,
+    /// Encoder that embedded every chunk of this version; `None` when the
+    /// chunks only carry term vectors.
+    pub embedding_model: Option<String>,
+    pub documents: Vec<KnowledgeDocumentRecord>,
+}
+
+/// Versioned knowledge-base index. A sync writes a complete new version and
+/// makes it active in the same transaction, so readers never see a mix.
+#[derive(Clone)]
+pub struct KnowledgeRepo {
+    path: PathBuf,
+}
+
+impl KnowledgeRepo {
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Failed to create knowledge base directory: {}",
+                    parent.display()
+                )
+            })?;
+        }
+        ensure_intact(&path)?;
+        let repo = Self { path };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        enable_wal(&conn)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS kb_versions (
+                version TEXT PRIMARY KEY,
+                created_at INTEGER NOT NULL,
+                active INTEGER NOT NULL DEFAULT 0
+            );
+            CREATE TABLE IF NOT EXISTS kb_documents (
+                version TEXT NOT NULL,
+                doc_id TEXT NOT NULL,
+                title TEXT NOT NULL,
+                content_hash TEXT NOT NULL,
+                PRIMARY KEY (version, doc_id)
+            );
+            CREATE TABLE IF NOT EXISTS kb_chunks (
+                version TEXT NOT NULL,
+                doc_id TEXT NOT NULL,
+                ordinal INTEGER NOT NULL,
+                text TEXT NOT NULL,
+                vector BLOB NOT NULL,
+                embedding BLOB,
+                PRIMARY KEY (version, doc_id, ordinal)
+            );",
+        )?;
+        // Stores created by older versions lack the embeddings.
+        if !has_column(&conn, "kb_versions", "embedding_model")? {
+            conn.execute_batch("ALTER TABLE kb_versions ADD COLUMN embedding_model TEXT")?;
+        }
+        if !has_column(&conn, "kb_chunks", "embedding")? {
+            conn.execute_batch("ALTER TABLE kb_chunks ADD COLUMN embedding BLOB")?;
+        }
+        Ok(())
+    }
+
+    /// The active version and its documents, or `None` before the first sync.
+    pub fn load_active(&self) -> Result<Option<KnowledgeVersionRecord>> {
+        let conn = Connection::open(&self.path)?;
+        let active: Option<(String, Option<String>)> = conn
+            .query_row(
+                "SELECT version, embedding_model FROM kb_versions WHERE active = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let Some((version, embedding_model)) = active else {
+            return Ok(None);
+        };
+
+        let mut documents = {
+            let mut stmt = conn.prepare(
+                "SELECT doc_id, title, content_hash FROM kb_documents
+                 WHERE version = ?1 ORDER BY doc_id",
+            )?;
+            let rows = stmt.query_map(params![version], |row| {
+                Ok(KnowledgeDocumentRecord {
+                    doc_id: row.get(0)?,
+                    title: row.get(1)?,
+                    content_hash: row.get(2)?,
+                    chunks: Vec::new(),
+                })
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT doc_id, text, vector, embedding FROM kb_chunks
+             WHERE version = ?1 ORDER BY doc_id, ordinal",
+        )?;
+        let mut rows = stmt.query(params![version])?;
+        let mut index = 0;
+        while let Some(row) = rows.next()? {
+            let doc_id: String = row.get(0)?;
+            while index < documents.len() && documents[index].doc_id != doc_id {
+                index += 1;
+            }
+            let Some(document) = documents.get_mut(index) else {
+                break;
+            };
+            document.chunks.push(KnowledgeChunkRecord {
+                text: row.get(1)?,
+                vector: decode_vector(&row.get::<_, Vec<u8>>(2)?),
+                embedding: row
+                    .get::<_, Option<Vec<u8>>>(3)?
+                    .map(|bytes| decode_vector(&bytes)),
+            });
+        }
+        Ok(Some(KnowledgeVersionRecord {
+            version,
+            embedding_model,
+            documents,
+        }))
+    }
+
+    /// Stores `documents` as `version`, makes it the active version and drops
+    /// the older ones, all in one transaction.
+    pub fn publish(
+        &self,
+        version: &str,
+        embedding_model: Option<&str>,
+        documents: &[KnowledgeDocumentRecord],
+    ) -> Result<()> {
+        let mut conn = Connection::open(&self.path)?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM kb_chunks WHERE version = ?1", params![version])?;
+        tx.execute(
+            "DELETE FROM kb_documents WHERE version = ?1",
+            params![version],
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO kb_versions (version, created_at, active, embedding_model)
+             VALUES (?1, ?2, 0, ?3)",
+            params![version, Utc::now().timestamp(), embedding_model],
+        )?;
+        for document in documents {
+            tx.execute(
+                "INSERT INTO kb_documents (version, doc_id, title, content_hash)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    version,
+                    document.doc_id,
+                    document.title,
+                    document.content_hash
+                ],
+            )?;
+            for (ordinal, chunk) in document.chunks.iter().enumerate() {
+                tx.execute(
+                    "INSERT INTO kb_chunks (version, doc_id, ordinal, text, vector, embedding)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        version,
+                        document.doc_id,
+                        ordinal as i64,
+                        chunk.text,
+                        encode_vector(&chunk.vector),
+                        chunk.embedding.as_deref().map(encode_vector)
+                    ],
+                )?;
+            }
+        }
+        tx.execute(
+            "UPDATE kb_versions SET active = (version = ?1)",
+            params![version],
+        )?;
+        tx.execute(
+            "DELETE FROM kb_chunks WHERE version != ?1",
+            params![version],
+        )?;
+        tx.execute(
+            "DELETE FROM kb_documents WHERE version != ?1",
+            params![version],
+        )?;
+        tx.execute(
+            "DELETE FROM kb_versions WHERE version != ?1",
+            params![version],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+fn decode_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
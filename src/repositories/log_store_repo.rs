@@ -0,0 +1,95 @@
+use anyhow::{Context, Result};
+use chrono::{Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::repositories::{enable_wal, ensure_intact};
+use std::fs;
+use std::path::PathBuf;
+
+/// Parsed log digests keyed by the hash of the uploaded payload.
+#[derive(Clone)]
+pub struct LogStoreRepo {
+    path: PathBuf,
+    retention_days: u32,
+}
+
+impl LogStoreRepo {
+    pub fn new(path: impl Into<PathBuf>, retention_days: u32) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create log store directory: {}", parent.display())
+            })?;
+        }
+        ensure_intact(&path)?;
+        let repo = Self {
+            path,
+            retention_days,
+        };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        enable_wal(&conn)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS log_digests (
+                content_hash TEXT PRIMARY KEY,
+                payload_bytes INTEGER NOT NULL,
+                digest_json TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                last_used_at INTEGER NOT NULL,
+                uses INTEGER NOT NULL DEFAULT 1
+            );
+            CREATE INDEX IF NOT EXISTS idx_log_digests_last_used
+                ON log_digests(last_used_at);",
+        )?;
+        Ok(())
+    }
+
+    /// Returns the stored digest and marks it as used.
+    pub fn get(&self, content_hash: &str) -> Result<Option<String>> {
+        let conn = Connection::open(&self.path)?;
+        let digest = conn
+            .query_row(
+                "SELECT digest_json FROM log_digests WHERE content_hash = ?1",
+                params![content_hash],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        if digest.is_some() {
+            conn.execute(
+                "UPDATE log_digests SET last_used_at = ?1, uses = uses + 1 WHERE content_hash = ?2",
+                params![Utc::now().timestamp(), content_hash],
+            )?;
+        }
+        Ok(digest)
+    }
+
+    /// Stores a digest and drops the ones unused for longer than the retention period.
+    pub fn put(&self, content_hash: &str, payload_bytes: usize, digest_json: &str) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        let now = Utc::now();
+        conn.execute(
+            "INSERT INTO log_digests
+                (content_hash, payload_bytes, digest_json, created_at, last_used_at)
+             VALUES (?1, ?2, ?3, ?4, ?4)
+             ON CONFLICT(content_hash) DO UPDATE SET last_used_at = excluded.last_used_at",
+            params![
+                content_hash,
+                payload_bytes as i64,
+                digest_json,
+                now.timestamp()
+            ],
+        )?;
+        if self.retention_days > 0 {
+            let cutoff = now - Duration::days(self.retention_days as i64);
+            conn.execute(
+                "DELETE FROM log_digests WHERE last_used_at < ?1",
+                params![cutoff.timestamp()],
+            )?;
+        }
+        Ok(())
+    }
+}
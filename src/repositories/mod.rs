@@ -1,5 +1,35 @@
+pub mod agent_repo;
 pub mod cache_repo;
+pub mod cache_stats_repo;
+pub mod capture_repo;
+pub mod conversation_repo;
+pub mod feedback_repo;
+pub mod integrity;
+pub mod knowledge_repo;
+pub mod log_store_repo;
+pub mod namespace_repo;
+pub mod pagination;
+pub mod postgres_repo;
+pub mod read_pool;
 pub mod redis_repo;
+pub mod routing_repo;
+pub mod spend_repo;
+pub mod topic_repo;
 
+pub use agent_repo::*;
 pub use cache_repo::*;
+pub use cache_stats_repo::*;
+pub use capture_repo::*;
+pub use conversation_repo::*;
+pub use feedback_repo::*;
+pub use integrity::*;
+pub use knowledge_repo::*;
+pub use log_store_repo::*;
+pub use namespace_repo::*;
+pub use pagination::*;
+pub use postgres_repo::*;
+pub use read_pool::*;
 pub use redis_repo::*;
+pub use routing_repo::*;
+pub use spend_repo::*;
+pub use topic_repo::*;
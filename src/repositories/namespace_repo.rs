@@ -0,0 +1,74 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::repositories::{enable_wal, ensure_intact};
+
+/// Per-template version counters that namespace cached answers, stored beside
+/// the SQLite cache.
+#[derive(Clone)]
+pub struct NamespaceRepo {
+    path: PathBuf,
+}
+
+impl NamespaceRepo {
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!("Failed to create cache directory: {}", parent.display())
+            })?;
+        }
+        ensure_intact(&path)?;
+        let repo = Self { path };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        enable_wal(&conn)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS cache_template_versions (
+                template TEXT PRIMARY KEY,
+                version INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );",
+        )?;
+        Ok(())
+    }
+
+    /// Every template that has been bumped at least once.
+    pub fn load(&self) -> Result<BTreeMap<String, u64>> {
+        let conn = Connection::open(&self.path)?;
+        let mut stmt = conn.prepare("SELECT template, version FROM cache_template_versions")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<BTreeMap<_, _>>>()?)
+    }
+
+    /// Increments the version of `template` and returns the new value.
+    pub fn bump(&self, template: &str) -> Result<u64> {
+        let mut conn = Connection::open(&self.path)?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO cache_template_versions (template, version, updated_at)
+             VALUES (?1, 1, ?2)
+             ON CONFLICT(template) DO UPDATE SET
+                version = version + 1,
+                updated_at = excluded.updated_at",
+            params![template, Utc::now().timestamp()],
+        )?;
+        let version: i64 = tx.query_row(
+            "SELECT version FROM cache_template_versions WHERE template = ?1",
+            params![template],
+            |row| row.get(0),
+        )?;
+        tx.commit()?;
+        Ok(version as u64)
+    }
+}
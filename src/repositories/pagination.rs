@@ -0,0 +1,51 @@
+use rusqlite::types::Value;
+
+use crate::models::{Page, SortOrder};
+
+/// Builds the `WHERE … ORDER BY … LIMIT` tail for keyset pagination over
+/// `(timestamp_column, id_column)`. One row past the page limit is requested so the
+/// caller can tell whether another page exists.
+pub fn keyset_clause(page: &Page, timestamp_column: &str, id_column: &str) -> (String, Vec<Value>) {
+    let mut conditions = Vec::new();
+    let mut params = Vec::new();
+
+    if let Some(since) = page.since {
+        params.push(Value::Integer(since.timestamp()));
+        conditions.push(format!("{} >= ?{}", timestamp_column, params.len()));
+    }
+    if let Some(until) = page.until {
+        params.push(Value::Integer(until.timestamp()));
+        conditions.push(format!("{} < ?{}", timestamp_column, params.len()));
+    }
+    if let Some(cursor) = &page.cursor {
+        params.push(Value::Integer(cursor.timestamp));
+        params.push(Value::Text(cursor.id.clone()));
+        let op = match page.sort {
+            SortOrder::Asc => ">",
+            SortOrder::Desc => "<",
+        };
+        conditions.push(format!(
+            "({}, {}) {} (?{}, ?{})",
+            timestamp_column,
+            id_column,
+            op,
+            params.len() - 1,
+            params.len()
+        ));
+    }
+
+    let mut sql = String::new();
+    if !conditions.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&conditions.join(" AND "));
+    }
+    params.push(Value::Integer(page.limit as i64 + 1));
+    sql.push_str(&format!(
+        " ORDER BY {ts} {dir}, {id} {dir} LIMIT ?{limit}",
+        ts = timestamp_column,
+        id = id_column,
+        dir = page.sort.as_sql(),
+        limit = params.len()
+    ));
+    (sql, params)
+}
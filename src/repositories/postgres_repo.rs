@@ -0,0 +1,315 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use postgres::{Client, Config};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+use crate::repositories::{CacheRecord, CacheStore, TaggedCacheRecord};
+
+/// Idle connections kept between cache calls.
+const MAX_IDLE_CONNECTIONS: usize = 4;
+
+/// TLS material for the Postgres connection. Whether TLS is used follows the
+/// URL's `sslmode`; the server certificate is always verified when it is.
+#[derive(Debug, Clone, Default)]
+pub struct PostgresTlsOptions {
+    pub ca_cert_path: Option<String>,
+    pub client_cert_path: Option<String>,
+    pub client_key_path: Option<String>,
+}
+
+/// Durable cache tier on PostgreSQL, with the same tables as the SQLite store,
+/// for deployments without persistent local disk.
+#[derive(Clone)]
+pub struct PostgresRepo {
+    config: Config,
+    tls: MakeRustlsConnect,
+    ttl_days: i64,
+    max_size_bytes: u64,
+    idle: Arc<Mutex<Vec<Client>>>,
+}
+
+impl PostgresRepo {
+    pub fn new(
+        url: &str,
+        ttl_days: u32,
+        max_size_gb: u64,
+        tls: &PostgresTlsOptions,
+    ) -> Result<Self> {
+        let repo = Self {
+            config: parse_url(url)?,
+            tls: tls_connector(tls)?,
+            ttl_days: ttl_days as i64,
+            max_size_bytes: max_size_gb * 1024 * 1024 * 1024,
+            idle: Arc::new(Mutex::new(Vec::new())),
+        };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        self.with(|client| {
+            client.batch_execute(
+                "CREATE TABLE IF NOT EXISTS ai_cache (
+                    cache_key TEXT PRIMARY KEY,
+                    response_json TEXT NOT NULL,
+                    created_at BIGINT NOT NULL,
+                    expires_at BIGINT NOT NULL,
+                    hits BIGINT NOT NULL DEFAULT 0
+                );
+                CREATE INDEX IF NOT EXISTS idx_ai_cache_expires ON ai_cache(expires_at);
+                CREATE TABLE IF NOT EXISTS ai_cache_tags (
+                    cache_key TEXT NOT NULL,
+                    tag TEXT NOT NULL,
+                    PRIMARY KEY (cache_key, tag)
+                );
+                CREATE INDEX IF NOT EXISTS idx_ai_cache_tags_tag ON ai_cache_tags(tag);",
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Runs `query` on a pooled connection. A connection that returned an
+    /// error is dropped rather than pooled, since it may be broken.
+    fn with<T>(&self, query: impl FnOnce(&mut Client) -> Result<T>) -> Result<T> {
+        let pooled = self.idle.lock().ok().and_then(|mut idle| idle.pop());
+        let mut client = match pooled {
+            Some(client) if !client.is_closed() => client,
+            _ => self
+                .config
+                .connect(self.tls.clone())
+                .context("Failed to connect to the Postgres cache")?,
+        };
+
+        let result = query(&mut client);
+        if result.is_ok() {
+            if let Ok(mut idle) = self.idle.lock() {
+                if idle.len() < MAX_IDLE_CONNECTIONS {
+                    idle.push(client);
+                }
+            }
+        }
+        result
+    }
+
+    fn cleanup_if_needed(&self, client: &mut Client) -> Result<()> {
+        if self.max_size_bytes == 0 {
+            return Ok(());
+        }
+        let size: i64 = client
+            .query_one(
+                "SELECT pg_total_relation_size('ai_cache') + pg_total_relation_size('ai_cache_tags')",
+                &[],
+            )?
+            .get(0);
+        if (size as u64) <= self.max_size_bytes {
+            return Ok(());
+        }
+
+        // Space is reused by later writes; autovacuum reclaims it, so no VACUUM here.
+        client.batch_execute(
+            "DELETE FROM ai_cache
+             WHERE cache_key IN (
+                SELECT cache_key FROM ai_cache ORDER BY created_at ASC LIMIT 500
+             );
+             DELETE FROM ai_cache_tags
+             WHERE cache_key NOT IN (SELECT cache_key FROM ai_cache);",
+        )?;
+        Ok(())
+    }
+}
+
+impl CacheStore for PostgresRepo {
+    fn get(&self, key: &str) -> Result<Option<CacheRecord>> {
+        self.with(|client| {
+            let now = Utc::now().timestamp();
+            let row = client.query_opt(
+                "UPDATE ai_cache SET hits = hits + 1
+                 WHERE cache_key = $1 AND expires_at > $2
+                 RETURNING cache_key, response_json, created_at, expires_at, hits",
+                &[&key, &now],
+            )?;
+            Ok(row.map(|row| CacheRecord {
+                key: row.get(0),
+                value_json: row.get(1),
+                created_at: timestamp(row.get(2)),
+                expires_at: timestamp(row.get(3)),
+                hits: row.get::<_, i64>(4) as u64,
+            }))
+        })
+    }
+
+    fn set(
+        &self,
+        key: &str,
+        value_json: &str,
+        tags: &[String],
+        ttl_seconds: Option<u64>,
+    ) -> Result<()> {
+        self.with(|client| {
+            let now = Utc::now();
+            let expires_at = match ttl_seconds {
+                Some(ttl_seconds) => now + Duration::seconds(ttl_seconds as i64),
+                None => now + Duration::days(self.ttl_days),
+            };
+
+            let mut tx = client.transaction()?;
+            tx.execute(
+                "INSERT INTO ai_cache (cache_key, response_json, created_at, expires_at, hits)
+                 VALUES ($1, $2, $3, $4, 0)
+                 ON CONFLICT (cache_key) DO UPDATE SET
+                    response_json = excluded.response_json,
+                    created_at = excluded.created_at,
+                    expires_at = excluded.expires_at",
+                &[&key, &value_json, &now.timestamp(), &expires_at.timestamp()],
+            )?;
+            tx.execute("DELETE FROM ai_cache_tags WHERE cache_key = $1", &[&key])?;
+            for tag in tags {
+                tx.execute(
+                    "INSERT INTO ai_cache_tags (cache_key, tag) VALUES ($1, $2)
+                     ON CONFLICT DO NOTHING",
+                    &[&key, tag],
+                )?;
+            }
+            tx.commit()?;
+            self.cleanup_if_needed(client)
+        })
+    }
+
+    fn invalidate_tag(&self, tag: &str) -> Result<Vec<String>> {
+        self.with(|client| {
+            let mut tx = client.transaction()?;
+            let keys = tx
+                .query(
+                    "DELETE FROM ai_cache
+                     WHERE cache_key IN (SELECT cache_key FROM ai_cache_tags WHERE tag = $1)
+                     RETURNING cache_key",
+                    &[&tag],
+                )?
+                .iter()
+                .map(|row| row.get::<_, String>(0))
+                .collect();
+            tx.execute(
+                "DELETE FROM ai_cache_tags
+                 WHERE cache_key IN (SELECT cache_key FROM ai_cache_tags WHERE tag = $1)",
+                &[&tag],
+            )?;
+            tx.commit()?;
+            Ok(keys)
+        })
+    }
+
+    fn get_stale(&self, key: &str, max_stale_seconds: u64) -> Result<Option<CacheRecord>> {
+        self.with(|client| {
+            let now = Utc::now().timestamp();
+            let row = client.query_opt(
+                "SELECT cache_key, response_json, created_at, expires_at, hits
+                 FROM ai_cache
+                 WHERE cache_key = $1 AND expires_at <= $2 AND expires_at > $3",
+                &[&key, &now, &(now - max_stale_seconds as i64)],
+            )?;
+            Ok(row.map(|row| CacheRecord {
+                key: row.get(0),
+                value_json: row.get(1),
+                created_at: timestamp(row.get(2)),
+                expires_at: timestamp(row.get(3)),
+                hits: row.get::<_, i64>(4) as u64,
+            }))
+        })
+    }
+
+    fn cleanup_expired(&self, retain_seconds: u64) -> Result<u64> {
+        self.with(|client| {
+            let cutoff = Utc::now().timestamp() - retain_seconds as i64;
+            let rows = client.execute("DELETE FROM ai_cache WHERE expires_at <= $1", &[&cutoff])?;
+            client.execute(
+                "DELETE FROM ai_cache_tags WHERE cache_key NOT IN (SELECT cache_key FROM ai_cache)",
+                &[],
+            )?;
+            Ok(rows)
+        })
+    }
+
+    fn list(
+        &self,
+        after: Option<&str>,
+        tag: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<TaggedCacheRecord>> {
+        self.with(|client| {
+            let now = Utc::now().timestamp();
+            let rows = client.query(
+                "SELECT c.cache_key, c.response_json, c.created_at, c.expires_at, c.hits,
+                        COALESCE(
+                            (SELECT array_agg(t.tag ORDER BY t.tag) FROM ai_cache_tags t
+                             WHERE t.cache_key = c.cache_key),
+                            '{}'
+                        )
+                 FROM ai_cache c
+                 WHERE c.expires_at > $1 AND c.cache_key > $2
+                   AND ($3::TEXT IS NULL OR EXISTS (
+                        SELECT 1 FROM ai_cache_tags t
+                        WHERE t.cache_key = c.cache_key AND t.tag = $3))
+                 ORDER BY c.cache_key
+                 LIMIT $4",
+                &[&now, &after.unwrap_or(""), &tag, &(limit as i64)],
+            )?;
+            Ok(rows
+                .iter()
+                .map(|row| TaggedCacheRecord {
+                    record: CacheRecord {
+                        key: row.get(0),
+                        value_json: row.get(1),
+                        created_at: timestamp(row.get(2)),
+                        expires_at: timestamp(row.get(3)),
+                        hits: row.get::<_, i64>(4) as u64,
+                    },
+                    tags: row.get(5),
+                })
+                .collect())
+        })
+    }
+}
+
+fn timestamp(seconds: i64) -> DateTime<Utc> {
+    DateTime::<Utc>::from_timestamp(seconds, 0).unwrap_or_default()
+}
+
+/// Parses the connection URL. The driver knows `sslmode` `disable`, `prefer`
+/// and `require`; `verify-ca` and `verify-full` are accepted as `require`,
+/// since the certificate and host name are verified whenever TLS is used.
+fn parse_url(url: &str) -> Result<Config> {
+    url.replace("sslmode=verify-full", "sslmode=require")
+        .replace("sslmode=verify-ca", "sslmode=require")
+        .parse()
+        .context("Invalid CACHE_POSTGRES_URL")
+}
+
+fn tls_connector(options: &PostgresTlsOptions) -> Result<MakeRustlsConnect> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if let Some(path) = &options.ca_cert_path {
+        for cert in rustls_pemfile::certs(&mut read_pem(path)?.as_slice()) {
+            roots.add(cert?)?;
+        }
+    }
+
+    let builder = rustls::ClientConfig::builder().with_root_certificates(roots);
+    let config = match (&options.client_cert_path, &options.client_key_path) {
+        (Some(cert), Some(key)) => {
+            let chain = rustls_pemfile::certs(&mut read_pem(cert)?.as_slice())
+                .collect::<std::io::Result<Vec<_>>>()?;
+            let key = rustls_pemfile::private_key(&mut read_pem(key)?.as_slice())?
+                .ok_or_else(|| anyhow!("No private key in {}", key))?;
+            builder.with_client_auth_cert(chain, key)?
+        }
+        (None, None) => builder.with_no_client_auth(),
+        _ => bail!("CACHE_POSTGRES_CLIENT_CERT and CACHE_POSTGRES_CLIENT_KEY must be set together"),
+    };
+    Ok(MakeRustlsConnect::new(config))
+}
+
+fn read_pem(path: &str) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("Failed to read Postgres certificate: {}", path))
+}
@@ -0,0 +1,71 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Small pool of read-only SQLite connections for admin reporting. The writers
+/// run in WAL mode, so these readers never block chat-path writes; pointing the
+/// pool at a replica file moves the load off the primary entirely.
+#[derive(Clone)]
+pub struct ReadPool {
+    path: PathBuf,
+    idle: Arc<Mutex<Vec<Connection>>>,
+    max_idle: usize,
+}
+
+impl ReadPool {
+    pub fn new(path: impl Into<PathBuf>, max_idle: usize) -> Self {
+        Self {
+            path: path.into(),
+            idle: Arc::new(Mutex::new(Vec::new())),
+            max_idle: max_idle.max(1),
+        }
+    }
+
+    /// Runs `query` on a pooled connection, returning it to the pool afterwards.
+    pub fn with<T>(&self, query: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        let pooled = self.idle.lock().ok().and_then(|mut idle| idle.pop());
+        let conn = match pooled {
+            Some(conn) => conn,
+            None => self.open()?,
+        };
+
+        let result = query(&conn);
+        if let Ok(mut idle) = self.idle.lock() {
+            if idle.len() < self.max_idle {
+                idle.push(conn);
+            }
+        }
+        result
+    }
+
+    fn open(&self) -> Result<Connection> {
+        let conn = Connection::open_with_flags(
+            &self.path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .with_context(|| format!("Failed to open reporting database: {}", self.path.display()))?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch("PRAGMA query_only = ON;")?;
+        Ok(conn)
+    }
+}
+
+/// Switches the database to WAL so readers and the writer do not block each other.
+pub fn enable_wal(conn: &Connection) -> Result<()> {
+    conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+    Ok(())
+}
+
+/// Whether `table` already has `column`, for adding columns to stores created by older versions.
+pub fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for name in names {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
@@ -1,23 +1,104 @@
-use anyhow::Result;
-use redis::aio::ConnectionManager;
-use redis::AsyncCommands;
+use anyhow::{bail, Context, Result};
+use redis::aio::{ConnectionManager, PubSub};
+use redis::{AsyncCommands, ClientTlsConfig, ConnectionAddr, IntoConnectionInfo, TlsCertificates};
+use std::fs;
+
+/// Tag set members checked per round trip while pruning.
+const PRUNE_BATCH_SIZE: usize = 500;
+
+/// Credentials and TLS material applied on top of the Redis URL.
+#[derive(Debug, Clone, Default)]
+pub struct RedisConnectOptions {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub ca_cert_path: Option<String>,
+    pub client_cert_path: Option<String>,
+    pub client_key_path: Option<String>,
+}
+
+impl RedisConnectOptions {
+    fn has_certificates(&self) -> bool {
+        self.ca_cert_path.is_some()
+            || self.client_cert_path.is_some()
+            || self.client_key_path.is_some()
+    }
+}
 
 #[derive(Clone)]
 pub struct RedisRepo {
+    client: redis::Client,
     manager: ConnectionManager,
     ttl_seconds: u64,
 }
 
 impl RedisRepo {
-    pub async fn new(redis_url: &str, ttl_seconds: u64) -> Result<Self> {
-        let client = redis::Client::open(redis_url)?;
+    pub async fn new(
+        redis_url: &str,
+        ttl_seconds: u64,
+        options: &RedisConnectOptions,
+    ) -> Result<Self> {
+        let mut info = redis_url.into_connection_info()?;
+        if let Some(username) = &options.username {
+            info.redis.username = Some(username.clone());
+        }
+        if let Some(password) = &options.password {
+            info.redis.password = Some(password.clone());
+        }
+
+        let client = if options.has_certificates() {
+            if !matches!(info.addr, ConnectionAddr::TcpTls { .. }) {
+                bail!("Redis TLS certificates are configured but REDIS_URL is not rediss://");
+            }
+            let root_cert = match &options.ca_cert_path {
+                Some(path) => Some(read_pem(path)?),
+                None => None,
+            };
+            let client_tls = match (&options.client_cert_path, &options.client_key_path) {
+                (Some(cert), Some(key)) => Some(ClientTlsConfig {
+                    client_cert: read_pem(cert)?,
+                    client_key: read_pem(key)?,
+                }),
+                (None, None) => None,
+                _ => bail!("REDIS_CLIENT_CERT and REDIS_CLIENT_KEY must be set together"),
+            };
+            redis::Client::build_with_tls(
+                info,
+                TlsCertificates {
+                    client_tls,
+                    root_cert,
+                },
+            )?
+        } else {
+            redis::Client::open(info)?
+        };
         let manager = client.get_connection_manager().await?;
         Ok(Self {
+            client,
             manager,
             ttl_seconds,
         })
     }
 
+    pub async fn ping(&self) -> Result<()> {
+        let mut conn = self.manager.clone();
+        redis::cmd("PING").query_async::<_, ()>(&mut conn).await?;
+        Ok(())
+    }
+
+    pub async fn publish(&self, channel: &str, message: &str) -> Result<()> {
+        let mut conn = self.manager.clone();
+        conn.publish::<_, _, ()>(channel, message).await?;
+        Ok(())
+    }
+
+    /// Opens a dedicated connection subscribed to `channel`. It is not
+    /// reconnected automatically; callers subscribe again once it closes.
+    pub async fn subscribe(&self, channel: &str) -> Result<PubSub> {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        pubsub.subscribe(channel).await?;
+        Ok(pubsub)
+    }
+
     pub async fn get(&self, key: &str) -> Result<Option<String>> {
         let mut conn = self.manager.clone();
         let value: Option<String> = conn.get(key).await?;
@@ -25,13 +106,131 @@ impl RedisRepo {
     }
 
     pub async fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.set_with_ttl(key, value, self.ttl_seconds).await
+    }
+
+    /// Like [`RedisRepo::set`] with an explicit expiry; 0 keeps the key forever.
+    pub async fn set_with_ttl(&self, key: &str, value: &str, ttl_seconds: u64) -> Result<()> {
         let mut conn = self.manager.clone();
-        if self.ttl_seconds > 0 {
-            conn.set_ex::<_, _, ()>(key, value, self.ttl_seconds)
-                .await?;
+        if ttl_seconds > 0 {
+            conn.set_ex::<_, _, ()>(key, value, ttl_seconds).await?;
         } else {
             conn.set::<_, _, ()>(key, value).await?;
         }
         Ok(())
     }
+
+    /// Stores the value and records its key in one set per tag. `ttl_seconds`
+    /// overrides the configured expiry for this entry.
+    pub async fn set_tagged(
+        &self,
+        key: &str,
+        value: &str,
+        tags: &[String],
+        ttl_seconds: Option<u64>,
+    ) -> Result<()> {
+        let ttl_seconds = ttl_seconds.unwrap_or(self.ttl_seconds);
+        self.set_with_ttl(key, value, ttl_seconds).await?;
+        if tags.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self.manager.clone();
+        let mut pipe = redis::pipe();
+        for tag in tags {
+            let tag_key = tag_set_key(tag);
+            pipe.sadd(&tag_key, key).ignore();
+            if self.ttl_seconds > 0 && ttl_seconds > 0 {
+                // The set only needs to outlive the newest entry it references;
+                // a shorter per-entry expiry must not cut it short for the others.
+                pipe.expire(&tag_key, ttl_seconds.max(self.ttl_seconds) as i64)
+                    .ignore();
+            }
+        }
+        pipe.query_async::<_, ()>(&mut conn).await?;
+        Ok(())
+    }
+
+    /// Deletes every key recorded under `tag` and returns the keys that still existed.
+    pub async fn invalidate_tag(&self, tag: &str) -> Result<Vec<String>> {
+        let mut conn = self.manager.clone();
+        let tag_key = tag_set_key(tag);
+        let keys: Vec<String> = conn.smembers(&tag_key).await?;
+        let mut removed = Vec::new();
+        for key in keys {
+            let deleted: u64 = conn.del(&key).await?;
+            if deleted > 0 {
+                removed.push(key);
+            }
+        }
+        conn.del::<_, ()>(&tag_key).await?;
+        Ok(removed)
+    }
+
+    /// Removes keys that have expired from the tag sets still referencing
+    /// them and returns how many were removed.
+    pub async fn prune_tag_sets(&self) -> Result<u64> {
+        let mut conn = self.manager.clone();
+        let tag_keys = {
+            let mut iter = conn.scan_match::<_, String>(tag_set_key("*")).await?;
+            let mut tag_keys = Vec::new();
+            while let Some(tag_key) = iter.next_item().await {
+                tag_keys.push(tag_key);
+            }
+            tag_keys
+        };
+
+        // Members are read with SSCAN in batches, so a large tag set never
+        // has to be loaded, or block Redis, in one reply.
+        let mut pruned = 0;
+        for tag_key in tag_keys {
+            let mut scan_conn = self.manager.clone();
+            let mut members = scan_conn.sscan::<_, String>(&tag_key).await?;
+            let mut batch = Vec::new();
+            loop {
+                let next = members.next_item().await;
+                let done = next.is_none();
+                batch.extend(next);
+                if batch.len() >= PRUNE_BATCH_SIZE || (done && !batch.is_empty()) {
+                    pruned += remove_expired_members(&mut conn, &tag_key, &batch).await?;
+                    batch.clear();
+                }
+                if done {
+                    break;
+                }
+            }
+        }
+        Ok(pruned)
+    }
+}
+
+/// Removes the `keys` that no longer exist from the tag set at `tag_key`.
+async fn remove_expired_members(
+    conn: &mut ConnectionManager,
+    tag_key: &str,
+    keys: &[String],
+) -> Result<u64> {
+    let mut pipe = redis::pipe();
+    for key in keys {
+        pipe.exists(key);
+    }
+    let exists: Vec<bool> = pipe.query_async(conn).await?;
+    let expired: Vec<&String> = keys
+        .iter()
+        .zip(exists)
+        .filter(|(_, exists)| !exists)
+        .map(|(key, _)| key)
+        .collect();
+    if expired.is_empty() {
+        return Ok(0);
+    }
+    Ok(conn.srem::<_, _, u64>(tag_key, expired).await?)
+}
+
+fn tag_set_key(tag: &str) -> String {
+    format!("cache:tag:{}", tag)
+}
+
+fn read_pem(path: &str) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("Failed to read Redis certificate: {}", path))
 }
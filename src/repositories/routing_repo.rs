@@ -0,0 +1,241 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+
+use crate::repositories::{enable_wal, ensure_intact, has_column, ReadPool};
+use std::fs;
+use std::path::PathBuf;
+
+/// Aggregated outcomes for one complexity tier.
+#[derive(Debug, Clone)]
+pub struct RoutingStatsRecord {
+    pub complexity: String,
+    pub forced: bool,
+    pub total: u64,
+    pub negative_feedback: u64,
+    pub escalated: u64,
+    pub avg_message_chars: f64,
+}
+
+/// One generated answer and how it was produced.
+#[derive(Debug, Clone)]
+pub struct RoutingDecisionRecord {
+    pub complexity: String,
+    pub forced: bool,
+    pub message_chars: usize,
+    pub model: Option<String>,
+    pub duration_ms: u64,
+    pub cost_usd: Option<f64>,
+    pub response_hash: Option<String>,
+}
+
+/// Outcomes of the answers one model produced.
+#[derive(Debug, Clone, Default)]
+pub struct ModelOutcomeRecord {
+    pub answers: u64,
+    /// Ascending.
+    pub durations_ms: Vec<u64>,
+    pub negative_feedback: u64,
+    pub escalated: u64,
+    pub cost_usd: f64,
+    /// Answers with a known cost; local answers have none.
+    pub costed_answers: u64,
+    pub positive_ratings: u64,
+    pub negative_ratings: u64,
+}
+
+#[derive(Clone)]
+pub struct RoutingRepo {
+    path: PathBuf,
+    reporting: ReadPool,
+}
+
+impl RoutingRepo {
+    /// `reporting` serves [`RoutingRepo::stats_since`]; writes always go to `path`.
+    pub fn new(path: impl Into<PathBuf>, reporting: ReadPool) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Failed to create routing store directory: {}",
+                    parent.display()
+                )
+            })?;
+        }
+        ensure_intact(&path)?;
+        let repo = Self { path, reporting };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        enable_wal(&conn)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS routing_decisions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                conversation_id TEXT NOT NULL,
+                complexity TEXT NOT NULL,
+                forced INTEGER NOT NULL DEFAULT 0,
+                message_chars INTEGER NOT NULL,
+                negative_feedback INTEGER NOT NULL DEFAULT 0,
+                escalated INTEGER NOT NULL DEFAULT 0,
+                created_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_routing_decisions_conversation
+                ON routing_decisions(conversation_id, id);
+            CREATE INDEX IF NOT EXISTS idx_routing_decisions_created
+                ON routing_decisions(created_at);",
+        )?;
+        // Stores created by older versions lack these columns.
+        for (column, definition) in [
+            ("model", "TEXT"),
+            ("duration_ms", "INTEGER"),
+            ("cost_usd", "REAL"),
+            ("response_hash", "TEXT"),
+        ] {
+            if !has_column(&conn, "routing_decisions", column)? {
+                conn.execute_batch(&format!(
+                    "ALTER TABLE routing_decisions ADD COLUMN {} {}",
+                    column, definition
+                ))?;
+            }
+        }
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS idx_routing_decisions_model
+                ON routing_decisions(model, created_at);",
+        )?;
+        Ok(())
+    }
+
+    pub fn record(&self, conversation_id: &str, decision: &RoutingDecisionRecord) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute(
+            "INSERT INTO routing_decisions
+                (conversation_id, complexity, forced, message_chars, model, duration_ms, cost_usd,
+                 response_hash, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                conversation_id,
+                decision.complexity,
+                decision.forced,
+                decision.message_chars as i64,
+                decision.model,
+                decision.duration_ms as i64,
+                decision.cost_usd,
+                decision.response_hash,
+                Utc::now().timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Flags the conversation's latest decision with negative feedback.
+    /// Returns `false` when the conversation has no recorded decision.
+    pub fn mark_negative_feedback(&self, conversation_id: &str) -> Result<bool> {
+        self.flag_latest(conversation_id, "negative_feedback")
+    }
+
+    /// Flags the conversation's latest decision as followed by an escalation.
+    pub fn mark_escalated(&self, conversation_id: &str) -> Result<bool> {
+        self.flag_latest(conversation_id, "escalated")
+    }
+
+    fn flag_latest(&self, conversation_id: &str, column: &str) -> Result<bool> {
+        let conn = Connection::open(&self.path)?;
+        let changed = conn.execute(
+            &format!(
+                "UPDATE routing_decisions SET {} = 1
+                 WHERE id = (SELECT MAX(id) FROM routing_decisions WHERE conversation_id = ?1)",
+                column
+            ),
+            params![conversation_id],
+        )?;
+        Ok(changed > 0)
+    }
+
+    pub fn stats_since(&self, since: DateTime<Utc>) -> Result<Vec<RoutingStatsRecord>> {
+        self.reporting.with(|conn| Self::query_stats(conn, since))
+    }
+
+    fn query_stats(conn: &Connection, since: DateTime<Utc>) -> Result<Vec<RoutingStatsRecord>> {
+        let mut stmt = conn.prepare(
+            "SELECT complexity, forced, COUNT(*), SUM(negative_feedback), SUM(escalated),
+                    AVG(message_chars)
+             FROM routing_decisions
+             WHERE created_at >= ?1
+             GROUP BY complexity, forced
+             ORDER BY complexity, forced",
+        )?;
+        let rows = stmt.query_map(params![since.timestamp()], |row| {
+            Ok(RoutingStatsRecord {
+                complexity: row.get(0)?,
+                forced: row.get(1)?,
+                total: row.get::<_, i64>(2)? as u64,
+                negative_feedback: row.get::<_, i64>(3)? as u64,
+                escalated: row.get::<_, i64>(4)? as u64,
+                avg_message_chars: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    pub fn model_outcomes_since(
+        &self,
+        model: &str,
+        since: DateTime<Utc>,
+    ) -> Result<ModelOutcomeRecord> {
+        self.reporting
+            .with(|conn| Self::query_model_outcomes(conn, model, since))
+    }
+
+    fn query_model_outcomes(
+        conn: &Connection,
+        model: &str,
+        since: DateTime<Utc>,
+    ) -> Result<ModelOutcomeRecord> {
+        let since = since.timestamp();
+        let mut outcome = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(negative_feedback), 0), COALESCE(SUM(escalated), 0),
+                    COALESCE(SUM(cost_usd), 0), COUNT(cost_usd)
+             FROM routing_decisions
+             WHERE model = ?1 AND created_at >= ?2",
+            params![model, since],
+            |row| {
+                Ok(ModelOutcomeRecord {
+                    answers: row.get::<_, i64>(0)? as u64,
+                    negative_feedback: row.get::<_, i64>(1)? as u64,
+                    escalated: row.get::<_, i64>(2)? as u64,
+                    cost_usd: row.get(3)?,
+                    costed_answers: row.get::<_, i64>(4)? as u64,
+                    ..Default::default()
+                })
+            },
+        )?;
+
+        let mut stmt = conn.prepare(
+            "SELECT duration_ms FROM routing_decisions
+             WHERE model = ?1 AND created_at >= ?2 AND duration_ms IS NOT NULL
+             ORDER BY duration_ms",
+        )?;
+        outcome.durations_ms = stmt
+            .query_map(params![model, since], |row| row.get::<_, i64>(0))?
+            .map(|duration| duration.map(|ms| ms.max(0) as u64))
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        // Ratings live in the feedback table of the same store, keyed by answer hash.
+        let (positive, negative) = conn.query_row(
+            "SELECT COALESCE(SUM(rating = 'positive'), 0), COALESCE(SUM(rating = 'negative'), 0)
+             FROM response_feedback
+             WHERE response_hash IN (
+                 SELECT response_hash FROM routing_decisions
+                 WHERE model = ?1 AND created_at >= ?2 AND response_hash IS NOT NULL
+             )",
+            params![model, since],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+        )?;
+        outcome.positive_ratings = positive as u64;
+        outcome.negative_ratings = negative as u64;
+        Ok(outcome)
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+
+use crate::repositories::{enable_wal, require_intact};
+use std::fs;
+use std::path::PathBuf;
+
+/// Cloud spend accumulated for one UTC day.
+#[derive(Debug, Clone, Default)]
+pub struct SpendRecord {
+    pub cost_usd: f64,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Per-day ledger of OpenRouter usage, keyed by `YYYY-MM-DD`.
+#[derive(Clone)]
+pub struct SpendRepo {
+    path: PathBuf,
+}
+
+impl SpendRepo {
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Failed to create spend ledger directory: {}",
+                    parent.display()
+                )
+            })?;
+        }
+        require_intact(&path)?;
+        let repo = Self { path };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        enable_wal(&conn)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS provider_spend (
+                day TEXT PRIMARY KEY,
+                cost_usd REAL NOT NULL DEFAULT 0,
+                requests INTEGER NOT NULL DEFAULT 0,
+                prompt_tokens INTEGER NOT NULL DEFAULT 0,
+                completion_tokens INTEGER NOT NULL DEFAULT 0
+            );",
+        )?;
+        Ok(())
+    }
+
+    pub fn add(&self, day: &str, usage: &SpendRecord) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute(
+            "INSERT INTO provider_spend (day, cost_usd, requests, prompt_tokens, completion_tokens)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(day) DO UPDATE SET
+                cost_usd = cost_usd + excluded.cost_usd,
+                requests = requests + excluded.requests,
+                prompt_tokens = prompt_tokens + excluded.prompt_tokens,
+                completion_tokens = completion_tokens + excluded.completion_tokens",
+            params![
+                day,
+                usage.cost_usd,
+                usage.requests as i64,
+                usage.prompt_tokens as i64,
+                usage.completion_tokens as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Sums every day whose key starts with `prefix` (`YYYY-MM-DD` or `YYYY-MM`).
+    pub fn total(&self, prefix: &str) -> Result<SpendRecord> {
+        let conn = Connection::open(&self.path)?;
+        let record = conn.query_row(
+            "SELECT COALESCE(SUM(cost_usd), 0), COALESCE(SUM(requests), 0),
+                    COALESCE(SUM(prompt_tokens), 0), COALESCE(SUM(completion_tokens), 0)
+             FROM provider_spend
+             WHERE day LIKE ?1 || '%'",
+            params![prefix],
+            |row| {
+                Ok(SpendRecord {
+                    cost_usd: row.get(0)?,
+                    requests: row.get::<_, i64>(1)? as u64,
+                    prompt_tokens: row.get::<_, i64>(2)? as u64,
+                    completion_tokens: row.get::<_, i64>(3)? as u64,
+                })
+            },
+        )?;
+        Ok(record)
+    }
+}
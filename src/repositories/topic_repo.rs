@@ -0,0 +1,146 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection};
+
+use crate::repositories::{enable_wal, ensure_intact, ReadPool};
+use std::fs;
+use std::path::PathBuf;
+
+/// A conversation whose topic is missing or predates its latest messages.
+#[derive(Debug, Clone)]
+pub struct UnlabeledConversation {
+    pub conversation_id: String,
+    pub message_count: u64,
+}
+
+/// Conversations per topic.
+#[derive(Debug, Clone)]
+pub struct TopicCountRecord {
+    pub topic: String,
+    pub conversations: u64,
+    pub escalated: u64,
+}
+
+/// Topic labels for conversations, kept beside the conversations they describe.
+#[derive(Clone)]
+pub struct TopicRepo {
+    path: PathBuf,
+    reporting: ReadPool,
+}
+
+impl TopicRepo {
+    /// `reporting` serves the stats queries; writes always go to `path`.
+    pub fn new(path: impl Into<PathBuf>, reporting: ReadPool) -> Result<Self> {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| {
+                format!(
+                    "Failed to create topic store directory: {}",
+                    parent.display()
+                )
+            })?;
+        }
+        ensure_intact(&path)?;
+        let repo = Self { path, reporting };
+        repo.init()?;
+        Ok(repo)
+    }
+
+    fn init(&self) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        enable_wal(&conn)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS conversation_topics (
+                conversation_id TEXT PRIMARY KEY,
+                topic TEXT NOT NULL,
+                message_count INTEGER NOT NULL,
+                labeled_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS idx_conversation_topics_topic
+                ON conversation_topics(topic);",
+        )?;
+        Ok(())
+    }
+
+    /// Conversations idle since `idle_before` that have no label, or have
+    /// changed since they were labeled. Oldest first.
+    pub fn unlabeled(
+        &self,
+        idle_before: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<UnlabeledConversation>> {
+        let conn = Connection::open(&self.path)?;
+        let mut stmt = conn.prepare(
+            "SELECT c.conversation_id, c.message_count
+             FROM conversations c
+             LEFT JOIN conversation_topics t ON t.conversation_id = c.conversation_id
+             WHERE c.message_count > 0 AND c.updated_at <= ?1
+               AND (t.conversation_id IS NULL OR t.message_count != c.message_count)
+             ORDER BY c.updated_at ASC
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![idle_before.timestamp(), limit as i64], |row| {
+            Ok(UnlabeledConversation {
+                conversation_id: row.get(0)?,
+                message_count: row.get::<_, i64>(1)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// What the user wrote in the conversation, oldest first.
+    pub fn user_messages(&self, conversation_id: &str) -> Result<Vec<String>> {
+        let conn = Connection::open(&self.path)?;
+        let mut stmt = conn.prepare(
+            "SELECT content FROM conversation_messages
+             WHERE conversation_id = ?1 AND role = 'user'
+             ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map(params![conversation_id], |row| row.get::<_, String>(0))?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    pub fn set_topic(&self, conversation_id: &str, topic: &str, message_count: u64) -> Result<()> {
+        let conn = Connection::open(&self.path)?;
+        conn.execute(
+            "INSERT INTO conversation_topics (conversation_id, topic, message_count, labeled_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(conversation_id) DO UPDATE SET
+                topic = excluded.topic,
+                message_count = excluded.message_count,
+                labeled_at = excluded.labeled_at",
+            params![
+                conversation_id,
+                topic,
+                message_count as i64,
+                Utc::now().timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Labeled conversations active since `since`, per topic, most common first.
+    pub fn counts_since(&self, since: DateTime<Utc>) -> Result<Vec<TopicCountRecord>> {
+        self.reporting.with(|conn| Self::query_counts(conn, since))
+    }
+
+    fn query_counts(conn: &Connection, since: DateTime<Utc>) -> Result<Vec<TopicCountRecord>> {
+        let mut stmt = conn.prepare(
+            "SELECT t.topic, COUNT(*),
+                    SUM(CASE WHEN c.escalated_at IS NOT NULL THEN 1 ELSE 0 END)
+             FROM conversation_topics t
+             JOIN conversations c ON c.conversation_id = t.conversation_id
+             WHERE c.updated_at >= ?1
+             GROUP BY t.topic
+             ORDER BY COUNT(*) DESC, t.topic",
+        )?;
+        let rows = stmt.query_map(params![since.timestamp()], |row| {
+            Ok(TopicCountRecord {
+                topic: row.get(0)?,
+                conversations: row.get::<_, i64>(1)? as u64,
+                escalated: row.get::<_, i64>(2)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+}
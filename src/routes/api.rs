@@ -1,14 +1,208 @@
+use crate::config::SecurityConfig;
 use crate::handlers;
+use actix_web::guard::{self, GuardContext};
+use actix_web::http::header::ContentType;
 use actix_web::{web, Scope};
 
-pub fn config() -> Scope {
-    web::scope("/api")
+/// Room for the largest JSON chat request on `/api/chat/images`: `MAX_IMAGES`
+/// base64 images plus text. Other routes keep the default JSON limit.
+const IMAGE_JSON_BODY_LIMIT: usize = 32 * 1024 * 1024;
+
+/// Routes of each endpoint group, as reported by `GET /api/capabilities`.
+/// Groups not listed in [`crate::config::ENDPOINT_GROUPS`] are always mounted.
+pub const ENDPOINT_GROUP_ROUTES: &[(&str, &[&str])] = &[
+    (
+        "health",
+        &[
+            "/api/health",
+            "/api/ready",
+            "/api/capabilities",
+            "/api/models",
+        ],
+    ),
+    (
+        "chat",
+        &[
+            "/api/chat",
+            "/api/chat/images",
+            "/api/chat/continue",
+            "/api/chat/stream/{token}",
+            "/api/chat/{conversation_id}/regenerate",
+            "/api/tokenize",
+        ],
+    ),
+    (
+        "conversations",
+        &[
+            "/api/conversations",
+            "/api/conversations/{id}",
+            "/api/conversations/{id}/archives",
+            "/api/conversations/{id}/redact",
+            "/api/conversations/{id}/feedback",
+        ],
+    ),
+    ("feedback", &["/api/feedback", "/api/feedback/stats"]),
+    ("agents", &["/api/agents/heartbeat"]),
+    ("scripts", &["/api/generate-script"]),
+    ("log_analysis", &["/api/analyze-logs"]),
+    (
+        "compat",
+        &[
+            "/api/generate",
+            "/api/tags",
+            "/api/version",
+            "/api/embeddings",
+        ],
+    ),
+    ("admin", &["/api/admin/*"]),
+];
+
+pub fn config(security: &SecurityConfig) -> Scope {
+    let mut scope = web::scope("/api")
         .route("/health", web::get().to(handlers::health_check))
         .route("/ready", web::get().to(handlers::ready_check))
+        .route("/capabilities", web::get().to(handlers::capabilities))
+        .route("/models", web::get().to(handlers::list_models))
         .route("/chat", web::post().to(handlers::chat))
-        .route("/analyze-logs", web::post().to(handlers::analyze_logs))
+        .service(
+            web::resource("/chat/images")
+                .app_data(web::JsonConfig::default().limit(IMAGE_JSON_BODY_LIMIT))
+                .route(
+                    web::post()
+                        .guard(guard::fn_guard(is_json))
+                        .to(handlers::chat),
+                )
+                .route(web::post().to(handlers::chat_with_images)),
+        )
+        .route("/chat/continue", web::post().to(handlers::continue_chat))
+        .route(
+            "/chat/stream/{token}",
+            web::get().to(handlers::poll_chat_stream),
+        )
+        .route("/tokenize", web::post().to(handlers::tokenize))
+        .route(
+            "/chat/{conversation_id}/regenerate",
+            web::post().to(handlers::regenerate_chat),
+        )
+        .route(
+            "/conversations",
+            web::get().to(handlers::list_conversations),
+        )
+        .route(
+            "/conversations/{id}",
+            web::get().to(handlers::get_conversation),
+        )
+        .route(
+            "/conversations/{id}/archives",
+            web::get().to(handlers::get_conversation_archives),
+        )
         .route(
+            "/conversations/{id}/redact",
+            web::post().to(handlers::redact_conversation),
+        )
+        .route(
+            "/conversations/{id}/feedback",
+            web::post().to(handlers::submit_feedback),
+        )
+        .route(
+            "/agents/heartbeat",
+            web::post().to(handlers::agent_heartbeat),
+        )
+        .route("/feedback", web::post().to(handlers::record_feedback))
+        .route("/feedback/stats", web::get().to(handlers::feedback_stats));
+
+    if security.endpoint_group_enabled("admin") {
+        scope = admin_routes(scope);
+    }
+    if security.endpoint_group_enabled("compat") {
+        scope = scope
+            .route("/generate", web::post().to(handlers::ollama_generate))
+            .route("/tags", web::get().to(handlers::ollama_tags))
+            .route("/version", web::get().to(handlers::ollama_version))
+            .route("/embeddings", web::post().to(handlers::create_embeddings));
+    }
+    if security.endpoint_group_enabled("log_analysis") {
+        scope = scope.route("/analyze-logs", web::post().to(handlers::analyze_logs));
+    }
+    if security.endpoint_group_enabled("scripts") {
+        scope = scope.route(
             "/generate-script",
             web::post().to(handlers::generate_script),
+        );
+    }
+    scope
+}
+
+/// Whether the request body is JSON, whatever its charset parameter.
+fn is_json(ctx: &GuardContext) -> bool {
+    ctx.header::<ContentType>()
+        .is_some_and(|content_type| content_type.essence_str() == "application/json")
+}
+
+fn admin_routes(scope: Scope) -> Scope {
+    scope
+        .route(
+            "/admin/cache/tags/{tag:.*}",
+            web::delete().to(handlers::invalidate_cache_tag),
+        )
+        .route("/admin/cache/stats", web::get().to(handlers::cache_stats))
+        .route(
+            "/admin/cache/stats/history",
+            web::get().to(handlers::cache_stats_history),
+        )
+        .route(
+            "/admin/cache/flush",
+            web::post().to(handlers::flush_memory_cache),
+        )
+        .route("/admin/cache/export", web::get().to(handlers::export_cache))
+        .route(
+            "/admin/cache/import",
+            web::post().to(handlers::import_cache),
+        )
+        .route(
+            "/admin/cache/tiers/{tier}/disable",
+            web::post().to(handlers::disable_cache_tier),
+        )
+        .route(
+            "/admin/cache/tiers/{tier}/enable",
+            web::post().to(handlers::enable_cache_tier),
+        )
+        .route(
+            "/admin/cache/templates",
+            web::get().to(handlers::list_template_versions),
+        )
+        .route(
+            "/admin/cache/templates/{template}/bump",
+            web::post().to(handlers::bump_template_version),
+        )
+        .route(
+            "/admin/routing-report",
+            web::get().to(handlers::routing_report),
+        )
+        .route("/admin/cloud-spend", web::get().to(handlers::cloud_spend))
+        .route("/admin/topics", web::get().to(handlers::topic_report))
+        .route("/admin/agents", web::get().to(handlers::list_agents))
+        .route("/admin/diagnostics", web::get().to(handlers::diagnostics))
+        .route("/admin/inflight", web::get().to(handlers::list_inflight))
+        .route(
+            "/admin/inflight/{id}/cancel",
+            web::post().to(handlers::cancel_inflight),
+        )
+        .route(
+            "/admin/models/compare",
+            web::get().to(handlers::compare_models),
+        )
+        .route(
+            "/admin/models/switch",
+            web::post().to(handlers::switch_model),
+        )
+        .route(
+            "/admin/model/reload",
+            web::post().to(handlers::reload_model),
+        )
+        .route("/admin/model/status", web::get().to(handlers::model_status))
+        .route(
+            "/admin/provider-captures/{request_id}",
+            web::get().to(handlers::get_provider_captures),
         )
 }
@@ -1,4 +1,5 @@
 pub mod api;
+pub mod ui;
 
 pub use api::*;
 
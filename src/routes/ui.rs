@@ -0,0 +1,9 @@
+use crate::handlers;
+use actix_web::{web, Scope};
+
+/// Manual-testing playground; only mounted when `UI_ENABLED` is set.
+pub fn config() -> Scope {
+    web::scope("/ui")
+        .route("", web::get().to(handlers::ui_index))
+        .route("/", web::get().to(handlers::ui_index))
+}
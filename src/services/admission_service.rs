@@ -0,0 +1,95 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Suggested wait before retrying a rejected request.
+const RETRY_AFTER_SECS: u64 = 5;
+
+/// A generation was refused because every slot is busy and the queue is full.
+#[derive(Debug, Clone)]
+pub struct Overloaded {
+    pub queued: usize,
+    pub retry_after_secs: u64,
+}
+
+impl fmt::Display for Overloaded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "All generation slots are busy and {} requests are already queued",
+            self.queued
+        )
+    }
+}
+
+impl std::error::Error for Overloaded {}
+
+/// Held for the duration of a generation; dropping it frees the slot.
+pub struct AdmissionPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Bounds concurrent generations and the number of requests waiting for one,
+/// so overload surfaces as a fast rejection instead of an unbounded number of
+/// generations sharing the CPU.
+#[derive(Clone)]
+pub struct AdmissionController {
+    /// `None` when concurrency is unlimited.
+    slots: Option<Arc<Semaphore>>,
+    waiting: Arc<AtomicUsize>,
+    max_queue: usize,
+}
+
+impl AdmissionController {
+    /// `max_concurrent` of 0 disables the limit; `max_queue` of 0 rejects as
+    /// soon as every slot is taken.
+    pub fn new(max_concurrent: usize, max_queue: usize) -> Self {
+        Self {
+            slots: (max_concurrent > 0).then(|| Arc::new(Semaphore::new(max_concurrent))),
+            waiting: Arc::new(AtomicUsize::new(0)),
+            max_queue,
+        }
+    }
+
+    pub async fn admit(&self) -> Result<AdmissionPermit, Overloaded> {
+        let Some(slots) = self.slots.clone() else {
+            return Ok(AdmissionPermit { _permit: None });
+        };
+        if let Ok(permit) = slots.clone().try_acquire_owned() {
+            return Ok(AdmissionPermit {
+                _permit: Some(permit),
+            });
+        }
+
+        let queued = self.waiting.fetch_add(1, Ordering::SeqCst);
+        // Leaves the queue even if the request is cancelled while waiting.
+        let _waiting = WaitingGuard(self.waiting.clone());
+        if queued >= self.max_queue {
+            return Err(Overloaded {
+                queued,
+                retry_after_secs: RETRY_AFTER_SECS,
+            });
+        }
+        let permit = slots
+            .acquire_owned()
+            .await
+            .expect("admission semaphore is never closed");
+        Ok(AdmissionPermit {
+            _permit: Some(permit),
+        })
+    }
+
+    /// Requests currently waiting for a slot.
+    pub fn queued(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+}
+
+struct WaitingGuard(Arc<AtomicUsize>);
+
+impl Drop for WaitingGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
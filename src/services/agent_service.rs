@@ -0,0 +1,90 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::AgentSettings;
+use crate::models::{AgentHeartbeat, Page};
+use crate::repositories::{AgentRecord, AgentRepo, AgentReport};
+
+/// Tracks the last reported state of installed selfcare agents.
+#[derive(Clone)]
+pub struct AgentService {
+    repo: Option<AgentRepo>,
+    settings: AgentSettings,
+}
+
+impl AgentService {
+    pub fn new(settings: &AgentSettings) -> Self {
+        let repo = if settings.sqlite_path.trim().is_empty() {
+            None
+        } else {
+            match AgentRepo::new(settings.sqlite_path.clone()) {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    tracing::warn!("Agent tracking disabled: {}", e);
+                    None
+                }
+            }
+        };
+        Self {
+            repo,
+            settings: settings.clone(),
+        }
+    }
+
+    pub async fn record(&self, heartbeat: AgentHeartbeat) -> Result<()> {
+        let repo = self.repo()?;
+        let report = AgentReport {
+            agent_id: heartbeat.agent_id,
+            version: heartbeat.version,
+            os: heartbeat.os,
+            os_version: heartbeat.os_version,
+            hostname: heartbeat.hostname,
+            health: heartbeat.health.as_str().to_string(),
+            message: heartbeat.message,
+        };
+        tokio::task::spawn_blocking(move || repo.upsert(&report)).await?
+    }
+
+    pub async fn list(&self, page: Page) -> Result<Vec<AgentRecord>> {
+        let repo = self.repo()?;
+        tokio::task::spawn_blocking(move || repo.list(&page)).await?
+    }
+
+    /// The configured current release. Versions agents report are never
+    /// trusted for this, so one agent cannot mark the whole fleet outdated.
+    pub fn latest_version(&self) -> Option<String> {
+        self.settings.latest_version.clone()
+    }
+
+    pub fn is_connected(&self, last_seen: DateTime<Utc>) -> bool {
+        Utc::now() - last_seen <= Duration::seconds(self.settings.offline_after_secs as i64)
+    }
+
+    pub fn is_outdated(version: &str, latest: Option<&str>) -> bool {
+        latest.is_some_and(|latest| version_key(version) < version_key(latest))
+    }
+
+    fn repo(&self) -> Result<AgentRepo> {
+        self.repo
+            .clone()
+            .ok_or_else(|| anyhow!("Agent tracking is disabled"))
+    }
+}
+
+/// Numeric components of a dotted version (`v1.10.2-rc1` → `[1, 10, 2]`), so
+/// `1.10` sorts after `1.9` and `1.2` equals `1.2.0`. Pre-release suffixes are ignored.
+fn version_key(version: &str) -> Vec<u64> {
+    let mut key: Vec<u64> = version
+        .trim()
+        .trim_start_matches(['v', 'V'])
+        .split('.')
+        .map(|part| {
+            let digits: String = part.chars().take_while(|c| c.is_ascii_digit()).collect();
+            digits.parse().unwrap_or(0)
+        })
+        .collect();
+    while key.last() == Some(&0) {
+        key.pop();
+    }
+    key
+}
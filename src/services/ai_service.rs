@@ -1,50 +1,831 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use futures::future::join_all;
 use serde_json::json;
-use tokio::sync::RwLock;
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedRwLockReadGuard, RwLock};
+use tracing::Instrument;
 
 use crate::config::{AiConfig, OpenRouterSettings};
-use crate::models::{ChatRequest, ChatResponse};
-use crate::models::AIModel;
-use crate::services::{ModelService, SearchService};
+use crate::models::{
+    condense_request, log_analysis_request, script_request, AIModel, ChatContext, Complexity,
+    GenerationParams, ModelUnavailable, PartialGeneration, PromptBudget, PromptFit, Provenance,
+    Route, SearchMode, ToolCall,
+};
+use crate::models::{
+    ChatRequest, ChatResponse, CloudModelInfo, ComplexityScore, LocalModelInfo, RequestContext,
+};
+use crate::services::{
+    inflight_cancel_signal, local_backend, note_inflight_route, AdmissionController,
+    AdmissionPermit, Coalesced, ConversationHistory, ConversationMemory, ConversationService,
+    DeadlineExceeded, EmbeddingService, GroundingService, HttpClients, InferencePool,
+    InflightRegistry, InjectionService, KnowledgeService, LocalBackend, LocalChat, ModelRegistry,
+    ModelService, Overloaded, ProviderCaptureService, ProviderExchange, RequestCoalescer,
+    SearchService, SpendService,
+};
+use crate::utils::{
+    bm25_scores, detect_language, estimate_tokens, format_tool_results, generate_chat_prompt,
+    generate_log_analysis_prompt, generate_script_prompt, generate_tool_prompt,
+    language_instruction, language_name, parse_tool_call, rewrite_search_queries, PiiPlaceholders,
+    PromptTemplates, ScriptLocale,
+};
 
 #[derive(Clone)]
 pub struct AIService {
     ai_model: Arc<RwLock<AIModel>>,
+    /// The default model above plus the named local models.
+    models: ModelRegistry,
     model_service: ModelService,
     search_service: SearchService,
+    knowledge: KnowledgeService,
+    embeddings: EmbeddingService,
+    grounding_service: GroundingService,
+    injection: InjectionService,
+    memory: ConversationMemory,
     openrouter: OpenRouterSettings,
+    capture: ProviderCaptureService,
+    spend: SpendService,
+    templates: Arc<PromptTemplates>,
+    admission: AdmissionController,
+    inference: InferencePool,
+    /// Model server answering for the local tier; `None` runs candle in-process.
+    backend: Option<Arc<dyn LocalBackend>>,
+    http: HttpClients,
+    inflight: InflightRegistry,
+    /// Generations shared by concurrent identical requests, by cache key.
+    coalescer: RequestCoalescer<ChatResponse>,
+    /// Name of the local model behind `ai_model`; changes on a model switch.
+    active_model: Arc<std::sync::RwLock<String>>,
+    /// Weights directory of the active model, when not the Hugging Face cache.
+    active_model_path: Arc<std::sync::RwLock<Option<String>>>,
+    switching: Arc<AtomicBool>,
+    /// OpenRouter's model list and when it was fetched.
+    cloud_catalog: Arc<tokio::sync::Mutex<Option<(Instant, Vec<CloudModelInfo>)>>>,
     ai_config: AiConfig,
 }
 
+/// How long OpenRouter's model list is reused before it is fetched again.
+const CLOUD_CATALOG_TTL: std::time::Duration = std::time::Duration::from_secs(3600);
+
 impl AIService {
     pub fn new(
         ai_model: Arc<RwLock<AIModel>>,
         ai_config: AiConfig,
         openrouter: OpenRouterSettings,
+        conversations: ConversationService,
+        capture: ProviderCaptureService,
+        spend: SpendService,
+        http: HttpClients,
+        knowledge: KnowledgeService,
+        embeddings: EmbeddingService,
     ) -> Self {
+        let templates = PromptTemplates::load(ai_config.prompt_templates_dir.as_deref())
+            .unwrap_or_else(|e| {
+                tracing::warn!("Using built-in prompt templates only: {}", e);
+                PromptTemplates::builtin()
+            });
+        let backend = local_backend(&ai_config, &http);
+        // Answers are labelled with the model a server is asked for, if any.
+        let active_model = backend
+            .as_ref()
+            .and_then(|backend| backend.model())
+            .unwrap_or(&ai_config.model_name)
+            .to_string();
         Self {
+            models: ModelRegistry::new(ai_model.clone(), &ai_config),
             ai_model,
-            model_service: ModelService::default(),
+            model_service: ModelService::new(&ai_config),
             search_service: SearchService::default(),
+            knowledge,
+            embeddings,
+            grounding_service: GroundingService::new(ai_config.grounding_threshold),
+            injection: InjectionService::new(&ai_config),
+            memory: ConversationMemory::new(conversations),
             openrouter,
+            capture,
+            spend,
+            templates: Arc::new(templates),
+            admission: AdmissionController::new(
+                ai_config.max_concurrent_generations,
+                ai_config.max_queued_generations,
+            ),
+            inference: InferencePool::new(
+                ai_config.inference_workers,
+                ai_config.inference_queue_depth,
+                ai_config.inference_cpu_cores.clone(),
+                ai_config.inference_thread_nice,
+            ),
+            backend,
+            http,
+            inflight: InflightRegistry::default(),
+            coalescer: RequestCoalescer::default(),
+            active_model: Arc::new(std::sync::RwLock::new(active_model)),
+            active_model_path: Arc::new(std::sync::RwLock::new(ai_config.model_path.clone())),
+            switching: Arc::new(AtomicBool::new(false)),
+            cloud_catalog: Arc::default(),
             ai_config,
         }
     }
 
-    pub async fn analyze_complexity(&self, req: &ChatRequest) -> crate::services::Complexity {
-        self.model_service.analyze_complexity(req)
+    /// Name of the local model currently serving requests.
+    pub fn model_name(&self) -> String {
+        self.active_model
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Weights directory the serving model was loaded from, if configured.
+    pub fn model_path(&self) -> Option<String> {
+        self.active_model_path
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn models(&self) -> &ModelRegistry {
+        &self.models
+    }
+
+    /// Local models with their size, precision and whether they are loaded.
+    pub fn local_models(&self) -> Vec<LocalModelInfo> {
+        let active = self.model_name();
+        // A switched-in model was found by name, not by the configured path.
+        let default_config = AiConfig {
+            model_path: self
+                .ai_config
+                .model_path
+                .clone()
+                .filter(|_| active == self.ai_config.model_name),
+            model_name: active,
+            ..self.ai_config.clone()
+        };
+        self.models.describe(&default_config)
+    }
+
+    pub fn default_cloud_model(&self) -> Option<String> {
+        Some(self.openrouter.default_model.clone())
+            .filter(|_| !self.openrouter.api_key.trim().is_empty())
+    }
+
+    /// OpenRouter's model catalog, fetched at most once per [`CLOUD_CATALOG_TTL`].
+    pub async fn cloud_models(&self) -> Result<Vec<CloudModelInfo>> {
+        if self.openrouter.api_key.trim().is_empty() {
+            bail!("OpenRouter is not configured; set OPENROUTER_API_KEY");
+        }
+        let mut catalog = self.cloud_catalog.lock().await;
+        if let Some((fetched_at, models)) = catalog.as_ref() {
+            if fetched_at.elapsed() < CLOUD_CATALOG_TTL {
+                return Ok(models.clone());
+            }
+        }
+
+        let endpoint = format!("{}/models", self.openrouter.base_url);
+        let body: serde_json::Value = self
+            .http
+            .for_url(&endpoint)
+            .get(&endpoint)
+            .bearer_auth(&self.openrouter.api_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // Prices come as USD-per-token strings.
+        let price = |model: &serde_json::Value, key: &str| {
+            model["pricing"][key]
+                .as_str()
+                .and_then(|price| price.parse::<f64>().ok())
+                .map(|per_token| per_token * 1_000_000.0)
+        };
+        let models: Vec<CloudModelInfo> = body["data"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|model| {
+                let id = model["id"].as_str()?.to_string();
+                Some(CloudModelInfo {
+                    name: model["name"].as_str().unwrap_or(&id).to_string(),
+                    context_length: model["context_length"].as_u64(),
+                    prompt_price_per_mtok: price(model, "prompt"),
+                    completion_price_per_mtok: price(model, "completion"),
+                    id,
+                })
+            })
+            .collect();
+        *catalog = Some((Instant::now(), models.clone()));
+        Ok(models)
+    }
+
+    /// Hugging Face id of the local model that answers `req`: the named
+    /// model it selects, or the default one, suffixed with `+adapter` when
+    /// it selects a LoRA adapter.
+    pub fn local_model_name(&self, req: &ChatRequest) -> String {
+        // A model server answers with the model it is configured for.
+        if self.backend.is_some() {
+            return self.model_name();
+        }
+        if let Some(adapter) = req.adapter.as_deref() {
+            return format!("{}+{}", self.model_name(), adapter);
+        }
+        req.model
+            .as_deref()
+            .and_then(|name| self.models.model_name(name))
+            .map(str::to_string)
+            .unwrap_or_else(|| self.model_name())
+    }
+
+    /// Knowledge-base version answers are grounded in: the synced index
+    /// version, or the configured snapshot when nothing has been synced.
+    pub fn knowledge_base_snapshot(&self) -> Option<String> {
+        self.knowledge
+            .active_version()
+            .or_else(|| self.ai_config.knowledge_base_snapshot.clone())
+    }
+
+    /// Claims the switch slot for [`AIService::switch_model`]; `false` while
+    /// another switch is still loading.
+    pub fn begin_model_switch(&self) -> bool {
+        self.switching
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    pub fn model_switch_in_progress(&self) -> bool {
+        self.switching.load(Ordering::SeqCst)
+    }
+
+    /// Loads `model_name` into a standby slot while the current model keeps
+    /// serving, then swaps it in and frees the old weights. The model lock is
+    /// only held for the swap itself. Releases the slot claimed with
+    /// [`AIService::begin_model_switch`].
+    pub async fn switch_model(&self, model_name: &str, model_path: Option<&str>) -> Result<()> {
+        let result = self.load_and_swap(model_name, model_path).await;
+        self.switching.store(false, Ordering::SeqCst);
+        result
+    }
+
+    async fn load_and_swap(&self, model_name: &str, model_path: Option<&str>) -> Result<()> {
+        let config = AiConfig {
+            model_name: model_name.to_string(),
+            model_path: model_path.map(str::to_string),
+            ..self.ai_config.clone()
+        };
+        let mut standby = AIModel::new(config);
+        standby
+            .load_model()
+            .await
+            .map_err(|e| e.context(format!("Failed to load standby model {}", model_name)))?;
+
+        let previous = {
+            let mut active = self.ai_model.write().await;
+            std::mem::replace(&mut *active, standby)
+        };
+        *self
+            .active_model
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = model_name.to_string();
+        *self
+            .active_model_path
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = model_path.map(str::to_string);
+        tracing::info!(
+            "Switched local model from {} to {}",
+            previous.model_name(),
+            model_name
+        );
+        // Unmapping large weights can take a while; keep it off the async workers.
+        tokio::task::spawn_blocking(move || drop(previous)).await?;
+        Ok(())
+    }
+
+    /// The request's forced tier, or the analyzed one with its score. Requests
+    /// with images always go to the cloud, the only path that can read them;
+    /// constrained ones stay local, the only path that can enforce them.
+    pub async fn analyze_complexity(
+        &self,
+        req: &ChatRequest,
+    ) -> (Complexity, Option<ComplexityScore>) {
+        if req.has_images() {
+            return (Complexity::High, None);
+        }
+        if req.constraint.is_some() {
+            return (Complexity::Low, None);
+        }
+        if let Some(forced) = req.forced_complexity() {
+            return (forced, None);
+        }
+        let (complexity, score) = self.model_service.analyze_complexity(req);
+        (complexity, Some(score))
+    }
+
+    /// Waits for a generation slot; rejects when the wait queue is full.
+    pub async fn admit(&self) -> std::result::Result<AdmissionPermit, Overloaded> {
+        self.admission.admit().await
+    }
+
+    /// Runs a local generation on the inference workers.
+    pub async fn run_inference<T, F>(&self, job: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.inference.run(job).await
+    }
+
+    pub fn inference_pool(&self) -> &InferencePool {
+        &self.inference
+    }
+
+    /// The model server answering for the local tier, when `LOCAL_BACKEND`
+    /// names one.
+    pub fn local_backend(&self) -> Option<&Arc<dyn LocalBackend>> {
+        self.backend.as_ref()
+    }
+
+    /// Log analysis on the local tier, listed as an in-flight generation.
+    pub async fn analyze_logs(
+        &self,
+        ctx: &RequestContext,
+        queue_wait: Duration,
+        logs: String,
+        context: Option<String>,
+    ) -> Result<String> {
+        self.inflight
+            .run(ctx.request_id.clone(), queue_wait, async {
+                note_inflight_route(Route::Local, &self.model_name());
+                if let Some(backend) = &self.backend {
+                    let (frame, params) =
+                        log_analysis_request(&self.ai_config, "", context.clone());
+                    let budget = self.remote_prompt_budget(&frame, params.max_tokens);
+                    let logs = self
+                        .fit_remote_input(backend, logs, &budget, params.seed)
+                        .await?;
+                    let prompt = generate_log_analysis_prompt(&logs, context);
+                    return backend.complete(&prompt, &params).await;
+                }
+                let model = self.default_model().await?;
+                // Taken here, since the sampling loop runs outside this task.
+                let cancel = inflight_cancel_signal();
+                self.run_inference(move || {
+                    futures::executor::block_on(model.analyze_logs(logs, context, cancel))
+                })
+                .await?
+            })
+            .await
+    }
+
+    /// Script generation on the local tier, listed as an in-flight generation.
+    pub async fn generate_script(
+        &self,
+        ctx: &RequestContext,
+        queue_wait: Duration,
+        requirement: String,
+        environment: &'static str,
+        language: &'static str,
+        locale: &'static ScriptLocale,
+    ) -> Result<String> {
+        self.inflight
+            .run(ctx.request_id.clone(), queue_wait, async {
+                note_inflight_route(Route::Local, &self.model_name());
+                if let Some(backend) = &self.backend {
+                    let (frame, params) =
+                        script_request(&self.ai_config, "", environment, language, locale);
+                    let budget = self.remote_prompt_budget(&frame, params.max_tokens);
+                    let requirement = self
+                        .fit_remote_input(backend, requirement, &budget, params.seed)
+                        .await?;
+                    let prompt =
+                        generate_script_prompt(&requirement, environment, language, locale);
+                    return backend.complete(&prompt, &params).await;
+                }
+                let model = self.default_model().await?;
+                // Taken here, since the sampling loop runs outside this task.
+                let cancel = inflight_cancel_signal();
+                self.run_inference(move || {
+                    futures::executor::block_on(model.generate_script(
+                        requirement,
+                        environment,
+                        language,
+                        locale,
+                        cancel,
+                    ))
+                })
+                .await?
+            })
+            .await
+    }
+
+    /// [`AIModel::prompt_budget`] for a model server, whose tokenizer is out
+    /// of reach, so tokens are estimated.
+    fn remote_prompt_budget(&self, frame: &str, max_new_tokens: usize) -> PromptBudget {
+        PromptBudget::new(
+            self.ai_config.context_length,
+            estimate_tokens(frame),
+            max_new_tokens,
+        )
+    }
+
+    /// [`AIModel::fit_input`] for a model server, which also condenses.
+    async fn fit_remote_input(
+        &self,
+        backend: &Arc<dyn LocalBackend>,
+        input: String,
+        budget: &PromptBudget,
+        seed: Option<u64>,
+    ) -> Result<String> {
+        match budget.plan(&input, &self.ai_config.prompt_overflow, &estimate_tokens)? {
+            PromptFit::Fits => Ok(input),
+            PromptFit::Truncated(text) => Ok(text),
+            PromptFit::Summarize(chunks) => {
+                let mut summaries = Vec::with_capacity(chunks.len());
+                for chunk in &chunks {
+                    let (prompt, params) = condense_request(chunk, seed);
+                    summaries.push(backend.complete(&prompt, &params).await?);
+                }
+                Ok(budget.join_summaries(&summaries, &estimate_tokens))
+            }
+        }
+    }
+
+    /// The serving model, loaded if needed; generations share it and a reload
+    /// or switch waits for them to finish.
+    async fn default_model(&self) -> Result<OwnedRwLockReadGuard<AIModel>> {
+        self.models
+            .acquire(None)
+            .await
+            .map_err(|e| ModelUnavailable(e.to_string()).into())
+    }
+
+    /// Requests waiting for a generation slot.
+    pub fn queued_generations(&self) -> usize {
+        self.admission.queued()
+    }
+
+    /// Generations currently running.
+    pub fn inflight(&self) -> &InflightRegistry {
+        &self.inflight
+    }
+
+    /// Routes a request to the local, enriched or cloud path based on its
+    /// complexity, giving up with [`DeadlineExceeded`] at the caller's deadline.
+    pub async fn generate(&self, ctx: &RequestContext, req: &ChatRequest) -> Result<ChatResponse> {
+        let Some(remaining) = ctx.remaining() else {
+            return self.admit_and_generate(ctx, req).await;
+        };
+        match tokio::time::timeout(remaining, self.admit_and_generate(ctx, req)).await {
+            Ok(result) => result,
+            Err(_) => Err(DeadlineExceeded.into()),
+        }
+    }
+
+    /// Like [`AIService::generate`], but concurrent requests with the same
+    /// cache key share one generation: the first starts it and the rest wait
+    /// for its answer, each up to its own deadline. The generation keeps
+    /// running while any of them waits. Answers a request joined are marked
+    /// [`ChatResponse::coalesced`].
+    pub async fn generate_coalesced(
+        &self,
+        ctx: &RequestContext,
+        req: &ChatRequest,
+        cache_key: &str,
+    ) -> Result<ChatResponse> {
+        // Local-only callers must not receive an answer from the cloud routes.
+        let key = format!("{}|local_only={}", cache_key, ctx.flags.local_only);
+        let (service, req) = (self.clone(), req.clone());
+        let shared_ctx = RequestContext {
+            deadline: None,
+            ..ctx.clone()
+        };
+        let work = async move { service.admit_and_generate(&shared_ctx, &req).await }
+            .instrument(ctx.span.clone());
+        let generation = self.coalescer.run(&key, work);
+        let (result, role) = match ctx.remaining() {
+            Some(remaining) => match tokio::time::timeout(remaining, generation).await {
+                Ok(outcome) => outcome,
+                Err(_) => return Err(DeadlineExceeded.into()),
+            },
+            None => generation.await,
+        };
+        let mut response = result?;
+        if role == Coalesced::Follower {
+            tracing::debug!("Answered from a generation started by an identical request");
+            response.coalesced = true;
+        }
+        Ok(response)
+    }
+
+    async fn admit_and_generate(
+        &self,
+        ctx: &RequestContext,
+        req: &ChatRequest,
+    ) -> Result<ChatResponse> {
+        let queued_at = Instant::now();
+        let _permit = self.admit().await?;
+        let queue_wait = queued_at.elapsed();
+        let (complexity, complexity_score) = self.analyze_complexity(req).await;
+        let mut response = self
+            .inflight
+            .run(
+                ctx.request_id.clone(),
+                queue_wait,
+                self.generate_for(ctx, req, complexity),
+            )
+            .await?;
+        response.complexity = Some(complexity);
+        response.complexity_score = complexity_score;
+        Ok(response)
+    }
+
+    async fn generate_for(
+        &self,
+        ctx: &RequestContext,
+        req: &ChatRequest,
+        complexity: Complexity,
+    ) -> Result<ChatResponse> {
+        let search_mode = req.search.unwrap_or_default();
+        let searched = match search_mode {
+            SearchMode::Off => false,
+            SearchMode::Auto => complexity != Complexity::Low,
+            SearchMode::Force => true,
+        };
+        // A failed search degrades to an answer without sources, which is
+        // served but not cached.
+        let mut search_failed = false;
+        let search_results = if searched {
+            match self.search(&req.message).await {
+                Ok(results) => results,
+                Err(e) => {
+                    tracing::warn!("Search failed; answering without sources: {}", e);
+                    search_failed = true;
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        let mut response = match complexity {
+            // Forced search on a simple question takes the enriched path.
+            Complexity::Low if searched => {
+                let response = self.enrich_and_generate(req, &search_results).await?;
+                self.apply_grounding(req, response, &search_results)
+            }
+            Complexity::Low => {
+                note_inflight_route(Route::Local, &self.local_model_name(req));
+                self.local_model_generate(req).await?
+            }
+            Complexity::Medium => {
+                let response = self.enrich_and_generate(req, &search_results).await?;
+                self.apply_grounding(req, response, &search_results)
+            }
+            Complexity::High => {
+                let response = self.cloud_model_generate(ctx, req, &search_results).await?;
+                self.apply_grounding(req, response, &search_results)
+            }
+        };
+        response.search_mode = Some(search_mode);
+        response.searched = searched && !search_failed;
+        if search_failed {
+            response.cacheable = false;
+        }
+        response.provenance = Some(self.provenance(req, &response, &search_results).await);
+        Ok(response)
+    }
+
+    async fn provenance(
+        &self,
+        req: &ChatRequest,
+        response: &ChatResponse,
+        search_results: &[crate::services::SearchResult],
+    ) -> Provenance {
+        let mut search_providers: Vec<String> = Vec::new();
+        for result in search_results {
+            if !search_providers.contains(&result.provider) {
+                search_providers.push(result.provider.clone());
+            }
+        }
+        // The local path answers from the model alone; sources only reach the
+        // enriched and cloud prompts.
+        let document_ids = match response.route {
+            Some(Route::Local) | None => Vec::new(),
+            Some(Route::Enriched) => self
+                .rank_sources(&req.message, search_results)
+                .await
+                .into_iter()
+                .map(|r| r.url)
+                .collect(),
+            Some(_) => search_results.iter().map(|r| r.url.clone()).collect(),
+        };
+        Provenance {
+            route: response.route,
+            model: response.model.clone(),
+            prompt_version: self.ai_config.prompt_version.clone(),
+            knowledge_base_snapshot: self.knowledge_base_snapshot(),
+            cache_tier: None,
+            search_providers,
+            document_ids,
+        }
+    }
+
+    /// Attaches a grounding report for source-backed answers, dropping unsupported
+    /// sentences when strict grounding is requested.
+    fn apply_grounding(
+        &self,
+        req: &ChatRequest,
+        mut response: ChatResponse,
+        search_results: &[crate::services::SearchResult],
+    ) -> ChatResponse {
+        // Tool calls are instructions for the client, not claims to check.
+        if search_results.is_empty() || !response.tool_calls.is_empty() {
+            return response;
+        }
+
+        let strict = req
+            .strict_grounding
+            .unwrap_or(self.ai_config.strict_grounding);
+        let report = self
+            .grounding_service
+            .check(&response.response, search_results, strict);
+        if strict && report.sentences.iter().any(|s| !s.supported) {
+            let grounded = self
+                .grounding_service
+                .strip_unsupported(&response.response, &report);
+            response.response = if grounded.trim().is_empty() {
+                response.cacheable = false;
+                "I could not verify an answer against the retrieved sources.".to_string()
+            } else {
+                grounded
+            };
+        }
+        response.grounding = Some(report);
+        response
     }
 
     pub async fn local_model_generate(&self, req: &ChatRequest) -> Result<ChatResponse> {
         let conversation_id = req.conversation_id.unwrap_or_else(uuid::Uuid::new_v4);
-        let mut model = self.ai_model.write().await;
-        let temperature = req.temperature.unwrap_or(self.ai_config.temperature);
-        let max_tokens = req.max_tokens.unwrap_or(self.ai_config.max_tokens);
-        let response = model
-            .chat_with_params(&req.message, Some(conversation_id.to_string()), temperature, max_tokens)
-            .await?;
-        Ok(ChatResponse::new(response, conversation_id))
+        if let (Some(backend), Some(_)) = (&self.backend, &req.constraint) {
+            bail!(
+                "Output constraints are only enforced by in-process generation, not LOCAL_BACKEND={}",
+                backend.kind()
+            );
+        }
+        if let (Some(backend), Some(_)) = (&self.backend, &req.adapter) {
+            bail!(
+                "LoRA adapters are only applied by in-process generation, not LOCAL_BACKEND={}",
+                backend.kind()
+            );
+        }
+        let params = GenerationParams {
+            temperature: req.temperature.unwrap_or(self.ai_config.temperature),
+            max_tokens: req.max_tokens.unwrap_or(self.ai_config.max_tokens),
+            top_k: req.top_k,
+            stop: req.stop.clone().unwrap_or_default(),
+            repetition_penalty: req.repetition_penalty,
+            frequency_penalty: req.frequency_penalty,
+            presence_penalty: req.presence_penalty,
+            seed: self.seed(req),
+            constraint: req.output_grammar()?,
+            // Checked by the sampling loop, which keeps the model locked until it returns.
+            cancel: inflight_cancel_signal(),
+        };
+        let mut system_prompt = self.system_prompt(req);
+        if let Some(tools) = req.tools.as_ref().filter(|tools| !tools.is_empty()) {
+            system_prompt = format!(
+                "{}\n\n{}",
+                system_prompt.trim(),
+                generate_tool_prompt(tools)
+            );
+        }
+        let message = self.user_message(req);
+        let language = self.response_language(req);
+        let history = match req.conversation_id {
+            Some(id) => self.memory.load(id).await,
+            None => ConversationHistory::default(),
+        };
+
+        let (response, partial_reason) = if let Some(backend) = &self.backend {
+            let frame = generate_chat_prompt(
+                &system_prompt,
+                "",
+                Some(conversation_id.to_string()),
+                &ChatContext::default(),
+                language,
+            );
+            let budget = self.remote_prompt_budget(&frame, params.max_tokens);
+            let message = self
+                .fit_remote_input(backend, message, &budget, params.seed)
+                .await?;
+            // The server's tokenizer is out of reach, so history is fitted by estimate.
+            let base_prompt = generate_chat_prompt(
+                &system_prompt,
+                &message,
+                Some(conversation_id.to_string()),
+                &ChatContext::default(),
+                language,
+            );
+            let context = self.memory.fit_recent(
+                history,
+                self.ai_config.context_length,
+                estimate_tokens(&base_prompt),
+                params.max_tokens,
+            );
+            if let Some(language) = language {
+                system_prompt = format!(
+                    "{}\n\n{}",
+                    system_prompt.trim(),
+                    language_instruction(language)
+                );
+            }
+            let chat = LocalChat {
+                system_prompt: &system_prompt,
+                history: &context,
+                message: &message,
+                params: &params,
+            };
+            (backend.chat(chat).await?, None)
+        } else {
+            let model = match req.adapter.as_deref() {
+                Some(adapter) => self.models.acquire_adapter(adapter).await?,
+                None => self.models.acquire(req.model.as_deref()).await?,
+            };
+            let memory = self.memory.clone();
+
+            // Generation is CPU-bound; keep it off the async workers that serve HTTP.
+            let (response, summary_update) = self
+                .run_inference(move || {
+                    let conversation = Some(conversation_id.to_string());
+                    let frame = generate_chat_prompt(
+                        &system_prompt,
+                        "",
+                        conversation.clone(),
+                        &ChatContext::default(),
+                        language,
+                    );
+                    let budget = model.prompt_budget(&frame, params.max_tokens);
+                    let message = model.fit_input(message, &budget, params.seed)?;
+                    let base_prompt = generate_chat_prompt(
+                        &system_prompt,
+                        &message,
+                        conversation.clone(),
+                        &ChatContext::default(),
+                        language,
+                    );
+                    let fitted = memory.fit(
+                        &model,
+                        history,
+                        model.count_tokens(&base_prompt),
+                        params.max_tokens,
+                        params.seed,
+                    );
+                    let response = futures::executor::block_on(model.chat_with_params(
+                        &system_prompt,
+                        &message,
+                        conversation,
+                        &fitted.context,
+                        &params,
+                        language,
+                    ));
+                    let response = match response {
+                        Ok(text) => (text, None),
+                        Err(e) => match e.downcast::<PartialGeneration>() {
+                            Ok(partial) => (partial.text, Some(partial.reason)),
+                            Err(e) => return Err(e),
+                        },
+                    };
+                    Ok::<_, anyhow::Error>((response, fitted.summary_update))
+                })
+                .await??;
+
+            if let Some((summary, summarized_count)) = summary_update {
+                self.memory
+                    .save(conversation_id, summary, summarized_count)
+                    .await;
+            }
+            response
+        };
+
+        let mut chat_response = ChatResponse::new(response, conversation_id);
+        chat_response.route = Some(Route::Local);
+        chat_response.model = Some(self.local_model_name(req));
+        if let Some(reason) = partial_reason {
+            tracing::warn!(conversation_id = %conversation_id, "Returning partial answer: {}", reason);
+            chat_response.partial = true;
+            chat_response.partial_reason = Some(reason);
+            return Ok(chat_response);
+        }
+        if let Some(call) = req
+            .tools
+            .as_deref()
+            .and_then(|tools| parse_tool_call(&chat_response.response, tools))
+        {
+            chat_response.response.clear();
+            chat_response.tool_calls.push(call);
+        }
+        Ok(chat_response)
     }
 
     pub async fn enrich_and_generate(
@@ -52,12 +833,21 @@ impl AIService {
         req: &ChatRequest,
         search_results: &[crate::services::SearchResult],
     ) -> Result<ChatResponse> {
+        note_inflight_route(Route::Enriched, &self.local_model_name(req));
         if search_results.is_empty() {
             return self.local_model_generate(req).await;
         }
+        let (search_results, injection) = self.injection.screen(search_results.to_vec());
+        if search_results.is_empty() {
+            let mut response = self.local_model_generate(req).await?;
+            response.injection = injection;
+            return Ok(response);
+        }
 
         let enrichment = json!({
-            "sources": search_results
+            "sources": self
+                .rank_sources(&req.message, &search_results)
+                .await
                 .iter()
                 .map(|result| {
                     json!({
@@ -75,64 +865,347 @@ impl AIService {
             enrichment
         );
 
+        // Detect on the user's own words; the sources are usually English.
         let enriched_req = ChatRequest {
             message: enriched_message,
-            conversation_id: req.conversation_id,
-            model: req.model.clone(),
-            temperature: req.temperature,
-            max_tokens: req.max_tokens,
-            cache_bypass: req.cache_bypass,
-            stream: req.stream,
+            language: req
+                .language
+                .clone()
+                .or_else(|| self.response_language(req).map(str::to_string)),
+            ..req.clone()
         };
 
-        self.local_model_generate(&enriched_req).await
+        let mut response = self.local_model_generate(&enriched_req).await?;
+        response.route = Some(Route::Enriched);
+        response.injection = injection;
+        Ok(response)
+    }
+
+    /// Orders `search_results` by relevance to `message` and keeps the top
+    /// `enrichment_max_sources` of them. Relevance is embedding similarity
+    /// once the embedding model is loaded, BM25 until then.
+    async fn rank_sources(
+        &self,
+        message: &str,
+        search_results: &[crate::services::SearchResult],
+    ) -> Vec<crate::services::SearchResult> {
+        let documents: Vec<String> = search_results
+            .iter()
+            .map(|result| format!("{} {}", result.title, result.snippet))
+            .collect();
+        let scores = match self.embeddings.similarities(message, &documents).await {
+            Some(scores) => scores,
+            None => bm25_scores(
+                message,
+                &documents.iter().map(String::as_str).collect::<Vec<_>>(),
+            ),
+        };
+        let mut ranked: Vec<_> = search_results.iter().zip(scores).collect();
+        // Stable, so equally relevant results keep their search order.
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let limit = match self.ai_config.enrichment_max_sources {
+            0 => ranked.len(),
+            max => max,
+        };
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|(result, _)| result.clone())
+            .collect()
     }
 
     pub async fn cloud_model_generate(
         &self,
+        ctx: &RequestContext,
         req: &ChatRequest,
         search_results: &[crate::services::SearchResult],
     ) -> Result<ChatResponse> {
+        if ctx.flags.local_only {
+            if req.has_images() {
+                bail!("Image input requires the cloud model, which this caller cannot use");
+            }
+            return self.enrich_and_generate(req, search_results).await;
+        }
+        // A request that picked a local model or adapter gets it, however complex.
+        if req.adapter.is_some() || self.models.is_local(req.model.as_deref()) {
+            if req.has_images() {
+                bail!("Image input requires the cloud model, not a local one");
+            }
+            return self.enrich_and_generate(req, search_results).await;
+        }
+        if req.has_images() {
+            if self.openrouter.api_key.trim().is_empty() {
+                bail!("Image input requires OpenRouter; set OPENROUTER_API_KEY");
+            }
+            if self.spend.is_capped().await {
+                bail!("Image input is unavailable while the OpenRouter spend cap is reached");
+            }
+        }
         if self.openrouter.api_key.trim().is_empty() {
             return self.enrich_and_generate(req, search_results).await;
         }
+        if self.spend.is_capped().await {
+            tracing::info!("OpenRouter spend cap reached; serving High-complexity request locally");
+            // Cached, this stand-in would outlive the cap.
+            let mut response = self.enrich_and_generate(req, search_results).await?;
+            response.cacheable = false;
+            return Ok(response);
+        }
 
         let _ = search_results;
-        let model = req
-            .model
-            .clone()
-            .unwrap_or_else(|| self.openrouter.default_model.clone());
+        let model = req.model.clone().unwrap_or_else(|| {
+            if req.has_images() {
+                self.openrouter.vision_model.clone()
+            } else {
+                self.openrouter.default_model.clone()
+            }
+        });
+        note_inflight_route(Route::Cloud, &model);
         let temperature = req.temperature.unwrap_or(self.ai_config.temperature);
         let max_tokens = req.max_tokens.unwrap_or(self.ai_config.max_tokens) as u32;
 
-        let response = reqwest::Client::new()
-            .post(format!("{}/chat/completions", self.openrouter.base_url))
-            .bearer_auth(&self.openrouter.api_key)
-            .json(&json!({
-                "model": model,
-                "messages": [{"role": "user", "content": req.message}],
-                "temperature": temperature,
-                "max_tokens": max_tokens,
-            }))
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<serde_json::Value>()
-            .await?;
+        // PII leaves the service only as placeholders, restored in the answer below.
+        let mut placeholders = PiiPlaceholders::default();
+        let mut system_prompt = self.system_prompt(req);
+        if let Some(language) = self.response_language(req) {
+            system_prompt = format!(
+                "{}\n\n{}",
+                system_prompt.trim(),
+                language_instruction(language)
+            );
+        }
+        let mut user_message = self.user_message(req);
+        if req.redact_pii.unwrap_or(self.openrouter.redact_pii) {
+            system_prompt = placeholders.mask(&system_prompt);
+            user_message = placeholders.mask(&user_message);
+            if !placeholders.is_empty() {
+                tracing::debug!(
+                    masked = placeholders.len(),
+                    "Masked PII before OpenRouter request"
+                );
+                system_prompt.push_str(
+                    "\n\nBracketed values such as [EMAIL_1] or [HOST_2] stand in for \
+                     withheld data. Repeat them exactly where the real value is needed.",
+                );
+            }
+        }
+
+        // Images travel as data URLs next to the text part.
+        let user_content = match req.images.as_ref().filter(|images| !images.is_empty()) {
+            Some(images) => {
+                let mut parts = vec![json!({"type": "text", "text": user_message})];
+                parts.extend(images.iter().map(
+                    |image| json!({"type": "image_url", "image_url": {"url": image.data_url()}}),
+                ));
+                json!(parts)
+            }
+            None => json!(user_message),
+        };
+
+        let mut payload = json!({
+            "model": model,
+            "messages": [
+                {"role": "system", "content": system_prompt},
+                {"role": "user", "content": user_content}
+            ],
+            "temperature": temperature,
+            "max_tokens": max_tokens,
+            // Asks OpenRouter to report the billed cost for spend accounting.
+            "usage": {"include": true},
+        });
+        // Optional sampling controls are only sent when set so provider defaults apply.
+        let optional = [
+            ("stop", req.stop.as_ref().map(|stop| json!(stop))),
+            ("top_k", req.top_k.map(|v| json!(v))),
+            (
+                "repetition_penalty",
+                req.repetition_penalty.map(|v| json!(v)),
+            ),
+            ("frequency_penalty", req.frequency_penalty.map(|v| json!(v))),
+            ("presence_penalty", req.presence_penalty.map(|v| json!(v))),
+            ("tools", req.tools.as_ref().map(|tools| json!(tools))),
+            ("tool_choice", req.tool_choice.clone()),
+            ("seed", self.seed(req).map(|seed| json!(seed))),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                payload[key] = value;
+            }
+        }
+
+        let endpoint = format!("{}/chat/completions", self.openrouter.base_url);
+        let started = Instant::now();
+        let outcome = async {
+            let response = self
+                .http
+                .for_url(&endpoint)
+                .post(&endpoint)
+                .bearer_auth(&self.openrouter.api_key)
+                .json(&payload)
+                .send()
+                .await?;
+            let status = response.status();
+            let text = response.text().await?;
+            let body = serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text));
+            Ok::<_, anyhow::Error>((status, body))
+        }
+        .await;
+
+        if let Some(request_id) = ctx.request_id.as_deref() {
+            self.capture
+                .record(ProviderExchange {
+                    request_id,
+                    provider: "openrouter",
+                    endpoint: &endpoint,
+                    request: &payload,
+                    response: outcome.as_ref().ok().map(|(_, body)| body),
+                    status: outcome.as_ref().ok().map(|(status, _)| status.as_u16()),
+                    error: outcome.as_ref().err().map(|e| e.to_string()),
+                    duration_ms: started.elapsed().as_millis() as u64,
+                })
+                .await;
+        }
+
+        let (status, response) = outcome?;
+        if !status.is_success() {
+            bail!("OpenRouter returned {}: {}", status, response);
+        }
+        let cost_usd = self.spend.record(&response).await;
 
-        let content = response
+        let message = response
             .get("choices")
             .and_then(|choices| choices.get(0))
-            .and_then(|choice| choice.get("message"))
+            .and_then(|choice| choice.get("message"));
+        let tool_calls: Vec<ToolCall> = message
+            .and_then(|message| message.get("tool_calls"))
+            .and_then(|calls| serde_json::from_value(calls.clone()).ok())
+            .unwrap_or_default();
+        let content = message
             .and_then(|message| message.get("content"))
-            .and_then(|content| content.as_str())
-            .unwrap_or("No response from OpenRouter");
+            .and_then(|content| content.as_str());
+        let placeholder = content.is_none() && tool_calls.is_empty();
+        let content = content.unwrap_or(if placeholder {
+            "No response from OpenRouter"
+        } else {
+            ""
+        });
 
         let conversation_id = req.conversation_id.unwrap_or_else(uuid::Uuid::new_v4);
-        Ok(ChatResponse::new(content.to_string(), conversation_id))
+        let mut chat_response = ChatResponse::new(placeholders.restore(content), conversation_id);
+        chat_response.cacheable = !placeholder;
+        chat_response.tool_calls = tool_calls
+            .into_iter()
+            .map(|mut call| {
+                call.function.arguments = placeholders.restore(&call.function.arguments);
+                call
+            })
+            .collect();
+        chat_response.route = Some(Route::Cloud);
+        // OpenRouter reports the concrete model behind routers such as `openrouter/auto`.
+        chat_response.model = Some(
+            response
+                .get("model")
+                .and_then(|model| model.as_str())
+                .map(str::to_string)
+                .unwrap_or(model),
+        );
+        chat_response.cost_usd = Some(cost_usd);
+        Ok(chat_response)
+    }
+
+    /// Sampler seed when the request or the deployment asks for deterministic output.
+    fn seed(&self, req: &ChatRequest) -> Option<u64> {
+        req.deterministic
+            .unwrap_or(self.ai_config.deterministic)
+            .then_some(self.ai_config.deterministic_seed)
+    }
+
+    pub fn templates(&self) -> &PromptTemplates {
+        &self.templates
+    }
+
+    /// The request's system prompt, then its template, then the configured default.
+    fn system_prompt(&self, req: &ChatRequest) -> String {
+        req.system_prompt
+            .as_deref()
+            .filter(|prompt| !prompt.trim().is_empty())
+            .or_else(|| {
+                req.template
+                    .as_deref()
+                    .and_then(|name| self.templates.get(name))
+            })
+            .unwrap_or(&self.ai_config.system_prompt)
+            .to_string()
     }
 
+    /// Language the answer should be written in: the request's `language`, else
+    /// the detected language of the message when it is not English. Detection is
+    /// skipped for custom system prompts and templates, which set their own.
+    fn response_language(&self, req: &ChatRequest) -> Option<&'static str> {
+        if let Some(requested) = req.language.as_deref() {
+            return language_name(requested);
+        }
+        if !self.ai_config.detect_language || req.system_prompt.is_some() || req.template.is_some()
+        {
+            return None;
+        }
+        detect_language(&req.message).filter(|language| *language != "English")
+    }
+
+    /// The user message with any client-supplied tool results appended.
+    fn user_message(&self, req: &ChatRequest) -> String {
+        match req
+            .tool_results
+            .as_ref()
+            .filter(|results| !results.is_empty())
+        {
+            Some(results) => format!(
+                "{}\n\nTool results:\n{}",
+                req.message,
+                format_tool_results(results)
+            ),
+            None => req.message.clone(),
+        }
+    }
+
+    /// Knowledge-base chunks for `query`, followed by web results for its
+    /// rewrites, searched concurrently. A rewrite whose search fails is
+    /// skipped; the search fails only when every rewrite does.
     pub async fn search(&self, query: &str) -> Result<Vec<crate::services::SearchResult>> {
-        self.search_service.search(query).await
+        let queries = rewrite_search_queries(query);
+        tracing::debug!(original = %query, rewritten = ?queries, "Rewrote search queries");
+
+        let (mut results, searches) = tokio::join!(
+            self.knowledge.search(query),
+            join_all(
+                queries
+                    .iter()
+                    .map(|rewritten| self.search_service.search(rewritten)),
+            )
+        );
+
+        let mut seen_urls = HashSet::new();
+        let mut last_error = None;
+        let mut succeeded = false;
+        for (rewritten, searched) in queries.iter().zip(searches) {
+            match searched {
+                Ok(found) => {
+                    succeeded = true;
+                    for result in found {
+                        if seen_urls.insert(result.url.clone()) {
+                            results.push(result);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(query = %rewritten, "Skipping failed search query: {}", e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) if !succeeded => Err(e),
+            _ => Ok(results),
+        }
     }
 }
@@ -0,0 +1,196 @@
+use chrono::Utc;
+use serde_json::json;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use crate::config::AlertSettings;
+use crate::models::AIModel;
+use crate::services::{CacheService, HttpClients, TaskHandle, TaskRegistry};
+use crate::utils::available_bytes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Check {
+    Redis,
+    Model,
+    Disk,
+}
+
+impl Check {
+    const ALL: [Check; 3] = [Check::Redis, Check::Model, Check::Disk];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Check::Redis => "redis",
+            Check::Model => "model",
+            Check::Disk => "disk",
+        }
+    }
+}
+
+/// Last reported state of one check and the observations pending a flip.
+struct CheckState {
+    healthy: bool,
+    streak: u32,
+}
+
+/// Polls dependencies and posts to a Slack-compatible webhook when one changes
+/// state, once the new state has held for `debounce_checks` consecutive polls.
+#[derive(Clone)]
+pub struct AlertService {
+    settings: AlertSettings,
+    cache: CacheService,
+    ai_model: Arc<RwLock<AIModel>>,
+    http: HttpClients,
+}
+
+impl AlertService {
+    pub fn new(
+        settings: AlertSettings,
+        cache: CacheService,
+        ai_model: Arc<RwLock<AIModel>>,
+        http: HttpClients,
+    ) -> Self {
+        Self {
+            settings,
+            cache,
+            ai_model,
+            http,
+        }
+    }
+
+    /// Starts the polling loop under `tasks`; does nothing unless a webhook is configured.
+    pub fn spawn(self, tasks: &TaskRegistry) {
+        if self.settings.webhook_url.is_none() {
+            return;
+        }
+        tasks.spawn("alerts", move |handle| self.clone().run(handle));
+    }
+
+    async fn run(self, handle: TaskHandle) -> anyhow::Result<()> {
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            self.settings.check_interval_secs.max(1),
+        ));
+        // Every check starts out assumed healthy, so only real failures alert.
+        let mut states: Vec<CheckState> = Check::ALL
+            .iter()
+            .map(|_| CheckState {
+                healthy: true,
+                streak: 0,
+            })
+            .collect();
+        let mut model_seen_ready = false;
+
+        loop {
+            interval.tick().await;
+            for (check, state) in Check::ALL.iter().zip(states.iter_mut()) {
+                let Some(result) = self.probe(*check, &mut model_seen_ready).await else {
+                    state.streak = 0;
+                    continue;
+                };
+                if result.is_ok() == state.healthy {
+                    state.streak = 0;
+                    continue;
+                }
+                state.streak += 1;
+                if state.streak >= self.settings.debounce_checks.max(1) {
+                    state.healthy = result.is_ok();
+                    state.streak = 0;
+                    self.notify(*check, result.err()).await;
+                }
+            }
+            handle.ran();
+        }
+    }
+
+    /// `None` means the check does not apply right now and is skipped.
+    async fn probe(&self, check: Check, model_seen_ready: &mut bool) -> Option<Result<(), String>> {
+        match check {
+            Check::Redis => self
+                .cache
+                .redis_health()
+                .await
+                .map(|result| result.map_err(|e| e.to_string())),
+            Check::Model => {
+                // The lock is only held while a model is loaded or swapped in.
+                let ready = match self.ai_model.try_read() {
+                    Ok(model) => model.is_ready() || model.is_idle_unloaded(),
+                    Err(_) => return None,
+                };
+                // The initial load is reported by /api/ready, not as an outage.
+                if !*model_seen_ready {
+                    *model_seen_ready = ready;
+                    return None;
+                }
+                Some(if ready {
+                    Ok(())
+                } else {
+                    Err("model is not loaded".to_string())
+                })
+            }
+            Check::Disk => {
+                let min_free = self.settings.disk_min_free_mb * 1024 * 1024;
+                match available_bytes(Path::new(&self.settings.disk_path)) {
+                    Ok(Some(available)) if available < min_free => Some(Err(format!(
+                        "{} MB free in {} (minimum {} MB)",
+                        available / (1024 * 1024),
+                        self.settings.disk_path,
+                        self.settings.disk_min_free_mb
+                    ))),
+                    Ok(Some(_)) => Some(Ok(())),
+                    Ok(None) => None,
+                    Err(e) => Some(Err(format!(
+                        "cannot stat {}: {}",
+                        self.settings.disk_path, e
+                    ))),
+                }
+            }
+        }
+    }
+
+    async fn notify(&self, check: Check, failure: Option<String>) {
+        let Some(url) = self.settings.webhook_url.as_deref() else {
+            return;
+        };
+        let (state, text) = match &failure {
+            Some(detail) => (
+                "down",
+                format!(
+                    ":red_circle: SelfCare AI: {} is down ({})",
+                    check.as_str(),
+                    detail
+                ),
+            ),
+            None => (
+                "recovered",
+                format!(
+                    ":large_green_circle: SelfCare AI: {} recovered",
+                    check.as_str()
+                ),
+            ),
+        };
+        tracing::warn!(check = check.as_str(), state, "Health state changed");
+
+        // `text` is what Slack renders; the remaining fields are for other receivers.
+        let payload = json!({
+            "text": text,
+            "check": check.as_str(),
+            "state": state,
+            "detail": failure,
+            "timestamp": Utc::now(),
+        });
+        let result = self
+            .http
+            .for_url(url)
+            .post(url)
+            .timeout(Duration::from_millis(self.settings.webhook_timeout_ms))
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            tracing::warn!("Alert webhook failed for {}: {}", check.as_str(), e);
+        }
+    }
+}
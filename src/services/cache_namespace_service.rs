@@ -0,0 +1,130 @@
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::config::CacheSettings;
+use crate::repositories::NamespaceRepo;
+use crate::services::TaskRegistry;
+
+/// Template name under which requests without a `template` are versioned.
+pub const DEFAULT_TEMPLATE_NAMESPACE: &str = "default";
+
+/// How often versions bumped by other instances are picked up.
+const REFRESH_INTERVAL_SECS: u64 = 30;
+
+/// Version counters that go into the chat cache key per prompt template, so
+/// bumping a template's version after editing it stops its old answers from
+/// being served. Versions start at 0 and persist beside the SQLite cache.
+#[derive(Clone)]
+pub struct CacheNamespaceService {
+    repo: Option<NamespaceRepo>,
+    versions: Arc<RwLock<BTreeMap<String, u64>>>,
+}
+
+impl CacheNamespaceService {
+    pub fn new(settings: &CacheSettings) -> Self {
+        let repo = if settings.sqlite_path.trim().is_empty() {
+            None
+        } else {
+            match NamespaceRepo::new(settings.sqlite_path.clone()) {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    tracing::warn!("Template cache versions disabled: {}", e);
+                    None
+                }
+            }
+        };
+        let versions = match repo.as_ref().map(NamespaceRepo::load) {
+            Some(Ok(versions)) => versions,
+            Some(Err(e)) => {
+                tracing::warn!("Failed to load template cache versions: {}", e);
+                BTreeMap::new()
+            }
+            None => BTreeMap::new(),
+        };
+        Self {
+            repo,
+            versions: Arc::new(RwLock::new(versions)),
+        }
+    }
+
+    /// Starts the task that reloads versions bumped by other instances.
+    pub fn spawn(&self, tasks: &TaskRegistry) {
+        if self.repo.is_none() {
+            return;
+        }
+        let service = self.clone();
+        tasks.spawn("cache_namespace_refresh", move |handle| {
+            let service = service.clone();
+            async move {
+                let mut interval =
+                    tokio::time::interval(std::time::Duration::from_secs(REFRESH_INTERVAL_SECS));
+                loop {
+                    interval.tick().await;
+                    match service.reload().await {
+                        Ok(()) => handle.ran(),
+                        Err(e) => {
+                            tracing::warn!("Template cache version refresh failed: {}", e);
+                            handle.failed(e);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    fn repo(&self) -> Result<NamespaceRepo> {
+        self.repo
+            .clone()
+            .ok_or_else(|| anyhow!("Template cache versions are disabled"))
+    }
+
+    /// The current version of `template`, or of the default namespace.
+    pub fn template_version(&self, template: Option<&str>) -> u64 {
+        let name = namespace_name(template);
+        self.versions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&name)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Every template whose version has been bumped.
+    pub fn versions(&self) -> BTreeMap<String, u64> {
+        self.versions
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Moves `template` to a new version and returns it. Answers cached under
+    /// earlier versions are no longer matched and age out under their TTL.
+    pub async fn bump(&self, template: Option<&str>) -> Result<u64> {
+        let repo = self.repo()?;
+        let name = namespace_name(template);
+        let bumped = name.clone();
+        let version = tokio::task::spawn_blocking(move || repo.bump(&bumped)).await??;
+        self.versions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name, version);
+        Ok(version)
+    }
+
+    async fn reload(&self) -> Result<()> {
+        let repo = self.repo()?;
+        let versions = tokio::task::spawn_blocking(move || repo.load()).await??;
+        *self
+            .versions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = versions;
+        Ok(())
+    }
+}
+
+fn namespace_name(template: Option<&str>) -> String {
+    template
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_else(|| DEFAULT_TEMPLATE_NAMESPACE.to_string())
+}
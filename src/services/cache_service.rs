@@ -1,20 +1,41 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
 use chrono::{DateTime, Duration, Utc};
-use lru::LruCache;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::num::NonZeroUsize;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::sync::{Arc, MutexGuard, PoisonError};
+use tokio::sync::{watch, Mutex, Notify};
 
-use crate::config::CacheSettings;
-use crate::repositories::{CacheRepo, RedisRepo};
+use crate::config::{CacheSettings, CacheWritePolicy};
+use crate::models::{CacheExportRecord, CacheTierStatus, RequestContext, Route};
+use crate::repositories::{
+    CacheRepo, CacheStatsBucket, CacheStatsRepo, CacheStatsSample, CacheStore, PostgresRepo,
+    PostgresTlsOptions, RedisConnectOptions, RedisRepo,
+};
+use crate::services::TaskRegistry;
+use crate::utils::{fuzzy_ratio, EvictionPolicy, MemoryCache};
+
+/// Marks an entry stored as base64 zstd rather than plain JSON, which never
+/// starts with this.
+const COMPRESSED_PREFIX: &str = "zstd:";
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Delay before retrying a failed cache write; doubles per attempt.
+const WRITE_RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(250);
+const MAX_WRITE_RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Delay before subscribing again after the invalidation channel drops.
+const RESUBSCRIBE_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
 
 #[derive(Debug, Clone, Copy)]
 pub enum CacheSource {
     Memory,
     Redis,
     Sqlite,
+    Postgres,
 }
 
 impl CacheSource {
@@ -23,6 +44,7 @@ impl CacheSource {
             CacheSource::Memory => "memory",
             CacheSource::Redis => "redis",
             CacheSource::Sqlite => "sqlite",
+            CacheSource::Postgres => "postgres",
         }
     }
 }
@@ -31,6 +53,147 @@ impl CacheSource {
 struct MemoryEntry {
     value: Value,
     expires_at: DateTime<Utc>,
+    tags: Vec<String>,
+}
+
+enum IdempotencySlot {
+    /// Dropping the sender wakes retries waiting on the key.
+    Running(watch::Sender<()>),
+    Done {
+        value: Value,
+        expires_at: DateTime<Utc>,
+    },
+}
+
+/// Outcome of [`CacheService::claim_idempotency`].
+pub enum IdempotencyClaim {
+    /// No earlier request used the key; complete the guard with the response.
+    Acquired(IdempotencyGuard),
+    /// Response stored by an earlier request with the same key.
+    Replay(Value),
+}
+
+/// Holds an idempotency key while its request runs. Dropping it without
+/// [`IdempotencyGuard::complete`] releases the key, so a retry runs again.
+pub struct IdempotencyGuard {
+    key: String,
+    cache: CacheService,
+}
+
+impl IdempotencyGuard {
+    /// Stores the response for retries and wakes the ones already waiting.
+    pub async fn complete(self, value: &Value) {
+        let ttl_seconds = self.cache.settings.idempotency_ttl_seconds;
+        if let Some(redis_repo) = &self.cache.redis_repo {
+            if let Ok(json) = serde_json::to_string(value) {
+                if let Err(e) = redis_repo.set_with_ttl(&self.key, &json, ttl_seconds).await {
+                    tracing::warn!("Failed to store idempotent response in Redis: {}", e);
+                }
+            }
+        }
+
+        // Completed responses stay in memory no longer than regular cache entries.
+        let retention = ttl_seconds.min(self.cache.settings.memory_ttl_seconds);
+        self.cache.idempotency_slots().insert(
+            self.key.clone(),
+            IdempotencySlot::Done {
+                value: value.clone(),
+                expires_at: Utc::now() + Duration::seconds(retention as i64),
+            },
+        );
+    }
+}
+
+impl Drop for IdempotencyGuard {
+    fn drop(&mut self) {
+        let mut slots = self.cache.idempotency_slots();
+        if matches!(slots.get(&self.key), Some(IdempotencySlot::Running(_))) {
+            slots.remove(&self.key);
+        }
+    }
+}
+
+/// A cache entry still to be written to Redis and/or the durable tier.
+struct PendingWrite {
+    key: String,
+    json: String,
+    tags: Vec<String>,
+    ttl_seconds: Option<u64>,
+    redis: bool,
+    durable: bool,
+    attempts: u32,
+    /// Set after a failure; the write is not retried before then.
+    retry_at: Option<tokio::time::Instant>,
+}
+
+/// Bounded buffer between [`CacheService::set`] and the background writer.
+#[derive(Default)]
+struct WriteQueue {
+    pending: std::sync::Mutex<VecDeque<PendingWrite>>,
+    wake: Notify,
+}
+
+impl WriteQueue {
+    fn pending(&self) -> MutexGuard<'_, VecDeque<PendingWrite>> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes the oldest write that is due, or returns when the next retry is.
+    /// Writes waiting on a retry stay queued without holding up the others.
+    fn take_due(&self) -> Result<PendingWrite, Option<tokio::time::Instant>> {
+        let now = tokio::time::Instant::now();
+        let mut pending = self.pending();
+        match pending
+            .iter()
+            .position(|write| !write.retry_at.is_some_and(|at| at > now))
+        {
+            Some(index) => Ok(pending.remove(index).expect("index is in bounds")),
+            None => Err(pending.iter().filter_map(|write| write.retry_at).min()),
+        }
+    }
+}
+
+/// Expired entries removed from each tier by one cleanup pass.
+#[derive(Debug, Clone, Default)]
+pub struct CacheCleanup {
+    pub memory: u64,
+    pub redis_tag_members: u64,
+    /// Durable tier, SQLite or Postgres.
+    pub sqlite: u64,
+}
+
+/// Announced on the invalidation channel so other instances drop the same
+/// entries from their memory tier; Redis and the durable tier are shared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum InvalidationEvent {
+    Key {
+        key: String,
+    },
+    /// `keys` are the entries the shared tiers reported, which may sit
+    /// untagged in memory after being promoted.
+    Tag {
+        tag: String,
+        keys: Vec<String>,
+    },
+    Flush,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InvalidationMessage {
+    /// Lets an instance skip its own announcements.
+    origin: String,
+    #[serde(flatten)]
+    event: InvalidationEvent,
+}
+
+/// Number of entries removed from each tier by a tag invalidation.
+#[derive(Debug, Clone, Default)]
+pub struct TagInvalidation {
+    pub memory: u64,
+    pub redis: u64,
+    /// Durable tier, SQLite or Postgres.
+    pub sqlite: u64,
 }
 
 #[derive(Debug)]
@@ -38,7 +201,12 @@ pub struct CacheStats {
     pub total_requests: AtomicU64,
     pub memory_hits: AtomicU64,
     pub redis_hits: AtomicU64,
+    /// Hits on the durable tier, SQLite or Postgres.
     pub sqlite_hits: AtomicU64,
+    /// Expired entries served while a fresh answer was generated.
+    pub stale_hits: AtomicU64,
+    /// Writes discarded because the queue was full or retries ran out.
+    pub dropped_writes: AtomicU64,
 }
 
 impl CacheStats {
@@ -48,50 +216,176 @@ impl CacheStats {
             memory_hits: AtomicU64::new(0),
             redis_hits: AtomicU64::new(0),
             sqlite_hits: AtomicU64::new(0),
+            stale_hits: AtomicU64::new(0),
+            dropped_writes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn snapshot(&self) -> CacheStatsSample {
+        CacheStatsSample {
+            requests: self.total_requests.load(Ordering::Relaxed),
+            memory_hits: self.memory_hits.load(Ordering::Relaxed),
+            redis_hits: self.redis_hits.load(Ordering::Relaxed),
+            durable_hits: self.sqlite_hits.load(Ordering::Relaxed),
+            stale_hits: self.stale_hits.load(Ordering::Relaxed),
+            dropped_writes: self.dropped_writes.load(Ordering::Relaxed),
         }
     }
 }
 
+/// Growth of the counters from `previous` to `current`.
+fn stats_delta(current: &CacheStatsSample, previous: &CacheStatsSample) -> CacheStatsSample {
+    CacheStatsSample {
+        requests: current.requests.saturating_sub(previous.requests),
+        memory_hits: current.memory_hits.saturating_sub(previous.memory_hits),
+        redis_hits: current.redis_hits.saturating_sub(previous.redis_hits),
+        durable_hits: current.durable_hits.saturating_sub(previous.durable_hits),
+        stale_hits: current.stale_hits.saturating_sub(previous.stale_hits),
+        dropped_writes: current
+            .dropped_writes
+            .saturating_sub(previous.dropped_writes),
+    }
+}
+
 #[derive(Clone)]
 pub struct CacheService {
     settings: CacheSettings,
-    memory_cache: Arc<Mutex<LruCache<String, MemoryEntry>>>,
+    memory_cache: Arc<Mutex<MemoryCache<MemoryEntry>>>,
     redis_repo: Option<RedisRepo>,
-    sqlite_repo: Option<CacheRepo>,
+    durable_repo: Option<Arc<dyn CacheStore>>,
+    durable_source: CacheSource,
+    /// Tiers an operator has taken out of service at runtime.
+    disabled_tiers: Arc<std::sync::RwLock<HashSet<String>>>,
+    idempotency: Arc<std::sync::Mutex<HashMap<String, IdempotencySlot>>>,
+    /// Keys whose stale entry is being regenerated in the background.
+    refreshing: Arc<std::sync::Mutex<HashSet<String>>>,
+    writes: Arc<WriteQueue>,
     stats: Arc<CacheStats>,
+    /// Snapshots of `stats` over time; `None` when disabled or unavailable.
+    stats_repo: Option<CacheStatsRepo>,
+    /// Identifies this instance's messages on the invalidation channel.
+    instance_id: String,
+    /// Recently stored chat questions per key scope, oldest first, for the
+    /// similar-question lookup.
+    questions: Arc<std::sync::Mutex<HashMap<String, VecDeque<IndexedQuestion>>>>,
+}
+
+#[derive(Debug, Clone)]
+struct IndexedQuestion {
+    question: String,
+    key: String,
+}
+
+/// An earlier question that passed the fuzzy pre-filter.
+#[derive(Debug, Clone)]
+pub struct SimilarCandidate {
+    pub question: String,
+    pub key: String,
+    /// Fuzzy ratio against the asked question.
+    pub ratio: f32,
+}
+
+/// Marks a key as being refreshed; dropping it lets the next stale read
+/// start another refresh.
+pub struct RefreshGuard {
+    key: String,
+    refreshing: Arc<std::sync::Mutex<HashSet<String>>>,
+}
+
+impl Drop for RefreshGuard {
+    fn drop(&mut self) {
+        self.refreshing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.key);
+    }
 }
 
 impl CacheService {
     pub async fn new(settings: CacheSettings) -> Result<Self> {
-        let memory_capacity = NonZeroUsize::new(settings.memory_cache_entries.max(1))
-            .unwrap_or_else(|| NonZeroUsize::new(1).unwrap());
-        let memory_cache = Arc::new(Mutex::new(LruCache::new(memory_capacity)));
+        let eviction_policy = EvictionPolicy::parse(&settings.memory_eviction_policy)
+            .unwrap_or_else(|| {
+                tracing::warn!(
+                    "Unknown MEMORY_CACHE_POLICY {:?}; using lru",
+                    settings.memory_eviction_policy
+                );
+                EvictionPolicy::Lru
+            });
+        let memory_cache = Arc::new(Mutex::new(MemoryCache::new(
+            eviction_policy,
+            settings.memory_cache_entries,
+            (settings.memory_max_mb as usize).saturating_mul(1024 * 1024),
+        )));
 
-        let redis_repo = if settings.redis_url.trim().is_empty() {
+        let redis_repo = if settings.redis_url.trim().is_empty() || !uses_tier(&settings, "redis") {
             None
         } else {
-            RedisRepo::new(&settings.redis_url, settings.redis_ttl_seconds)
-                .await
-                .ok()
+            let options = RedisConnectOptions {
+                username: settings.redis_username.clone(),
+                password: settings.redis_password.clone(),
+                ca_cert_path: settings.redis_ca_cert_path.clone(),
+                client_cert_path: settings.redis_client_cert_path.clone(),
+                client_key_path: settings.redis_client_key_path.clone(),
+            };
+            match RedisRepo::new(&settings.redis_url, settings.redis_ttl_seconds, &options).await {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    tracing::warn!("Redis cache disabled: {}", e);
+                    None
+                }
+            }
         };
 
-        let sqlite_repo = if settings.sqlite_path.trim().is_empty() {
-            None
-        } else {
-            CacheRepo::new(
-                settings.sqlite_path.clone(),
-                settings.sqlite_ttl_days,
-                settings.sqlite_max_size_gb,
-            )
-            .ok()
+        // The Postgres client blocks, so it must not connect on an async worker.
+        let tier_settings = settings.clone();
+        let durable_tier =
+            tokio::task::spawn_blocking(move || open_durable_tier(&tier_settings)).await?;
+        let (durable_repo, durable_source) = match durable_tier {
+            Ok(tier) => tier,
+            Err(e) => {
+                tracing::warn!("Durable cache tier disabled: {}", e);
+                (None, CacheSource::Sqlite)
+            }
         };
 
+        let stats_repo =
+            if settings.sqlite_path.trim().is_empty() || settings.stats_flush_interval_secs == 0 {
+                None
+            } else {
+                match CacheStatsRepo::new(settings.sqlite_path.clone()) {
+                    Ok(repo) => Some(repo),
+                    Err(e) => {
+                        tracing::warn!("Cache statistics history disabled: {}", e);
+                        None
+                    }
+                }
+            };
+
         Ok(Self {
             settings,
             memory_cache,
             redis_repo,
-            sqlite_repo,
+            durable_repo,
+            durable_source,
+            disabled_tiers: Arc::new(std::sync::RwLock::new(HashSet::new())),
+            idempotency: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            refreshing: Arc::new(std::sync::Mutex::new(HashSet::new())),
+            writes: Arc::new(WriteQueue::default()),
             stats: Arc::new(CacheStats::new()),
+            stats_repo,
+            instance_id: uuid::Uuid::new_v4().to_string(),
+            questions: Arc::new(std::sync::Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Redis reachability; `None` when Redis is not a configured tier.
+    pub async fn redis_health(&self) -> Option<Result<()>> {
+        if self.settings.redis_url.trim().is_empty() || !uses_tier(&self.settings, "redis") {
+            return None;
+        }
+        Some(match &self.redis_repo {
+            Some(redis_repo) => redis_repo.ping().await,
+            None => Err(anyhow::anyhow!("not connected since startup")),
         })
     }
 
@@ -99,80 +393,984 @@ impl CacheService {
         self.stats.clone()
     }
 
+    /// Looks the key up in each configured tier in order. A hit below the
+    /// memory tier is copied into memory when memory comes earlier and is writable.
     pub async fn get(&self, key: &str) -> Option<(Value, CacheSource)> {
         self.stats.total_requests.fetch_add(1, Ordering::Relaxed);
 
-        if let Some(value) = self.get_from_memory(key).await {
-            self.stats.memory_hits.fetch_add(1, Ordering::Relaxed);
-            return Some((value, CacheSource::Memory));
+        for (position, tier) in self.settings.tiers.iter().enumerate() {
+            if self.is_disabled(&tier.name) {
+                continue;
+            }
+            let (value, source) = match tier.name.as_str() {
+                "memory" => match self.get_from_memory(key).await {
+                    Some(value) => (value, CacheSource::Memory),
+                    None => continue,
+                },
+                "redis" => match self.get_from_redis(key).await {
+                    Some(value) => (value, CacheSource::Redis),
+                    None => continue,
+                },
+                "durable" => match self.get_from_durable(key).await {
+                    Some(value) => (value, self.durable_source),
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let hits = match source {
+                CacheSource::Memory => &self.stats.memory_hits,
+                CacheSource::Redis => &self.stats.redis_hits,
+                CacheSource::Sqlite | CacheSource::Postgres => &self.stats.sqlite_hits,
+            };
+            hits.fetch_add(1, Ordering::Relaxed);
+
+            let promote = self.settings.tiers[..position]
+                .iter()
+                .any(|earlier| earlier.name == "memory" && earlier.write)
+                && !self.is_disabled("memory");
+            if promote {
+                self.set_memory(
+                    key,
+                    value.clone(),
+                    Vec::new(),
+                    self.settings.memory_ttl_seconds,
+                )
+                .await;
+            }
+            return Some((value, source));
         }
 
-        if let Some(redis_repo) = &self.redis_repo {
-            if let Ok(Some(value)) = redis_repo.get(key).await {
-                if let Ok(json) = serde_json::from_str::<Value>(&value) {
-                    self.stats.redis_hits.fetch_add(1, Ordering::Relaxed);
-                    self.set_memory(key, json.clone()).await;
-                    return Some((json, CacheSource::Redis));
+        None
+    }
+
+    /// Records that `key` holds the answer to `question` within `scope`. Each
+    /// scope keeps the latest `memory_cache_entries` questions in memory; the
+    /// durable tier indexes them all, in the background.
+    pub fn remember_question(&self, scope: &str, question: &str, key: &str) {
+        {
+            let mut questions = self
+                .questions
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            let recent = questions.entry(scope.to_string()).or_default();
+            recent.retain(|indexed| indexed.key != key);
+            recent.push_back(IndexedQuestion {
+                question: question.to_string(),
+                key: key.to_string(),
+            });
+            while recent.len() > self.settings.memory_cache_entries.max(1) {
+                recent.pop_front();
+            }
+        }
+
+        if let Some(repo) = self.active_durable().filter(|_| self.writes_to("durable")) {
+            let repo = repo.clone();
+            let (scope, question, key) = (scope.to_string(), question.to_string(), key.to_string());
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = repo.index_question(&scope, &question, &key) {
+                    tracing::warn!("Failed to index cached question: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Earlier questions in `scope`, other than the one stored under `key`,
+    /// whose fuzzy ratio to `question` reaches `fuzzy_min_ratio`; the best
+    /// `max_similar_results` of them, best first. Candidates come from this
+    /// instance's recent questions and the durable tier's LSH index.
+    pub async fn similar_candidates(
+        &self,
+        scope: &str,
+        key: &str,
+        question: &str,
+    ) -> Vec<SimilarCandidate> {
+        let mut indexed: HashMap<String, String> = self
+            .questions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(scope)
+            .map(|recent| {
+                recent
+                    .iter()
+                    .map(|indexed| (indexed.key.clone(), indexed.question.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if let Some(repo) = self.active_durable() {
+            let repo = repo.clone();
+            let (scope, question) = (scope.to_string(), question.to_string());
+            let limit = self.settings.memory_cache_entries.max(1);
+            let durable = tokio::task::spawn_blocking(move || {
+                repo.similar_questions(&scope, &question, limit)
+            })
+            .await;
+            match durable {
+                Ok(Ok(similar)) => indexed.extend(similar),
+                Ok(Err(e)) => tracing::warn!("Similar-question index lookup failed: {}", e),
+                Err(e) => tracing::warn!("Similar-question index lookup failed: {}", e),
+            }
+        }
+
+        let mut candidates: Vec<SimilarCandidate> = indexed
+            .into_iter()
+            .filter(|(indexed_key, _)| indexed_key != key)
+            .map(|(indexed_key, indexed_question)| SimilarCandidate {
+                ratio: fuzzy_ratio(self.settings.fuzzy_method, question, &indexed_question),
+                question: indexed_question,
+                key: indexed_key,
+            })
+            .filter(|candidate| candidate.ratio >= self.settings.fuzzy_min_ratio)
+            .collect();
+        candidates.sort_by(|a, b| b.ratio.total_cmp(&a.ratio));
+        candidates.truncate(self.settings.max_similar_results);
+        candidates
+    }
+
+    /// An entry that expired no more than `stale_while_revalidate_seconds`
+    /// ago, from memory or the durable tier. Call after [`CacheService::get`]
+    /// missed; `None` when the grace period is off.
+    pub async fn get_stale(&self, key: &str) -> Option<(Value, CacheSource)> {
+        let grace = self.settings.stale_while_revalidate_seconds;
+        if grace == 0 {
+            return None;
+        }
+        let memory = if uses_tier(&self.settings, "memory") && !self.is_disabled("memory") {
+            let cache = self.memory_cache.lock().await;
+            cache
+                .peek(key)
+                .filter(|entry| entry.expires_at + Duration::seconds(grace as i64) > Utc::now())
+                .map(|entry| (entry.value.clone(), CacheSource::Memory))
+        } else {
+            None
+        };
+        let found = match memory {
+            Some(found) => Some(found),
+            None => {
+                let repo = self.active_durable()?.clone();
+                let key = key.to_string();
+                let record = tokio::task::spawn_blocking(move || repo.get_stale(&key, grace))
+                    .await
+                    .ok()?
+                    .ok()??;
+                decode_entry(&record.value_json)
+                    .ok()
+                    .map(|value| (value, self.durable_source))
+            }
+        };
+        if found.is_some() {
+            self.stats.stale_hits.fetch_add(1, Ordering::Relaxed);
+        }
+        found
+    }
+
+    /// Claims the background refresh of `key`; `None` while another request
+    /// is already refreshing it.
+    pub fn begin_refresh(&self, key: &str) -> Option<RefreshGuard> {
+        let mut refreshing = self
+            .refreshing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        refreshing.insert(key.to_string()).then(|| RefreshGuard {
+            key: key.to_string(),
+            refreshing: self.refreshing.clone(),
+        })
+    }
+
+    async fn get_from_redis(&self, key: &str) -> Option<Value> {
+        let value = self.redis_repo.as_ref()?.get(key).await.ok()??;
+        decode_entry(&value).ok()
+    }
+
+    async fn get_from_durable(&self, key: &str) -> Option<Value> {
+        let repo = self.durable_repo.clone()?;
+        let key = key.to_string();
+        let record = tokio::task::spawn_blocking(move || repo.get(&key))
+            .await
+            .ok()?
+            .ok()??;
+        decode_entry(&record.value_json).ok()
+    }
+
+    /// Whether `tier` is configured, enabled and takes new entries.
+    fn writes_to(&self, tier: &str) -> bool {
+        self.settings
+            .tiers
+            .iter()
+            .any(|configured| configured.name == tier && configured.write)
+            && !self.is_disabled(tier)
+    }
+
+    /// Redis, unless it is unavailable or disabled.
+    fn active_redis(&self) -> Option<&RedisRepo> {
+        self.redis_repo
+            .as_ref()
+            .filter(|_| !self.is_disabled("redis"))
+    }
+
+    /// The durable tier, unless it is unavailable or disabled.
+    fn active_durable(&self) -> Option<&Arc<dyn CacheStore>> {
+        self.durable_repo
+            .as_ref()
+            .filter(|_| !self.is_disabled("durable"))
+    }
+
+    fn is_disabled(&self, tier: &str) -> bool {
+        self.disabled_tiers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(tier)
+    }
+
+    /// Takes a configured tier out of service, or puts it back. A disabled tier
+    /// is skipped by lookups, writes and expiry sweeps but still has tags
+    /// invalidated. Returns false when `tier` is not configured.
+    pub fn set_tier_enabled(&self, tier: &str, enabled: bool) -> bool {
+        if !uses_tier(&self.settings, tier) {
+            return false;
+        }
+        let mut disabled = self
+            .disabled_tiers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if enabled {
+            disabled.remove(tier);
+        } else {
+            disabled.insert(tier.to_string());
+        }
+        true
+    }
+
+    /// Configured tiers in lookup order with their runtime state.
+    pub fn tier_statuses(&self) -> Vec<CacheTierStatus> {
+        self.settings
+            .tiers
+            .iter()
+            .map(|tier| CacheTierStatus {
+                name: tier.name.clone(),
+                enabled: !self.is_disabled(&tier.name),
+                write: tier.write,
+                connected: match tier.name.as_str() {
+                    "redis" => self.redis_repo.is_some(),
+                    "durable" => self.durable_repo.is_some(),
+                    _ => true,
+                },
+            })
+            .collect()
+    }
+
+    /// Whether the write policy stores an answer produced on `route`.
+    pub fn should_store(&self, route: Option<Route>) -> bool {
+        match self.settings.write_policy {
+            CacheWritePolicy::Always => true,
+            CacheWritePolicy::Cloud => matches!(route, Some(Route::Enriched | Route::Cloud)),
+            CacheWritePolicy::Never => false,
+        }
+    }
+
+    /// Stores the value in memory and queues it for Redis and the durable
+    /// tier, skipping tiers that are read-only or not configured, so a slow or
+    /// failing backend never delays the caller. `tags` allow later removal
+    /// through [`CacheService::invalidate_tag`]; `ttl_seconds` overrides each
+    /// tier's configured expiry.
+    pub async fn set(
+        &self,
+        key: &str,
+        value: &Value,
+        tags: &[String],
+        ttl_seconds: Option<u64>,
+    ) -> Result<()> {
+        let memory_ttl = ttl_seconds.map_or(self.settings.memory_ttl_seconds, |ttl| {
+            ttl.min(self.settings.memory_ttl_seconds)
+        });
+        if self.writes_to("memory") {
+            self.set_memory(key, value.clone(), tags.to_vec(), memory_ttl)
+                .await;
+        }
+
+        let redis = self.redis_repo.is_some() && self.writes_to("redis");
+        let durable = self.durable_repo.is_some() && self.writes_to("durable");
+        if !redis && !durable {
+            return Ok(());
+        }
+        let write = PendingWrite {
+            key: key.to_string(),
+            json: encode_entry(value, self.settings.compression_min_bytes)?,
+            tags: tags.to_vec(),
+            ttl_seconds,
+            redis,
+            durable,
+            attempts: 0,
+            retry_at: None,
+        };
+        {
+            let mut pending = self.writes.pending();
+            // The newer value supersedes a queued or retrying write of the same key.
+            pending.retain(|queued| queued.key != write.key);
+            if pending.len() >= self.settings.write_queue_capacity.max(1) {
+                pending.pop_front();
+                self.stats.dropped_writes.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("Cache write queue full, dropped the oldest write");
+            }
+            pending.push_back(write);
+        }
+        self.writes.wake.notify_one();
+        Ok(())
+    }
+
+    /// Cache writes waiting for Redis or the durable tier.
+    pub fn pending_writes(&self) -> usize {
+        self.writes.pending().len()
+    }
+
+    /// Starts the background task that drains queued writes into Redis and
+    /// the durable tier, retrying failures with backoff.
+    pub fn spawn_writer(&self, tasks: &TaskRegistry) {
+        if self.redis_repo.is_none() && self.durable_repo.is_none() {
+            return;
+        }
+        let cache = self.clone();
+        tasks.spawn("cache_writer", move |handle| {
+            let cache = cache.clone();
+            async move {
+                loop {
+                    let mut write = match cache.writes.take_due() {
+                        Ok(write) => write,
+                        Err(Some(retry_at)) => {
+                            tokio::select! {
+                                _ = cache.writes.wake.notified() => {}
+                                _ = tokio::time::sleep_until(retry_at) => {}
+                            }
+                            continue;
+                        }
+                        Err(None) => {
+                            cache.writes.wake.notified().await;
+                            continue;
+                        }
+                    };
+                    match cache.flush_write(&mut write).await {
+                        Ok(()) => handle.ran(),
+                        Err(e) => {
+                            write.attempts += 1;
+                            handle.failed(&e);
+                            if write.attempts >= cache.settings.write_max_attempts.max(1) {
+                                cache.stats.dropped_writes.fetch_add(1, Ordering::Relaxed);
+                                tracing::warn!(
+                                    "Dropping cache write for {} after {} attempts: {}",
+                                    write.key,
+                                    write.attempts,
+                                    e
+                                );
+                                continue;
+                            }
+                            let backoff = WRITE_RETRY_BACKOFF
+                                .saturating_mul(1u32 << (write.attempts - 1).min(16))
+                                .min(MAX_WRITE_RETRY_BACKOFF);
+                            tracing::debug!(
+                                "Cache write for {} failed, retrying in {:?}: {}",
+                                write.key,
+                                backoff,
+                                e
+                            );
+                            let mut pending = cache.writes.pending();
+                            // A newer write of the key queued meanwhile replaces this one.
+                            if pending.iter().all(|queued| queued.key != write.key) {
+                                write.retry_at = Some(tokio::time::Instant::now() + backoff);
+                                pending.push_back(write);
+                            }
+                        }
+                    }
                 }
             }
+        });
+    }
+
+    /// Starts the task that evicts expired entries every
+    /// `cleanup_interval_secs`; 0 disables it.
+    pub fn spawn_cleanup(&self, tasks: &TaskRegistry) {
+        if self.settings.cleanup_interval_secs == 0 {
+            return;
         }
+        let cache = self.clone();
+        tasks.spawn("cache_cleanup", move |handle| {
+            let cache = cache.clone();
+            async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+                    cache.settings.cleanup_interval_secs,
+                ));
+                loop {
+                    interval.tick().await;
+                    match cache.cleanup_expired().await {
+                        Ok(removed) => {
+                            tracing::debug!(
+                                memory = removed.memory,
+                                redis_tag_members = removed.redis_tag_members,
+                                sqlite = removed.sqlite,
+                                "Removed expired cache entries"
+                            );
+                            handle.ran();
+                        }
+                        Err(e) => {
+                            tracing::warn!("Cache cleanup failed: {}", e);
+                            handle.failed(e);
+                        }
+                    }
+                }
+            }
+        });
+    }
 
-        if let Some(sqlite_repo) = &self.sqlite_repo {
-            let key = key.to_string();
-            let repo = sqlite_repo.clone();
-            if let Ok(Some(record)) = tokio::task::spawn_blocking(move || repo.get(&key))
-                .await
-                .ok()?
-            {
-                if let Ok(json) = serde_json::from_str::<Value>(&record.value_json) {
-                    self.stats.sqlite_hits.fetch_add(1, Ordering::Relaxed);
-                    self.set_memory(&record.key, json.clone()).await;
-                    return Some((json, CacheSource::Sqlite));
+    /// Starts the task that stores how much the hit counters grew every
+    /// `stats_flush_interval_secs` and deletes snapshots older than
+    /// `stats_retention_days`. A failed write is carried into the next one.
+    pub fn spawn_stats_recorder(&self, tasks: &TaskRegistry) {
+        let Some(repo) = self.stats_repo.clone() else {
+            return;
+        };
+        let cache = self.clone();
+        tasks.spawn("cache_stats_recorder", move |handle| {
+            let cache = cache.clone();
+            let repo = repo.clone();
+            async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+                    cache.settings.stats_flush_interval_secs,
+                ));
+                interval.tick().await;
+                let mut recorded = cache.stats.snapshot();
+                loop {
+                    interval.tick().await;
+                    let current = cache.stats.snapshot();
+                    let sample = stats_delta(&current, &recorded);
+                    match cache.record_stats(repo.clone(), sample).await {
+                        Ok(()) => {
+                            recorded = current;
+                            handle.ran();
+                        }
+                        Err(e) => {
+                            tracing::warn!("Recording cache statistics failed: {}", e);
+                            handle.failed(e);
+                        }
+                    }
                 }
             }
+        });
+    }
+
+    async fn record_stats(&self, repo: CacheStatsRepo, sample: CacheStatsSample) -> Result<()> {
+        let retention_days = self.settings.stats_retention_days;
+        tokio::task::spawn_blocking(move || {
+            if !sample.is_empty() {
+                repo.record(Utc::now(), &sample)?;
+            }
+            if retention_days > 0 {
+                repo.prune(Utc::now() - Duration::days(retention_days as i64))?;
+            }
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Recorded hit counters since `since`, summed per `bucket_seconds`.
+    pub async fn stats_history(
+        &self,
+        since: DateTime<Utc>,
+        bucket_seconds: i64,
+    ) -> Result<Vec<CacheStatsBucket>> {
+        let repo = self
+            .stats_repo
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("Cache statistics history is disabled"))?;
+        tokio::task::spawn_blocking(move || repo.history(since, bucket_seconds)).await?
+    }
+
+    /// Starts the task that applies invalidations announced by other instances
+    /// to the memory tier. Needs Redis and a non-empty `invalidation_channel`.
+    pub fn spawn_invalidation_listener(&self, tasks: &TaskRegistry) {
+        let Some(redis_repo) = self.redis_repo.clone() else {
+            return;
+        };
+        if self.settings.invalidation_channel.is_empty() {
+            return;
         }
+        let cache = self.clone();
+        tasks.spawn("cache_invalidation_listener", move |handle| {
+            let cache = cache.clone();
+            let redis_repo = redis_repo.clone();
+            async move {
+                let channel = cache.settings.invalidation_channel.clone();
+                let mut reconnecting = false;
+                loop {
+                    let mut pubsub = match redis_repo.subscribe(&channel).await {
+                        Ok(pubsub) => pubsub,
+                        Err(e) => {
+                            tracing::warn!("Cache invalidation subscribe failed: {}", e);
+                            handle.failed(e);
+                            tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+                            continue;
+                        }
+                    };
+                    if reconnecting {
+                        // Announcements sent while disconnected are lost.
+                        let dropped = cache.clear_memory().await;
+                        tracing::info!(
+                            dropped,
+                            "Resubscribed to cache invalidations; cleared memory tier"
+                        );
+                    }
+                    reconnecting = true;
 
-        None
+                    let mut messages = pubsub.on_message();
+                    while let Some(message) = messages.next().await {
+                        let payload: String = match message.get_payload() {
+                            Ok(payload) => payload,
+                            Err(e) => {
+                                handle.failed(e);
+                                continue;
+                            }
+                        };
+                        match serde_json::from_str::<InvalidationMessage>(&payload) {
+                            Ok(message) if message.origin == cache.instance_id => {}
+                            Ok(message) => {
+                                cache.apply_invalidation(&message.event).await;
+                                handle.ran();
+                            }
+                            Err(e) => {
+                                tracing::warn!("Ignoring malformed cache invalidation: {}", e);
+                                handle.failed(e);
+                            }
+                        }
+                    }
+                    tracing::warn!("Cache invalidation subscription closed; resubscribing");
+                    handle.failed("Invalidation subscription closed");
+                    tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+                }
+            }
+        });
+    }
+
+    /// Tells other instances to apply `event` to their memory tier. Failures
+    /// are logged only: the entries still expire under their TTL.
+    async fn announce(&self, event: InvalidationEvent) {
+        let Some(redis_repo) = &self.redis_repo else {
+            return;
+        };
+        if self.settings.invalidation_channel.is_empty() {
+            return;
+        }
+        let message = InvalidationMessage {
+            origin: self.instance_id.clone(),
+            event,
+        };
+        let result = match serde_json::to_string(&message) {
+            Ok(payload) => {
+                redis_repo
+                    .publish(&self.settings.invalidation_channel, &payload)
+                    .await
+            }
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to announce cache invalidation: {}", e);
+        }
+    }
+
+    /// Applies an invalidation announced by another instance.
+    async fn apply_invalidation(&self, event: &InvalidationEvent) {
+        match event {
+            InvalidationEvent::Key { key } => {
+                self.memory_cache.lock().await.pop(key);
+            }
+            InvalidationEvent::Tag { tag, keys } => {
+                let keys: HashSet<String> = keys.iter().cloned().collect();
+                self.evict_tag_from_memory(tag, &keys).await;
+            }
+            InvalidationEvent::Flush => {
+                self.clear_memory().await;
+            }
+        }
+    }
+
+    /// Empties the memory tier on this and every other instance and returns
+    /// how many entries this instance dropped. Redis and the durable tier are
+    /// left as they are, so entries are promoted again on their next hit.
+    pub async fn flush_memory(&self) -> u64 {
+        let dropped = self.clear_memory().await;
+        self.announce(InvalidationEvent::Flush).await;
+        dropped
+    }
+
+    async fn clear_memory(&self) -> u64 {
+        self.memory_cache.lock().await.clear() as u64
+    }
+
+    /// Drops expired entries from memory and the durable tier, and the references to
+    /// expired keys from Redis tag sets. Redis expires the entries themselves.
+    /// Entries within the stale-while-revalidate grace period are kept.
+    pub async fn cleanup_expired(&self) -> Result<CacheCleanup> {
+        let mut removed = CacheCleanup::default();
+        let grace = self.settings.stale_while_revalidate_seconds;
+        {
+            let mut cache = self.memory_cache.lock().await;
+            let now = Utc::now() - Duration::seconds(grace as i64);
+            let expired: Vec<String> = cache
+                .iter()
+                .filter(|(_, entry)| entry.expires_at <= now)
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in expired {
+                cache.pop(&key);
+                removed.memory += 1;
+            }
+        }
+
+        // A Redis failure must not keep the durable tier from being cleaned.
+        if let Some(redis_repo) = self.active_redis() {
+            match redis_repo.prune_tag_sets().await {
+                Ok(pruned) => removed.redis_tag_members = pruned,
+                Err(e) => tracing::warn!("Failed to prune Redis tag sets: {}", e),
+            }
+        }
+
+        if let Some(durable_repo) = self.active_durable() {
+            let repo = durable_repo.clone();
+            removed.sqlite =
+                tokio::task::spawn_blocking(move || repo.cleanup_expired(grace)).await??;
+        }
+
+        Ok(removed)
+    }
+
+    /// Writes to the tiers still pending, clearing each one that succeeds. Each
+    /// tier is attempted even when the other fails, so a Redis outage does not
+    /// hold back the durable tier. A tier disabled since the write was queued
+    /// is cleared without writing.
+    async fn flush_write(&self, write: &mut PendingWrite) -> Result<()> {
+        let mut failures = Vec::new();
+        if write.redis {
+            match self.active_redis() {
+                Some(redis_repo) => match redis_repo
+                    .set_tagged(&write.key, &write.json, &write.tags, write.ttl_seconds)
+                    .await
+                {
+                    Ok(()) => write.redis = false,
+                    Err(e) => failures.push(format!("Redis: {}", e)),
+                },
+                None => write.redis = false,
+            }
+        }
+
+        if write.durable {
+            match self.active_durable() {
+                Some(durable_repo) => {
+                    let repo = durable_repo.clone();
+                    let (key, json, tags) =
+                        (write.key.clone(), write.json.clone(), write.tags.clone());
+                    let ttl_seconds = write.ttl_seconds;
+                    match tokio::task::spawn_blocking(move || {
+                        repo.set(&key, &json, &tags, ttl_seconds)
+                    })
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .and_then(|result| result)
+                    {
+                        Ok(()) => write.durable = false,
+                        Err(e) => failures.push(format!("durable tier: {}", e)),
+                    }
+                }
+                None => write.durable = false,
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            anyhow::bail!("{}", failures.join("; "))
+        }
     }
 
-    pub async fn set(&self, key: &str, value: &Value) -> Result<()> {
-        self.set_memory(key, value.clone()).await;
+    /// Up to `limit` live durable-tier entries with keys after `after`, in key
+    /// order and decoded, optionally only those tagged `tag`. Only the durable
+    /// tier can be enumerated, so entries held solely in memory or Redis are
+    /// not included.
+    pub async fn export_page(
+        &self,
+        after: Option<String>,
+        tag: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<CacheExportRecord>> {
+        let repo = self
+            .active_durable()
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("The durable cache tier is not available"))?;
+        let rows =
+            tokio::task::spawn_blocking(move || repo.list(after.as_deref(), tag.as_deref(), limit))
+                .await??;
+        rows.into_iter()
+            .map(|row| {
+                Ok(CacheExportRecord {
+                    value: decode_entry(&row.record.value_json)
+                        .with_context(|| format!("Unreadable cache entry {}", row.record.key))?,
+                    key: row.record.key,
+                    tags: row.tags,
+                    created_at: row.record.created_at,
+                    expires_at: row.record.expires_at,
+                    hits: row.record.hits,
+                })
+            })
+            .collect()
+    }
+
+    /// Writes an exported entry straight to Redis and the durable tier, where
+    /// writable, keeping its tags and remaining lifetime. Returns false for an
+    /// entry that has already expired.
+    pub async fn import(&self, record: &CacheExportRecord) -> Result<bool> {
+        let remaining = (record.expires_at - Utc::now()).num_seconds();
+        if remaining <= 0 {
+            return Ok(false);
+        }
+        let redis = self.active_redis().is_some() && self.writes_to("redis");
+        let durable = self.active_durable().is_some() && self.writes_to("durable");
+        if !redis && !durable {
+            anyhow::bail!("No writable Redis or durable cache tier to import into");
+        }
+        let mut write = PendingWrite {
+            key: record.key.clone(),
+            json: encode_entry(&record.value, self.settings.compression_min_bytes)?,
+            tags: record.tags.clone(),
+            ttl_seconds: Some(remaining as u64),
+            redis,
+            durable,
+            attempts: 0,
+            retry_at: None,
+        };
+        self.flush_write(&mut write).await?;
+        // Replaces whatever this or another instance holds in memory.
+        self.memory_cache.lock().await.pop(&record.key);
+        self.announce(InvalidationEvent::Key {
+            key: record.key.clone(),
+        })
+        .await;
+        Ok(true)
+    }
+
+    /// Removes every entry tagged with `tag` from memory, Redis and the durable tier.
+    pub async fn invalidate_tag(&self, tag: &str) -> Result<TagInvalidation> {
+        let mut removed = TagInvalidation::default();
+        let mut stale_keys = HashSet::new();
+
+        // Queued writes would otherwise bring the entries back after this returns.
+        self.writes
+            .pending()
+            .retain(|write| !write.tags.iter().any(|t| t == tag));
 
         if let Some(redis_repo) = &self.redis_repo {
-            let json = serde_json::to_string(value)?;
-            let _ = redis_repo.set(key, &json).await;
+            let keys = redis_repo.invalidate_tag(tag).await?;
+            removed.redis = keys.len() as u64;
+            stale_keys.extend(keys);
         }
 
-        if let Some(sqlite_repo) = &self.sqlite_repo {
-            let json = serde_json::to_string(value)?;
-            let key = key.to_string();
-            let repo = sqlite_repo.clone();
-            let _ = tokio::task::spawn_blocking(move || repo.set(&key, &json)).await;
+        if let Some(durable_repo) = &self.durable_repo {
+            let tag = tag.to_string();
+            let repo = durable_repo.clone();
+            let keys = tokio::task::spawn_blocking(move || repo.invalidate_tag(&tag)).await??;
+            removed.sqlite = keys.len() as u64;
+            stale_keys.extend(keys);
         }
 
-        Ok(())
+        removed.memory = self.evict_tag_from_memory(tag, &stale_keys).await;
+        self.announce(InvalidationEvent::Tag {
+            tag: tag.to_string(),
+            keys: stale_keys.into_iter().collect(),
+        })
+        .await;
+
+        Ok(removed)
     }
 
-    async fn get_from_memory(&self, key: &str) -> Option<Value> {
+    /// Entries promoted from Redis or the durable tier are stored untagged in
+    /// memory, so this also drops `stale_keys` the lower tiers reported.
+    async fn evict_tag_from_memory(&self, tag: &str, stale_keys: &HashSet<String>) -> u64 {
         let mut cache = self.memory_cache.lock().await;
-        if let Some(entry) = cache.get(key) {
-            if entry.expires_at > Utc::now() {
-                return Some(entry.value.clone());
+        let keys: Vec<String> = cache
+            .iter()
+            .filter(|(key, entry)| {
+                entry.tags.iter().any(|t| t == tag) || stale_keys.contains(key.as_str())
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &keys {
+            cache.pop(key);
+        }
+        keys.len() as u64
+    }
+
+    /// Claims an `Idempotency-Key`. While another request holds the key this
+    /// waits for it, then replays its response or takes over if it failed.
+    /// Completed responses are shared through Redis for
+    /// `idempotency_ttl_seconds`. Keys are scoped to the caller's tenant and
+    /// API key.
+    pub async fn claim_idempotency(&self, ctx: &RequestContext, key: &str) -> IdempotencyClaim {
+        let key = format!("idempotency:{}", ctx.caller_scoped(key));
+        let mut checked_redis = false;
+        loop {
+            let running = {
+                let mut slots = self.idempotency_slots();
+                let now = Utc::now();
+                slots.retain(|_, slot| {
+                    !matches!(slot, IdempotencySlot::Done { expires_at, .. } if *expires_at <= now)
+                });
+                match slots.get(&key) {
+                    Some(IdempotencySlot::Done { value, .. }) => {
+                        return IdempotencyClaim::Replay(value.clone());
+                    }
+                    Some(IdempotencySlot::Running(sender)) => Some(sender.subscribe()),
+                    None if checked_redis || self.redis_repo.is_none() => {
+                        slots.insert(key.clone(), IdempotencySlot::Running(watch::channel(()).0));
+                        return IdempotencyClaim::Acquired(IdempotencyGuard {
+                            key,
+                            cache: self.clone(),
+                        });
+                    }
+                    None => None,
+                }
+            };
+
+            match running {
+                // Nothing is ever sent, so this returns once the holder completes or gives up.
+                Some(mut receiver) => {
+                    let _ = receiver.changed().await;
+                }
+                None => {
+                    // Another instance may have completed the request.
+                    if let Some(redis_repo) = self.active_redis() {
+                        if let Ok(Some(json)) = redis_repo.get(&key).await {
+                            if let Ok(value) = serde_json::from_str::<Value>(&json) {
+                                return IdempotencyClaim::Replay(value);
+                            }
+                        }
+                    }
+                    checked_redis = true;
+                }
             }
         }
-        cache.pop(key);
+    }
+
+    fn idempotency_slots(&self) -> MutexGuard<'_, HashMap<String, IdempotencySlot>> {
+        self.idempotency
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    async fn get_from_memory(&self, key: &str) -> Option<Value> {
+        let mut cache = self.memory_cache.lock().await;
+        let now = Utc::now();
+        let grace = Duration::seconds(self.settings.stale_while_revalidate_seconds as i64);
+        let entry = cache.get(key)?;
+        if entry.expires_at > now {
+            return Some(entry.value.clone());
+        }
+        // Kept through the grace period for `get_stale`.
+        if entry.expires_at + grace <= now {
+            cache.pop(key);
+        }
         None
     }
 
-    async fn set_memory(&self, key: &str, value: Value) {
+    /// Entries count against `memory_max_mb` by their serialized size.
+    async fn set_memory(&self, key: &str, value: Value, tags: Vec<String>, ttl_seconds: u64) {
+        let bytes = key.len()
+            + serde_json::to_vec(&value).map_or(0, |json| json.len())
+            + tags.iter().map(String::len).sum::<usize>();
+        let expires_at = Utc::now() + Duration::seconds(ttl_seconds as i64);
         let mut cache = self.memory_cache.lock().await;
-        let expires_at = Utc::now() + Duration::seconds(self.settings.memory_ttl_seconds as i64);
-        cache.put(
+        let admitted = cache.put(
             key.to_string(),
             MemoryEntry {
                 value,
                 expires_at,
+                tags,
             },
+            bytes,
         );
+        if !admitted {
+            tracing::trace!(key, bytes, "Memory cache did not admit entry");
+        }
+    }
+}
+
+fn uses_tier(settings: &CacheSettings, tier: &str) -> bool {
+    settings
+        .tiers
+        .iter()
+        .any(|configured| configured.name == tier)
+}
+
+/// Opens the durable tier chosen by `durable_backend`; `None` when it is not
+/// among the configured tiers or its path is empty.
+fn open_durable_tier(
+    settings: &CacheSettings,
+) -> Result<(Option<Arc<dyn CacheStore>>, CacheSource)> {
+    if !uses_tier(settings, "durable") {
+        return Ok((None, CacheSource::Sqlite));
     }
+    match settings.durable_backend.as_str() {
+        "postgres" => {
+            if settings.postgres_url.trim().is_empty() {
+                anyhow::bail!("CACHE_DURABLE_BACKEND is postgres but CACHE_POSTGRES_URL is empty");
+            }
+            let tls = PostgresTlsOptions {
+                ca_cert_path: settings.postgres_ca_cert_path.clone(),
+                client_cert_path: settings.postgres_client_cert_path.clone(),
+                client_key_path: settings.postgres_client_key_path.clone(),
+            };
+            let repo = PostgresRepo::new(
+                &settings.postgres_url,
+                settings.sqlite_ttl_days,
+                settings.sqlite_max_size_gb,
+                &tls,
+            )?;
+            Ok((Some(Arc::new(repo)), CacheSource::Postgres))
+        }
+        "sqlite" => {
+            if settings.sqlite_path.trim().is_empty() {
+                return Ok((None, CacheSource::Sqlite));
+            }
+            let repo = CacheRepo::new(
+                settings.sqlite_path.clone(),
+                settings.sqlite_ttl_days,
+                settings.sqlite_max_size_gb,
+            )?;
+            Ok((Some(Arc::new(repo)), CacheSource::Sqlite))
+        }
+        other => anyhow::bail!("Unknown CACHE_DURABLE_BACKEND {:?}", other),
+    }
+}
+
+/// Serializes an entry for Redis and the durable tier, compressing it once
+/// the JSON reaches `min_bytes` (0 never compresses).
+fn encode_entry(value: &Value, min_bytes: usize) -> Result<String> {
+    let json = serde_json::to_string(value)?;
+    if min_bytes == 0 || json.len() < min_bytes {
+        return Ok(json);
+    }
+    let compressed = zstd::encode_all(json.as_bytes(), COMPRESSION_LEVEL)?;
+    let encoded = format!("{}{}", COMPRESSED_PREFIX, STANDARD.encode(compressed));
+    // Small or already dense payloads can grow once base64-encoded.
+    Ok(if encoded.len() < json.len() {
+        encoded
+    } else {
+        json
+    })
+}
+
+/// Reads an entry written by [`encode_entry`] or by a version without compression.
+fn decode_entry(stored: &str) -> Result<Value> {
+    let Some(encoded) = stored.strip_prefix(COMPRESSED_PREFIX) else {
+        return Ok(serde_json::from_str(stored)?);
+    };
+    let compressed = STANDARD
+        .decode(encoded)
+        .context("Corrupt compressed cache entry")?;
+    let json = zstd::decode_all(compressed.as_slice())?;
+    Ok(serde_json::from_slice(&json)?)
 }
@@ -0,0 +1,104 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use serde_json::Value;
+
+use crate::config::ProviderCaptureSettings;
+use crate::repositories::{CaptureRecord, CaptureRepo};
+use crate::utils::redact_json;
+
+/// One outbound provider call, before redaction.
+pub struct ProviderExchange<'a> {
+    pub request_id: &'a str,
+    pub provider: &'a str,
+    pub endpoint: &'a str,
+    pub request: &'a Value,
+    pub response: Option<&'a Value>,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Stores redacted provider payloads for debugging; a no-op unless enabled.
+#[derive(Clone)]
+pub struct ProviderCaptureService {
+    repo: Option<CaptureRepo>,
+    retention_hours: u64,
+}
+
+impl ProviderCaptureService {
+    pub fn new(settings: &ProviderCaptureSettings) -> Self {
+        let repo = if !settings.enabled || settings.sqlite_path.trim().is_empty() {
+            None
+        } else {
+            match CaptureRepo::new(settings.sqlite_path.clone()) {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    tracing::warn!("Provider capture disabled: {}", e);
+                    None
+                }
+            }
+        };
+        Self {
+            repo,
+            retention_hours: settings.retention_hours,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.repo.is_some()
+    }
+
+    /// Redacts and stores the exchange, pruning expired captures. Failures are only logged.
+    pub async fn record(&self, exchange: ProviderExchange<'_>) {
+        let Some(repo) = self.repo.clone() else {
+            return;
+        };
+
+        let mut request = exchange.request.clone();
+        redact_json(&mut request);
+        let response = exchange.response.map(|response| {
+            let mut response = response.clone();
+            redact_json(&mut response);
+            response.to_string()
+        });
+        let record = CaptureRecord {
+            request_id: exchange.request_id.to_string(),
+            provider: exchange.provider.to_string(),
+            endpoint: exchange.endpoint.to_string(),
+            status: exchange.status,
+            error: exchange.error.as_deref().map(crate::utils::redact_text),
+            duration_ms: exchange.duration_ms,
+            request_json: request.to_string(),
+            response_json: response,
+            created_at: Utc::now(),
+        };
+        let cutoff = Utc::now() - Duration::hours(self.retention_hours as i64);
+
+        let result = tokio::task::spawn_blocking(move || {
+            repo.insert(&record)?;
+            repo.purge_before(cutoff)
+        })
+        .await;
+        match result {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to store provider capture: {}", e),
+            Err(e) => tracing::warn!("Provider capture task failed: {}", e),
+        }
+    }
+
+    /// Captures for `request_id` that are still within the retention window.
+    pub async fn get(&self, request_id: &str) -> Result<Vec<CaptureRecord>> {
+        let repo = self
+            .repo
+            .clone()
+            .ok_or_else(|| anyhow!("Provider capture is disabled"))?;
+        let request_id = request_id.to_string();
+        let cutoff = Utc::now() - Duration::hours(self.retention_hours as i64);
+        let records =
+            tokio::task::spawn_blocking(move || repo.by_request_id(&request_id)).await??;
+        Ok(records
+            .into_iter()
+            .filter(|record| record.created_at >= cutoff)
+            .collect())
+    }
+}
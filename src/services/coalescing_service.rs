@@ -0,0 +1,121 @@
+use anyhow::Result;
+use futures_util::future::{BoxFuture, FutureExt, Shared, WeakShared};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::services::{DeadlineExceeded, GenerationCancelled, Overloaded};
+
+type SharedResult<T> = std::result::Result<T, Arc<anyhow::Error>>;
+type Work<T> = BoxFuture<'static, SharedResult<T>>;
+type SharedWork<T> = Shared<Work<T>>;
+type RunningWork<T> = Arc<Mutex<HashMap<String, (u64, WeakShared<Work<T>>)>>>;
+
+/// Lets concurrent identical requests share one piece of work: the first
+/// caller for a key starts it and later callers await the same result.
+///
+/// The work is polled by whoever is waiting, so it keeps running while any
+/// caller waits and is dropped once all of them have given up. Entries hold
+/// only weak references and are removed when the work finishes.
+pub struct RequestCoalescer<T> {
+    running: RunningWork<T>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl<T> Clone for RequestCoalescer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            running: self.running.clone(),
+            next_id: self.next_id.clone(),
+        }
+    }
+}
+
+impl<T> Default for RequestCoalescer<T> {
+    fn default() -> Self {
+        Self {
+            running: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+/// Whether the caller started the work or joined work already running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coalesced {
+    Leader,
+    Follower,
+}
+
+impl<T: Clone + Send + Sync + 'static> RequestCoalescer<T> {
+    /// Runs `work` unless work for `key` is already running, in which case
+    /// its result is awaited instead and `work` is never polled.
+    pub async fn run<F>(&self, key: &str, work: F) -> (Result<T>, Coalesced)
+    where
+        F: Future<Output = Result<T>> + Send + 'static,
+    {
+        let (shared, role) = self.join_or_start(key, work);
+        let result = shared.await.map_err(|e| share_error(&e));
+        (result, role)
+    }
+
+    fn join_or_start<F>(&self, key: &str, work: F) -> (SharedWork<T>, Coalesced)
+    where
+        F: Future<Output = Result<T>> + Send + 'static,
+    {
+        let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(shared) = running.get(key).and_then(|(_, weak)| weak.upgrade()) {
+            return (shared, Coalesced::Follower);
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let guard = EntryGuard {
+            running: self.running.clone(),
+            key: key.to_string(),
+            id,
+        };
+        let shared = async move {
+            let _guard = guard;
+            work.await.map_err(Arc::new)
+        }
+        .boxed()
+        .shared();
+        if let Some(weak) = shared.downgrade() {
+            running.insert(key.to_string(), (id, weak));
+        }
+        (shared, Coalesced::Leader)
+    }
+}
+
+/// Removes the entry when the work finishes or is dropped, unless a newer
+/// run for the same key has replaced it.
+struct EntryGuard<T> {
+    running: RunningWork<T>,
+    key: String,
+    id: u64,
+}
+
+impl<T> Drop for EntryGuard<T> {
+    fn drop(&mut self) {
+        let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+        if running.get(&self.key).is_some_and(|(id, _)| *id == self.id) {
+            running.remove(&self.key);
+        }
+    }
+}
+
+/// Rebuilds a shared error for one caller, keeping the types handlers map to
+/// specific responses; anything else keeps only its message.
+fn share_error(error: &anyhow::Error) -> anyhow::Error {
+    if let Some(overloaded) = error.downcast_ref::<Overloaded>() {
+        return overloaded.clone().into();
+    }
+    if let Some(cancelled) = error.downcast_ref::<GenerationCancelled>() {
+        return cancelled.clone().into();
+    }
+    if let Some(exceeded) = error.downcast_ref::<DeadlineExceeded>() {
+        return exceeded.clone().into();
+    }
+    anyhow::anyhow!("{:#}", error)
+}
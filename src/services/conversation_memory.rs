@@ -0,0 +1,177 @@
+use uuid::Uuid;
+
+use crate::models::{AIModel, ChatContext, ConversationMessage, SUMMARY_MAX_TOKENS};
+use crate::services::{ConversationHistory, ConversationService};
+use crate::utils::{estimate_tokens, format_transcript};
+
+/// Approximate per-message cost of the `User:` / `Assistant:` framing.
+const TURN_OVERHEAD_TOKENS: usize = 4;
+
+/// Result of fitting history into the context window.
+pub struct FittedContext {
+    pub context: ChatContext,
+    /// New rolling summary and the number of messages it covers, when older
+    /// turns had to be folded in.
+    pub summary_update: Option<(String, usize)>,
+}
+
+/// Keeps conversation history within the model's context window by folding the
+/// oldest turns into a rolling summary instead of dropping them.
+#[derive(Clone)]
+pub struct ConversationMemory {
+    conversations: ConversationService,
+}
+
+impl ConversationMemory {
+    pub fn new(conversations: ConversationService) -> Self {
+        Self { conversations }
+    }
+
+    /// Loads stored history; an empty history is returned when persistence is off or fails.
+    pub async fn load(&self, conversation_id: Uuid) -> ConversationHistory {
+        if !self.conversations.is_enabled() {
+            return ConversationHistory::default();
+        }
+        match self.conversations.history(conversation_id).await {
+            Ok(history) => history,
+            Err(e) => {
+                tracing::warn!("Failed to load conversation history: {}", e);
+                ConversationHistory::default()
+            }
+        }
+    }
+
+    /// Selects the newest turns that fit next to the prompt and the generation budget,
+    /// summarizing whatever is older. Runs on the inference thread with the model held.
+    pub fn fit(
+        &self,
+        model: &AIModel,
+        history: ConversationHistory,
+        prompt_tokens: usize,
+        max_new_tokens: usize,
+        seed: Option<u64>,
+    ) -> FittedContext {
+        let messages: Vec<ConversationMessage> =
+            history.messages.into_iter().map(Into::into).collect();
+        let budget = model
+            .context_length()
+            .saturating_sub(prompt_tokens + max_new_tokens);
+
+        let split = messages.len() - kept_turns(&messages, budget, |text| model.count_tokens(text));
+        if split == 0 {
+            return FittedContext {
+                context: ChatContext {
+                    summary: history.summary,
+                    turns: messages,
+                },
+                summary_update: None,
+            };
+        }
+
+        let (older, recent) = messages.split_at(split);
+        let summary = match self.summarize(model, history.summary.clone(), older, seed) {
+            Ok(summary) => summary,
+            Err(e) => {
+                tracing::warn!(
+                    "Conversation summarization failed, dropping older turns: {}",
+                    e
+                );
+                return FittedContext {
+                    context: ChatContext {
+                        summary: history.summary,
+                        turns: recent.to_vec(),
+                    },
+                    summary_update: None,
+                };
+            }
+        };
+
+        FittedContext {
+            context: ChatContext {
+                summary: Some(summary.clone()),
+                turns: recent.to_vec(),
+            },
+            summary_update: Some((summary, history.summarized_count + split)),
+        }
+    }
+
+    /// Like [`ConversationMemory::fit`] for a model served out of process:
+    /// tokens are estimated at ~4 chars each and older turns are dropped,
+    /// leaving the stored summary as it is.
+    pub fn fit_recent(
+        &self,
+        history: ConversationHistory,
+        context_length: usize,
+        prompt_tokens: usize,
+        max_new_tokens: usize,
+    ) -> ChatContext {
+        let messages: Vec<ConversationMessage> =
+            history.messages.into_iter().map(Into::into).collect();
+        let budget = context_length.saturating_sub(prompt_tokens + max_new_tokens);
+        let split = messages.len() - kept_turns(&messages, budget, estimate_tokens);
+        ChatContext {
+            summary: history.summary,
+            turns: messages[split..].to_vec(),
+        }
+    }
+
+    pub async fn save(&self, conversation_id: Uuid, summary: String, summarized_count: usize) {
+        self.conversations
+            .save_summary(conversation_id, summary, summarized_count)
+            .await;
+    }
+
+    /// Folds `messages` into the summary in chunks small enough for one prompt.
+    fn summarize(
+        &self,
+        model: &AIModel,
+        mut summary: Option<String>,
+        messages: &[ConversationMessage],
+        seed: Option<u64>,
+    ) -> anyhow::Result<String> {
+        let chunk_budget = model
+            .context_length()
+            .saturating_sub(2 * SUMMARY_MAX_TOKENS)
+            .max(SUMMARY_MAX_TOKENS);
+
+        let mut start = 0;
+        while start < messages.len() {
+            let mut end = start;
+            let mut used = summary.as_deref().map_or(0, |s| model.count_tokens(s));
+            while end < messages.len() {
+                let cost = model.count_tokens(&format_transcript(&messages[end..=end]))
+                    + TURN_OVERHEAD_TOKENS;
+                // Always take at least one message so an oversized one cannot stall the loop.
+                if end > start && used + cost > chunk_budget {
+                    break;
+                }
+                used += cost;
+                end += 1;
+            }
+            summary = Some(model.summarize(summary.as_deref(), &messages[start..end], seed)?);
+            start = end;
+        }
+
+        Ok(summary.unwrap_or_default())
+    }
+}
+
+/// How many of the newest `messages` fit in `budget` tokens.
+fn kept_turns(
+    messages: &[ConversationMessage],
+    budget: usize,
+    count_tokens: impl Fn(&str) -> usize,
+) -> usize {
+    // The summary slot is always reserved so a freshly written summary still fits.
+    let mut used = SUMMARY_MAX_TOKENS;
+    let mut kept = 0;
+    for message in messages.iter().rev() {
+        let cost = count_tokens(&message.content) + TURN_OVERHEAD_TOKENS;
+        if used + cost > budget {
+            break;
+        }
+        used += cost;
+        kept += 1;
+    }
+    kept
+}
@@ -0,0 +1,364 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use regex::Regex;
+use std::ops::Range;
+use uuid::Uuid;
+
+use crate::config::ConversationSettings;
+use crate::models::{Page, RedactionMode};
+use crate::repositories::{
+    ArchiveRecord, ConversationRecord, ConversationRepo, MessageRecord, RedactionAudit,
+};
+use crate::services::TaskRegistry;
+use crate::utils::{redact_text, REDACTED};
+
+/// Stored context for a conversation: the rolling summary plus the newer
+/// messages it does not cover.
+#[derive(Debug, Clone, Default)]
+pub struct ConversationHistory {
+    pub summary: Option<String>,
+    pub summarized_count: usize,
+    pub messages: Vec<MessageRecord>,
+}
+
+/// What to redact from a stored conversation.
+pub struct RedactionPlan {
+    /// Message positions handled according to `mode`.
+    pub ranges: Vec<Range<usize>>,
+    pub mode: RedactionMode,
+    /// Matches are masked in messages outside `ranges`.
+    pub patterns: Vec<Regex>,
+    pub builtin_patterns: bool,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RedactionOutcome {
+    pub removed: usize,
+    pub masked: usize,
+    pub scrubbed: usize,
+}
+
+/// Conversations archived per pass, so a backlog is worked off gradually.
+const ARCHIVE_BATCH_SIZE: usize = 100;
+
+#[derive(Clone)]
+pub struct ConversationService {
+    repo: Option<ConversationRepo>,
+    max_messages: usize,
+    max_message_age_days: u64,
+    archive_interval_secs: u64,
+}
+
+impl ConversationService {
+    pub fn new(settings: &ConversationSettings) -> Self {
+        let repo = if settings.sqlite_path.trim().is_empty() {
+            None
+        } else {
+            match ConversationRepo::new(settings.sqlite_path.clone()) {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    tracing::warn!("Conversation persistence disabled: {}", e);
+                    None
+                }
+            }
+        };
+        Self {
+            repo,
+            max_messages: settings.max_messages,
+            max_message_age_days: settings.max_message_age_days,
+            archive_interval_secs: settings.archive_interval_secs,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.repo.is_some()
+    }
+
+    /// Starts the archiving task; does nothing when the store, the interval or
+    /// both limits are off.
+    pub fn spawn(&self, tasks: &TaskRegistry) {
+        if self.repo.is_none()
+            || self.archive_interval_secs == 0
+            || (self.max_messages == 0 && self.max_message_age_days == 0)
+        {
+            return;
+        }
+        let service = self.clone();
+        tasks.spawn("conversation_archiver", move |handle| {
+            let service = service.clone();
+            async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+                    service.archive_interval_secs,
+                ));
+                loop {
+                    interval.tick().await;
+                    match service.archive_pass().await {
+                        Ok(archived) => {
+                            if archived > 0 {
+                                tracing::debug!("Archived {} conversation messages", archived);
+                            }
+                            handle.ran();
+                        }
+                        Err(e) => {
+                            tracing::warn!("Conversation archiving failed: {}", e);
+                            handle.failed(e);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Archives the older turns of conversations over the message count or age
+    /// limit and returns the number of messages moved.
+    pub async fn archive_pass(&self) -> Result<usize> {
+        let repo = self.repo()?;
+        let max_messages = self.max_messages;
+        let older_than = (self.max_message_age_days > 0)
+            .then(|| Utc::now() - Duration::days(self.max_message_age_days as i64));
+        tokio::task::spawn_blocking(move || {
+            let mut archived = 0;
+            for conversation_id in repo.over_limit(max_messages, older_than, ARCHIVE_BATCH_SIZE)? {
+                archived += repo.archive_oldest(&conversation_id, max_messages, older_than)?;
+            }
+            Ok(archived)
+        })
+        .await?
+    }
+
+    /// Archived turns of a conversation, one page at a time.
+    pub async fn archives(&self, conversation_id: Uuid, page: Page) -> Result<Vec<ArchiveRecord>> {
+        let repo = self.repo()?;
+        let conversation_id = conversation_id.to_string();
+        tokio::task::spawn_blocking(move || repo.archives(&conversation_id, &page)).await?
+    }
+
+    /// Persists a completed exchange. Failures are logged, never surfaced to the caller.
+    pub async fn record_turn(&self, conversation_id: Uuid, user: &str, assistant: &str) {
+        self.persist_turn(conversation_id, user, assistant, None)
+            .await
+    }
+
+    /// Persists an exchange whose answer was cut short, marked with `reason` so it
+    /// can be resumed later.
+    pub async fn record_partial_turn(
+        &self,
+        conversation_id: Uuid,
+        user: &str,
+        assistant: &str,
+        reason: &str,
+    ) {
+        self.persist_turn(conversation_id, user, assistant, Some(reason.to_string()))
+            .await
+    }
+
+    async fn persist_turn(
+        &self,
+        conversation_id: Uuid,
+        user: &str,
+        assistant: &str,
+        interrupted: Option<String>,
+    ) {
+        let Some(repo) = self.repo.clone() else {
+            return;
+        };
+        let conversation_id = conversation_id.to_string();
+        let user = user.to_string();
+        let assistant = assistant.to_string();
+        let result = tokio::task::spawn_blocking(move || {
+            repo.append_turn(&conversation_id, &user, &assistant, interrupted.as_deref())
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to persist conversation turn: {}", e),
+            Err(e) => tracing::warn!("Conversation persistence task failed: {}", e),
+        }
+    }
+
+    /// The conversation's last answer when it was interrupted and can be resumed.
+    pub async fn interrupted_answer(&self, conversation_id: Uuid) -> Result<Option<MessageRecord>> {
+        let repo = self.repo()?;
+        let conversation_id = conversation_id.to_string();
+        tokio::task::spawn_blocking(move || repo.interrupted_answer(&conversation_id)).await?
+    }
+
+    /// Stores the resumed answer in place of the partial one.
+    pub async fn resume_answer(
+        &self,
+        conversation_id: Uuid,
+        message_id: i64,
+        content: &str,
+        interrupted: Option<&str>,
+    ) -> Result<bool> {
+        let repo = self.repo()?;
+        let conversation_id = conversation_id.to_string();
+        let content = content.to_string();
+        let interrupted = interrupted.map(str::to_string);
+        tokio::task::spawn_blocking(move || {
+            repo.resume_answer(
+                &conversation_id,
+                message_id,
+                &content,
+                interrupted.as_deref(),
+            )
+        })
+        .await?
+    }
+
+    /// Removes the latest exchange so it can be generated again; empty when there is none.
+    pub async fn pop_last_turn(&self, conversation_id: Uuid) -> Result<Vec<MessageRecord>> {
+        let repo = self.repo()?;
+        let conversation_id = conversation_id.to_string();
+        tokio::task::spawn_blocking(move || repo.pop_last_turn(&conversation_id)).await?
+    }
+
+    /// Applies `plan` to the stored messages; `Ok(None)` when the conversation does not exist.
+    pub async fn redact(
+        &self,
+        conversation_id: Uuid,
+        plan: RedactionPlan,
+    ) -> Result<Option<RedactionOutcome>> {
+        let repo = self.repo()?;
+        let conversation_id = conversation_id.to_string();
+        tokio::task::spawn_blocking(move || {
+            if repo.get(&conversation_id)?.is_none() {
+                return Ok(None);
+            }
+
+            let scrub = |text: &str| {
+                let content = plan.patterns.iter().fold(text.to_string(), |text, regex| {
+                    regex.replace_all(&text, REDACTED).into_owned()
+                });
+                if plan.builtin_patterns {
+                    redact_text(&content)
+                } else {
+                    content
+                }
+            };
+            let mut outcome = RedactionOutcome::default();
+            let mut removals = Vec::new();
+            let mut rewrites = Vec::new();
+            for (index, message) in repo.messages(&conversation_id)?.into_iter().enumerate() {
+                if plan.ranges.iter().any(|range| range.contains(&index)) {
+                    match plan.mode {
+                        RedactionMode::Remove => {
+                            removals.push(message.id);
+                            outcome.removed += 1;
+                        }
+                        RedactionMode::Mask => {
+                            rewrites.push((message.id, REDACTED.to_string()));
+                            outcome.masked += 1;
+                        }
+                    }
+                    continue;
+                }
+
+                let content = scrub(&message.content);
+                if content != message.content {
+                    rewrites.push((message.id, content));
+                    outcome.scrubbed += 1;
+                }
+            }
+
+            let audit = RedactionAudit {
+                mode: plan.mode.as_str().to_string(),
+                reason: plan.reason.clone(),
+                messages_removed: outcome.removed,
+                messages_masked: outcome.masked,
+                messages_scrubbed: outcome.scrubbed,
+                pattern_count: plan.patterns.len(),
+            };
+            outcome.scrubbed +=
+                repo.apply_redaction(&conversation_id, &removals, &rewrites, scrub, &audit)?;
+            Ok(Some(outcome))
+        })
+        .await?
+    }
+
+    /// Marks the conversation as escalated; `Ok(true)` only for the first escalation.
+    pub async fn escalate(&self, conversation_id: Uuid, reason: &str) -> Result<bool> {
+        let repo = self.repo()?;
+        let conversation_id = conversation_id.to_string();
+        let reason = reason.to_string();
+        tokio::task::spawn_blocking(move || repo.mark_escalated(&conversation_id, &reason)).await?
+    }
+
+    pub async fn get(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Option<(ConversationRecord, Vec<MessageRecord>)>> {
+        let repo = self.repo()?;
+        let conversation_id = conversation_id.to_string();
+        tokio::task::spawn_blocking(move || {
+            let Some(record) = repo.get(&conversation_id)? else {
+                return Ok(None);
+            };
+            let messages = repo.messages(&conversation_id)?;
+            Ok(Some((record, messages)))
+        })
+        .await?
+    }
+
+    /// Whether the conversation already has stored turns. Errors count as "no".
+    pub async fn has_history(&self, conversation_id: Uuid) -> bool {
+        let Some(repo) = self.repo.clone() else {
+            return false;
+        };
+        let conversation_id = conversation_id.to_string();
+        matches!(
+            tokio::task::spawn_blocking(move || repo.get(&conversation_id)).await,
+            Ok(Ok(Some(record))) if record.message_count > 0
+        )
+    }
+
+    /// Loads the rolling summary and the messages it does not cover yet.
+    pub async fn history(&self, conversation_id: Uuid) -> Result<ConversationHistory> {
+        let repo = self.repo()?;
+        let conversation_id = conversation_id.to_string();
+        tokio::task::spawn_blocking(move || {
+            let (summary, summarized_count) = repo.summary(&conversation_id)?;
+            let messages = repo.messages(&conversation_id)?;
+            let summarized_count = summarized_count.min(messages.len());
+            Ok(ConversationHistory {
+                summary,
+                summarized_count,
+                messages: messages.into_iter().skip(summarized_count).collect(),
+            })
+        })
+        .await?
+    }
+
+    pub async fn save_summary(
+        &self,
+        conversation_id: Uuid,
+        summary: String,
+        summarized_count: usize,
+    ) {
+        let Some(repo) = self.repo.clone() else {
+            return;
+        };
+        let conversation_id = conversation_id.to_string();
+        let result = tokio::task::spawn_blocking(move || {
+            repo.set_summary(&conversation_id, &summary, summarized_count)
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to persist conversation summary: {}", e),
+            Err(e) => tracing::warn!("Conversation summary task failed: {}", e),
+        }
+    }
+
+    pub async fn list(&self, page: Page) -> Result<Vec<ConversationRecord>> {
+        let repo = self.repo()?;
+        tokio::task::spawn_blocking(move || repo.list(&page)).await?
+    }
+
+    fn repo(&self) -> Result<ConversationRepo> {
+        self.repo
+            .clone()
+            .ok_or_else(|| anyhow!("Conversation persistence is disabled"))
+    }
+}
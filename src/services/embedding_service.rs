@@ -0,0 +1,244 @@
+use anyhow::{anyhow, Context, Result};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config as BertConfig};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError, RwLock};
+use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
+use tracing::info;
+
+use crate::config::AiConfig;
+use crate::models::{
+    cached_snapshot, download_model, expand_home, hf_cache_location, weight_files, DownloadRequest,
+};
+use crate::utils::embedding_similarity;
+
+/// Longest input the sentence encoder sees; the rest is truncated.
+const MAX_EMBEDDING_TOKENS: usize = 256;
+
+struct Encoder {
+    model: BertModel,
+    tokenizer: Tokenizer,
+    device: Device,
+}
+
+impl Encoder {
+    /// Mean-pooled, unit-length sentence embeddings of `texts`, and the
+    /// number of tokens the encoder read.
+    fn embed(&self, texts: &[&str]) -> Result<(Vec<Vec<f32>>, usize)> {
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts.to_vec(), true)
+            .map_err(|e| anyhow!("Tokenization error: {}", e))?;
+        let tokens = encodings
+            .iter()
+            .map(|e| {
+                e.get_attention_mask()
+                    .iter()
+                    .filter(|mask| **mask != 0)
+                    .count()
+            })
+            .sum();
+        let ids = encodings
+            .iter()
+            .map(|e| Tensor::new(e.get_ids(), &self.device))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        let masks = encodings
+            .iter()
+            .map(|e| Tensor::new(e.get_attention_mask(), &self.device))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        let ids = Tensor::stack(&ids, 0)?;
+        let mask = Tensor::stack(&masks, 0)?;
+        let token_types = ids.zeros_like()?;
+
+        let hidden = self.model.forward(&ids, &token_types, Some(&mask))?;
+        // Padding positions are masked out of the mean.
+        let mask = mask.to_dtype(DType::F32)?.unsqueeze(2)?;
+        let summed = hidden.broadcast_mul(&mask)?.sum(1)?;
+        let counts = mask.sum(1)?.clamp(1e-9, f64::MAX)?;
+        let pooled = summed.broadcast_div(&counts)?;
+        let norms = pooled
+            .sqr()?
+            .sum_keepdim(1)?
+            .sqrt()?
+            .clamp(1e-12, f64::MAX)?;
+        Ok((pooled.broadcast_div(&norms)?.to_vec2::<f32>()?, tokens))
+    }
+}
+
+/// Sentence embeddings from a small local encoder (all-MiniLM by default),
+/// for comparing texts by meaning rather than shared words. Loaded in the
+/// background; until then, and when no model is configured, callers fall
+/// back to lexical scoring.
+#[derive(Clone, Default)]
+pub struct EmbeddingService {
+    config: Option<AiConfig>,
+    encoder: Arc<RwLock<Option<Arc<Encoder>>>>,
+}
+
+impl EmbeddingService {
+    pub fn new(config: &AiConfig) -> Self {
+        Self {
+            config: config
+                .embedding_model
+                .as_ref()
+                .filter(|name| !name.trim().is_empty())
+                .map(|_| config.clone()),
+            encoder: Arc::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.is_some()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.encoder
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+    }
+
+    fn encoder(&self) -> Option<Arc<Encoder>> {
+        self.encoder
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn model_name(&self) -> Option<&str> {
+        self.config
+            .as_ref()
+            .and_then(|c| c.embedding_model.as_deref())
+    }
+
+    /// Loads the encoder, downloading it first when allowed. Does nothing
+    /// when no embedding model is configured.
+    pub async fn load(&self) -> Result<()> {
+        let Some(config) = &self.config else {
+            return Ok(());
+        };
+        if self.is_ready() {
+            return Ok(());
+        }
+        let model_name = config.embedding_model.clone().unwrap_or_default();
+        let model_dir = self.resolve_model_dir(config, &model_name).await?;
+        info!(
+            "Loading embedding model {} from {}",
+            model_name,
+            model_dir.display()
+        );
+
+        let encoder = tokio::task::spawn_blocking(move || {
+            let mut tokenizer = Tokenizer::from_file(model_dir.join("tokenizer.json"))
+                .map_err(|e| anyhow!("Failed to load embedding tokenizer: {}", e))?;
+            tokenizer.with_padding(Some(PaddingParams::default()));
+            tokenizer
+                .with_truncation(Some(TruncationParams {
+                    max_length: MAX_EMBEDDING_TOKENS,
+                    ..Default::default()
+                }))
+                .map_err(|e| anyhow!("Failed to configure embedding tokenizer: {}", e))?;
+
+            let config_bytes = fs::read(model_dir.join("config.json")).with_context(|| {
+                format!("Failed to read embedding config in {}", model_dir.display())
+            })?;
+            let bert_config: BertConfig = serde_json::from_slice(&config_bytes)?;
+            // Small enough that the CPU keeps up; leaves the GPU to generation.
+            let device = Device::Cpu;
+            let weights = weight_files(&model_dir)?;
+            let vb = unsafe { VarBuilder::from_mmaped_safetensors(&weights, DType::F32, &device)? };
+            let model = BertModel::load(vb, &bert_config)?;
+            Ok::<_, anyhow::Error>(Encoder {
+                model,
+                tokenizer,
+                device,
+            })
+        })
+        .await??;
+
+        *self.encoder.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(encoder));
+        info!("Embedding model loaded successfully");
+        Ok(())
+    }
+
+    async fn resolve_model_dir(&self, config: &AiConfig, model_name: &str) -> Result<PathBuf> {
+        if let Some(path) = config
+            .embedding_model_path
+            .as_deref()
+            .filter(|p| !p.trim().is_empty())
+        {
+            return Ok(expand_home(path));
+        }
+        let (cache_dir, repo_name) = hf_cache_location(config, model_name);
+        if let Some(snapshot) = cached_snapshot(&cache_dir, &repo_name) {
+            return Ok(snapshot);
+        }
+        if !config.model_auto_download {
+            return Err(anyhow!(
+                "Embedding model {} not found in {}; set EMBEDDING_MODEL_PATH or MODEL_AUTO_DOWNLOAD=true",
+                model_name,
+                cache_dir.display()
+            ));
+        }
+        let repo_dir = cache_dir.join("hub").join(repo_name);
+        download_model(DownloadRequest {
+            model_name,
+            repo_dir: &repo_dir,
+            token: config.huggingface_token.as_deref(),
+            min_free_bytes: config.model_min_free_disk_mb * 1024 * 1024,
+            max_concurrent: config.model_max_concurrent_downloads,
+        })
+        .await
+        .with_context(|| format!("Failed to download embedding model {}", model_name))
+    }
+
+    /// Embeddings of `texts` in order, or `None` while no encoder is loaded.
+    pub async fn embed(&self, texts: Vec<String>) -> Option<Result<Vec<Vec<f32>>>> {
+        let result = self.embed_counted(texts).await?;
+        Some(result.map(|(vectors, _)| vectors))
+    }
+
+    /// [`EmbeddingService::embed`] plus the tokens read, for usage reporting.
+    pub async fn embed_counted(
+        &self,
+        texts: Vec<String>,
+    ) -> Option<Result<(Vec<Vec<f32>>, usize)>> {
+        let encoder = self.encoder()?;
+        let result = tokio::task::spawn_blocking(move || {
+            let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+            encoder.embed(&texts)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+        Some(result)
+    }
+
+    /// Similarity of `query` to each of `candidates` in order, or `None` when
+    /// embeddings are unavailable and the caller should score lexically.
+    pub async fn similarities(&self, query: &str, candidates: &[String]) -> Option<Vec<f32>> {
+        if candidates.is_empty() {
+            return Some(Vec::new());
+        }
+        let texts = std::iter::once(query.to_string())
+            .chain(candidates.iter().cloned())
+            .collect();
+        match self.embed(texts).await? {
+            Ok(vectors) => {
+                let (query, candidates) = vectors.split_first()?;
+                Some(
+                    candidates
+                        .iter()
+                        .map(|candidate| embedding_similarity(query, candidate))
+                        .collect(),
+                )
+            }
+            Err(e) => {
+                tracing::warn!("Embedding failed; using lexical scores: {}", e);
+                None
+            }
+        }
+    }
+}
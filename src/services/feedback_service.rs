@@ -0,0 +1,82 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::config::ConversationSettings;
+use crate::models::FeedbackRating;
+use crate::repositories::{FeedbackRepo, FeedbackTotalsRecord, ReadPool, ResponseFeedbackRecord};
+
+/// Stores per-answer ratings and comments for prompt iteration. Shares the
+/// conversation store.
+#[derive(Clone)]
+pub struct FeedbackService {
+    repo: Option<FeedbackRepo>,
+}
+
+impl FeedbackService {
+    pub fn new(settings: &ConversationSettings) -> Self {
+        let repo = if settings.sqlite_path.trim().is_empty() {
+            None
+        } else {
+            let reporting_path = settings
+                .analytics_replica_path
+                .clone()
+                .unwrap_or_else(|| settings.sqlite_path.clone());
+            let reporting = ReadPool::new(reporting_path, settings.analytics_pool_size);
+            match FeedbackRepo::new(settings.sqlite_path.clone(), reporting) {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    tracing::warn!("Response feedback disabled: {}", e);
+                    None
+                }
+            }
+        };
+        Self { repo }
+    }
+
+    /// Returns how many distinct callers have rated the answer negatively,
+    /// including this submission.
+    pub async fn submit(
+        &self,
+        conversation_id: Uuid,
+        response_hash: &str,
+        rating: FeedbackRating,
+        comment: Option<String>,
+        caller: String,
+    ) -> Result<u64> {
+        let repo = self.repo()?;
+        let conversation_id = conversation_id.to_string();
+        let response_hash = response_hash.to_string();
+        tokio::task::spawn_blocking(move || {
+            repo.insert(
+                &conversation_id,
+                &response_hash,
+                rating.as_str(),
+                comment.as_deref().filter(|c| !c.trim().is_empty()),
+                &caller,
+            )
+        })
+        .await?
+    }
+
+    pub async fn stats_since(
+        &self,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<(FeedbackTotalsRecord, Vec<ResponseFeedbackRecord>)> {
+        let repo = self.repo()?;
+        tokio::task::spawn_blocking(move || {
+            Ok((
+                repo.totals_since(since)?,
+                repo.most_negative_since(since, limit)?,
+            ))
+        })
+        .await?
+    }
+
+    fn repo(&self) -> Result<FeedbackRepo> {
+        self.repo
+            .clone()
+            .ok_or_else(|| anyhow!("Response feedback is disabled"))
+    }
+}
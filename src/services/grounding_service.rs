@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::models::{GroundingReport, GroundingSentence};
+use crate::services::SearchResult;
+
+/// Sentences shorter than this are treated as connective text rather than claims.
+const MIN_CLAIM_TOKENS: usize = 4;
+
+#[derive(Clone)]
+pub struct GroundingService {
+    threshold: f32,
+}
+
+impl Default for GroundingService {
+    fn default() -> Self {
+        Self { threshold: 0.2 }
+    }
+}
+
+impl GroundingService {
+    pub fn new(threshold: f32) -> Self {
+        Self { threshold }
+    }
+
+    /// Scores every claim-like sentence of `answer` by bigram overlap with the sources.
+    pub fn check(&self, answer: &str, sources: &[SearchResult], strict: bool) -> GroundingReport {
+        let source_bigrams: Vec<HashSet<(String, String)>> = sources
+            .iter()
+            .map(|source| bigrams(&format!("{} {}", source.title, source.snippet)))
+            .collect();
+
+        let mut sentences = Vec::new();
+        for sentence in split_sentences(answer) {
+            let sentence_bigrams = bigrams(&sentence);
+            if sentence_bigrams.len() + 1 < MIN_CLAIM_TOKENS {
+                continue;
+            }
+
+            let (score, best_source) = source_bigrams
+                .iter()
+                .enumerate()
+                .map(|(index, source)| {
+                    let shared = sentence_bigrams.intersection(source).count() as f32;
+                    (shared / sentence_bigrams.len() as f32, index)
+                })
+                .fold((0.0_f32, None), |best, (score, index)| {
+                    if score > best.0 {
+                        (score, Some(index))
+                    } else {
+                        best
+                    }
+                });
+
+            let supported = score >= self.threshold;
+            sentences.push(GroundingSentence {
+                text: sentence,
+                score,
+                supported,
+                source_url: best_source
+                    .filter(|_| supported)
+                    .map(|index| sources[index].url.clone()),
+            });
+        }
+
+        let supported_ratio = if sentences.is_empty() {
+            1.0
+        } else {
+            sentences.iter().filter(|s| s.supported).count() as f32 / sentences.len() as f32
+        };
+
+        GroundingReport {
+            supported_ratio,
+            strict,
+            sentences,
+        }
+    }
+
+    /// Removes the sentences the report marked as unsupported. Everything else
+    /// is kept as written: the spacing between the remaining sentences, line
+    /// breaks, indentation and list markers. A line left without sentences is
+    /// dropped with its line break.
+    pub fn strip_unsupported(&self, answer: &str, report: &GroundingReport) -> String {
+        let unsupported: HashSet<&str> = report
+            .sentences
+            .iter()
+            .filter(|s| !s.supported)
+            .map(|s| s.text.as_str())
+            .collect();
+
+        let mut stripped = String::with_capacity(answer.len());
+        // Sentences never span a line break, so each line is handled on its own.
+        for line in answer.split_inclusive('\n') {
+            let spans = sentence_spans(line);
+            let kept: Vec<usize> = (0..spans.len())
+                .filter(|&i| !unsupported.contains(&line[spans[i].clone()]))
+                .collect();
+            if kept.len() == spans.len() {
+                stripped.push_str(line);
+                continue;
+            }
+            if kept.is_empty() {
+                continue;
+            }
+
+            stripped.push_str(&line[..spans[0].start]);
+            for (n, &i) in kept.iter().enumerate() {
+                if n > 0 {
+                    // The separator that followed the previous kept sentence.
+                    let previous = kept[n - 1];
+                    stripped.push_str(&line[spans[previous].end..spans[previous + 1].start]);
+                }
+                stripped.push_str(&line[spans[i].clone()]);
+            }
+            stripped.push_str(&line[spans[spans.len() - 1].end..]);
+        }
+        stripped
+    }
+}
+
+fn split_sentences(text: &str) -> Vec<String> {
+    sentence_spans(text)
+        .into_iter()
+        .map(|span| text[span].to_string())
+        .collect()
+}
+
+/// Byte ranges of the sentences in `text`, without surrounding whitespace.
+/// A sentence ends at `.`, `!` or `?` before whitespace, or at a line break.
+fn sentence_spans(text: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        if c == '\n' {
+            push_span(text, &mut spans, start..index);
+            start = index + 1;
+            continue;
+        }
+        if matches!(c, '.' | '!' | '?')
+            && chars.peek().map_or(true, |(_, next)| next.is_whitespace())
+        {
+            let end = index + c.len_utf8();
+            push_span(text, &mut spans, start..end);
+            start = end;
+        }
+    }
+    push_span(text, &mut spans, start..text.len());
+    spans
+}
+
+fn push_span(text: &str, spans: &mut Vec<Range<usize>>, range: Range<usize>) {
+    let segment = &text[range.clone()];
+    let trimmed = segment.trim();
+    if !trimmed.is_empty() {
+        let start = range.start + (segment.len() - segment.trim_start().len());
+        spans.push(start..start + trimmed.len());
+    }
+}
+
+fn bigrams(text: &str) -> HashSet<(String, String)> {
+    let tokens: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(|token| token.to_lowercase())
+        .collect();
+    tokens
+        .windows(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect()
+}
@@ -0,0 +1,482 @@
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::config::GuardrailSettings;
+use crate::models::{
+    ChatPayload, ChatRequest, ChatResponse, LogAnalysisRequest, LogAnalysisResponse,
+    OllamaChatRequest, OllamaGenerateRequest, ScriptGenerationRequest, ScriptResponse,
+};
+use crate::utils::REDACTED;
+
+/// What happens to text that matches a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GuardrailAction {
+    Allow,
+    Block,
+    /// Replace the matched spans and continue.
+    Redact,
+}
+
+/// A category rule as read from `GUARDRAILS_RULES_PATH`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GuardrailRuleConfig {
+    pub category: String,
+    pub policy_code: String,
+    /// Case-insensitive regexes; any match triggers the rule.
+    pub patterns: Vec<String>,
+    #[serde(default = "default_input_action")]
+    pub input: GuardrailAction,
+    #[serde(default = "default_output_action")]
+    pub output: GuardrailAction,
+    /// Returned as the error details when the rule blocks.
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+fn default_input_action() -> GuardrailAction {
+    GuardrailAction::Block
+}
+
+fn default_output_action() -> GuardrailAction {
+    GuardrailAction::Redact
+}
+
+struct BuiltinRule {
+    category: &'static str,
+    policy_code: &'static str,
+    patterns: &'static [&'static str],
+    input: GuardrailAction,
+    output: GuardrailAction,
+    message: Option<&'static str>,
+}
+
+/// Built-in categories, selectable with `GUARDRAILS_CATEGORIES`.
+const BUILTIN_RULES: &[BuiltinRule] = &[
+    BuiltinRule {
+        category: "self_harm",
+        policy_code: "self_harm",
+        patterns: &[
+            r"\b(kill|hurt|harm|cut)\s+myself\b",
+            r"\bend\s+my\s+(own\s+)?life\b",
+            r"\bsuicid(e|al)\b",
+            r"\bself[- ]harm",
+        ],
+        input: GuardrailAction::Block,
+        // Answers may point to crisis resources, so only requests are screened.
+        output: GuardrailAction::Allow,
+        message: Some(
+            "If you are thinking about harming yourself, please contact your local emergency number or a crisis line now.",
+        ),
+    },
+    BuiltinRule {
+        category: "malware",
+        policy_code: "malware_request",
+        patterns: &[
+            r"\b(write|create|build|make|generate|code)\b.{0,40}\b(ransomware|keylogger|botnet|rootkit|trojan|malware|computer virus)\b",
+            r"\b(disable|bypass|evade)\b.{0,30}\b(antivirus|anti-virus|edr|windows defender|av detection)\b",
+            r"\b(encrypt|lock)\b.{0,30}\bfiles\b.{0,40}\b(ransom|bitcoin)\b",
+        ],
+        input: GuardrailAction::Block,
+        output: GuardrailAction::Block,
+        message: None,
+    },
+    BuiltinRule {
+        category: "credential_exfiltration",
+        policy_code: "credential_exfiltration",
+        patterns: &[
+            r"\b(steal|exfiltrate|dump|harvest|grab)\b.{0,40}\b(passwords?|credentials?|cookies|session tokens?|password hashes|keychain|lsass)\b",
+            r"\bmimikatz\b",
+            r"\b(send|upload|post|curl|scp|nc)\b.{0,60}(\.ssh/id_[a-z0-9]+|/etc/shadow|\.aws/credentials)",
+        ],
+        input: GuardrailAction::Block,
+        output: GuardrailAction::Redact,
+        message: None,
+    },
+];
+
+const PROMPT_LEAK_CATEGORY: &str = "prompt_leak";
+const PROMPT_LEAK_POLICY_CODE: &str = "system_prompt_leak";
+
+struct GuardrailRule {
+    category: String,
+    policy_code: String,
+    patterns: Vec<Regex>,
+    input: GuardrailAction,
+    output: GuardrailAction,
+    message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardrailStage {
+    Input,
+    Output,
+}
+
+impl GuardrailStage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GuardrailStage::Input => "input",
+            GuardrailStage::Output => "output",
+        }
+    }
+}
+
+/// Text rejected by a blocking rule.
+#[derive(Debug, Clone)]
+pub struct GuardrailViolation {
+    pub category: String,
+    pub policy_code: String,
+    pub stage: GuardrailStage,
+    pub message: Option<String>,
+}
+
+impl fmt::Display for GuardrailViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} blocked by content policy {}",
+            self.stage.as_str(),
+            self.policy_code
+        )
+    }
+}
+
+impl std::error::Error for GuardrailViolation {}
+
+/// Detects answers that repeat a protected prompt verbatim, by looking for
+/// runs of `min_words` consecutive words shared with it.
+struct PromptLeakGuard {
+    action: GuardrailAction,
+    min_words: usize,
+    shingles: HashSet<String>,
+}
+
+impl PromptLeakGuard {
+    fn new(settings: &GuardrailSettings, prompts: &[String]) -> Option<Self> {
+        let action = match settings.prompt_leak_policy.as_str() {
+            "allow" => return None,
+            "refuse" | "block" => GuardrailAction::Block,
+            "redact" => GuardrailAction::Redact,
+            other => {
+                tracing::warn!("Unknown PROMPT_LEAK_POLICY {:?}; redacting leaks", other);
+                GuardrailAction::Redact
+            }
+        };
+        let min_words = settings.prompt_leak_min_words.max(3);
+        let shingles: HashSet<String> = prompts
+            .iter()
+            .flat_map(|prompt| {
+                let words: Vec<String> = words(prompt).into_iter().map(|(_, word)| word).collect();
+                words
+                    .windows(min_words)
+                    .map(|window| window.join(" "))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        (!shingles.is_empty()).then_some(Self {
+            action,
+            min_words,
+            shingles,
+        })
+    }
+
+    /// Byte ranges of `text` that repeat a protected prompt, merged and in order.
+    fn leaked_spans(&self, text: &str) -> Vec<Range<usize>> {
+        let words = words(text);
+        let mut spans: Vec<Range<usize>> = Vec::new();
+        for window in words.windows(self.min_words) {
+            let shingle = window
+                .iter()
+                .map(|(_, word)| word.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            if !self.shingles.contains(&shingle) {
+                continue;
+            }
+            let span = window[0].0.start..window[window.len() - 1].0.end;
+            match spans.last_mut() {
+                Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+                _ => spans.push(span),
+            }
+        }
+        spans
+    }
+}
+
+/// Lowercased words of `text` with their byte ranges; punctuation and spacing
+/// are ignored so reformatted copies still match.
+fn words(text: &str) -> Vec<(Range<usize>, String)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (index, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(index),
+            (false, Some(begin)) => {
+                words.push((begin..index, text[begin..index].to_lowercase()));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// Text of a request or an answer that the guardrails check. Generation
+/// routes screen whole requests and answers, so a new field is covered once
+/// it is listed here.
+pub trait GuardedText {
+    fn guarded_text(&mut self) -> Vec<&mut String>;
+}
+
+impl GuardedText for String {
+    fn guarded_text(&mut self) -> Vec<&mut String> {
+        vec![self]
+    }
+}
+
+impl GuardedText for ChatRequest {
+    fn guarded_text(&mut self) -> Vec<&mut String> {
+        let mut text = vec![&mut self.message];
+        text.extend(self.system_prompt.as_mut());
+        text
+    }
+}
+
+impl GuardedText for OllamaGenerateRequest {
+    fn guarded_text(&mut self) -> Vec<&mut String> {
+        let mut text = vec![&mut self.prompt];
+        text.extend(self.system.as_mut());
+        text
+    }
+}
+
+impl GuardedText for OllamaChatRequest {
+    fn guarded_text(&mut self) -> Vec<&mut String> {
+        self.messages
+            .iter_mut()
+            .map(|message| &mut message.content)
+            .collect()
+    }
+}
+
+impl GuardedText for ChatPayload {
+    fn guarded_text(&mut self) -> Vec<&mut String> {
+        match self {
+            ChatPayload::Ollama(req) => req.guarded_text(),
+            ChatPayload::Native(req) => req.guarded_text(),
+        }
+    }
+}
+
+impl GuardedText for LogAnalysisRequest {
+    fn guarded_text(&mut self) -> Vec<&mut String> {
+        let mut text = vec![&mut self.logs];
+        text.extend(self.context.as_mut());
+        text
+    }
+}
+
+impl GuardedText for ScriptGenerationRequest {
+    fn guarded_text(&mut self) -> Vec<&mut String> {
+        vec![&mut self.requirement]
+    }
+}
+
+impl GuardedText for ChatResponse {
+    fn guarded_text(&mut self) -> Vec<&mut String> {
+        vec![&mut self.response]
+    }
+}
+
+impl GuardedText for LogAnalysisResponse {
+    fn guarded_text(&mut self) -> Vec<&mut String> {
+        vec![&mut self.analysis]
+    }
+}
+
+impl GuardedText for ScriptResponse {
+    fn guarded_text(&mut self) -> Vec<&mut String> {
+        vec![&mut self.script, &mut self.explanation]
+    }
+}
+
+/// Screens requests and completions against category rules. Requests are
+/// checked before they reach a model; completions before they are returned
+/// or stored, including for verbatim copies of the system prompt or templates.
+#[derive(Clone)]
+pub struct GuardrailService {
+    rules: Arc<Vec<GuardrailRule>>,
+    leak_guard: Option<Arc<PromptLeakGuard>>,
+}
+
+impl GuardrailService {
+    /// `protected_prompts` are the instructions answers must not repeat.
+    pub fn new(settings: &GuardrailSettings, protected_prompts: &[String]) -> Self {
+        let leak_guard = PromptLeakGuard::new(settings, protected_prompts).map(Arc::new);
+        if !settings.enabled {
+            return Self {
+                rules: Arc::new(Vec::new()),
+                leak_guard,
+            };
+        }
+
+        let mut configs: Vec<GuardrailRuleConfig> = BUILTIN_RULES
+            .iter()
+            .filter(|rule| settings.categories.iter().any(|c| c == rule.category))
+            .map(|rule| GuardrailRuleConfig {
+                category: rule.category.to_string(),
+                policy_code: rule.policy_code.to_string(),
+                patterns: rule.patterns.iter().map(|p| p.to_string()).collect(),
+                input: rule.input,
+                output: rule.output,
+                message: rule.message.map(str::to_string),
+            })
+            .collect();
+        if let Some(path) = settings.rules_path.as_deref() {
+            match load_rules(path) {
+                Ok(custom) => {
+                    for rule in custom {
+                        configs.retain(|existing| existing.category != rule.category);
+                        configs.push(rule);
+                    }
+                }
+                Err(e) => tracing::warn!("Using built-in guardrail rules only: {:#}", e),
+            }
+        }
+
+        let rules = configs
+            .into_iter()
+            .filter_map(|config| match compile_rule(&config) {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    tracing::warn!("Skipping guardrail rule {}: {}", config.category, e);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            rules: Arc::new(rules),
+            leak_guard,
+        }
+    }
+
+    /// Checks every text field of a request, redacting in place where a rule
+    /// allows that.
+    pub fn screen_request(&self, request: &mut impl GuardedText) -> Result<(), GuardrailViolation> {
+        request
+            .guarded_text()
+            .into_iter()
+            .try_for_each(|text| self.screen(text, GuardrailStage::Input))
+    }
+
+    /// Checks every text field of an answer, redacting in place where a rule
+    /// allows that.
+    pub fn screen_answer(&self, answer: &mut impl GuardedText) -> Result<(), GuardrailViolation> {
+        answer.guarded_text().into_iter().try_for_each(|text| {
+            self.screen(text, GuardrailStage::Output)?;
+            self.screen_prompt_leak(text)
+        })
+    }
+
+    fn screen_prompt_leak(&self, text: &mut String) -> Result<(), GuardrailViolation> {
+        let Some(guard) = self.leak_guard.as_deref() else {
+            return Ok(());
+        };
+        let spans = guard.leaked_spans(text);
+        if spans.is_empty() {
+            return Ok(());
+        }
+
+        tracing::warn!(
+            category = PROMPT_LEAK_CATEGORY,
+            policy_code = PROMPT_LEAK_POLICY_CODE,
+            action = ?guard.action,
+            spans = spans.len(),
+            "Answer repeated a protected prompt"
+        );
+        if guard.action == GuardrailAction::Block {
+            return Err(GuardrailViolation {
+                category: PROMPT_LEAK_CATEGORY.to_string(),
+                policy_code: PROMPT_LEAK_POLICY_CODE.to_string(),
+                stage: GuardrailStage::Output,
+                message: Some("The answer repeated the assistant's instructions.".to_string()),
+            });
+        }
+        // Replace from the end so earlier ranges stay valid.
+        for span in spans.into_iter().rev() {
+            text.replace_range(span, REDACTED);
+        }
+        Ok(())
+    }
+
+    fn screen(&self, text: &mut String, stage: GuardrailStage) -> Result<(), GuardrailViolation> {
+        for rule in self.rules.iter() {
+            let action = match stage {
+                GuardrailStage::Input => rule.input,
+                GuardrailStage::Output => rule.output,
+            };
+            if action == GuardrailAction::Allow
+                || !rule.patterns.iter().any(|pattern| pattern.is_match(text))
+            {
+                continue;
+            }
+
+            tracing::warn!(
+                category = %rule.category,
+                policy_code = %rule.policy_code,
+                stage = stage.as_str(),
+                action = ?action,
+                "Guardrail rule matched"
+            );
+            if action == GuardrailAction::Block {
+                return Err(GuardrailViolation {
+                    category: rule.category.clone(),
+                    policy_code: rule.policy_code.clone(),
+                    stage,
+                    message: rule.message.clone(),
+                });
+            }
+            *text = rule.patterns.iter().fold(text.clone(), |current, pattern| {
+                pattern.replace_all(&current, REDACTED).into_owned()
+            });
+        }
+        Ok(())
+    }
+}
+
+fn load_rules(path: &str) -> Result<Vec<GuardrailRuleConfig>> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read guardrail rules {}", path))?;
+    serde_json::from_str(&raw).with_context(|| format!("Invalid guardrail rules in {}", path))
+}
+
+fn compile_rule(config: &GuardrailRuleConfig) -> Result<GuardrailRule> {
+    let patterns = config
+        .patterns
+        .iter()
+        .map(|pattern| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .with_context(|| format!("invalid pattern {:?}", pattern))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(GuardrailRule {
+        category: config.category.clone(),
+        policy_code: config.policy_code.clone(),
+        patterns,
+        input: config.input,
+        output: config.output,
+        message: config.message.clone(),
+    })
+}
@@ -0,0 +1,173 @@
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::json;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::config::HandoffSettings;
+use crate::models::{ChatResponse, ConversationMessage};
+use crate::services::{ConversationService, HttpClients, RoutingMetricsService};
+
+const HUMAN_REQUEST_PHRASES: &[&str] = &[
+    "talk to a human",
+    "speak to a human",
+    "talk to a person",
+    "speak to a person",
+    "real person",
+    "human agent",
+    "live agent",
+    "talk to an agent",
+    "speak to an agent",
+    "support agent",
+    "customer service representative",
+    "talk to someone",
+    "speak with someone",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HandoffReason {
+    UserRequested,
+    LowConfidence,
+}
+
+impl HandoffReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HandoffReason::UserRequested => "user_requested",
+            HandoffReason::LowConfidence => "low_confidence",
+        }
+    }
+}
+
+/// Escalates conversations to human support when the user asks for it or the
+/// answer is poorly grounded, notifying the configured webhook once per conversation.
+#[derive(Clone)]
+pub struct HandoffService {
+    settings: HandoffSettings,
+    conversations: ConversationService,
+    routing_metrics: RoutingMetricsService,
+    http: HttpClients,
+}
+
+impl HandoffService {
+    pub fn new(
+        settings: HandoffSettings,
+        conversations: ConversationService,
+        routing_metrics: RoutingMetricsService,
+        http: HttpClients,
+    ) -> Self {
+        Self {
+            settings,
+            conversations,
+            routing_metrics,
+            http,
+        }
+    }
+
+    pub fn detect(&self, message: &str, response: &ChatResponse) -> Option<HandoffReason> {
+        let message = message.to_lowercase();
+        if HUMAN_REQUEST_PHRASES
+            .iter()
+            .any(|phrase| message.contains(phrase))
+        {
+            return Some(HandoffReason::UserRequested);
+        }
+
+        response
+            .grounding
+            .as_ref()
+            .filter(|report| report.supported_ratio < self.settings.confidence_threshold)
+            .map(|_| HandoffReason::LowConfidence)
+    }
+
+    /// Checks the turn for a handoff trigger and escalates in the background.
+    pub fn evaluate(&self, conversation_id: Uuid, message: &str, response: &ChatResponse) {
+        let Some(reason) = self.detect(message, response) else {
+            return;
+        };
+
+        let service = self.clone();
+        let current_turn = vec![
+            turn_message("user", message),
+            turn_message("assistant", &response.response),
+        ];
+        tokio::spawn(async move {
+            service
+                .escalate(conversation_id, reason, current_turn)
+                .await;
+        });
+    }
+
+    async fn escalate(
+        &self,
+        conversation_id: Uuid,
+        reason: HandoffReason,
+        current_turn: Vec<ConversationMessage>,
+    ) {
+        // Without persistence every trigger is treated as new and only the current
+        // turn can be sent.
+        let transcript = if self.conversations.is_enabled() {
+            match self
+                .conversations
+                .escalate(conversation_id, reason.as_str())
+                .await
+            {
+                Ok(false) => return,
+                Ok(true) => match self.conversations.get(conversation_id).await {
+                    Ok(Some((_, messages))) => messages.into_iter().map(Into::into).collect(),
+                    _ => current_turn,
+                },
+                Err(e) => {
+                    tracing::warn!(
+                        "Failed to mark conversation {} as escalated: {}",
+                        conversation_id,
+                        e
+                    );
+                    current_turn
+                }
+            }
+        } else {
+            current_turn
+        };
+
+        self.routing_metrics.mark_escalated(conversation_id).await;
+        tracing::info!(
+            conversation_id = %conversation_id,
+            reason = reason.as_str(),
+            "Conversation escalated to human support"
+        );
+
+        let Some(url) = self.settings.webhook_url.as_deref() else {
+            return;
+        };
+        let payload = json!({
+            "event": "conversation.escalated",
+            "conversation_id": conversation_id,
+            "reason": reason,
+            "escalated_at": Utc::now(),
+            "transcript": transcript,
+        });
+        let result = self
+            .http
+            .for_url(url)
+            .post(url)
+            .timeout(Duration::from_millis(self.settings.webhook_timeout_ms))
+            .json(&payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            tracing::warn!("Handoff webhook failed for {}: {}", conversation_id, e);
+        }
+    }
+}
+
+fn turn_message(role: &str, content: &str) -> ConversationMessage {
+    ConversationMessage {
+        role: role.to_string(),
+        content: content.to_string(),
+        created_at: Utc::now(),
+        interrupted: None,
+    }
+}
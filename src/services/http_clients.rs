@@ -0,0 +1,75 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::sync::Arc;
+
+use crate::config::{ClientCertRule, OutboundSettings};
+
+/// HTTP clients for outbound calls. Destinations that require mutual TLS get
+/// a client presenting the certificate configured for their host.
+#[derive(Clone, Default)]
+pub struct HttpClients {
+    default: reqwest::Client,
+    by_host: Arc<Vec<(String, reqwest::Client)>>,
+}
+
+impl HttpClients {
+    pub fn new(settings: &OutboundSettings) -> Result<Self> {
+        let by_host = settings
+            .client_certs
+            .iter()
+            .map(|rule| {
+                let client = mtls_client(rule).with_context(|| {
+                    format!("Invalid client certificate for {}", rule.host_pattern)
+                })?;
+                Ok((rule.host_pattern.clone(), client))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            default: reqwest::Client::new(),
+            by_host: Arc::new(by_host),
+        })
+    }
+
+    /// The client for `url`: the first one whose host pattern matches, or the default.
+    pub fn for_url(&self, url: &str) -> &reqwest::Client {
+        let Some(host) = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+        else {
+            return &self.default;
+        };
+        self.by_host
+            .iter()
+            .find(|(pattern, _)| host_matches(pattern, &host))
+            .map(|(_, client)| client)
+            .unwrap_or(&self.default)
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.len() > 1 && prefix.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+fn mtls_client(rule: &ClientCertRule) -> Result<reqwest::Client> {
+    // reqwest takes the certificate chain and private key as one PEM bundle.
+    let mut bundle = read_pem(&rule.cert_path)?;
+    bundle.push(b'\n');
+    bundle.extend(read_pem(&rule.key_path)?);
+    let mut builder = reqwest::Client::builder()
+        .use_rustls_tls()
+        .identity(reqwest::Identity::from_pem(&bundle)?);
+    if let Some(ca_cert_path) = &rule.ca_cert_path {
+        builder =
+            builder.add_root_certificate(reqwest::Certificate::from_pem(&read_pem(ca_cert_path)?)?);
+    }
+    Ok(builder.build()?)
+}
+
+fn read_pem(path: &str) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("Failed to read client certificate: {}", path))
+}
@@ -0,0 +1,100 @@
+use anyhow::{anyhow, Result};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use tokio::sync::oneshot;
+
+use crate::utils::tune_inference_thread;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Dedicated OS threads that run local generations, so CPU-bound sampling
+/// never occupies the async workers serving HTTP. Each worker is pinned and
+/// niced once, when it starts.
+#[derive(Clone)]
+pub struct InferencePool {
+    sender: SyncSender<Job>,
+    queued: Arc<AtomicUsize>,
+    workers: usize,
+}
+
+impl InferencePool {
+    /// `workers` threads (at least one) sharing a queue of `queue_depth` jobs.
+    pub fn new(
+        workers: usize,
+        queue_depth: usize,
+        cpu_cores: Vec<usize>,
+        nice: Option<i32>,
+    ) -> Self {
+        let workers = workers.max(1);
+        let (sender, receiver) = mpsc::sync_channel::<Job>(queue_depth);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let queued = Arc::new(AtomicUsize::new(0));
+        for index in 0..workers {
+            let receiver = receiver.clone();
+            let queued = queued.clone();
+            let cpu_cores = cpu_cores.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("inference-{}", index))
+                .spawn(move || {
+                    tune_inference_thread(&cpu_cores, nice);
+                    while let Some(job) = next_job(&receiver) {
+                        queued.fetch_sub(1, Ordering::SeqCst);
+                        job();
+                    }
+                });
+            if let Err(e) = spawned {
+                tracing::error!("Failed to start inference worker {}: {}", index, e);
+            }
+        }
+        Self {
+            sender,
+            queued,
+            workers,
+        }
+    }
+
+    /// Runs `job` on a worker and waits for its result. Fails at once when
+    /// the queue is full.
+    pub async fn run<T, F>(&self, job: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            // A panicking generation drops `reply`, failing only its own request.
+            if let Ok(value) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                let _ = reply.send(value);
+            }
+        });
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        if let Err(e) = self.sender.try_send(job) {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return Err(match e {
+                TrySendError::Full(_) => anyhow!("Inference queue is full"),
+                TrySendError::Disconnected(_) => anyhow!("Inference workers have stopped"),
+            });
+        }
+        result.await.map_err(|_| anyhow!("Inference job panicked"))
+    }
+
+    /// Jobs waiting for a free worker.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+}
+
+fn next_job(receiver: &Mutex<Receiver<Job>>) -> Option<Job> {
+    receiver
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .recv()
+        .ok()
+}
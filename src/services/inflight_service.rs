@@ -0,0 +1,171 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use uuid::Uuid;
+
+use crate::models::{InflightGeneration, Route};
+
+tokio::task_local! {
+    static CURRENT: InflightHandle;
+}
+
+/// Returned by a generation an operator cancelled.
+#[derive(Debug, Clone)]
+pub struct GenerationCancelled {
+    pub id: String,
+}
+
+impl fmt::Display for GenerationCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Generation {} was cancelled by an operator", self.id)
+    }
+}
+
+impl std::error::Error for GenerationCancelled {}
+
+/// Returned when the caller's `X-Request-Timeout-Ms` ran out while the
+/// request waited for a slot or was being generated.
+#[derive(Debug, Clone)]
+pub struct DeadlineExceeded;
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The request deadline passed before the answer was ready")
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+struct InflightEntry {
+    request_id: Option<String>,
+    route: Option<Route>,
+    model: Option<String>,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    queue_wait: Duration,
+    cancel: watch::Sender<bool>,
+}
+
+#[derive(Clone)]
+struct InflightHandle {
+    id: String,
+    registry: InflightRegistry,
+    cancelled: watch::Receiver<bool>,
+}
+
+/// Generations currently running, so operators can find and cancel one that
+/// is stuck holding the model.
+#[derive(Clone, Default)]
+pub struct InflightRegistry {
+    entries: Arc<Mutex<HashMap<String, InflightEntry>>>,
+}
+
+impl InflightRegistry {
+    /// Runs `generation` as a listed in-flight entry. Cancelling the entry
+    /// drops `generation` and returns [`GenerationCancelled`].
+    pub async fn run<T>(
+        &self,
+        request_id: Option<String>,
+        queue_wait: Duration,
+        generation: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        let id = Uuid::new_v4().to_string();
+        let (cancel, mut cancelled) = watch::channel(false);
+        self.entries().insert(
+            id.clone(),
+            InflightEntry {
+                request_id,
+                route: None,
+                model: None,
+                started_at: Utc::now(),
+                started: Instant::now(),
+                queue_wait,
+                cancel,
+            },
+        );
+        let _entry = EntryGuard {
+            id: id.clone(),
+            registry: self.clone(),
+        };
+
+        let handle = InflightHandle {
+            id: id.clone(),
+            registry: self.clone(),
+            cancelled: cancelled.clone(),
+        };
+        tokio::select! {
+            result = CURRENT.scope(handle, generation) => result,
+            Ok(_) = cancelled.wait_for(|cancelled| *cancelled) => {
+                tracing::warn!(generation = %id, "Generation cancelled by an operator");
+                Err(GenerationCancelled { id }.into())
+            }
+        }
+    }
+
+    /// Every running generation, oldest first.
+    pub fn snapshot(&self) -> Vec<InflightGeneration> {
+        let mut generations: Vec<InflightGeneration> = self
+            .entries()
+            .iter()
+            .map(|(id, entry)| InflightGeneration {
+                id: id.clone(),
+                request_id: entry.request_id.clone(),
+                route: entry.route,
+                model: entry.model.clone(),
+                started_at: entry.started_at,
+                elapsed_ms: entry.started.elapsed().as_millis() as u64,
+                queue_wait_ms: entry.queue_wait.as_millis() as u64,
+                cancelling: *entry.cancel.borrow(),
+            })
+            .collect();
+        generations.sort_by_key(|generation| generation.started_at);
+        generations
+    }
+
+    /// Signals the generation to stop. Returns `false` when it is not running.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.entries().get(id) {
+            Some(entry) => {
+                entry.cancel.send_replace(true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, InflightEntry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Removes the entry once its generation finishes or is dropped.
+struct EntryGuard {
+    id: String,
+    registry: InflightRegistry,
+}
+
+impl Drop for EntryGuard {
+    fn drop(&mut self) {
+        self.registry.entries().remove(&self.id);
+    }
+}
+
+/// Records the path and model the current in-flight generation is using.
+pub fn note_inflight_route(route: Route, model: &str) {
+    let _ = CURRENT.try_with(|handle| {
+        if let Some(entry) = handle.registry.entries().get_mut(&handle.id) {
+            entry.route = Some(route);
+            entry.model = Some(model.to_string());
+        }
+    });
+}
+
+/// Cancellation signal of the current in-flight generation, for work that
+/// runs outside the future and has to check it itself.
+pub fn inflight_cancel_signal() -> Option<watch::Receiver<bool>> {
+    CURRENT.try_with(|handle| handle.cancelled.clone()).ok()
+}
@@ -0,0 +1,535 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::config::KnowledgeBaseSettings;
+use crate::models::KnowledgeSyncStatus;
+use crate::repositories::{KnowledgeChunkRecord, KnowledgeDocumentRecord, KnowledgeRepo};
+use crate::services::{EmbeddingService, HttpClients, SearchResult, TaskRegistry};
+use crate::utils::{cache_key, chunk_text, embedding_similarity, term_vector, vector_similarity};
+
+/// Reported as the provider of knowledge-base search results.
+pub const KNOWLEDGE_BASE_PROVIDER: &str = "knowledge_base";
+
+/// Files read from Git sources; anything else in the repository is ignored.
+const DOCUMENT_EXTENSIONS: &[&str] = &["md", "markdown", "txt", "rst", "adoc"];
+const MAX_DOCUMENT_BYTES: u64 = 1024 * 1024;
+const GIT_TIMEOUT: Duration = Duration::from_secs(300);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+/// Chunks sent to the encoder at once while embedding a sync.
+const EMBEDDING_BATCH: usize = 32;
+
+/// A document as pulled from its source, before chunking.
+struct FetchedDocument {
+    doc_id: String,
+    title: String,
+    text: String,
+}
+
+/// The index version serving retrieval; replaced as a whole by each sync.
+#[derive(Default)]
+struct KnowledgeIndex {
+    version: Option<String>,
+    /// Encoder whose embeddings every chunk carries, if any.
+    embedding_model: Option<String>,
+    documents: Vec<KnowledgeDocumentRecord>,
+}
+
+/// Keeps runbooks and docs from Git repositories and URLs in a local index,
+/// searched alongside the web for requests that search. Chunks are matched
+/// by sentence embedding when the encoder is loaded, and by hashed term
+/// vectors otherwise.
+#[derive(Clone)]
+pub struct KnowledgeService {
+    settings: KnowledgeBaseSettings,
+    repo: Option<KnowledgeRepo>,
+    http: HttpClients,
+    embeddings: EmbeddingService,
+    index: Arc<RwLock<Arc<KnowledgeIndex>>>,
+    status: Arc<Mutex<KnowledgeSyncStatus>>,
+}
+
+impl KnowledgeService {
+    pub fn new(
+        settings: &KnowledgeBaseSettings,
+        http: HttpClients,
+        embeddings: EmbeddingService,
+    ) -> Self {
+        let repo = if settings.sqlite_path.trim().is_empty() {
+            None
+        } else {
+            match KnowledgeRepo::new(settings.sqlite_path.clone()) {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    tracing::warn!("Knowledge base disabled: {}", e);
+                    None
+                }
+            }
+        };
+        let index = match repo.as_ref().map(KnowledgeRepo::load_active) {
+            Some(Ok(Some(active))) => KnowledgeIndex {
+                version: Some(active.version),
+                embedding_model: active.embedding_model,
+                documents: active.documents,
+            },
+            Some(Err(e)) => {
+                tracing::warn!("Failed to load the knowledge base index: {}", e);
+                KnowledgeIndex::default()
+            }
+            _ => KnowledgeIndex::default(),
+        };
+        let status = KnowledgeSyncStatus {
+            enabled: repo.is_some(),
+            ..KnowledgeSyncStatus::default()
+        };
+        Self {
+            settings: settings.clone(),
+            repo,
+            http,
+            embeddings,
+            index: Arc::new(RwLock::new(Arc::new(index))),
+            status: Arc::new(Mutex::new(status)),
+        }
+    }
+
+    /// Starts the sync task, which first runs immediately; does nothing when
+    /// the store, the sources or the interval is off.
+    pub fn spawn(&self, tasks: &TaskRegistry) {
+        if self.repo.is_none() || self.settings.sources.is_empty() {
+            return;
+        }
+        if self.settings.sync_interval_secs == 0 {
+            return;
+        }
+        let service = self.clone();
+        tasks.spawn("knowledge_sync", move |handle| {
+            let service = service.clone();
+            async move {
+                let mut interval =
+                    tokio::time::interval(Duration::from_secs(service.settings.sync_interval_secs));
+                loop {
+                    interval.tick().await;
+                    match service.sync().await {
+                        Ok(changed) => {
+                            if changed > 0 {
+                                tracing::info!(
+                                    version = ?service.active_version(),
+                                    "Knowledge base updated, {} documents changed",
+                                    changed
+                                );
+                            }
+                            handle.ran();
+                        }
+                        Err(e) => {
+                            tracing::warn!("Knowledge base sync failed: {:#}", e);
+                            handle.failed(format!("{:#}", e));
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Pulls every source and, when anything changed, publishes a new index
+    /// version and swaps it in. Only new or changed documents are re-chunked.
+    /// A failing source aborts the sync and leaves the current version serving.
+    /// Returns how many documents were added, changed or removed.
+    pub async fn sync(&self) -> Result<usize> {
+        let repo = self.repo()?;
+        self.update_status(|status| status.syncing = true);
+        let result = self.sync_with(repo).await;
+        self.update_status(|status| {
+            status.syncing = false;
+            status.last_sync_at = Some(Utc::now());
+            match &result {
+                Ok(changed) => {
+                    status.last_success_at = status.last_sync_at;
+                    status.last_changed_documents = *changed;
+                    status.last_error = None;
+                }
+                Err(e) => status.last_error = Some(format!("{:#}", e)),
+            }
+        });
+        result
+    }
+
+    async fn sync_with(&self, repo: KnowledgeRepo) -> Result<usize> {
+        let mut fetched = Vec::new();
+        for source in &self.settings.sources {
+            let documents = match source.strip_prefix("git+") {
+                Some(repository) => self.fetch_git(repository).await,
+                None => self.fetch_url(source).await.map(|document| vec![document]),
+            }
+            .with_context(|| format!("Failed to pull {}", source))?;
+            fetched.extend(documents);
+        }
+
+        let previous = self.current_index();
+        let chunk_chars = self.settings.chunk_chars;
+        let (mut documents, changed) = {
+            let previous = previous.clone();
+            tokio::task::spawn_blocking(move || build_documents(fetched, &previous, chunk_chars))
+                .await?
+        };
+        // An encoder loaded or replaced since the last sync re-embeds the index.
+        let encoder_changed = self.embeddings.is_ready()
+            && previous.embedding_model.as_deref() != self.embeddings.model_name();
+        if changed == 0 && !encoder_changed && previous.version.is_some() {
+            return Ok(0);
+        }
+        let embedding_model = self
+            .embed_chunks(&mut documents, previous.embedding_model.as_deref())
+            .await;
+
+        let version = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let documents = {
+            let version = version.clone();
+            let embedding_model = embedding_model.clone();
+            tokio::task::spawn_blocking(move || {
+                repo.publish(&version, embedding_model.as_deref(), &documents)?;
+                Ok::<_, anyhow::Error>(documents)
+            })
+            .await??
+        };
+        *self.index.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(KnowledgeIndex {
+            version: Some(version),
+            embedding_model,
+            documents,
+        });
+        Ok(changed)
+    }
+
+    /// Gives every chunk an embedding from the loaded encoder, keeping those
+    /// `previous_model` already made. Returns the encoder's name once all
+    /// chunks are embedded; without an encoder, or when embedding fails, the
+    /// chunks are left with term vectors only.
+    async fn embed_chunks(
+        &self,
+        documents: &mut [KnowledgeDocumentRecord],
+        previous_model: Option<&str>,
+    ) -> Option<String> {
+        let model = self
+            .embeddings
+            .model_name()
+            .filter(|_| self.embeddings.is_ready())
+            .map(str::to_string);
+        let reusable = model.is_some() && model.as_deref() == previous_model;
+        let mut pending = Vec::new();
+        for (d, document) in documents.iter_mut().enumerate() {
+            for (c, chunk) in document.chunks.iter_mut().enumerate() {
+                if !reusable {
+                    chunk.embedding = None;
+                }
+                if chunk.embedding.is_none() {
+                    pending.push((d, c));
+                }
+            }
+        }
+        let model = model?;
+
+        for batch in pending.chunks(EMBEDDING_BATCH) {
+            let texts = batch
+                .iter()
+                .map(|&(d, c)| documents[d].chunks[c].text.clone())
+                .collect();
+            match self.embeddings.embed(texts).await {
+                Some(Ok(vectors)) => {
+                    for (&(d, c), vector) in batch.iter().zip(vectors) {
+                        documents[d].chunks[c].embedding = Some(vector);
+                    }
+                }
+                failed => {
+                    if let Some(Err(e)) = failed {
+                        tracing::warn!("Knowledge base embedding failed: {:#}", e);
+                    }
+                    for chunk in documents.iter_mut().flat_map(|d| d.chunks.iter_mut()) {
+                        chunk.embedding = None;
+                    }
+                    return None;
+                }
+            }
+        }
+        Some(model)
+    }
+
+    /// The chunks most similar to `query` from the active index version, by
+    /// sentence embedding when the loaded encoder embedded the index and by
+    /// term vector otherwise.
+    pub async fn search(&self, query: &str) -> Vec<SearchResult> {
+        let index = self.current_index();
+        if index.documents.is_empty() || self.settings.max_results == 0 {
+            return Vec::new();
+        }
+        let query_embedding = match index.embedding_model.as_deref() {
+            Some(model) if self.embeddings.model_name() == Some(model) => {
+                match self.embeddings.embed(vec![query.to_string()]).await {
+                    Some(Ok(mut vectors)) => vectors.pop(),
+                    Some(Err(e)) => {
+                        tracing::warn!("Query embedding failed; using term vectors: {:#}", e);
+                        None
+                    }
+                    None => None,
+                }
+            }
+            _ => None,
+        };
+        let query_vector = term_vector(query);
+        let score = |chunk: &KnowledgeChunkRecord| match (&query_embedding, &chunk.embedding) {
+            (Some(query), Some(embedding)) => embedding_similarity(query, embedding),
+            _ => vector_similarity(&query_vector, &chunk.vector),
+        };
+        let mut scored: Vec<(f32, &KnowledgeDocumentRecord, usize)> = index
+            .documents
+            .iter()
+            .flat_map(|document| {
+                let score = &score;
+                document
+                    .chunks
+                    .iter()
+                    .enumerate()
+                    .map(move |(ordinal, chunk)| (score(chunk), document, ordinal))
+            })
+            .filter(|(score, _, _)| *score >= self.settings.min_score)
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(self.settings.max_results);
+        scored
+            .into_iter()
+            .map(|(_, document, ordinal)| SearchResult {
+                title: document.title.clone(),
+                url: format!("{}#{}", document.doc_id, ordinal + 1),
+                snippet: document.chunks[ordinal].text.clone(),
+                provider: KNOWLEDGE_BASE_PROVIDER.to_string(),
+            })
+            .collect()
+    }
+
+    /// Version of the index serving retrieval, `None` before the first sync.
+    pub fn active_version(&self) -> Option<String> {
+        self.current_index().version.clone()
+    }
+
+    pub fn status(&self) -> KnowledgeSyncStatus {
+        let index = self.current_index();
+        let mut status = self
+            .status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        status.active_version = index.version.clone();
+        status.documents = index.documents.len();
+        status.chunks = index.documents.iter().map(|d| d.chunks.len()).sum();
+        status
+    }
+
+    /// Downloads one document, refusing bodies over `MAX_DOCUMENT_BYTES` like
+    /// the files of Git sources.
+    async fn fetch_url(&self, url: &str) -> Result<FetchedDocument> {
+        let mut response = self
+            .http
+            .for_url(url)
+            .get(url)
+            .timeout(FETCH_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_DOCUMENT_BYTES)
+        {
+            bail!("Document is larger than {} bytes", MAX_DOCUMENT_BYTES);
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if (body.len() + chunk.len()) as u64 > MAX_DOCUMENT_BYTES {
+                bail!("Document is larger than {} bytes", MAX_DOCUMENT_BYTES);
+            }
+            body.extend_from_slice(&chunk);
+        }
+        let text = String::from_utf8(body).context("Document is not UTF-8 text")?;
+        Ok(FetchedDocument {
+            doc_id: url.to_string(),
+            title: url.to_string(),
+            text,
+        })
+    }
+
+    /// Updates a shallow working copy of `source` (`<url>[#branch]`) and reads
+    /// its documents.
+    async fn fetch_git(&self, source: &str) -> Result<Vec<FetchedDocument>> {
+        let (url, branch) = match source.split_once('#') {
+            Some((url, branch)) => (url, Some(branch)),
+            None => (source, None),
+        };
+        let checkout_dir = Path::new(&self.settings.checkout_dir);
+        let checkout = checkout_dir.join(cache_key(&[source]));
+        if checkout.join(".git").exists() {
+            run_git(
+                &checkout,
+                &["fetch", "--depth", "1", "origin", branch.unwrap_or("HEAD")],
+            )
+            .await?;
+            run_git(&checkout, &["reset", "--hard", "FETCH_HEAD"]).await?;
+        } else {
+            // Left over from an interrupted clone.
+            if checkout.exists() {
+                tokio::fs::remove_dir_all(&checkout).await?;
+            }
+            tokio::fs::create_dir_all(checkout_dir).await?;
+            let target = checkout.to_string_lossy().to_string();
+            let mut args = vec!["clone", "--depth", "1"];
+            if let Some(branch) = branch {
+                args.extend(["--branch", branch]);
+            }
+            args.extend(["--", url, target.as_str()]);
+            run_git(checkout_dir, &args).await?;
+        }
+
+        let url = url.trim_end_matches('/').to_string();
+        tokio::task::spawn_blocking(move || read_documents(&checkout, &url)).await?
+    }
+
+    fn current_index(&self) -> Arc<KnowledgeIndex> {
+        self.index
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn update_status(&self, apply: impl FnOnce(&mut KnowledgeSyncStatus)) {
+        apply(&mut self.status.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    fn repo(&self) -> Result<KnowledgeRepo> {
+        self.repo
+            .clone()
+            .ok_or_else(|| anyhow!("Knowledge base is disabled"))
+    }
+}
+
+/// Chunks and vectorizes the fetched documents, reusing the previous
+/// version's chunks for documents whose content is unchanged. Returns the
+/// documents and how many were added, changed or removed.
+fn build_documents(
+    fetched: Vec<FetchedDocument>,
+    previous: &KnowledgeIndex,
+    chunk_chars: usize,
+) -> (Vec<KnowledgeDocumentRecord>, usize) {
+    let previous_documents: HashMap<&str, &KnowledgeDocumentRecord> = previous
+        .documents
+        .iter()
+        .map(|document| (document.doc_id.as_str(), document))
+        .collect();
+    let mut seen = HashSet::new();
+    let mut documents = Vec::new();
+    let mut changed = 0;
+    for document in fetched {
+        if !seen.insert(document.doc_id.clone()) {
+            continue;
+        }
+        let content_hash = cache_key(&[&document.text]);
+        match previous_documents.get(document.doc_id.as_str()) {
+            Some(unchanged) if unchanged.content_hash == content_hash => {
+                documents.push((*unchanged).clone());
+            }
+            _ => {
+                changed += 1;
+                let chunks = chunk_text(&document.text, chunk_chars)
+                    .into_iter()
+                    .map(|text| KnowledgeChunkRecord {
+                        vector: term_vector(&text),
+                        embedding: None,
+                        text,
+                    })
+                    .collect();
+                documents.push(KnowledgeDocumentRecord {
+                    doc_id: document.doc_id,
+                    title: document.title,
+                    content_hash,
+                    chunks,
+                });
+            }
+        }
+    }
+    changed += previous_documents
+        .keys()
+        .filter(|doc_id| !seen.contains(**doc_id))
+        .count();
+    (documents, changed)
+}
+
+/// Text documents under `root`, skipping hidden entries, identified as
+/// `<url>/<relative path>`.
+fn read_documents(root: &Path, url: &str) -> Result<Vec<FetchedDocument>> {
+    let mut documents = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let is_document = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| {
+                    DOCUMENT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+                });
+            if !file_type.is_file() || !is_document || entry.metadata()?.len() > MAX_DOCUMENT_BYTES
+            {
+                continue;
+            }
+            let Ok(text) = String::from_utf8(std::fs::read(&path)?) else {
+                continue;
+            };
+            let relative = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            documents.push(FetchedDocument {
+                doc_id: format!("{}/{}", url, relative),
+                title: relative,
+                text,
+            });
+        }
+    }
+    documents.sort_by(|a, b| a.doc_id.cmp(&b.doc_id));
+    Ok(documents)
+}
+
+async fn run_git(dir: &Path, args: &[&str]) -> Result<()> {
+    let output = tokio::time::timeout(
+        GIT_TIMEOUT,
+        Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| anyhow!("git {} timed out", args[0]))??;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
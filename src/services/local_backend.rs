@@ -0,0 +1,302 @@
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::AiConfig;
+use crate::models::{ChatContext, GenerationParams};
+use crate::services::HttpClients;
+
+/// How long a readiness probe waits for the model server.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// One local-tier generation handed to a model server.
+pub struct LocalChat<'a> {
+    /// Persona, tool and language instructions.
+    pub system_prompt: &'a str,
+    pub history: &'a ChatContext,
+    pub message: &'a str,
+    pub params: &'a GenerationParams,
+}
+
+/// A model server that answers for the local tier in place of in-process
+/// candle inference. Servers apply their own chat template, so they receive
+/// messages rather than a rendered prompt.
+#[async_trait]
+pub trait LocalBackend: Send + Sync {
+    /// The `LOCAL_BACKEND` value selecting this backend.
+    fn kind(&self) -> &'static str;
+
+    /// Model the server is asked for, when the request names one; otherwise
+    /// the server answers with the model it was started with.
+    fn model(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether the server is up with its model loaded.
+    async fn is_ready(&self) -> bool;
+
+    /// The assistant's reply to `chat`. Dropping the future abandons the
+    /// request, which is how a cancelled generation stops.
+    async fn chat(&self, chat: LocalChat<'_>) -> Result<String>;
+
+    /// The reply to a self-contained `prompt` sent as a single user turn.
+    async fn complete(&self, prompt: &str, params: &GenerationParams) -> Result<String> {
+        self.chat(LocalChat {
+            system_prompt: "",
+            history: &ChatContext::default(),
+            message: prompt,
+            params,
+        })
+        .await
+    }
+}
+
+/// The backend `LOCAL_BACKEND` selects; `None` for in-process candle inference.
+pub fn local_backend(config: &AiConfig, http: &HttpClients) -> Option<Arc<dyn LocalBackend>> {
+    match config.local_backend.as_str() {
+        "llama_cpp" => Some(Arc::new(LlamaCppBackend::new(config, http.clone()))),
+        "ollama" => Some(Arc::new(OllamaBackend::new(config, http.clone()))),
+        _ => None,
+    }
+}
+
+/// Proxies to llama.cpp's `llama-server` over its OpenAI-compatible API.
+pub struct LlamaCppBackend {
+    base_url: String,
+    api_key: Option<String>,
+    /// Sent as `model`; `llama-server` serves the model it was started with.
+    model: String,
+    top_p: f32,
+    timeout_secs: u64,
+    http: HttpClients,
+}
+
+impl LlamaCppBackend {
+    pub fn new(config: &AiConfig, http: HttpClients) -> Self {
+        Self {
+            base_url: config.llama_cpp_url.clone(),
+            api_key: config.llama_cpp_api_key.clone(),
+            model: config.model_name.clone(),
+            top_p: config.top_p,
+            timeout_secs: config.generation_timeout_secs,
+            http,
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}{}", self.base_url, path);
+        let request = self.http.for_url(&url).request(method, &url);
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+}
+
+#[async_trait]
+impl LocalBackend for LlamaCppBackend {
+    fn kind(&self) -> &'static str {
+        "llama_cpp"
+    }
+
+    async fn is_ready(&self) -> bool {
+        // `/health` answers 503 while the server is still loading its model.
+        self.request(reqwest::Method::GET, "/health")
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await
+            .is_ok_and(|response| response.status().is_success())
+    }
+
+    async fn chat(&self, chat: LocalChat<'_>) -> Result<String> {
+        let params = chat.params;
+        let mut payload = json!({
+            "model": self.model,
+            "messages": chat_messages(&chat),
+            "temperature": params.temperature,
+            "top_p": self.top_p,
+            "max_tokens": params.max_tokens,
+            "stream": false,
+        });
+        for (key, value) in sampling_options(params) {
+            payload[key] = value;
+        }
+
+        let request = self
+            .request(reqwest::Method::POST, "/v1/chat/completions")
+            .json(&payload);
+        let body = send(request, self.timeout_secs, "llama-server").await?;
+        body.get("choices")
+            .and_then(|choices| choices.get(0))
+            .and_then(|choice| choice.pointer("/message/content"))
+            .and_then(|content| content.as_str())
+            .map(|content| content.trim().to_string())
+            .ok_or_else(|| anyhow!("llama-server returned no message"))
+    }
+}
+
+/// Proxies to an Ollama daemon's `/api/chat`, reusing the models it has pulled.
+pub struct OllamaBackend {
+    base_url: String,
+    model: String,
+    context_length: usize,
+    top_p: f32,
+    timeout_secs: u64,
+    http: HttpClients,
+}
+
+impl OllamaBackend {
+    pub fn new(config: &AiConfig, http: HttpClients) -> Self {
+        Self {
+            base_url: config.ollama_url.clone(),
+            model: config.ollama_model.clone(),
+            context_length: config.context_length,
+            top_p: config.top_p,
+            timeout_secs: config.generation_timeout_secs,
+            http,
+        }
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let url = format!("{}{}", self.base_url, path);
+        self.http.for_url(&url).request(method, &url)
+    }
+}
+
+#[async_trait]
+impl LocalBackend for OllamaBackend {
+    fn kind(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    async fn is_ready(&self) -> bool {
+        // `/api/show` fails until the model has been pulled.
+        self.request(reqwest::Method::POST, "/api/show")
+            .json(&json!({"model": self.model}))
+            .timeout(PROBE_TIMEOUT)
+            .send()
+            .await
+            .is_ok_and(|response| response.status().is_success())
+    }
+
+    async fn chat(&self, chat: LocalChat<'_>) -> Result<String> {
+        let params = chat.params;
+        let mut options = json!({
+            "temperature": params.temperature,
+            "top_p": self.top_p,
+            "num_predict": params.max_tokens,
+            // The window history was fitted to, not the daemon's default.
+            "num_ctx": self.context_length,
+        });
+        for (key, value) in sampling_options(params) {
+            options[key] = value;
+        }
+        let payload = json!({
+            "model": self.model,
+            "messages": chat_messages(&chat),
+            "stream": false,
+            "options": options,
+        });
+
+        let request = self
+            .request(reqwest::Method::POST, "/api/chat")
+            .json(&payload);
+        let body = send(request, self.timeout_secs, "Ollama").await?;
+        body.pointer("/message/content")
+            .and_then(|content| content.as_str())
+            .map(|content| content.trim().to_string())
+            .ok_or_else(|| anyhow!("Ollama returned no message"))
+    }
+}
+
+/// Sampling controls set on `params`, under the names llama.cpp and Ollama
+/// share. Unset ones are left out so server defaults apply.
+fn sampling_options(params: &GenerationParams) -> Vec<(&'static str, Value)> {
+    [
+        (
+            "stop",
+            (!params.stop.is_empty()).then(|| json!(params.stop)),
+        ),
+        ("top_k", params.top_k.map(|v| json!(v))),
+        (
+            "repeat_penalty",
+            params.repetition_penalty.map(|v| json!(v)),
+        ),
+        (
+            "frequency_penalty",
+            params.frequency_penalty.map(|v| json!(v)),
+        ),
+        (
+            "presence_penalty",
+            params.presence_penalty.map(|v| json!(v)),
+        ),
+        ("seed", params.seed.map(|v| json!(v))),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| value.map(|value| (key, value)))
+    .collect()
+}
+
+/// Sends `request` within `timeout_secs` (0 = none) and returns the JSON
+/// body of a successful reply.
+async fn send(
+    mut request: reqwest::RequestBuilder,
+    timeout_secs: u64,
+    server: &str,
+) -> Result<Value> {
+    if timeout_secs > 0 {
+        request = request.timeout(Duration::from_secs(timeout_secs));
+    }
+    let response = request.send().await.map_err(|e| {
+        if e.is_timeout() {
+            anyhow!("generation exceeded {}s", timeout_secs)
+        } else {
+            anyhow!("{} is unreachable: {}", server, e)
+        }
+    })?;
+    let status = response.status();
+    let text = response.text().await?;
+    let body = serde_json::from_str(&text).unwrap_or(Value::String(text));
+    if !status.is_success() {
+        bail!("{} returned {}: {}", server, status, body);
+    }
+    Ok(body)
+}
+
+/// OpenAI-style messages for `chat`: the system prompt with any rolling
+/// summary, the kept turns, then the new message.
+pub fn chat_messages(chat: &LocalChat<'_>) -> Vec<Value> {
+    let mut system_prompt = chat.system_prompt.trim().to_string();
+    if let Some(summary) = chat
+        .history
+        .summary
+        .as_deref()
+        .filter(|summary| !summary.trim().is_empty())
+    {
+        system_prompt.push_str(&format!(
+            "\n\nSummary of the earlier conversation:\n{}",
+            summary.trim()
+        ));
+    }
+
+    let mut messages = Vec::with_capacity(chat.history.turns.len() + 2);
+    if !system_prompt.is_empty() {
+        messages.push(json!({"role": "system", "content": system_prompt}));
+    }
+    for turn in &chat.history.turns {
+        let role = if turn.role == "assistant" {
+            "assistant"
+        } else {
+            "user"
+        };
+        messages.push(json!({"role": role, "content": turn.content.trim()}));
+    }
+    messages.push(json!({"role": "user", "content": chat.message}));
+    messages
+}
@@ -0,0 +1,78 @@
+use anyhow::{anyhow, Result};
+
+use crate::config::LogStoreSettings;
+use crate::repositories::LogStoreRepo;
+use crate::utils::{cache_key, LogDigest, LOG_DIGEST_VERSION};
+
+/// A parsed upload and whether it came from the store.
+pub struct StoredDigest {
+    pub content_hash: String,
+    pub digest: LogDigest,
+    pub reused: bool,
+}
+
+/// Content-addressed store of parsed log uploads, so retried analyses of the
+/// same file skip parsing. Only digests are kept, never the raw logs.
+#[derive(Clone)]
+pub struct LogStoreService {
+    repo: Option<LogStoreRepo>,
+}
+
+impl LogStoreService {
+    pub fn new(settings: &LogStoreSettings) -> Self {
+        let repo = if settings.sqlite_path.trim().is_empty() {
+            None
+        } else {
+            match LogStoreRepo::new(settings.sqlite_path.clone(), settings.retention_days) {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    tracing::warn!("Log digest store disabled: {}", e);
+                    None
+                }
+            }
+        };
+        Self { repo }
+    }
+
+    /// Returns the stored digest for `logs`, parsing and storing it on a miss.
+    /// Storage failures fall back to parsing without reuse.
+    pub async fn digest(&self, logs: &str) -> Result<StoredDigest> {
+        let content_hash = cache_key(&[LOG_DIGEST_VERSION, logs]);
+        let repo = self.repo.clone();
+        let logs = logs.to_string();
+        tokio::task::spawn_blocking(move || {
+            if let Some(repo) = repo.as_ref() {
+                match repo.get(&content_hash) {
+                    Ok(Some(json)) => match serde_json::from_str(&json) {
+                        Ok(digest) => {
+                            return Ok(StoredDigest {
+                                content_hash,
+                                digest,
+                                reused: true,
+                            })
+                        }
+                        Err(e) => tracing::warn!("Discarding unreadable log digest: {}", e),
+                    },
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Log digest lookup failed: {}", e),
+                }
+            }
+
+            let digest = LogDigest::parse(&logs);
+            if let Some(repo) = repo.as_ref() {
+                let stored = serde_json::to_string(&digest)
+                    .map_err(|e| anyhow!(e))
+                    .and_then(|json| repo.put(&content_hash, logs.len(), &json));
+                if let Err(e) = stored {
+                    tracing::warn!("Failed to store log digest: {}", e);
+                }
+            }
+            Ok(StoredDigest {
+                content_hash,
+                digest,
+                reused: false,
+            })
+        })
+        .await?
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::config::LoopGuardSettings;
+use crate::utils::cache_key;
+
+const WINDOW: Duration = Duration::from_secs(60);
+/// Conversations idle for longer are forgotten.
+const IDLE_EXPIRY: Duration = Duration::from_secs(600);
+/// Recent answers kept per conversation for echo detection.
+const RECENT_ANSWERS: usize = 4;
+/// Tracked conversations above which idle ones are swept on the next check.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+#[derive(Default)]
+struct ConversationActivity {
+    accepted: VecDeque<Instant>,
+    answer_hashes: VecDeque<String>,
+    consecutive_echoes: u32,
+    last_seen: Option<Instant>,
+}
+
+/// Why a conversation was refused.
+#[derive(Debug, Clone)]
+pub enum LoopDetected {
+    RateExceeded {
+        limit: u32,
+    },
+    /// The client kept sending the conversation's own answers back.
+    Echo {
+        repeats: u32,
+    },
+}
+
+impl fmt::Display for LoopDetected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoopDetected::RateExceeded { limit } => {
+                write!(
+                    f,
+                    "More than {} messages per minute in this conversation",
+                    limit
+                )
+            }
+            LoopDetected::Echo { repeats } => write!(
+                f,
+                "{} consecutive messages repeated an earlier answer of this conversation",
+                repeats
+            ),
+        }
+    }
+}
+
+/// Refuses messages from conversations that look like a runaway automated loop:
+/// too many messages per minute, or the model's answers fed back as input.
+/// State is per process and kept in memory.
+#[derive(Clone)]
+pub struct LoopGuardService {
+    settings: LoopGuardSettings,
+    conversations: Arc<Mutex<HashMap<Uuid, ConversationActivity>>>,
+}
+
+impl LoopGuardService {
+    pub fn new(settings: LoopGuardSettings) -> Self {
+        Self {
+            settings,
+            conversations: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Admits an incoming message, counting it toward the conversation's rate
+    /// only when it is accepted.
+    pub fn check(
+        &self,
+        conversation_id: Uuid,
+        tenant: Option<&str>,
+        message: &str,
+    ) -> Result<(), LoopDetected> {
+        let limit = tenant
+            .and_then(|tenant| self.settings.tenant_limits.get(tenant))
+            .copied()
+            .unwrap_or(self.settings.max_messages_per_minute);
+        let now = Instant::now();
+        let mut conversations = self
+            .conversations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if conversations.len() > SWEEP_THRESHOLD {
+            conversations.retain(|_, activity| {
+                activity
+                    .last_seen
+                    .is_some_and(|seen| now.duration_since(seen) < IDLE_EXPIRY)
+            });
+        }
+
+        let activity = conversations.entry(conversation_id).or_default();
+        activity.last_seen = Some(now);
+        while activity
+            .accepted
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= WINDOW)
+        {
+            activity.accepted.pop_front();
+        }
+
+        if self.settings.echo_threshold > 0 {
+            if activity.answer_hashes.contains(&fingerprint(message)) {
+                activity.consecutive_echoes += 1;
+            } else {
+                activity.consecutive_echoes = 0;
+            }
+            if activity.consecutive_echoes >= self.settings.echo_threshold {
+                return Err(LoopDetected::Echo {
+                    repeats: activity.consecutive_echoes,
+                });
+            }
+        }
+        if limit > 0 && activity.accepted.len() >= limit as usize {
+            return Err(LoopDetected::RateExceeded { limit });
+        }
+
+        activity.accepted.push_back(now);
+        Ok(())
+    }
+
+    /// Remembers an answer so it can be recognized if it comes back as input.
+    pub fn record_answer(&self, conversation_id: Uuid, answer: &str) {
+        if self.settings.echo_threshold == 0 || answer.trim().is_empty() {
+            return;
+        }
+        let mut conversations = self
+            .conversations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let activity = conversations.entry(conversation_id).or_default();
+        activity.last_seen = Some(Instant::now());
+        activity.answer_hashes.push_back(fingerprint(answer));
+        if activity.answer_hashes.len() > RECENT_ANSWERS {
+            activity.answer_hashes.pop_front();
+        }
+    }
+}
+
+/// Case- and whitespace-insensitive hash, so reformatted echoes still match.
+fn fingerprint(text: &str) -> String {
+    let normalized = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    cache_key(&[&normalized])
+}
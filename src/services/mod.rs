@@ -1,9 +1,67 @@
+pub mod admission_service;
+pub mod agent_service;
 pub mod ai_service;
+pub mod alert_service;
+pub mod cache_namespace_service;
 pub mod cache_service;
+pub mod capture_service;
+pub mod coalescing_service;
+pub mod conversation_memory;
+pub mod conversation_service;
+pub mod embedding_service;
+pub mod feedback_service;
+pub mod grounding_service;
+pub mod guardrail_service;
+pub mod handoff_service;
+pub mod http_clients;
+pub mod inference_pool;
+pub mod inflight_service;
+pub mod injection_service;
+pub mod knowledge_service;
+pub mod local_backend;
+pub mod log_store_service;
+pub mod loop_guard_service;
+pub mod model_registry;
 pub mod model_service;
+pub mod routing_metrics_service;
+pub mod sandbox_service;
 pub mod search_service;
+pub mod spend_service;
+pub mod stream_service;
+pub mod task_registry;
+pub mod topic_service;
+pub mod trial_service;
 
+pub use admission_service::*;
+pub use agent_service::*;
 pub use ai_service::*;
+pub use alert_service::*;
+pub use cache_namespace_service::*;
 pub use cache_service::*;
+pub use capture_service::*;
+pub use coalescing_service::*;
+pub use conversation_memory::*;
+pub use conversation_service::*;
+pub use embedding_service::*;
+pub use feedback_service::*;
+pub use grounding_service::*;
+pub use guardrail_service::*;
+pub use handoff_service::*;
+pub use http_clients::*;
+pub use inference_pool::*;
+pub use inflight_service::*;
+pub use injection_service::*;
+pub use knowledge_service::*;
+pub use local_backend::*;
+pub use log_store_service::*;
+pub use loop_guard_service::*;
+pub use model_registry::*;
 pub use model_service::*;
+pub use routing_metrics_service::*;
+pub use sandbox_service::*;
 pub use search_service::*;
+pub use spend_service::*;
+pub use stream_service::*;
+pub use task_registry::*;
+pub use topic_service::*;
+pub use trial_service::*;
@@ -0,0 +1,290 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, OwnedRwLockReadGuard, RwLock};
+
+use crate::config::AiConfig;
+use crate::models::{local_weights_size, AIModel, LocalModelInfo};
+use crate::services::TaskRegistry;
+
+/// Longest wait between checks for idle models.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+struct NamedModel {
+    model_name: String,
+    config: AiConfig,
+    model: Arc<RwLock<AIModel>>,
+}
+
+/// The default local model plus the named ones from `LOCAL_MODELS`, which a
+/// chat request selects with `model`, and the default model with each
+/// `LORA_ADAPTERS` entry merged in, selected with `adapter`. Named models and
+/// adapters are loaded on first use and then stay in memory, unless
+/// `MODEL_IDLE_UNLOAD_SECS` unloads them. Each adapter model is a full copy
+/// of the default model's weights, so at most `LORA_MAX_LOADED` are loaded at
+/// once and loading another unloads the least recently used.
+#[derive(Clone)]
+pub struct ModelRegistry {
+    default: Arc<RwLock<AIModel>>,
+    named: Arc<HashMap<String, NamedModel>>,
+    adapted: Arc<HashMap<String, Arc<RwLock<AIModel>>>>,
+    max_loaded_adapters: usize,
+    /// Held while an adapter model loads, so loads make room one at a time.
+    adapter_loads: Arc<Mutex<()>>,
+    /// Precision of the default model at startup, reported for a model that
+    /// is being loaded.
+    precision: &'static str,
+}
+
+impl ModelRegistry {
+    pub fn new(default: Arc<RwLock<AIModel>>, config: &AiConfig) -> Self {
+        let named = config
+            .local_models
+            .iter()
+            .map(|local| {
+                // The draft model is paired with the default model's vocabulary.
+                let model_config = AiConfig {
+                    model_name: local.model_name.clone(),
+                    model_path: local.model_path.clone(),
+                    draft_model_name: None,
+                    draft_model_path: None,
+                    ..config.clone()
+                };
+                (
+                    local.name.clone(),
+                    NamedModel {
+                        model_name: local.model_name.clone(),
+                        model: Arc::new(RwLock::new(AIModel::new(model_config.clone()))),
+                        config: model_config,
+                    },
+                )
+            })
+            .collect();
+        let adapted = config
+            .lora_adapters
+            .iter()
+            .map(|adapter| {
+                let mut lora_merge = config.lora_merge.clone();
+                if !lora_merge.contains(&adapter.name) {
+                    lora_merge.push(adapter.name.clone());
+                }
+                let model_config = AiConfig {
+                    lora_merge,
+                    ..config.clone()
+                };
+                (
+                    adapter.name.clone(),
+                    Arc::new(RwLock::new(AIModel::new(model_config))),
+                )
+            })
+            .collect();
+        // Nothing else holds the lock yet at startup.
+        let precision = default
+            .try_read()
+            .map(|model| model.precision())
+            .unwrap_or("f32");
+        Self {
+            default,
+            named: Arc::new(named),
+            adapted: Arc::new(adapted),
+            max_loaded_adapters: config.lora_max_loaded.max(1),
+            adapter_loads: Arc::new(Mutex::new(())),
+            precision,
+        }
+    }
+
+    /// Whether `name` selects a registered local model rather than a cloud one.
+    pub fn is_local(&self, name: Option<&str>) -> bool {
+        name.is_some_and(|name| self.named.contains_key(name))
+    }
+
+    /// Names requests can select, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.named.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Adapter names requests can select, sorted.
+    pub fn adapters(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.adapted.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Whether `name` is an adapter from `LORA_ADAPTERS`.
+    pub fn has_adapter(&self, name: &str) -> bool {
+        self.adapted.contains_key(name)
+    }
+
+    /// Hugging Face id behind a registered name.
+    pub fn model_name(&self, name: &str) -> Option<&str> {
+        self.named.get(name).map(|named| named.model_name.as_str())
+    }
+
+    /// The model `name` selects, the default one without a name, as is:
+    /// named models may not be loaded yet.
+    pub fn get(&self, name: Option<&str>) -> Option<Arc<RwLock<AIModel>>> {
+        match name {
+            Some(name) => self.named.get(name).map(|named| named.model.clone()),
+            None => Some(self.default.clone()),
+        }
+    }
+
+    /// The default model (running `default_config`'s model) followed by the
+    /// named ones in name order.
+    pub fn describe(&self, default_config: &AiConfig) -> Vec<LocalModelInfo> {
+        let mut models = vec![LocalModelInfo {
+            name: None,
+            model_name: default_config.model_name.clone(),
+            default: true,
+            size_bytes: local_weights_size(default_config),
+            quantization: precision(&self.default, self.precision).to_string(),
+            loaded: is_loaded(&self.default),
+        }];
+        for name in self.names() {
+            let named = &self.named[&name];
+            models.push(LocalModelInfo {
+                name: Some(name.clone()),
+                model_name: named.model_name.clone(),
+                default: false,
+                size_bytes: local_weights_size(&named.config),
+                quantization: precision(&named.model, self.precision).to_string(),
+                loaded: is_loaded(&named.model),
+            });
+        }
+        models
+    }
+
+    /// The model `name` selects, loaded and read-locked for a generation;
+    /// the default model for any other name. Named models load on first use
+    /// and models unloaded for idleness load again. The default model is
+    /// otherwise left to the startup loader.
+    pub async fn acquire(&self, name: Option<&str>) -> Result<OwnedRwLockReadGuard<AIModel>> {
+        match name.and_then(|name| self.named.get(name)) {
+            Some(named) => acquire_model(&named.model, true).await,
+            None => acquire_model(&self.default, false).await,
+        }
+    }
+
+    /// The default model with `adapter` merged in, loaded on first use and
+    /// read-locked for a generation. Loading it may first unload the least
+    /// recently used adapter models.
+    pub async fn acquire_adapter(&self, adapter: &str) -> Result<OwnedRwLockReadGuard<AIModel>> {
+        let model = self
+            .adapted
+            .get(adapter)
+            .ok_or_else(|| anyhow::anyhow!("Unknown LoRA adapter {}", adapter))?;
+        let reader = model.clone().read_owned().await;
+        if reader.is_ready() {
+            return Ok(reader);
+        }
+        drop(reader);
+        let _loading = self.adapter_loads.lock().await;
+        self.evict_adapters(adapter).await;
+        acquire_model(model, true).await
+    }
+
+    /// Unloads the least recently used adapter models other than `keep`
+    /// until loading `keep` stays within `LORA_MAX_LOADED`. Waits for
+    /// generations still running on a model before unloading it.
+    async fn evict_adapters(&self, keep: &str) {
+        let mut loaded: Vec<(Duration, &Arc<RwLock<AIModel>>)> = self
+            .adapted
+            .iter()
+            .filter(|(name, _)| name.as_str() != keep)
+            .filter_map(|(_, model)| {
+                let model_ref = model.try_read().ok()?;
+                model_ref.is_ready().then(|| (model_ref.idle_for(), model))
+            })
+            .collect();
+        let excess = (loaded.len() + 1).saturating_sub(self.max_loaded_adapters);
+        loaded.sort_by_key(|(idle, _)| std::cmp::Reverse(*idle));
+        for (_, model) in loaded.into_iter().take(excess) {
+            let mut model = model.write().await;
+            let weights = model.unload();
+            drop(model);
+            let _ = tokio::task::spawn_blocking(move || drop(weights)).await;
+        }
+    }
+
+    /// Unloads every model that has not generated for `idle`. A model in
+    /// use holds a read lock, so it is never unloaded mid-generation.
+    /// Returns how many were unloaded.
+    pub async fn unload_idle(&self, idle: Duration) -> usize {
+        let models = std::iter::once(&self.default)
+            .chain(self.named.values().map(|named| &named.model))
+            .chain(self.adapted.values());
+        let mut unloaded = 0;
+        for model in models {
+            let Ok(mut model) = model.try_write() else {
+                continue;
+            };
+            if !model.is_ready() || model.idle_for() < idle {
+                continue;
+            }
+            let weights = model.unload();
+            drop(model);
+            // Freeing gigabytes of weights can take a while; keep it off the async workers.
+            let _ = tokio::task::spawn_blocking(move || drop(weights)).await;
+            unloaded += 1;
+        }
+        unloaded
+    }
+
+    /// Runs [`ModelRegistry::unload_idle`] as the `model_idle_unloader` task.
+    pub fn spawn_idle_unloader(&self, tasks: &TaskRegistry, idle: Duration) {
+        let registry = self.clone();
+        tasks.spawn("model_idle_unloader", move |handle| {
+            let registry = registry.clone();
+            async move {
+                let mut interval = tokio::time::interval(idle.min(IDLE_CHECK_INTERVAL));
+                loop {
+                    interval.tick().await;
+                    registry.unload_idle(idle).await;
+                    handle.ran();
+                }
+            }
+        });
+    }
+}
+
+/// `model` read-locked once it is loaded. With `load_on_use` it is loaded
+/// here; otherwise only a model unloaded for idleness is.
+async fn acquire_model(
+    model: &Arc<RwLock<AIModel>>,
+    load_on_use: bool,
+) -> Result<OwnedRwLockReadGuard<AIModel>> {
+    loop {
+        let reader = model.clone().read_owned().await;
+        if reader.is_ready() || !(load_on_use || reader.is_idle_unloaded()) {
+            return Ok(reader);
+        }
+        drop(reader);
+        // Holding the write lock makes concurrent requests wait for one load.
+        let mut writer = model.clone().write_owned().await;
+        if !writer.is_ready() {
+            let model_name = writer.model_name().to_string();
+            // Reading and converting the weights blocks for seconds; keep it
+            // off the async workers, which keep serving other requests.
+            let runtime = tokio::runtime::Handle::current();
+            tokio::task::spawn_blocking(move || runtime.block_on(writer.load_model()))
+                .await?
+                .map_err(|e| e.context(format!("Failed to load model {}", model_name)))?;
+        }
+    }
+}
+
+/// Generations only read the model; the lock is held while it is loaded or
+/// swapped, when it is reported as not loaded.
+fn is_loaded(model: &RwLock<AIModel>) -> bool {
+    model.try_read().is_ok_and(|model| model.is_ready())
+}
+
+fn precision(model: &RwLock<AIModel>, busy: &'static str) -> &'static str {
+    model
+        .try_read()
+        .map(|model| model.precision())
+        .unwrap_or(busy)
+}
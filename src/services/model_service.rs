@@ -1,24 +1,216 @@
-use crate::models::ChatRequest;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::{Arc, OnceLock};
 
-#[derive(Debug, Clone, Copy)]
-pub enum Complexity {
-    Low,
-    Medium,
-    High,
+use crate::config::AiConfig;
+use crate::models::{ChatRequest, Complexity, ComplexityScore};
+use crate::utils::detect_language;
+
+/// Fenced code blocks that add to the score; more add nothing.
+const MAX_SCORED_CODE_BLOCKS: usize = 3;
+/// Questions that add to the score; the first is free.
+const MAX_SCORED_QUESTIONS: usize = 5;
+
+/// Content features a message is routed by.
+#[derive(Debug, Clone)]
+pub struct MessageSignals {
+    /// Length in characters.
+    pub length: usize,
+    /// A stack trace or traceback was pasted in.
+    pub stack_trace: bool,
+    /// Fenced code blocks; an unclosed fence counts as one.
+    pub code_blocks: usize,
+    pub questions: usize,
+    /// The message's language, when reliably detected and not English.
+    pub language: Option<&'static str>,
+}
+
+impl MessageSignals {
+    pub fn of(message: &str) -> Self {
+        Self {
+            length: message.chars().count(),
+            stack_trace: stack_trace_pattern().is_match(message),
+            code_blocks: message.matches("```").count().div_ceil(2),
+            questions: question_pattern().find_iter(message).count(),
+            language: detect_language(message).filter(|language| *language != "English"),
+        }
+    }
 }
 
-#[derive(Default, Clone)]
-pub struct ModelService;
+fn stack_trace_pattern() -> &'static Regex {
+    static STACK_TRACE: OnceLock<Regex> = OnceLock::new();
+    STACK_TRACE.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?m)Traceback \(most recent call last\)",
+            r#"|^\s+File ".+", line \d+"#,
+            r"|^\s+at \S+ ?\(.*:\d+(:\d+)?\)\s*$",
+            r"|^\s+at .+ in .+:line \d+",
+            r#"|Exception in thread ""#,
+            r"|thread '.*' panicked at",
+            r"|^goroutine \d+ \[",
+        ))
+        .expect("valid stack trace pattern")
+    })
+}
 
-impl ModelService {
-    pub fn analyze_complexity(&self, request: &ChatRequest) -> Complexity {
-        let length = request.message.chars().count();
-        if length < 200 {
+fn question_pattern() -> &'static Regex {
+    static QUESTIONS: OnceLock<Regex> = OnceLock::new();
+    QUESTIONS.get_or_init(|| Regex::new(r"[?？؟]+").expect("valid question pattern"))
+}
+
+/// Logistic regression over [`MessageSignals`], read from the JSON file at
+/// `COMPLEXITY_CLASSIFIER_PATH`. The probability it gives that a message
+/// needs a stronger tier is compared with `medium` and `high`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ComplexityClassifier {
+    #[serde(default)]
+    bias: f32,
+    #[serde(default)]
+    weights: ClassifierWeights,
+    medium: f32,
+    high: f32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ClassifierWeights {
+    /// Per 1000 characters.
+    length: f32,
+    stack_trace: f32,
+    code_blocks: f32,
+    questions: f32,
+    non_english: f32,
+}
+
+impl ComplexityClassifier {
+    pub fn load(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read complexity classifier {}", path))?;
+        let classifier: Self = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid complexity classifier {}", path))?;
+        if !(0.0..=1.0).contains(&classifier.medium)
+            || !(classifier.medium..=1.0).contains(&classifier.high)
+        {
+            bail!("Classifier cutoffs must satisfy 0 <= medium <= high <= 1");
+        }
+        Ok(classifier)
+    }
+
+    fn probability(&self, signals: &MessageSignals) -> f32 {
+        let weights = &self.weights;
+        let z = self.bias
+            + weights.length * signals.length as f32 / 1000.0
+            + weights.stack_trace * f32::from(u8::from(signals.stack_trace))
+            + weights.code_blocks * signals.code_blocks as f32
+            + weights.questions * signals.questions as f32
+            + weights.non_english * f32::from(u8::from(signals.language.is_some()));
+        1.0 / (1.0 + (-z).exp())
+    }
+
+    fn tier(&self, probability: f32) -> Complexity {
+        if probability < self.medium {
             Complexity::Low
-        } else if length < 800 {
+        } else if probability < self.high {
             Complexity::Medium
         } else {
             Complexity::High
         }
     }
 }
+
+/// Classifies requests by a score: message length in characters plus a
+/// weight for each content signal found, compared with the thresholds. A
+/// configured classifier decides the tier instead.
+#[derive(Clone)]
+pub struct ModelService {
+    medium_threshold: usize,
+    high_threshold: usize,
+    stack_trace_weight: usize,
+    code_block_weight: usize,
+    question_weight: usize,
+    language_weight: usize,
+    classifier: Option<Arc<ComplexityClassifier>>,
+}
+
+impl ModelService {
+    pub fn new(config: &AiConfig) -> Self {
+        let classifier = config
+            .complexity_classifier_path
+            .as_deref()
+            .and_then(|path| match ComplexityClassifier::load(path) {
+                Ok(classifier) => Some(Arc::new(classifier)),
+                Err(e) => {
+                    tracing::warn!("Routing by weighted score only: {:#}", e);
+                    None
+                }
+            });
+        Self {
+            medium_threshold: config.complexity_medium_threshold,
+            high_threshold: config.complexity_high_threshold,
+            stack_trace_weight: config.complexity_stack_trace_weight,
+            code_block_weight: config.complexity_code_block_weight,
+            question_weight: config.complexity_question_weight,
+            language_weight: config.complexity_language_weight,
+            classifier,
+        }
+    }
+
+    /// The tier for `request`, with how it was scored.
+    pub fn analyze_complexity(&self, request: &ChatRequest) -> (Complexity, ComplexityScore) {
+        let signals = MessageSignals::of(&request.message);
+
+        let mut breakdown = BTreeMap::from([("length".to_string(), signals.length)]);
+        let weighted = [
+            (
+                "stack_trace",
+                self.stack_trace_weight * usize::from(signals.stack_trace),
+            ),
+            (
+                "code_blocks",
+                self.code_block_weight * signals.code_blocks.min(MAX_SCORED_CODE_BLOCKS),
+            ),
+            (
+                "questions",
+                self.question_weight
+                    * signals
+                        .questions
+                        .min(MAX_SCORED_QUESTIONS)
+                        .saturating_sub(1),
+            ),
+            (
+                "language",
+                self.language_weight * usize::from(signals.language.is_some()),
+            ),
+        ];
+        for (signal, weight) in weighted {
+            if weight > 0 {
+                breakdown.insert(signal.to_string(), weight);
+            }
+        }
+        let score = breakdown.values().sum();
+
+        let (complexity, classifier_probability) = match &self.classifier {
+            Some(classifier) => {
+                let probability = classifier.probability(&signals);
+                (classifier.tier(probability), Some(probability))
+            }
+            None if score < self.medium_threshold => (Complexity::Low, None),
+            None if score < self.high_threshold => (Complexity::Medium, None),
+            None => (Complexity::High, None),
+        };
+
+        let score = ComplexityScore {
+            score,
+            signals: breakdown,
+            medium_threshold: self.medium_threshold,
+            high_threshold: self.high_threshold,
+            language: signals.language.map(str::to_string),
+            classifier_probability,
+        };
+        (complexity, score)
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::config::ConversationSettings;
+use crate::models::{ChatResponse, Complexity};
+use crate::repositories::{
+    ModelOutcomeRecord, ReadPool, RoutingDecisionRecord, RoutingRepo, RoutingStatsRecord,
+};
+
+/// Records complexity routing decisions and their outcomes (negative feedback,
+/// escalations) so thresholds can be tuned from data. Shares the conversation store.
+#[derive(Clone)]
+pub struct RoutingMetricsService {
+    repo: Option<RoutingRepo>,
+}
+
+impl RoutingMetricsService {
+    pub fn new(settings: &ConversationSettings) -> Self {
+        let repo = if settings.sqlite_path.trim().is_empty() {
+            None
+        } else {
+            let reporting_path = settings
+                .analytics_replica_path
+                .clone()
+                .unwrap_or_else(|| settings.sqlite_path.clone());
+            let reporting = ReadPool::new(reporting_path, settings.analytics_pool_size);
+            match RoutingRepo::new(settings.sqlite_path.clone(), reporting) {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    tracing::warn!("Routing metrics disabled: {}", e);
+                    None
+                }
+            }
+        };
+        Self { repo }
+    }
+
+    /// Records a generated (non-cached) answer's tier, model, latency and cost.
+    /// Failures are only logged.
+    pub async fn record(
+        &self,
+        conversation_id: Uuid,
+        complexity: Complexity,
+        forced: bool,
+        message_chars: usize,
+        answer: &ChatResponse,
+        duration_ms: u64,
+    ) {
+        let Some(repo) = self.repo.clone() else {
+            return;
+        };
+        let conversation_id = conversation_id.to_string();
+        let decision = RoutingDecisionRecord {
+            complexity: complexity.as_str().to_string(),
+            forced,
+            message_chars,
+            model: answer.model.clone(),
+            duration_ms,
+            cost_usd: answer.cost_usd,
+            response_hash: answer.response_hash.clone(),
+        };
+        let result =
+            tokio::task::spawn_blocking(move || repo.record(&conversation_id, &decision)).await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to record routing decision: {}", e),
+            Err(e) => tracing::warn!("Routing metrics task failed: {}", e),
+        }
+    }
+
+    /// Returns `Ok(false)` when the conversation has no recorded decision.
+    pub async fn mark_negative_feedback(&self, conversation_id: Uuid) -> Result<bool> {
+        let repo = self.repo()?;
+        let conversation_id = conversation_id.to_string();
+        tokio::task::spawn_blocking(move || repo.mark_negative_feedback(&conversation_id)).await?
+    }
+
+    pub async fn mark_escalated(&self, conversation_id: Uuid) {
+        let Some(repo) = self.repo.clone() else {
+            return;
+        };
+        let conversation_id = conversation_id.to_string();
+        let result =
+            tokio::task::spawn_blocking(move || repo.mark_escalated(&conversation_id)).await;
+        match result {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to flag routing escalation: {}", e),
+            Err(e) => tracing::warn!("Routing metrics task failed: {}", e),
+        }
+    }
+
+    pub async fn stats_since(&self, since: DateTime<Utc>) -> Result<Vec<RoutingStatsRecord>> {
+        let repo = self.repo()?;
+        tokio::task::spawn_blocking(move || repo.stats_since(since)).await?
+    }
+
+    pub async fn model_outcomes_since(
+        &self,
+        model: &str,
+        since: DateTime<Utc>,
+    ) -> Result<ModelOutcomeRecord> {
+        let repo = self.repo()?;
+        let model = model.to_string();
+        tokio::task::spawn_blocking(move || repo.model_outcomes_since(&model, since)).await?
+    }
+
+    fn repo(&self) -> Result<RoutingRepo> {
+        self.repo
+            .clone()
+            .ok_or_else(|| anyhow!("Routing metrics are disabled"))
+    }
+}
@@ -0,0 +1,299 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+
+use crate::config::SandboxSettings;
+use crate::models::CodeExecution;
+
+/// Runs short Python snippets from model answers in a bubblewrap sandbox.
+///
+/// Each snippet gets no network, a read-only root, a cleared environment, a scratch
+/// working directory, rlimits on CPU, memory, file size, open files and processes,
+/// and its own process group, which is killed when the snippet ends.
+#[derive(Clone)]
+pub struct SandboxService {
+    settings: SandboxSettings,
+}
+
+struct Snippet {
+    language: String,
+    code: String,
+}
+
+impl SandboxService {
+    pub fn new(settings: SandboxSettings) -> Self {
+        Self { settings }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.enabled
+    }
+
+    /// Executes the runnable fenced code blocks in `answer` and returns their results.
+    pub async fn execute_snippets(&self, answer: &str) -> Vec<CodeExecution> {
+        let mut executions = Vec::new();
+        for snippet in extract_snippets(answer)
+            .into_iter()
+            .filter(|snippet| snippet.code.chars().count() <= self.settings.max_snippet_chars)
+            .take(self.settings.max_snippets)
+        {
+            match self.run(&snippet).await {
+                Ok(execution) => executions.push(execution),
+                Err(e) => tracing::warn!("Sandbox execution failed: {}", e),
+            }
+        }
+        executions
+    }
+
+    async fn run(&self, snippet: &Snippet) -> Result<CodeExecution> {
+        let interpreter = &self.settings.python_path;
+        let workdir =
+            std::env::temp_dir().join(format!("selfcare-sandbox-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&workdir).await?;
+        let script = workdir.join("snippet");
+        tokio::fs::write(&script, &snippet.code).await?;
+
+        let result = self.spawn(interpreter, &script, &workdir).await;
+        let _ = tokio::fs::remove_dir_all(&workdir).await;
+        let (output, timed_out, elapsed) = result?;
+
+        let (stdout, stderr, exit_code) = match output {
+            Some(output) => (
+                truncate_output(&output.stdout, self.settings.max_output_bytes),
+                truncate_output(&output.stderr, self.settings.max_output_bytes),
+                output.status.code(),
+            ),
+            None => (String::new(), String::new(), None),
+        };
+
+        Ok(CodeExecution {
+            language: snippet.language.clone(),
+            code: snippet.code.clone(),
+            stdout,
+            stderr,
+            exit_code,
+            timed_out,
+            duration_ms: elapsed.as_millis() as u64,
+        })
+    }
+
+    async fn spawn(
+        &self,
+        interpreter: &str,
+        script: &Path,
+        workdir: &Path,
+    ) -> Result<(Option<std::process::Output>, bool, Duration)> {
+        let mut command = Command::new(&self.settings.bwrap_path);
+        command
+            .args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"])
+            .args(["--tmpfs", "/tmp", "--bind"])
+            .arg(workdir)
+            .arg(workdir)
+            .args(["--unshare-all", "--die-with-parent", "--chdir"])
+            .arg(workdir)
+            .arg("--")
+            .arg(interpreter)
+            .arg(script)
+            .current_dir(workdir)
+            .env_clear()
+            .env("PATH", "/usr/local/bin:/usr/bin:/bin")
+            .env("HOME", workdir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        #[cfg(unix)]
+        {
+            // A group of its own lets a timeout reach processes the snippet backgrounds.
+            command.process_group(0);
+            let cpu_seconds = self.settings.timeout_ms.div_ceil(1000).max(1);
+            let memory_bytes = self.settings.memory_limit_mb * 1024 * 1024;
+            let file_bytes = self.settings.max_output_bytes as u64 * 4;
+            // RLIMIT_NPROC counts every process and thread of the user, the
+            // service's own threads included.
+            let processes = service_threads() + self.settings.max_processes;
+            // SAFETY: only async-signal-safe setrlimit calls run between fork and exec.
+            unsafe {
+                command.pre_exec(move || {
+                    check_os(libc::setrlimit(libc::RLIMIT_CPU, &rlimit(cpu_seconds)))?;
+                    check_os(libc::setrlimit(libc::RLIMIT_AS, &rlimit(memory_bytes)))?;
+                    check_os(libc::setrlimit(libc::RLIMIT_FSIZE, &rlimit(file_bytes)))?;
+                    check_os(libc::setrlimit(libc::RLIMIT_NOFILE, &rlimit(32)))?;
+                    check_os(libc::setrlimit(libc::RLIMIT_NPROC, &rlimit(processes)))?;
+                    Ok(())
+                });
+            }
+        }
+
+        let started = Instant::now();
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to start {}", self.settings.bwrap_path))?;
+        // Read while the snippet runs so a full pipe cannot stall it.
+        let limit = self.settings.max_output_bytes;
+        let stdout = tokio::spawn(read_pipe(child.stdout.take(), limit));
+        let stderr = tokio::spawn(read_pipe(child.stderr.take(), limit));
+
+        let timeout = Duration::from_millis(self.settings.timeout_ms);
+        let mut timed_out = false;
+        if let Some(leader) = child.id() {
+            let exited = tokio::task::spawn_blocking(move || wait_exited(leader));
+            timed_out = tokio::time::timeout(timeout, exited).await.is_err();
+            // The leader is not reaped yet, so its pid still names this group
+            // and cannot have been reused for another.
+            kill_group(leader);
+        }
+        let status = child.wait().await?;
+        let elapsed = started.elapsed();
+        if timed_out {
+            return Ok((None, true, elapsed));
+        }
+        let output = std::process::Output {
+            status,
+            stdout: stdout.await??,
+            stderr: stderr.await??,
+        };
+        Ok((Some(output), false, elapsed))
+    }
+}
+
+/// Everything `pipe` yields, keeping at most one byte past `limit` so the
+/// caller can tell the output was cut.
+async fn read_pipe<R: AsyncRead + Unpin>(
+    pipe: Option<R>,
+    limit: usize,
+) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    if let Some(mut pipe) = pipe {
+        (&mut pipe)
+            .take(limit as u64 + 1)
+            .read_to_end(&mut bytes)
+            .await?;
+        tokio::io::copy(&mut pipe, &mut tokio::io::sink()).await?;
+    }
+    Ok(bytes)
+}
+
+/// Blocks until `pid` exits, leaving it unreaped.
+#[cfg(unix)]
+fn wait_exited(pid: u32) -> std::io::Result<()> {
+    loop {
+        // SAFETY: info is a zeroed siginfo_t that waitid only writes to.
+        let result = unsafe {
+            let mut info: libc::siginfo_t = std::mem::zeroed();
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if result == 0 {
+            return Ok(());
+        }
+        let error = std::io::Error::last_os_error();
+        if error.kind() != std::io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn wait_exited(_pid: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Kills every process left in the process group led by `leader`.
+#[cfg(unix)]
+fn kill_group(leader: u32) {
+    // SAFETY: kill has no memory-safety preconditions; a group already gone
+    // just yields ESRCH.
+    unsafe {
+        libc::kill(-(leader as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(_leader: u32) {}
+
+/// Threads this process runs, which RLIMIT_NPROC counts against the snippet.
+#[cfg(unix)]
+fn service_threads() -> u64 {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("Threads:"))
+                .and_then(|count| count.trim().parse().ok())
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+fn rlimit(value: u64) -> libc::rlimit {
+    libc::rlimit {
+        rlim_cur: value as libc::rlim_t,
+        rlim_max: value as libc::rlim_t,
+    }
+}
+
+#[cfg(unix)]
+fn check_os(result: libc::c_int) -> std::io::Result<()> {
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Finds ```python fenced blocks.
+fn extract_snippets(answer: &str) -> Vec<Snippet> {
+    let mut snippets = Vec::new();
+    let mut current: Option<Snippet> = None;
+
+    for line in answer.lines() {
+        let trimmed = line.trim_start();
+        match current.as_mut() {
+            None => {
+                if let Some(tag) = trimmed.strip_prefix("```") {
+                    if matches!(
+                        tag.trim().to_lowercase().as_str(),
+                        "python" | "py" | "python3"
+                    ) {
+                        current = Some(Snippet {
+                            language: "python".to_string(),
+                            code: String::new(),
+                        });
+                    }
+                }
+            }
+            Some(snippet) => {
+                if trimmed.starts_with("```") {
+                    if let Some(snippet) = current.take() {
+                        if !snippet.code.trim().is_empty() {
+                            snippets.push(snippet);
+                        }
+                    }
+                } else {
+                    snippet.code.push_str(line);
+                    snippet.code.push('\n');
+                }
+            }
+        }
+    }
+
+    snippets
+}
+
+fn truncate_output(bytes: &[u8], max_bytes: usize) -> String {
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(max_bytes)]).to_string();
+    if bytes.len() > max_bytes {
+        format!("{}\n[output truncated]", text)
+    } else {
+        text
+    }
+}
@@ -5,6 +5,8 @@ pub struct SearchResult {
     pub title: String,
     pub url: String,
     pub snippet: String,
+    /// Search backend that returned the result.
+    pub provider: String,
 }
 
 #[derive(Default, Clone)]
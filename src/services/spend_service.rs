@@ -0,0 +1,138 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use serde_json::Value;
+
+use crate::config::OpenRouterSettings;
+use crate::models::CloudSpendStatus;
+use crate::repositories::{SpendRecord, SpendRepo};
+
+/// Accounts OpenRouter spend per UTC day and enforces the daily/monthly hard caps.
+///
+/// Caps are checked before a request is sent, so concurrent requests can overshoot
+/// a cap by the cost of the calls already in flight.
+#[derive(Clone)]
+pub struct SpendService {
+    repo: Option<SpendRepo>,
+    prompt_price_per_mtok: f64,
+    completion_price_per_mtok: f64,
+    daily_cap_usd: Option<f64>,
+    monthly_cap_usd: Option<f64>,
+}
+
+impl SpendService {
+    /// Fails when the ledger cannot be opened: running on without it would
+    /// forget the spend recorded so far and lift the caps.
+    pub fn new(settings: &OpenRouterSettings) -> Result<Self> {
+        let repo = if settings.spend_sqlite_path.trim().is_empty() {
+            None
+        } else {
+            Some(
+                SpendRepo::new(settings.spend_sqlite_path.clone())
+                    .context("Failed to open the OpenRouter spend ledger")?,
+            )
+        };
+        if repo.is_none()
+            && (settings.daily_spend_cap_usd.is_some() || settings.monthly_spend_cap_usd.is_some())
+        {
+            tracing::warn!(
+                "OpenRouter spend caps are configured but cannot be enforced without a ledger"
+            );
+        }
+        Ok(Self {
+            repo,
+            prompt_price_per_mtok: settings.prompt_price_per_mtok,
+            completion_price_per_mtok: settings.completion_price_per_mtok,
+            daily_cap_usd: settings.daily_spend_cap_usd,
+            monthly_cap_usd: settings.monthly_spend_cap_usd,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.repo.is_some()
+    }
+
+    /// Adds the cost of one completion and returns it. Uses the provider-reported
+    /// `usage.cost` when present, otherwise the configured per-token prices.
+    /// Failures are only logged.
+    pub async fn record(&self, response: &Value) -> f64 {
+        let usage = response.get("usage");
+        let tokens = |key: &str| {
+            usage
+                .and_then(|usage| usage.get(key))
+                .and_then(Value::as_u64)
+                .unwrap_or(0)
+        };
+        let prompt_tokens = tokens("prompt_tokens");
+        let completion_tokens = tokens("completion_tokens");
+        let cost_usd = usage
+            .and_then(|usage| usage.get("cost"))
+            .and_then(Value::as_f64)
+            .unwrap_or_else(|| {
+                (prompt_tokens as f64 * self.prompt_price_per_mtok
+                    + completion_tokens as f64 * self.completion_price_per_mtok)
+                    / 1_000_000.0
+            });
+        let Some(repo) = self.repo.clone() else {
+            return cost_usd;
+        };
+        let record = SpendRecord {
+            cost_usd,
+            requests: 1,
+            prompt_tokens,
+            completion_tokens,
+        };
+
+        let day = Utc::now().format("%Y-%m-%d").to_string();
+        let result = tokio::task::spawn_blocking(move || repo.add(&day, &record)).await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::warn!("Failed to record OpenRouter spend: {}", e),
+            Err(e) => tracing::warn!("Spend accounting task failed: {}", e),
+        }
+        cost_usd
+    }
+
+    pub async fn status(&self) -> Result<CloudSpendStatus> {
+        let repo = self
+            .repo
+            .clone()
+            .ok_or_else(|| anyhow!("OpenRouter spend accounting is disabled"))?;
+        let now = Utc::now();
+        let day = now.format("%Y-%m-%d").to_string();
+        let month = now.format("%Y-%m").to_string();
+        let (daily, monthly) = tokio::task::spawn_blocking(move || {
+            Ok::<_, anyhow::Error>((repo.total(&day)?, repo.total(&month)?))
+        })
+        .await??;
+
+        let reached = |cap: Option<f64>, spent: f64| cap.is_some_and(|cap| spent >= cap);
+        Ok(CloudSpendStatus {
+            capped: reached(self.daily_cap_usd, daily.cost_usd)
+                || reached(self.monthly_cap_usd, monthly.cost_usd),
+            daily_spend_usd: daily.cost_usd,
+            daily_cap_usd: self.daily_cap_usd,
+            daily_requests: daily.requests,
+            monthly_spend_usd: monthly.cost_usd,
+            monthly_cap_usd: self.monthly_cap_usd,
+            monthly_requests: monthly.requests,
+        })
+    }
+
+    /// Whether cloud traffic must be held back. An unreadable ledger counts as
+    /// capped so a storage fault cannot lift the cap.
+    pub async fn is_capped(&self) -> bool {
+        if !self.is_enabled() || (self.daily_cap_usd.is_none() && self.monthly_cap_usd.is_none()) {
+            return false;
+        }
+        match self.status().await {
+            Ok(status) => status.capped,
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to read OpenRouter spend, treating cap as reached: {}",
+                    e
+                );
+                true
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Notify};
+use uuid::Uuid;
+
+/// Buffers older than this are dropped the next time a buffer is created.
+const BUFFER_TTL: Duration = Duration::from_secs(600);
+
+struct GenerationBuffer {
+    chunks: Vec<String>,
+    done: bool,
+    error: Option<String>,
+    metadata: Option<Value>,
+    notify: Arc<Notify>,
+    updated_at: Instant,
+}
+
+/// A view of a generation buffer starting at a client-supplied offset.
+#[derive(Debug, Clone)]
+pub struct BufferSnapshot {
+    pub chunks: Vec<String>,
+    pub next_offset: usize,
+    pub done: bool,
+    pub error: Option<String>,
+    pub metadata: Option<Value>,
+}
+
+/// Holds generated chunks by token so clients can read them independently of the
+/// request that produced them (long-poll transport).
+#[derive(Clone, Default)]
+pub struct StreamService {
+    buffers: Arc<Mutex<HashMap<String, GenerationBuffer>>>,
+}
+
+impl StreamService {
+    pub async fn create(&self) -> String {
+        let token = Uuid::new_v4().simple().to_string();
+        let mut buffers = self.buffers.lock().await;
+        buffers.retain(|_, buffer| buffer.updated_at.elapsed() < BUFFER_TTL);
+        buffers.insert(
+            token.clone(),
+            GenerationBuffer {
+                chunks: Vec::new(),
+                done: false,
+                error: None,
+                metadata: None,
+                notify: Arc::new(Notify::new()),
+                updated_at: Instant::now(),
+            },
+        );
+        token
+    }
+
+    pub async fn append(&self, token: &str, chunk: String) {
+        let mut buffers = self.buffers.lock().await;
+        if let Some(buffer) = buffers.get_mut(token) {
+            buffer.chunks.push(chunk);
+            buffer.updated_at = Instant::now();
+            buffer.notify.notify_waiters();
+        }
+    }
+
+    pub async fn finish(&self, token: &str, metadata: Option<Value>) {
+        self.close(token, None, metadata).await;
+    }
+
+    pub async fn fail(&self, token: &str, error: String) {
+        self.close(token, Some(error), None).await;
+    }
+
+    async fn close(&self, token: &str, error: Option<String>, metadata: Option<Value>) {
+        let mut buffers = self.buffers.lock().await;
+        if let Some(buffer) = buffers.get_mut(token) {
+            buffer.done = true;
+            buffer.error = error;
+            buffer.metadata = metadata;
+            buffer.updated_at = Instant::now();
+            buffer.notify.notify_waiters();
+        }
+    }
+
+    /// Returns chunks after `offset`, waiting up to `wait` for new ones to arrive.
+    /// `None` means the token is unknown or has expired.
+    pub async fn poll(&self, token: &str, offset: usize, wait: Duration) -> Option<BufferSnapshot> {
+        let buffers = self.buffers.lock().await;
+        let buffer = buffers.get(token)?;
+        if buffer.done || buffer.chunks.len() > offset {
+            return Some(snapshot(buffer, offset));
+        }
+
+        // Created while the lock is held so a notify_waiters() after unlocking is not lost.
+        let notify = buffer.notify.clone();
+        let notified = notify.notified();
+        drop(buffers);
+        let _ = tokio::time::timeout(wait, notified).await;
+
+        let buffers = self.buffers.lock().await;
+        buffers.get(token).map(|buffer| snapshot(buffer, offset))
+    }
+}
+
+fn snapshot(buffer: &GenerationBuffer, offset: usize) -> BufferSnapshot {
+    let start = offset.min(buffer.chunks.len());
+    BufferSnapshot {
+        chunks: buffer.chunks[start..].to_vec(),
+        next_offset: buffer.chunks.len(),
+        done: buffer.done,
+        error: buffer.error.clone(),
+        metadata: buffer.metadata.clone(),
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::Result;
+use chrono::Utc;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::models::{BackgroundTaskStatus, TaskState};
+
+/// Delay before restarting a task after its first panic; doubles per panic.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Passed to a supervised task so it can report progress.
+#[derive(Clone)]
+pub struct TaskHandle {
+    name: &'static str,
+    registry: TaskRegistry,
+}
+
+impl TaskHandle {
+    /// Records a completed unit of work, such as one iteration of a polling loop.
+    pub fn ran(&self) {
+        self.registry
+            .update(self.name, |task| task.last_run_at = Some(Utc::now()));
+    }
+
+    /// Records an error the task handled itself and kept running after.
+    pub fn failed(&self, error: impl ToString) {
+        let error = error.to_string();
+        self.registry
+            .update(self.name, |task| task.last_error = Some(error));
+    }
+}
+
+/// Tracks long-running background tasks and restarts the ones that panic.
+/// A task that returns an error is recorded as failed and not restarted.
+#[derive(Clone, Default)]
+pub struct TaskRegistry {
+    tasks: Arc<Mutex<BTreeMap<&'static str, BackgroundTaskStatus>>>,
+}
+
+impl TaskRegistry {
+    /// Runs `task` under supervision. It is called again, after a backoff,
+    /// each time the future it returned panics.
+    pub fn spawn<F, Fut>(&self, name: &'static str, task: F)
+    where
+        F: Fn(TaskHandle) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        self.tasks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                name,
+                BackgroundTaskStatus {
+                    name: name.to_string(),
+                    state: TaskState::Running,
+                    started_at: Utc::now(),
+                    last_run_at: None,
+                    last_error: None,
+                    restarts: 0,
+                },
+            );
+
+        let registry = self.clone();
+        tokio::spawn(async move {
+            let handle = TaskHandle {
+                name,
+                registry: registry.clone(),
+            };
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                let started = Instant::now();
+                registry.update(name, |task| {
+                    task.state = TaskState::Running;
+                    task.started_at = Utc::now();
+                });
+                match tokio::spawn(task(handle.clone())).await {
+                    Ok(Ok(())) => {
+                        registry.update(name, |task| {
+                            task.state = TaskState::Finished;
+                            task.last_run_at = Some(Utc::now());
+                        });
+                        return;
+                    }
+                    Ok(Err(e)) => {
+                        tracing::error!(task = name, "Background task failed: {}", e);
+                        registry.update(name, |task| {
+                            task.state = TaskState::Failed;
+                            task.last_error = Some(e.to_string());
+                        });
+                        return;
+                    }
+                    Err(e) if e.is_cancelled() => {
+                        registry.update(name, |task| task.state = TaskState::Finished);
+                        return;
+                    }
+                    Err(e) => {
+                        let message = panic_message(e.into_panic());
+                        // A task that ran healthily for a while starts over with a short delay.
+                        if started.elapsed() > MAX_BACKOFF {
+                            backoff = INITIAL_BACKOFF;
+                        }
+                        tracing::error!(
+                            task = name,
+                            "Background task panicked, restarting in {:?}: {}",
+                            backoff,
+                            message
+                        );
+                        registry.update(name, |task| {
+                            task.state = TaskState::Restarting;
+                            task.last_error = Some(format!("panicked: {}", message));
+                            task.restarts += 1;
+                        });
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Current status of every registered task, by name.
+    pub fn snapshot(&self) -> Vec<BackgroundTaskStatus> {
+        self.tasks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    fn update(&self, name: &str, apply: impl FnOnce(&mut BackgroundTaskStatus)) {
+        let mut tasks = self.tasks.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(task) = tasks.get_mut(name) {
+            apply(task);
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    }
+}
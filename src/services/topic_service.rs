@@ -0,0 +1,107 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::ConversationSettings;
+use crate::repositories::{ReadPool, TopicCountRecord, TopicRepo};
+use crate::services::TaskRegistry;
+use crate::utils::label_topic;
+
+/// Conversations labeled per pass, so a backlog is worked off gradually.
+const LABEL_BATCH_SIZE: usize = 200;
+
+/// Labels finished conversations with a topic from the user's messages so
+/// admins can see what users ask about. Shares the conversation store.
+#[derive(Clone)]
+pub struct TopicService {
+    repo: Option<TopicRepo>,
+    interval_secs: u64,
+    idle_minutes: i64,
+}
+
+impl TopicService {
+    pub fn new(settings: &ConversationSettings) -> Self {
+        let repo = if settings.sqlite_path.trim().is_empty() {
+            None
+        } else {
+            let reporting_path = settings
+                .analytics_replica_path
+                .clone()
+                .unwrap_or_else(|| settings.sqlite_path.clone());
+            let reporting = ReadPool::new(reporting_path, settings.analytics_pool_size);
+            match TopicRepo::new(settings.sqlite_path.clone(), reporting) {
+                Ok(repo) => Some(repo),
+                Err(e) => {
+                    tracing::warn!("Topic labeling disabled: {}", e);
+                    None
+                }
+            }
+        };
+        Self {
+            repo,
+            interval_secs: settings.topic_label_interval_secs,
+            idle_minutes: settings.topic_idle_minutes as i64,
+        }
+    }
+
+    /// Starts the labeling task; does nothing when the store or the interval is off.
+    pub fn spawn(&self, tasks: &TaskRegistry) {
+        if self.repo.is_none() || self.interval_secs == 0 {
+            return;
+        }
+        let service = self.clone();
+        tasks.spawn("topic_labeler", move |handle| {
+            let service = service.clone();
+            async move {
+                let mut interval =
+                    tokio::time::interval(std::time::Duration::from_secs(service.interval_secs));
+                loop {
+                    interval.tick().await;
+                    match service.label_pending().await {
+                        Ok(labeled) => {
+                            if labeled > 0 {
+                                tracing::debug!("Labeled {} conversations", labeled);
+                            }
+                            handle.ran();
+                        }
+                        Err(e) => {
+                            tracing::warn!("Topic labeling failed: {}", e);
+                            handle.failed(e);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Labels conversations idle for `idle_minutes` that are new or changed
+    /// since their last label. Returns how many were labeled.
+    pub async fn label_pending(&self) -> Result<usize> {
+        let repo = self.repo()?;
+        let idle_before = Utc::now() - Duration::minutes(self.idle_minutes);
+        tokio::task::spawn_blocking(move || {
+            let pending = repo.unlabeled(idle_before, LABEL_BATCH_SIZE)?;
+            for conversation in &pending {
+                let messages = repo.user_messages(&conversation.conversation_id)?;
+                let topic = label_topic(&messages.join("\n"));
+                repo.set_topic(
+                    &conversation.conversation_id,
+                    topic,
+                    conversation.message_count,
+                )?;
+            }
+            Ok(pending.len())
+        })
+        .await?
+    }
+
+    pub async fn counts_since(&self, since: DateTime<Utc>) -> Result<Vec<TopicCountRecord>> {
+        let repo = self.repo()?;
+        tokio::task::spawn_blocking(move || repo.counts_since(since)).await?
+    }
+
+    fn repo(&self) -> Result<TopicRepo> {
+        self.repo
+            .clone()
+            .ok_or_else(|| anyhow!("Topic labeling is disabled"))
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::config::TrialSettings;
+
+/// Tracked clients above which expired windows are swept on the next check.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// A trial client used up its requests for the current window.
+#[derive(Debug, Clone)]
+pub struct TrialExhausted {
+    pub limit: u32,
+    pub retry_after_secs: u64,
+}
+
+impl fmt::Display for TrialExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Trial allows {} requests per window; retry in {}s or use an API key",
+            self.limit, self.retry_after_secs
+        )
+    }
+}
+
+/// Fixed-window request counter for keyless trial callers, keyed by client
+/// address. State is per process and kept in memory.
+#[derive(Clone)]
+pub struct TrialLimiter {
+    settings: TrialSettings,
+    windows: Arc<Mutex<HashMap<String, (Instant, u32)>>>,
+}
+
+impl TrialLimiter {
+    pub fn new(settings: TrialSettings) -> Self {
+        Self {
+            settings,
+            windows: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Counts one request from `client`, refusing it once the window is full.
+    pub fn check(&self, client: &str) -> Result<(), TrialExhausted> {
+        let window = Duration::from_secs(self.settings.window_secs.max(1));
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap_or_else(PoisonError::into_inner);
+        if windows.len() > SWEEP_THRESHOLD {
+            windows.retain(|_, (started, _)| now.duration_since(*started) < window);
+        }
+
+        let (started, count) = windows.entry(client.to_string()).or_insert((now, 0));
+        if now.duration_since(*started) >= window {
+            *started = now;
+            *count = 0;
+        }
+        if *count >= self.settings.requests_per_window {
+            let elapsed = now.duration_since(*started);
+            return Err(TrialExhausted {
+                limit: self.settings.requests_per_window,
+                retry_after_secs: window.saturating_sub(elapsed).as_secs().max(1),
+            });
+        }
+        *count += 1;
+        Ok(())
+    }
+}
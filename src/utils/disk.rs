@@ -0,0 +1,22 @@
+use std::path::Path;
+
+/// Bytes available to unprivileged users on the filesystem holding `path`, or
+/// `None` where the platform offers no check.
+#[cfg(unix)]
+pub fn available_bytes(path: &Path) -> std::io::Result<Option<u64>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: statvfs only writes into the zeroed struct we pass.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(Some(stat.f_bavail as u64 * stat.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+pub fn available_bytes(_path: &Path) -> std::io::Result<Option<u64>> {
+    Ok(None)
+}
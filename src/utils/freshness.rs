@@ -0,0 +1,72 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Phrases whose answer depends on when the question is asked: release
+/// versions, service status, advisories and relative dates.
+const TIME_SENSITIVE_PHRASES: &[&str] = &[
+    "latest version",
+    "latest release",
+    "newest version",
+    "most recent version",
+    "current version",
+    "current release",
+    "current status",
+    "still supported",
+    "end of life",
+    "is it down",
+    "outage",
+    "security advisory",
+    "advisories",
+    "vulnerability",
+    "zero-day",
+    "release notes",
+    "changelog",
+    "right now",
+    "today",
+    "yesterday",
+    "tomorrow",
+    "this week",
+    "this month",
+    "this year",
+    "last week",
+    "recently",
+];
+
+fn date_pattern() -> &'static Regex {
+    static DATES: OnceLock<Regex> = OnceLock::new();
+    DATES.get_or_init(|| {
+        Regex::new(
+            r"(?ix)\b(?:
+                20\d{2}-\d{2}-\d{2}
+                | cve-\d{4}-\d+
+                | (?:jan|feb|mar|apr|may|jun|jul|aug|sep|oct|nov|dec)[a-z]*\.?\ +\d{1,2}
+                  (?:st|nd|rd|th)?,?\ +20\d{2}
+                | (?:in|since|as\ of|for)\ +20\d{2}
+            )\b",
+        )
+        .expect("valid date pattern")
+    })
+}
+
+/// Whether an answer to `message` is likely to go stale within days: it asks
+/// for the latest version or current status of something, names an advisory,
+/// or mentions a specific or relative date.
+pub fn is_time_sensitive(message: &str) -> bool {
+    let lowered = message.to_lowercase();
+    TIME_SENSITIVE_PHRASES
+        .iter()
+        .any(|phrase| contains_phrase(&lowered, phrase))
+        || date_pattern().is_match(message)
+}
+
+/// `phrase` occurs in `text` on word boundaries, so "today" does not match
+/// "todays_backup".
+fn contains_phrase(text: &str, phrase: &str) -> bool {
+    text.match_indices(phrase).any(|(start, _)| {
+        let end = start + phrase.len();
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        !before.is_some_and(|c| c.is_alphanumeric() || c == '_')
+            && !after.is_some_and(|c| c.is_alphanumeric() || c == '_')
+    })
+}
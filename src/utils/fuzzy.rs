@@ -0,0 +1,72 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Names accepted by [`FuzzyMethod::parse`].
+pub const FUZZY_METHODS: &[&str] = &["edit", "token_sort"];
+
+/// How two strings are compared by [`fuzzy_ratio`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FuzzyMethod {
+    /// Normalized edit distance over the whole strings.
+    Edit,
+    /// Edit distance after sorting the words, so reordered phrasings match.
+    #[default]
+    TokenSort,
+}
+
+impl FuzzyMethod {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim() {
+            "edit" => Ok(Self::Edit),
+            "token_sort" => Ok(Self::TokenSort),
+            other => bail!(
+                "Unknown fuzzy match method {:?}, expected one of {}",
+                other,
+                FUZZY_METHODS.join(", ")
+            ),
+        }
+    }
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// `1 - distance / longer length`: 1 for equal strings, 0 for nothing in common.
+pub fn edit_ratio(a: &str, b: &str) -> f32 {
+    let longest = a.chars().count().max(b.chars().count());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(a, b) as f32 / longest as f32
+}
+
+/// [`edit_ratio`] of the lowercased words of each string in sorted order.
+pub fn token_sort_ratio(a: &str, b: &str) -> f32 {
+    let sorted = |text: &str| {
+        let mut words: Vec<String> = text.split_whitespace().map(str::to_lowercase).collect();
+        words.sort();
+        words.join(" ")
+    };
+    edit_ratio(&sorted(a), &sorted(b))
+}
+
+pub fn fuzzy_ratio(method: FuzzyMethod, a: &str, b: &str) -> f32 {
+    match method {
+        FuzzyMethod::Edit => edit_ratio(a, b),
+        FuzzyMethod::TokenSort => token_sort_ratio(a, b),
+    }
+}
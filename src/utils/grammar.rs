@@ -0,0 +1,269 @@
+use anyhow::{anyhow, bail, Context, Result};
+use regex_automata::dfa::{dense, Automaton};
+use regex_automata::util::primitives::StateID;
+use regex_automata::{Anchored, Input};
+use std::collections::HashMap;
+
+use crate::models::OutputConstraint;
+
+/// Upper bound on the compiled automaton, so a pathological pattern is
+/// rejected instead of exhausting memory.
+const DFA_SIZE_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Longest regex a grammar may expand to. Rules referenced from several
+/// places are inlined each time, so a short grammar can otherwise grow
+/// exponentially before the DFA limit is ever reached.
+const MAX_EXPANDED_LEN: usize = 64 * 1024;
+
+/// A compiled output constraint. Generation walks it one token at a time and
+/// only samples tokens that keep the text a prefix of some accepted output.
+#[derive(Debug)]
+pub struct OutputGrammar {
+    dfa: dense::DFA<Vec<u32>>,
+    start: StateID,
+}
+
+impl OutputGrammar {
+    pub fn compile(constraint: &OutputConstraint) -> Result<Self> {
+        let pattern = match constraint {
+            OutputConstraint::Regex(pattern) => pattern.clone(),
+            OutputConstraint::Grammar(grammar) => gbnf_to_regex(grammar)?,
+        };
+        // Whitespace around the output is dropped afterwards, so allow it here.
+        let pattern = format!(r"\s*(?:{})\s*", pattern);
+        let dfa = dense::Builder::new()
+            .configure(
+                dense::DFA::config()
+                    .dfa_size_limit(Some(DFA_SIZE_LIMIT))
+                    .determinize_size_limit(Some(DFA_SIZE_LIMIT)),
+            )
+            .build(&pattern)
+            .context("Invalid output constraint")?;
+        let start = dfa
+            .start_state_forward(&Input::new("").anchored(Anchored::Yes))
+            .map_err(|e| anyhow!("Unsupported output constraint: {}", e))?;
+        Ok(Self { dfa, start })
+    }
+
+    pub fn start(&self) -> StateID {
+        self.start
+    }
+
+    /// The state after `text`, or `None` when no accepted output starts that way.
+    pub fn advance(&self, state: StateID, text: &str) -> Option<StateID> {
+        let mut state = state;
+        for byte in text.bytes() {
+            state = self.dfa.next_state(state, byte);
+            if self.dfa.is_dead_state(state) || self.dfa.is_quit_state(state) {
+                return None;
+            }
+        }
+        Some(state)
+    }
+
+    /// Whether the text that led to `state` is a complete accepted output.
+    pub fn accepts(&self, state: StateID) -> bool {
+        self.dfa.is_match_state(self.dfa.next_eoi_state(state))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum GrammarToken {
+    Ident(String),
+    Defines,
+    /// A quoted literal, already unescaped.
+    Literal(String),
+    /// A character class copied as written, brackets included.
+    Class(String),
+    Open,
+    Close,
+    Pipe,
+    /// `*`, `+`, `?` or a `{m,n}` repetition, copied as written.
+    Repeat(String),
+}
+
+/// Converts a GBNF-style grammar (`root ::= ...`) to an equivalent regex.
+/// Rules may reference each other but not recursively, which keeps the
+/// language regular. Each rule is expanded once, and the result may not
+/// exceed [`MAX_EXPANDED_LEN`].
+pub fn gbnf_to_regex(grammar: &str) -> Result<String> {
+    let tokens = lex_grammar(grammar)?;
+    let mut rules: HashMap<String, Vec<GrammarToken>> = HashMap::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        let (GrammarToken::Ident(name), Some(GrammarToken::Defines)) =
+            (&tokens[index], tokens.get(index + 1))
+        else {
+            bail!("Expected `name ::=` at grammar token {}", index + 1);
+        };
+        let body_start = index + 2;
+        let mut body_end = body_start;
+        while body_end < tokens.len()
+            && !(matches!(tokens[body_end], GrammarToken::Ident(_))
+                && tokens.get(body_end + 1) == Some(&GrammarToken::Defines))
+        {
+            body_end += 1;
+        }
+        if rules
+            .insert(name.clone(), tokens[body_start..body_end].to_vec())
+            .is_some()
+        {
+            bail!("Grammar rule `{}` is defined twice", name);
+        }
+        index = body_end;
+    }
+    if !rules.contains_key("root") {
+        bail!("Grammar has no `root` rule");
+    }
+    expand_rule("root", &rules, &mut HashMap::new(), &mut Vec::new())
+}
+
+fn expand_rule(
+    name: &str,
+    rules: &HashMap<String, Vec<GrammarToken>>,
+    expanded: &mut HashMap<String, String>,
+    stack: &mut Vec<String>,
+) -> Result<String> {
+    if let Some(regex) = expanded.get(name) {
+        return Ok(regex.clone());
+    }
+    if stack.iter().any(|active| active == name) {
+        bail!(
+            "Grammar rule `{}` is recursive, which is not supported",
+            name
+        );
+    }
+    let body = rules
+        .get(name)
+        .ok_or_else(|| anyhow!("Grammar rule `{}` is not defined", name))?;
+    stack.push(name.to_string());
+    let mut regex = String::new();
+    let mut depth = 0usize;
+    for token in body {
+        match token {
+            GrammarToken::Ident(reference) => regex.push_str(&format!(
+                "(?:{})",
+                expand_rule(reference, rules, expanded, stack)?
+            )),
+            GrammarToken::Literal(text) => regex.push_str(&format!("(?:{})", regex::escape(text))),
+            GrammarToken::Class(class) => regex.push_str(class),
+            GrammarToken::Open => {
+                depth += 1;
+                regex.push_str("(?:");
+            }
+            GrammarToken::Close => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| anyhow!("Unbalanced `)` in grammar rule `{}`", name))?;
+                regex.push(')');
+            }
+            GrammarToken::Pipe => regex.push('|'),
+            GrammarToken::Repeat(repeat) => regex.push_str(repeat),
+            GrammarToken::Defines => bail!("Unexpected `::=` in grammar rule `{}`", name),
+        }
+        if regex.len() > MAX_EXPANDED_LEN {
+            bail!(
+                "Grammar rule `{}` expands to more than {} bytes of regex",
+                name,
+                MAX_EXPANDED_LEN
+            );
+        }
+    }
+    if depth != 0 {
+        bail!("Unbalanced `(` in grammar rule `{}`", name);
+    }
+    stack.pop();
+    expanded.insert(name.to_string(), regex.clone());
+    Ok(regex)
+}
+
+fn lex_grammar(grammar: &str) -> Result<Vec<GrammarToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = grammar.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            ':' => {
+                if chars.next() != Some(':') || chars.next() != Some('=') {
+                    bail!("Expected `::=` in grammar");
+                }
+                tokens.push(GrammarToken::Defines);
+            }
+            '"' => {
+                let mut literal = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => literal.push(match chars.next() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('r') => '\r',
+                            Some(other) => other,
+                            None => bail!("Unterminated string in grammar"),
+                        }),
+                        Some(other) => literal.push(other),
+                        None => bail!("Unterminated string in grammar"),
+                    }
+                }
+                tokens.push(GrammarToken::Literal(literal));
+            }
+            '[' => {
+                let mut class = String::from("[");
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some('\\') => {
+                            class.push('\\');
+                            class.push(
+                                chars
+                                    .next()
+                                    .ok_or_else(|| anyhow!("Unterminated character class"))?,
+                            );
+                        }
+                        Some(other) => class.push(other),
+                        None => bail!("Unterminated character class in grammar"),
+                    }
+                }
+                class.push(']');
+                tokens.push(GrammarToken::Class(class));
+            }
+            '{' => {
+                let mut repeat = String::from("{");
+                for c in chars.by_ref() {
+                    repeat.push(c);
+                    if c == '}' {
+                        break;
+                    }
+                }
+                if !repeat.ends_with('}') {
+                    bail!("Unterminated repetition in grammar");
+                }
+                tokens.push(GrammarToken::Repeat(repeat));
+            }
+            '(' => tokens.push(GrammarToken::Open),
+            ')' => tokens.push(GrammarToken::Close),
+            '|' => tokens.push(GrammarToken::Pipe),
+            '*' | '+' | '?' => tokens.push(GrammarToken::Repeat(c.to_string())),
+            c if c.is_alphanumeric() || c == '_' || c == '-' => {
+                let mut ident = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_' || next == '-') {
+                        break;
+                    }
+                    ident.push(next);
+                    chars.next();
+                }
+                tokens.push(GrammarToken::Ident(ident));
+            }
+            other => bail!("Unexpected `{}` in grammar", other),
+        }
+    }
+    Ok(tokens)
+}
@@ -1,7 +1,32 @@
+use ring::digest::{digest, SHA256};
+
+/// Prefix of every key from [`cache_key`]. Bump it when the derivation
+/// changes, so entries written under the old scheme are never matched.
+pub const CACHE_KEY_VERSION: &str = "v2";
+
+/// SHA-256 over the length-prefixed `parts`, so `["ab", "c"]` and
+/// `["a", "bc"]` hash differently.
 pub fn cache_key(parts: &[&str]) -> String {
-    let mut combined = String::new();
+    let mut combined = Vec::new();
     for part in parts {
-        combined.push_str(part);
+        combined.extend_from_slice(&(part.len() as u64).to_le_bytes());
+        combined.extend_from_slice(part.as_bytes());
     }
-    format!("{:x}", md5::compute(combined.as_bytes()))
+    let hash = digest(&SHA256, &combined);
+    let hex: String = hash.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}", CACHE_KEY_VERSION, hex)
+}
+
+/// Compares a presented secret with the expected one in time that does not
+/// depend on where they differ. Both sides are hashed first, so their
+/// lengths are not revealed either.
+pub fn secrets_match(provided: &str, expected: &str) -> bool {
+    let provided = digest(&SHA256, provided.as_bytes());
+    let expected = digest(&SHA256, expected.as_bytes());
+    provided
+        .as_ref()
+        .iter()
+        .zip(expected.as_ref())
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+        == 0
 }
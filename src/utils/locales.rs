@@ -0,0 +1,97 @@
+/// Prompt fragment that makes script comments and explanations come back in a
+/// given language while the code stays as-is.
+pub struct ScriptLocale {
+    pub code: &'static str,
+    pub name: &'static str,
+    /// Instruction written in the target language; `None` for English.
+    pub instruction: Option<&'static str>,
+}
+
+pub const DEFAULT_SCRIPT_LOCALE: &str = "en";
+
+pub const SCRIPT_LOCALES: &[ScriptLocale] = &[
+    ScriptLocale {
+        code: "en",
+        name: "English",
+        instruction: None,
+    },
+    ScriptLocale {
+        code: "es",
+        name: "Spanish",
+        instruction: Some("Escribe todos los comentarios en línea y la explicación en español. No traduzcas el código: mantén los comandos, nombres de variables, identificadores y cadenas de texto sin cambios."),
+    },
+    ScriptLocale {
+        code: "fr",
+        name: "French",
+        instruction: Some("Rédige tous les commentaires en ligne et l'explication en français. Ne traduis pas le code : conserve les commandes, les noms de variables, les identifiants et les chaînes de caractères tels quels."),
+    },
+    ScriptLocale {
+        code: "de",
+        name: "German",
+        instruction: Some("Schreibe alle Inline-Kommentare und die Erklärung auf Deutsch. Übersetze den Code nicht: Befehle, Variablennamen, Bezeichner und Zeichenketten bleiben unverändert."),
+    },
+    ScriptLocale {
+        code: "pt",
+        name: "Portuguese",
+        instruction: Some("Escreva todos os comentários no código e a explicação em português. Não traduza o código: mantenha comandos, nomes de variáveis, identificadores e strings inalterados."),
+    },
+    ScriptLocale {
+        code: "fa",
+        name: "Persian",
+        instruction: Some("همه‌ی کامنت‌های درون کد و توضیح نهایی را به زبان فارسی بنویس. خود کد را ترجمه نکن: دستورات، نام متغیرها، شناسه‌ها و رشته‌ها را بدون تغییر نگه دار."),
+    },
+    ScriptLocale {
+        code: "ar",
+        name: "Arabic",
+        instruction: Some("اكتب جميع التعليقات داخل الشيفرة والشرح باللغة العربية. لا تترجم الشيفرة نفسها: أبقِ الأوامر وأسماء المتغيرات والمعرّفات والسلاسل النصية كما هي."),
+    },
+    ScriptLocale {
+        code: "ru",
+        name: "Russian",
+        instruction: Some("Пиши все встроенные комментарии и пояснение на русском языке. Не переводи сам код: команды, имена переменных, идентификаторы и строковые литералы оставь без изменений."),
+    },
+    ScriptLocale {
+        code: "zh",
+        name: "Chinese (Simplified)",
+        instruction: Some("请用简体中文编写所有行内注释和说明。不要翻译代码本身：命令、变量名、标识符和字符串字面量保持不变。"),
+    },
+    ScriptLocale {
+        code: "ja",
+        name: "Japanese",
+        instruction: Some("すべてのインラインコメントと説明を日本語で書いてください。コード自体は翻訳しないでください。コマンド、変数名、識別子、文字列リテラルはそのままにしてください。"),
+    },
+];
+
+/// Looks up a locale by BCP 47 tag, matching on the primary subtag (`pt-BR` -> `pt`).
+pub fn script_locale(tag: &str) -> Option<&'static ScriptLocale> {
+    let primary = tag
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    SCRIPT_LOCALES.iter().find(|locale| locale.code == primary)
+}
+
+/// Language of `text` as an English name, when it can be identified reliably.
+/// Short messages usually can't.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let info = whatlang::detect(text)?;
+    info.is_reliable().then(|| info.lang().eng_name())
+}
+
+/// Resolves a requested answer language, given as a BCP 47 tag, an ISO 639-3
+/// code or an English name, to the name used in prompts.
+pub fn language_name(requested: &str) -> Option<&'static str> {
+    let requested = requested.trim();
+    if let Some(locale) = script_locale(requested) {
+        return Some(locale.name);
+    }
+    whatlang::Lang::from_code(requested.to_ascii_lowercase())
+        .or_else(|| {
+            whatlang::Lang::all()
+                .iter()
+                .copied()
+                .find(|lang| lang.eng_name().eq_ignore_ascii_case(requested))
+        })
+        .map(|lang| lang.eng_name())
+}
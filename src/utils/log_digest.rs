@@ -0,0 +1,196 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::utils::PiiPlaceholders;
+
+/// Bumped whenever parsing changes, so stored digests of the old shape are not reused.
+pub const LOG_DIGEST_VERSION: &str = "2";
+
+/// Longest example line kept per cluster.
+const MAX_EXAMPLE_CHARS: usize = 400;
+/// Longest template kept per cluster.
+const MAX_TEMPLATE_CHARS: usize = 400;
+/// Budget for the rendered digest handed to the model.
+const MAX_RENDERED_CHARS: usize = 16_000;
+
+/// Lines that differ only in timestamps, ids, addresses and numbers, grouped
+/// together. `template` and `example` are stored, so emails, credentials,
+/// hosts and phone numbers in them are masked with PII placeholders.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogCluster {
+    pub template: String,
+    pub count: usize,
+    pub level: Option<String>,
+    /// 1-based line number of the first occurrence.
+    pub first_line: usize,
+    pub example: String,
+}
+
+/// Parsed form of a log upload, cheap to store and to reuse for repeated uploads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogDigest {
+    pub total_lines: usize,
+    /// Most severe first, then most frequent.
+    pub clusters: Vec<LogCluster>,
+}
+
+fn variable_parts() -> &'static [(Regex, &'static str)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            (
+                r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|[+-]\d{2}:?\d{2})?",
+                "<ts>",
+            ),
+            (r"\b[A-Z][a-z]{2} +\d{1,2} \d{2}:\d{2}:\d{2}\b", "<ts>"),
+            (
+                r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b",
+                "<uuid>",
+            ),
+            (r"\b(?:\d{1,3}\.){3}\d{1,3}(?::\d+)?\b", "<ip>"),
+            (r"(?i)\b0x[0-9a-f]+\b|\b[0-9a-f]{12,}\b", "<hex>"),
+            (r"\d+", "<n>"),
+        ]
+        .into_iter()
+        .map(|(pattern, replacement)| {
+            (Regex::new(pattern).expect("valid log pattern"), replacement)
+        })
+        .collect()
+    })
+}
+
+/// `line` with its timestamps, ids, addresses and numbers replaced by markers.
+fn templatize(line: &str) -> String {
+    variable_parts()
+        .iter()
+        .fold(line.to_string(), |text, (regex, replacement)| {
+            regex.replace_all(&text, *replacement).into_owned()
+        })
+}
+
+fn level_pattern() -> &'static Regex {
+    static LEVEL: OnceLock<Regex> = OnceLock::new();
+    LEVEL.get_or_init(|| {
+        Regex::new(
+            r"(?i)\b(emerg|panic|fatal|crit(?:ical)?|err(?:or)?|warn(?:ing)?|notice|info|debug|trace)\b",
+        )
+        .expect("valid level pattern")
+    })
+}
+
+fn level_of(line: &str) -> Option<&'static str> {
+    let word = level_pattern().find(line)?.as_str().to_ascii_lowercase();
+    Some(match word.as_str() {
+        "emerg" | "panic" | "fatal" | "crit" | "critical" => "critical",
+        "err" | "error" => "error",
+        "warn" | "warning" => "warning",
+        "notice" | "info" => "info",
+        _ => "debug",
+    })
+}
+
+fn severity_rank(level: Option<&str>) -> u8 {
+    match level {
+        Some("critical") => 0,
+        Some("error") => 1,
+        Some("warning") => 2,
+        Some("info") => 3,
+        None => 4,
+        _ => 5,
+    }
+}
+
+impl LogDigest {
+    pub fn parse(logs: &str) -> Self {
+        let mut clusters: Vec<LogCluster> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        let mut total_lines = 0;
+        // One set for the whole upload, so a value keeps its placeholder across clusters.
+        let mut placeholders = PiiPlaceholders::default();
+
+        for (number, line) in logs.lines().enumerate() {
+            let line = line.trim_end();
+            if line.trim().is_empty() {
+                continue;
+            }
+            total_lines += 1;
+            let template = templatize(line);
+            match index.get(&template) {
+                Some(&position) => clusters[position].count += 1,
+                None => {
+                    // Placeholder numbers are templated again, so uploads
+                    // with different values still share a signature.
+                    let masked_template = templatize(&placeholders.mask(&template));
+                    index.insert(template, clusters.len());
+                    clusters.push(LogCluster {
+                        level: level_of(line).map(str::to_string),
+                        example: placeholders
+                            .mask(line)
+                            .chars()
+                            .take(MAX_EXAMPLE_CHARS)
+                            .collect(),
+                        template: masked_template.chars().take(MAX_TEMPLATE_CHARS).collect(),
+                        count: 1,
+                        first_line: number + 1,
+                    });
+                }
+            }
+        }
+
+        clusters.sort_by(|a, b| {
+            severity_rank(a.level.as_deref())
+                .cmp(&severity_rank(b.level.as_deref()))
+                .then(b.count.cmp(&a.count))
+                .then(a.first_line.cmp(&b.first_line))
+        });
+        Self {
+            total_lines,
+            clusters,
+        }
+    }
+
+    /// Prompt text: one example line per cluster with its count, within a fixed budget.
+    pub fn render(&self) -> String {
+        let mut rendered = format!(
+            "{} lines, {} distinct patterns (similar lines grouped; counts in brackets)\n",
+            self.total_lines,
+            self.clusters.len()
+        );
+        for (shown, cluster) in self.clusters.iter().enumerate() {
+            let entry = format!(
+                "[{}x, first at line {}] {}\n",
+                cluster.count, cluster.first_line, cluster.example
+            );
+            if rendered.len() + entry.len() > MAX_RENDERED_CHARS {
+                rendered.push_str(&format!(
+                    "... {} less severe or rarer patterns omitted\n",
+                    self.clusters.len() - shown
+                ));
+                break;
+            }
+            rendered.push_str(&entry);
+        }
+        rendered
+    }
+
+    /// Identifies uploads with the same set of patterns and levels, whatever
+    /// their timestamps, ids, counts or line order.
+    pub fn signature(&self) -> String {
+        let mut patterns: Vec<String> = self
+            .clusters
+            .iter()
+            .map(|cluster| {
+                format!(
+                    "{}|{}",
+                    cluster.level.as_deref().unwrap_or("-"),
+                    cluster.template
+                )
+            })
+            .collect();
+        patterns.sort();
+        patterns.dedup();
+        patterns.join("\n")
+    }
+}
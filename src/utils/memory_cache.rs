@@ -0,0 +1,241 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::BuildHasher;
+
+/// Which entry the memory tier gives up when it is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Least recently used.
+    Lru,
+    /// Least frequently used, least recently used among equals.
+    Lfu,
+    /// Least recently used, but a new entry is only admitted when it has been
+    /// requested more often than the entries it would evict.
+    TinyLfu,
+}
+
+impl EvictionPolicy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "lru" => Some(Self::Lru),
+            "lfu" => Some(Self::Lfu),
+            "tinylfu" | "tiny-lfu" | "tiny_lfu" => Some(Self::TinyLfu),
+            _ => None,
+        }
+    }
+}
+
+struct Slot<V> {
+    value: V,
+    bytes: usize,
+    hits: u64,
+    last_used: u64,
+}
+
+/// Bounded map for the memory cache tier, limited by entry count and,
+/// optionally, by the total size its caller reports for the entries.
+pub struct MemoryCache<V> {
+    policy: EvictionPolicy,
+    max_entries: usize,
+    /// 0 means no byte limit.
+    max_bytes: usize,
+    entries: HashMap<String, Slot<V>>,
+    /// Eviction order, first out first: `(rank, last_used, key)`.
+    order: BTreeSet<(u64, u64, String)>,
+    bytes: usize,
+    clock: u64,
+    sketch: Option<FrequencySketch>,
+}
+
+impl<V> MemoryCache<V> {
+    pub fn new(policy: EvictionPolicy, max_entries: usize, max_bytes: usize) -> Self {
+        let max_entries = max_entries.max(1);
+        Self {
+            policy,
+            max_entries,
+            max_bytes,
+            entries: HashMap::new(),
+            order: BTreeSet::new(),
+            bytes: 0,
+            clock: 0,
+            sketch: (policy == EvictionPolicy::TinyLfu).then(|| FrequencySketch::new(max_entries)),
+        }
+    }
+
+    /// The entry for `key`, counting the access. Misses are counted too, so
+    /// TinyLFU knows how often a key is asked for before it is stored.
+    pub fn get(&mut self, key: &str) -> Option<&V> {
+        if let Some(sketch) = self.sketch.as_mut() {
+            sketch.record(key);
+        }
+        self.touch(key);
+        self.entries.get(key).map(|slot| &slot.value)
+    }
+
+    /// The entry for `key` without counting an access.
+    pub fn peek(&self, key: &str) -> Option<&V> {
+        self.entries.get(key).map(|slot| &slot.value)
+    }
+
+    /// Stores `value`, evicting entries until both limits hold. Returns false
+    /// when the entry was not admitted: it is larger than the byte budget, or
+    /// TinyLFU found the entries it would displace more popular. A replaced
+    /// entry is removed either way, so a stale value is never left behind.
+    pub fn put(&mut self, key: String, value: V, bytes: usize) -> bool {
+        let previous = self.remove(&key);
+        if self.max_bytes > 0 && bytes > self.max_bytes {
+            return false;
+        }
+
+        let victims = self.victims_for(bytes);
+        if previous.is_none() && !self.admits(&key, &victims) {
+            return false;
+        }
+        for victim in &victims {
+            self.remove(victim);
+        }
+
+        self.clock += 1;
+        let slot = Slot {
+            value,
+            bytes,
+            hits: previous.map_or(0, |slot| slot.hits),
+            last_used: self.clock,
+        };
+        self.insert(key, slot);
+        true
+    }
+
+    pub fn pop(&mut self, key: &str) -> Option<V> {
+        self.remove(key).map(|slot| slot.value)
+    }
+
+    /// Drops every entry and returns how many there were.
+    pub fn clear(&mut self) -> usize {
+        let dropped = self.entries.len();
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+        dropped
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &V)> {
+        self.entries.iter().map(|(key, slot)| (key, &slot.value))
+    }
+
+    fn touch(&mut self, key: &str) {
+        let Some(mut slot) = self.remove(key) else {
+            return;
+        };
+        self.clock += 1;
+        slot.hits += 1;
+        slot.last_used = self.clock;
+        self.insert(key.to_string(), slot);
+    }
+
+    fn insert(&mut self, key: String, slot: Slot<V>) {
+        self.order.insert(self.rank(&slot, &key));
+        self.bytes += slot.bytes;
+        self.entries.insert(key, slot);
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Slot<V>> {
+        let slot = self.entries.remove(key)?;
+        self.order.remove(&self.rank(&slot, key));
+        self.bytes -= slot.bytes;
+        Some(slot)
+    }
+
+    fn rank(&self, slot: &Slot<V>, key: &str) -> (u64, u64, String) {
+        let rank = match self.policy {
+            EvictionPolicy::Lfu => slot.hits,
+            EvictionPolicy::Lru | EvictionPolicy::TinyLfu => 0,
+        };
+        (rank, slot.last_used, key.to_string())
+    }
+
+    /// Entries that would have to go, in eviction order, to make room for
+    /// one more entry of `bytes`.
+    fn victims_for(&self, bytes: usize) -> Vec<String> {
+        let mut count = self.entries.len() + 1;
+        let mut total = self.bytes + bytes;
+        let mut victims = Vec::new();
+        for (_, _, key) in &self.order {
+            let over_bytes = self.max_bytes > 0 && total > self.max_bytes;
+            if count <= self.max_entries && !over_bytes {
+                break;
+            }
+            count -= 1;
+            total -= self.entries.get(key).map_or(0, |slot| slot.bytes);
+            victims.push(key.clone());
+        }
+        victims
+    }
+
+    /// TinyLFU admission: the newcomer must have been requested more often
+    /// than every entry it would evict.
+    fn admits(&self, key: &str, victims: &[String]) -> bool {
+        let Some(sketch) = self.sketch.as_ref() else {
+            return true;
+        };
+        let frequency = sketch.estimate(key);
+        victims
+            .iter()
+            .all(|victim| sketch.estimate(victim) < frequency)
+    }
+}
+
+/// Count-min sketch of how often keys were requested, with small saturating
+/// counters that are halved periodically so old popularity fades.
+struct FrequencySketch {
+    rows: [Vec<u8>; 4],
+    mask: usize,
+    additions: usize,
+    reset_after: usize,
+    hasher: RandomState,
+}
+
+const MAX_FREQUENCY: u8 = 15;
+
+impl FrequencySketch {
+    fn new(capacity: usize) -> Self {
+        let width = capacity.max(16).next_power_of_two();
+        Self {
+            rows: std::array::from_fn(|_| vec![0; width]),
+            mask: width - 1,
+            additions: 0,
+            reset_after: width * 10,
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn record(&mut self, key: &str) {
+        let indexes = self.indexes(key);
+        for (row, index) in self.rows.iter_mut().zip(indexes) {
+            row[index] = row[index].saturating_add(1).min(MAX_FREQUENCY);
+        }
+        self.additions += 1;
+        if self.additions >= self.reset_after {
+            for row in &mut self.rows {
+                row.iter_mut().for_each(|counter| *counter /= 2);
+            }
+            self.additions /= 2;
+        }
+    }
+
+    fn estimate(&self, key: &str) -> u8 {
+        let indexes = self.indexes(key);
+        self.rows
+            .iter()
+            .zip(indexes)
+            .map(|(row, index)| row[index])
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn indexes(&self, key: &str) -> [usize; 4] {
+        let hash = self.hasher.hash_one(key);
+        let (low, high) = (hash as u32 as usize, (hash >> 32) as usize);
+        std::array::from_fn(|row| low.wrapping_add(row.wrapping_mul(high)) & self.mask)
+    }
+}
@@ -0,0 +1,91 @@
+/// Hash functions in a MinHash signature.
+pub const MINHASH_PERMUTATIONS: usize = 64;
+/// Signature values per LSH band. With 16 bands of 4, questions with a shingle
+/// Jaccard similarity around 0.5 share a bucket about half the time, and
+/// near-duplicates almost always do.
+pub const LSH_ROWS_PER_BAND: usize = 4;
+pub const LSH_BANDS: usize = MINHASH_PERMUTATIONS / LSH_ROWS_PER_BAND;
+/// Characters per shingle.
+const SHINGLE_CHARS: usize = 3;
+
+/// MinHash signature of the character shingles of `text`, lowercased with
+/// whitespace collapsed. Hashing is fixed, so signatures stay comparable
+/// across processes and restarts.
+pub fn minhash_signature(text: &str) -> Vec<u32> {
+    let normalized: Vec<char> = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .chars()
+        .collect();
+    let shingles: Vec<u64> = if normalized.len() <= SHINGLE_CHARS {
+        vec![fnv1a(&normalized)]
+    } else {
+        normalized.windows(SHINGLE_CHARS).map(fnv1a).collect()
+    };
+
+    (0..MINHASH_PERMUTATIONS as u64)
+        .map(|permutation| {
+            let seed = splitmix64(permutation);
+            shingles
+                .iter()
+                .map(|shingle| splitmix64(shingle ^ seed) as u32)
+                .min()
+                .unwrap_or(u32::MAX)
+        })
+        .collect()
+}
+
+/// Share of equal positions in two signatures: an estimate of the Jaccard
+/// similarity of their shingle sets.
+pub fn minhash_similarity(a: &[u32], b: &[u32]) -> f32 {
+    if a.is_empty() || a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f32 / a.len() as f32
+}
+
+/// One bucket per band of `signature`, as `(band, bucket)`. Two signatures
+/// that agree on every value of some band land in the same bucket for it.
+pub fn lsh_buckets(signature: &[u32]) -> Vec<(usize, i64)> {
+    signature
+        .chunks(LSH_ROWS_PER_BAND)
+        .take(LSH_BANDS)
+        .enumerate()
+        .map(|(band, rows)| {
+            let bucket = rows
+                .iter()
+                .fold(band as u64, |hash, row| splitmix64(hash ^ u64::from(*row)));
+            (band, bucket as i64)
+        })
+        .collect()
+}
+
+/// Signature as stored in a BLOB column.
+pub fn signature_to_bytes(signature: &[u32]) -> Vec<u8> {
+    signature
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+pub fn signature_from_bytes(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+fn fnv1a(chars: &[char]) -> u64 {
+    chars.iter().fold(0xcbf2_9ce4_8422_2325, |hash, c| {
+        (hash ^ u64::from(*c as u32)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -1,7 +1,37 @@
 pub mod prompts;
+pub mod disk;
+pub mod freshness;
+pub mod fuzzy;
+pub mod grammar;
 pub mod hashing;
+pub mod locales;
+pub mod log_digest;
+pub mod memory_cache;
+pub mod minhash;
+pub mod normalize;
+pub mod query;
 pub mod ranking;
+pub mod redaction;
+pub mod retrieval;
+pub mod threading;
+pub mod tools;
+pub mod topics;
 
 pub use prompts::*;
+pub use disk::*;
+pub use freshness::*;
+pub use fuzzy::*;
+pub use grammar::*;
 pub use hashing::*;
+pub use locales::*;
+pub use log_digest::*;
+pub use memory_cache::*;
+pub use minhash::*;
+pub use normalize::*;
+pub use query::*;
 pub use ranking::*;
+pub use redaction::*;
+pub use retrieval::*;
+pub use threading::*;
+pub use tools::*;
+pub use topics::*;
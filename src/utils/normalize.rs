@@ -0,0 +1,101 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Names accepted by [`NormalizationRules::parse`].
+pub const NORMALIZATION_RULES: &[&str] = &[
+    "trim",
+    "collapse_whitespace",
+    "lowercase",
+    "strip_trailing_punctuation",
+];
+
+/// Sentence-final marks dropped by `strip_trailing_punctuation`, including
+/// full-width and Arabic-script forms.
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', '!', '?', ';', ':', '…', '？', '！', '。', '؟'];
+
+/// How a message is normalized before it is hashed into a cache key, so
+/// trivially different phrasings of one question share an entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormalizationRules {
+    pub trim: bool,
+    /// Replaces each run of whitespace, including newlines, with one space.
+    pub collapse_whitespace: bool,
+    pub lowercase: bool,
+    pub strip_trailing_punctuation: bool,
+}
+
+impl Default for NormalizationRules {
+    fn default() -> Self {
+        Self {
+            trim: true,
+            collapse_whitespace: true,
+            lowercase: true,
+            strip_trailing_punctuation: true,
+        }
+    }
+}
+
+impl NormalizationRules {
+    /// Enables exactly the comma-separated rules in `list`; an empty list
+    /// disables normalization.
+    pub fn parse(list: &str) -> Result<Self> {
+        let mut rules = Self {
+            trim: false,
+            collapse_whitespace: false,
+            lowercase: false,
+            strip_trailing_punctuation: false,
+        };
+        for rule in list.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+            match rule {
+                "trim" => rules.trim = true,
+                "collapse_whitespace" => rules.collapse_whitespace = true,
+                "lowercase" => rules.lowercase = true,
+                "strip_trailing_punctuation" => rules.strip_trailing_punctuation = true,
+                other => bail!(
+                    "Unknown normalization rule {:?}, expected one of {}",
+                    other,
+                    NORMALIZATION_RULES.join(", ")
+                ),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+/// Applies `rules` to `text`.
+pub fn normalize_message(text: &str, rules: &NormalizationRules) -> String {
+    let mut normalized = if rules.collapse_whitespace {
+        let mut collapsed = String::with_capacity(text.len());
+        let mut in_whitespace = false;
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !in_whitespace {
+                    collapsed.push(' ');
+                }
+                in_whitespace = true;
+            } else {
+                collapsed.push(c);
+                in_whitespace = false;
+            }
+        }
+        collapsed
+    } else {
+        text.to_string()
+    };
+    if rules.trim {
+        normalized = normalized.trim().to_string();
+    }
+    if rules.strip_trailing_punctuation {
+        // Whitespace between the marks goes too, so "why ? !" loses both.
+        let kept = normalized
+            .trim_end_matches(|c: char| TRAILING_PUNCTUATION.contains(&c) || c.is_whitespace())
+            .len();
+        if kept > 0 {
+            normalized.truncate(kept);
+        }
+    }
+    if rules.lowercase {
+        normalized = normalized.to_lowercase();
+    }
+    normalized
+}
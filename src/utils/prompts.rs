@@ -1,19 +1,202 @@
-pub fn generate_chat_prompt(message: &str, conversation_id: Option<String>) -> String {
-    let context = if let Some(id) = conversation_id {
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::models::{ChatContext, ConversationMessage};
+use crate::utils::ScriptLocale;
+
+/// Persona used when neither the request nor `SYSTEM_PROMPT` provides one.
+pub const DEFAULT_SYSTEM_PROMPT: &str =
+    "You are a helpful AI assistant specializing in troubleshooting and technical support.";
+
+/// Built-in system prompts selectable per request with `template`.
+pub const PROMPT_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "troubleshooter",
+        "You are a methodical technical support engineer. Ask for missing details when they matter, work from the most likely cause to the least, and give numbered steps the user can follow and verify one at a time.",
+    ),
+    (
+        "tutor",
+        "You are a patient teacher helping the user understand their system. Explain why each step works, define jargon the first time you use it, and end with a short recap of what they learned.",
+    ),
+    (
+        "concise",
+        "You are a technical support assistant. Answer in as few words as possible: give the fix or the command first, and add at most one sentence of explanation.",
+    ),
+    (
+        "translator",
+        "You are a technical translator. Translate the user's text into English, or into the language they name, keeping commands, code, file paths and error messages unchanged. Reply with the translation only.",
+    ),
+];
+
+/// Named system prompts: the built-ins, overridden or extended by `<name>.txt`
+/// files from a directory.
+#[derive(Debug, Clone, Default)]
+pub struct PromptTemplates {
+    templates: BTreeMap<String, String>,
+}
+
+impl PromptTemplates {
+    pub fn builtin() -> Self {
+        Self {
+            templates: PROMPT_TEMPLATES
+                .iter()
+                .map(|(name, prompt)| (name.to_string(), prompt.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn load(dir: Option<&str>) -> Result<Self> {
+        let mut templates = Self::builtin();
+        let Some(dir) = dir else {
+            return Ok(templates);
+        };
+
+        let entries = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read prompt template directory {}", dir))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("txt") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let prompt = read_template(&path)?;
+            if !prompt.is_empty() {
+                templates
+                    .templates
+                    .insert(name.to_ascii_lowercase(), prompt);
+            }
+        }
+        Ok(templates)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.templates
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.templates.keys().map(String::as_str).collect()
+    }
+
+    /// Every template's prompt text.
+    pub fn prompts(&self) -> impl Iterator<Item = &str> {
+        self.templates.values().map(String::as_str)
+    }
+}
+
+fn read_template(path: &Path) -> Result<String> {
+    let prompt = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read prompt template {}", path.display()))?;
+    Ok(prompt.trim().to_string())
+}
+
+/// Tells the model which language to answer in, since it otherwise leans to English.
+pub fn language_instruction(language: &str) -> String {
+    format!(
+        "Write your answer in {}, even though these instructions are in English. Keep commands, code and file paths unchanged.",
+        language
+    )
+}
+
+/// Rough token count of `text` at ~4 characters per token, for when no
+/// tokenizer is at hand.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+pub fn generate_chat_prompt(
+    system_prompt: &str,
+    message: &str,
+    conversation_id: Option<String>,
+    history: &ChatContext,
+    language: Option<&str>,
+) -> String {
+    let mut context = if let Some(id) = conversation_id {
         format!("\n[Conversation ID: {}]", id)
     } else {
         String::new()
     };
+    if let Some(language) = language {
+        context.push_str(&format!("\n\n{}", language_instruction(language)));
+    }
+    if let Some(summary) = history.summary.as_deref().filter(|s| !s.trim().is_empty()) {
+        context.push_str(&format!(
+            "\n\nSummary of the earlier conversation:\n{}",
+            summary.trim()
+        ));
+    }
+    for turn in &history.turns {
+        context.push_str(&format!(
+            "\n\n{}: {}",
+            speaker(&turn.role),
+            turn.content.trim()
+        ));
+    }
 
     format!(
-        r#"You are a helpful AI assistant specializing in troubleshooting and technical support.{}
+        r#"{}{}
 
 User: {}
 Assistant: "#,
-        context, message
+        system_prompt.trim(),
+        context,
+        message
     )
 }
 
+pub fn generate_summary_prompt(previous_summary: Option<&str>, transcript: &str) -> String {
+    let previous = previous_summary
+        .filter(|s| !s.trim().is_empty())
+        .map(|s| format!("Existing summary:\n{}\n\n", s.trim()))
+        .unwrap_or_default();
+
+    format!(
+        r#"Summarize the following support conversation for later reference. Keep the user's goal, system details, errors, steps already tried and any conclusions. Be concise and factual.
+
+{}New messages:
+{}
+
+Updated summary:"#,
+        previous, transcript
+    )
+}
+
+/// Asks for a shorter rendering of part of an input that does not fit in
+/// the context window.
+pub fn generate_condense_prompt(text: &str) -> String {
+    format!(
+        r#"Condense the following text so it can stand in for the original. Keep error messages, error codes, file paths, timestamps, counts, commands and questions verbatim where possible, and drop repetition. Do not add commentary.
+
+Text:
+{}
+
+Condensed:"#,
+        text
+    )
+}
+
+/// Renders messages as `User:` / `Assistant:` lines.
+pub fn format_transcript(messages: &[ConversationMessage]) -> String {
+    messages
+        .iter()
+        .map(|m| format!("{}: {}", speaker(&m.role), m.content.trim()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn speaker(role: &str) -> &'static str {
+    match role {
+        "assistant" => "Assistant",
+        "system" => "System",
+        _ => "User",
+    }
+}
+
 pub fn generate_log_analysis_prompt(logs: &str, context: Option<String>) -> String {
     let context_info = context.unwrap_or_else(|| "No additional context provided".to_string());
 
@@ -22,7 +205,7 @@ pub fn generate_log_analysis_prompt(logs: &str, context: Option<String>) -> Stri
 
 Context: {}
 
-Logs to analyze:
+Logs to analyze (similar lines are grouped into one example with an occurrence count, most severe first):
 {}
 
 Please analyze these logs and provide:
@@ -32,12 +215,25 @@ Please analyze these logs and provide:
 4. Specific recommendations to resolve issues
 5. Severity assessment (low/medium/high/critical)
 
-Focus on actionable insights and be specific about file names, timestamps, and error codes when available."#,
+Focus on actionable insights and be specific about file names, timestamps, and error codes when available. Use the occurrence counts to tell recurring problems from one-off events."#,
         context_info, logs
     )
 }
 
-pub fn generate_script_prompt(requirement: &str, environment: &str, language: &str) -> String {
+pub fn generate_script_prompt(
+    requirement: &str,
+    environment: &str,
+    language: &str,
+    locale: &ScriptLocale,
+) -> String {
+    let localization = match locale.instruction {
+        Some(instruction) => format!(
+            "\n\nLanguage of comments and explanation: {}\n{}\nKeep the section label \"Explanation:\" in English.",
+            locale.name, instruction
+        ),
+        None => String::new(),
+    };
+
     format!(
         r#"You are an expert DevOps engineer and system administrator. Generate a script based on the following requirements:
 
@@ -57,9 +253,9 @@ Ensure the script:
 - Includes proper error handling
 - Has clear documentation
 - Is safe to run (include warnings if destructive operations are needed)
-- Is optimized for the specified environment
+- Is optimized for the specified environment{}
 
 Script:"#,
-        requirement, environment, language
+        requirement, environment, language, localization
     )
 }
@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+
+const MAX_QUERIES: usize = 3;
+const MAX_QUERY_TERMS: usize = 12;
+
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "can", "could", "do", "does", "for",
+    "from", "get", "getting", "had", "has", "have", "hello", "help", "hi", "how", "i", "i'm", "if",
+    "in", "into", "is", "it", "its", "it's", "me", "my", "of", "on", "or", "please", "so", "some",
+    "thanks", "that", "the", "this", "to", "was", "we", "what", "when", "where", "which", "why",
+    "with", "would", "you", "your",
+];
+
+const ERROR_MARKERS: &[&str] = &[
+    "error",
+    "exception",
+    "failed",
+    "fatal",
+    "panic",
+    "traceback",
+    "denied",
+    "not found",
+    "timeout",
+    "refused",
+];
+
+/// Turns a free-form user message into a short list of search-engine friendly queries.
+///
+/// Lines that look like error output are kept nearly verbatim (they are the most useful
+/// thing to search for), followed by a keyword query built from the rest of the message.
+pub fn rewrite_search_queries(message: &str) -> Vec<String> {
+    let mut queries = Vec::new();
+
+    for line in message.lines() {
+        let trimmed = line.trim();
+        let lowered = trimmed.to_lowercase();
+        if !trimmed.is_empty() && ERROR_MARKERS.iter().any(|marker| lowered.contains(marker)) {
+            queries.push(limit_terms(trimmed));
+        }
+    }
+
+    let keywords = extract_keywords(message);
+    if !keywords.is_empty() {
+        queries.push(keywords.join(" "));
+    }
+
+    let mut seen = HashSet::new();
+    queries.retain(|query| !query.is_empty() && seen.insert(query.to_lowercase()));
+    queries.truncate(MAX_QUERIES);
+
+    if queries.is_empty() {
+        let fallback = limit_terms(message.trim());
+        if !fallback.is_empty() {
+            queries.push(fallback);
+        }
+    }
+
+    queries
+}
+
+fn extract_keywords(message: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    message
+        .split_whitespace()
+        .map(|token| {
+            token
+                .trim_matches(|c: char| !c.is_alphanumeric() && c != '.' && c != '-' && c != '_')
+                .trim_end_matches('.')
+                .to_lowercase()
+        })
+        .filter(|token| token.len() > 1 && !STOP_WORDS.contains(&token.as_str()))
+        .filter(|token| seen.insert(token.clone()))
+        .take(MAX_QUERY_TERMS)
+        .collect()
+}
+
+fn limit_terms(text: &str) -> String {
+    text.split_whitespace()
+        .take(MAX_QUERY_TERMS)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -24,3 +24,75 @@ pub fn jaccard_similarity(a: &str, b: &str) -> f32 {
         intersection / union
     }
 }
+
+/// Term-frequency saturation for [`bm25_scores`].
+pub const BM25_K1: f32 = 1.2;
+/// Document-length normalization for [`bm25_scores`].
+pub const BM25_B: f32 = 0.75;
+
+/// Lowercased alphanumeric words of `text`, as BM25 sees them.
+pub fn bm25_tokens(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Okapi BM25 score of each document against `query`, in document order.
+/// Document frequencies come from `documents` alone, so scores only compare
+/// within one call.
+pub fn bm25_scores(query: &str, documents: &[&str]) -> Vec<f32> {
+    let documents: Vec<Vec<String>> = documents.iter().map(|d| bm25_tokens(d)).collect();
+    if documents.is_empty() {
+        return Vec::new();
+    }
+    let mut query_terms = bm25_tokens(query);
+    query_terms.sort();
+    query_terms.dedup();
+
+    let count = documents.len() as f32;
+    let average_len = documents.iter().map(Vec::len).sum::<usize>() as f32 / count;
+    let idf: Vec<f32> = query_terms
+        .iter()
+        .map(|term| {
+            let containing = documents.iter().filter(|doc| doc.contains(term)).count() as f32;
+            ((count - containing + 0.5) / (containing + 0.5) + 1.0).ln()
+        })
+        .collect();
+
+    documents
+        .iter()
+        .map(|doc| {
+            let len_norm = if average_len > 0.0 {
+                1.0 - BM25_B + BM25_B * doc.len() as f32 / average_len
+            } else {
+                1.0
+            };
+            query_terms
+                .iter()
+                .zip(&idf)
+                .map(|(term, idf)| {
+                    let tf = doc.iter().filter(|token| *token == term).count() as f32;
+                    idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * len_norm)
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// Cosine similarity of two embeddings; 0 when either is empty or zero.
+/// Unlike [`jaccard_similarity`], paraphrases with no words in common still
+/// score high when the embeddings come from a sentence encoder.
+pub fn embedding_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
@@ -0,0 +1,203 @@
+use regex::{Captures, Regex};
+use serde_json::Value;
+use std::sync::OnceLock;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Object keys whose values are always replaced (case-insensitive exact match).
+const SENSITIVE_KEYS: &[&str] = &[
+    "authorization",
+    "api_key",
+    "apikey",
+    "x-api-key",
+    "token",
+    "access_token",
+    "refresh_token",
+    "password",
+    "secret",
+    "client_secret",
+    "cookie",
+];
+
+fn patterns() -> &'static [(Regex, &'static str)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            (r"(?i)bearer\s+[a-z0-9._~+/=-]+", "Bearer [REDACTED]"),
+            (r"\bsk-[A-Za-z0-9_-]{16,}", "[REDACTED_KEY]"),
+            (
+                r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+                "[REDACTED_EMAIL]",
+            ),
+            (r"\b(?:\d[ -]?){13,19}\b", "[REDACTED_NUMBER]"),
+            (
+                r"\+?\d{1,3}[ .-]?\(?\d{2,4}\)?[ .-]?\d{3,4}[ .-]?\d{3,4}\b",
+                "[REDACTED_PHONE]",
+            ),
+            (r"\b(?:\d{1,3}\.){3}\d{1,3}\b", "[REDACTED_IP]"),
+        ]
+        .into_iter()
+        .map(|(pattern, replacement)| {
+            (
+                Regex::new(pattern).expect("valid redaction pattern"),
+                replacement,
+            )
+        })
+        .collect()
+    })
+}
+
+/// Masks credentials and common PII (emails, phone/card numbers, IPs) in free text.
+pub fn redact_text(text: &str) -> String {
+    patterns()
+        .iter()
+        .fold(text.to_string(), |text, (regex, replacement)| {
+            regex.replace_all(&text, *replacement).into_owned()
+        })
+}
+
+/// Redacts a JSON document in place: sensitive keys are blanked, strings are scrubbed
+/// and inline base64 data is dropped.
+pub fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_lowercase();
+                if SENSITIVE_KEYS.contains(&key.as_str()) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        // Inline images are large and never useful in a capture.
+        Value::String(text) if text.starts_with("data:") && text.contains(";base64,") => {
+            let prefix = text.split(";base64,").next().unwrap_or("data:").to_string();
+            *text = format!("{};base64,[OMITTED]", prefix);
+        }
+        Value::String(text) => *text = redact_text(text),
+        _ => {}
+    }
+}
+
+/// Final labels that mark a dotted name as a file rather than a host.
+const FILE_EXTENSIONS: &[&str] = &[
+    "bak", "bz2", "conf", "csv", "gz", "html", "ini", "js", "json", "log", "md", "py", "rs",
+    "service", "sh", "socket", "tar", "timer", "toml", "ts", "txt", "xml", "xz", "yaml", "yml",
+    "zip",
+];
+
+/// Reversible patterns, applied in order so emails are taken before their hosts.
+fn pii_patterns() -> &'static [(Regex, &'static str)] {
+    static PATTERNS: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            (r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}", "EMAIL"),
+            (r"(?i)\bbearer\s+[a-z0-9._~+/=-]{8,}", "API_KEY"),
+            (r"\b(?:sk|pk|rk)-[A-Za-z0-9_-]{16,}", "API_KEY"),
+            (r"\bAKIA[0-9A-Z]{16}\b", "API_KEY"),
+            (r"\bgh[pousr]_[A-Za-z0-9]{30,}\b", "API_KEY"),
+            (r"\bxox[abprs]-[A-Za-z0-9-]{10,}", "API_KEY"),
+            // Only the value is masked, so the model still sees which setting it was.
+            (
+                r#"(?i)\b(?:api[_-]?key|secret|token|password|passwd)\s*[:=]\s*["']?([^\s"']{8,})"#,
+                "API_KEY",
+            ),
+            (r"\b(?:\d{1,3}\.){3}\d{1,3}\b", "IP"),
+            (r"(?i)\b(?:[0-9a-f]{1,4}:){7}[0-9a-f]{1,4}\b", "IP"),
+            (r"(?i)\b(?:[0-9a-f]{1,4}:){1,6}:[0-9a-f]{1,4}\b", "IP"),
+            (
+                r"(?i)\b(?:[a-z0-9](?:[a-z0-9-]{0,61}[a-z0-9])?\.){2,}[a-z]{2,24}\b",
+                "HOST",
+            ),
+            (
+                r"(?i)\b[a-z0-9][a-z0-9-]*\.(?:local|lan|internal|corp|intranet)\b",
+                "HOST",
+            ),
+            (
+                r"(?:\+\d{1,3}[ .-]?)?\(?\d{2,4}\)?[ .-]\d{3,4}[ .-]\d{3,4}\b",
+                "PHONE",
+            ),
+            (r"\+\d{8,15}\b", "PHONE"),
+        ]
+        .into_iter()
+        .map(|(pattern, kind)| (Regex::new(pattern).expect("valid PII pattern"), kind))
+        .collect()
+    })
+}
+
+/// Swaps PII for numbered placeholders such as `[EMAIL_1]` and maps them back
+/// afterwards. The same value always gets the same placeholder.
+#[derive(Debug, Default)]
+pub struct PiiPlaceholders {
+    /// (placeholder, original) in the order they were assigned.
+    values: Vec<(String, String)>,
+}
+
+impl PiiPlaceholders {
+    pub fn mask(&mut self, text: &str) -> String {
+        pii_patterns()
+            .iter()
+            .fold(text.to_string(), |text, (regex, kind)| {
+                regex
+                    .replace_all(&text, |caps: &Captures| {
+                        let whole = caps.get(0).expect("match has a whole group");
+                        let value = caps.get(1).unwrap_or(whole);
+                        if *kind == "HOST" && is_file_name(value.as_str()) {
+                            return whole.as_str().to_string();
+                        }
+                        let placeholder = self.placeholder(kind, value.as_str());
+                        format!(
+                            "{}{}{}",
+                            &whole.as_str()[..value.start() - whole.start()],
+                            placeholder,
+                            &whole.as_str()[value.end() - whole.start()..]
+                        )
+                    })
+                    .into_owned()
+            })
+    }
+
+    /// Puts the original values back wherever their placeholders appear. Later
+    /// placeholders go first, since their values may contain earlier ones.
+    pub fn restore(&self, text: &str) -> String {
+        self.values
+            .iter()
+            .rev()
+            .fold(text.to_string(), |text, (placeholder, original)| {
+                text.replace(placeholder.as_str(), original)
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn placeholder(&mut self, kind: &str, value: &str) -> String {
+        if let Some((placeholder, _)) = self.values.iter().find(|(_, original)| original == value) {
+            return placeholder.clone();
+        }
+        let prefix = format!("[{}_", kind);
+        let number = self
+            .values
+            .iter()
+            .filter(|(placeholder, _)| placeholder.starts_with(&prefix))
+            .count()
+            + 1;
+        let placeholder = format!("{}{}]", prefix, number);
+        self.values.push((placeholder.clone(), value.to_string()));
+        placeholder
+    }
+}
+
+fn is_file_name(candidate: &str) -> bool {
+    candidate
+        .rsplit('.')
+        .next()
+        .is_some_and(|suffix| FILE_EXTENSIONS.contains(&suffix.to_ascii_lowercase().as_str()))
+}
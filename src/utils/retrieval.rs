@@ -0,0 +1,67 @@
+/// Dimensions of a term vector. Words are hashed into buckets, so collisions
+/// only add a little noise at this size.
+pub const TERM_VECTOR_DIMS: usize = 512;
+
+/// Splits a document into chunks of about `max_chars`, breaking between
+/// paragraphs where possible so a chunk keeps related lines together.
+pub fn chunk_text(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        if !current.is_empty() && current.len() + paragraph.len() + 2 > max_chars {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if paragraph.len() > max_chars {
+            // A single oversized paragraph is split between words.
+            for word in paragraph.split_whitespace() {
+                if !current.is_empty() && current.len() + word.len() + 1 > max_chars {
+                    chunks.push(std::mem::take(&mut current));
+                }
+                if !current.is_empty() {
+                    current.push(' ');
+                }
+                current.push_str(word);
+            }
+            continue;
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Unit-length bag-of-words vector of `text`, with words hashed into
+/// [`TERM_VECTOR_DIMS`] buckets and damped by log frequency.
+pub fn term_vector(text: &str) -> Vec<f32> {
+    let mut vector = vec![0f32; TERM_VECTOR_DIMS];
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+    {
+        let digest = md5::compute(word.to_lowercase().as_bytes());
+        let bucket = u32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]]) as usize
+            % TERM_VECTOR_DIMS;
+        vector[bucket] += 1.0;
+    }
+    for weight in vector.iter_mut().filter(|w| **w > 0.0) {
+        *weight = 1.0 + weight.ln();
+    }
+    let norm = vector.iter().map(|w| w * w).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for weight in &mut vector {
+            *weight /= norm;
+        }
+    }
+    vector
+}
+
+/// Cosine similarity of two vectors from [`term_vector`].
+pub fn vector_similarity(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
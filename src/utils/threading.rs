@@ -0,0 +1,55 @@
+/// Pins the calling thread to `cpu_cores` (when non-empty) and applies the `nice`
+/// value, so CPU-heavy inference can be kept away from the HTTP workers.
+pub fn tune_inference_thread(cpu_cores: &[usize], nice: Option<i32>) {
+    if cpu_cores.is_empty() && nice.is_none() {
+        return;
+    }
+    platform::tune(cpu_cores, nice);
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    pub fn tune(cpu_cores: &[usize], nice: Option<i32>) {
+        if !cpu_cores.is_empty() {
+            // SAFETY: cpu_set_t is plain data; CPU_SET only writes bits inside the set.
+            let result = unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                libc::CPU_ZERO(&mut set);
+                for core in cpu_cores {
+                    libc::CPU_SET(*core, &mut set);
+                }
+                libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+            };
+            if result != 0 {
+                tracing::warn!(
+                    "Failed to pin inference thread to cores {:?}: {}",
+                    cpu_cores,
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+
+        if let Some(nice) = nice {
+            // On Linux, PRIO_PROCESS with id 0 applies to the calling thread only.
+            let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) };
+            if result != 0 {
+                tracing::warn!(
+                    "Failed to set inference thread nice value {}: {}",
+                    nice,
+                    std::io::Error::last_os_error()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    static WARN_ONCE: std::sync::Once = std::sync::Once::new();
+
+    pub fn tune(_cpu_cores: &[usize], _nice: Option<i32>) {
+        WARN_ONCE.call_once(|| {
+            tracing::warn!("Inference thread pinning and priority are only supported on Linux");
+        });
+    }
+}
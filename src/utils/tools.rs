@@ -0,0 +1,84 @@
+use serde_json::Value;
+
+use crate::models::{ToolCall, ToolCallFunction, ToolDefinition, ToolResult};
+
+/// System prompt addendum describing the available tools to a model without
+/// native tool calling.
+pub fn generate_tool_prompt(tools: &[ToolDefinition]) -> String {
+    let definitions = tools
+        .iter()
+        .map(|tool| {
+            format!(
+                "- {}: {}\n  Parameters (JSON schema): {}",
+                tool.function.name,
+                tool.function
+                    .description
+                    .as_deref()
+                    .unwrap_or("No description"),
+                tool.function.parameters
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        r#"You can call the following tools:
+{}
+
+To call a tool, reply with only a JSON object of the form {{"tool": "<name>", "arguments": {{...}}}} and nothing else. If no tool is needed, answer normally."#,
+        definitions
+    )
+}
+
+/// Renders tool outputs from the client so the model can use them in its answer.
+pub fn format_tool_results(results: &[ToolResult]) -> String {
+    results
+        .iter()
+        .map(|result| {
+            format!(
+                "[{} ({})]\n{}",
+                result.name.as_deref().unwrap_or("tool"),
+                result.tool_call_id,
+                result.content.trim()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Extracts a tool call from a model answer that follows the format requested by
+/// [`generate_tool_prompt`]. Calls to unknown tools are ignored.
+pub fn parse_tool_call(text: &str, tools: &[ToolDefinition]) -> Option<ToolCall> {
+    for (start, _) in text.match_indices('{') {
+        let Some(Ok(value)) = serde_json::Deserializer::from_str(&text[start..])
+            .into_iter::<Value>()
+            .next()
+        else {
+            continue;
+        };
+
+        let call = value.get("tool_call").unwrap_or(&value);
+        let name = call
+            .get("tool")
+            .or_else(|| call.get("name"))
+            .and_then(Value::as_str);
+        let Some(name) = name.filter(|name| tools.iter().any(|t| t.function.name == *name)) else {
+            continue;
+        };
+        let arguments = match call.get("arguments") {
+            Some(Value::String(raw)) => raw.clone(),
+            Some(arguments) => arguments.to_string(),
+            None => "{}".to_string(),
+        };
+
+        return Some(ToolCall {
+            id: format!("call_{}", uuid::Uuid::new_v4().simple()),
+            kind: "function".to_string(),
+            function: ToolCallFunction {
+                name: name.to_string(),
+                arguments,
+            },
+        });
+    }
+    None
+}
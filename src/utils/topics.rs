@@ -0,0 +1,155 @@
+/// Label for conversations that match no topic keyword.
+pub const OTHER_TOPIC: &str = "other";
+
+/// Topics users bring to the assistant and the words that suggest them,
+/// matched against the lowercase words of the user's messages.
+const TOPICS: &[(&str, &[&str])] = &[
+    (
+        "network",
+        &[
+            "network",
+            "wifi",
+            "wi-fi",
+            "internet",
+            "dns",
+            "vpn",
+            "ethernet",
+            "ip",
+            "router",
+            "proxy",
+            "ping",
+            "firewall",
+            "connection",
+        ],
+    ),
+    (
+        "disk_storage",
+        &[
+            "disk",
+            "storage",
+            "space",
+            "partition",
+            "drive",
+            "ssd",
+            "mount",
+            "filesystem",
+            "inode",
+        ],
+    ),
+    (
+        "performance",
+        &[
+            "slow",
+            "cpu",
+            "memory",
+            "ram",
+            "freeze",
+            "freezes",
+            "lag",
+            "hang",
+            "hangs",
+            "performance",
+        ],
+    ),
+    (
+        "updates",
+        &[
+            "update", "updates", "upgrade", "upgrades", "patch", "apt", "dnf", "yum", "pacman",
+            "kernel",
+        ],
+    ),
+    (
+        "software",
+        &[
+            "install",
+            "uninstall",
+            "package",
+            "app",
+            "application",
+            "software",
+            "crash",
+            "crashes",
+        ],
+    ),
+    (
+        "accounts",
+        &[
+            "password",
+            "login",
+            "account",
+            "user",
+            "sudo",
+            "permission",
+            "permissions",
+            "locked",
+        ],
+    ),
+    (
+        "security",
+        &[
+            "virus",
+            "malware",
+            "security",
+            "ssh",
+            "certificate",
+            "encryption",
+            "antivirus",
+        ],
+    ),
+    (
+        "printing",
+        &["printer", "print", "printing", "scanner", "cups"],
+    ),
+    (
+        "display_audio",
+        &[
+            "display",
+            "monitor",
+            "screen",
+            "resolution",
+            "audio",
+            "sound",
+            "speaker",
+            "microphone",
+            "bluetooth",
+        ],
+    ),
+    (
+        "email",
+        &["email", "mail", "outlook", "thunderbird", "smtp", "imap"],
+    ),
+    (
+        "scripting",
+        &[
+            "script",
+            "bash",
+            "powershell",
+            "cron",
+            "automation",
+            "automate",
+        ],
+    ),
+    (
+        "logs",
+        &["log", "logs", "error", "errors", "journalctl", "syslog"],
+    ),
+];
+
+/// The topic whose keywords occur most often in `text`, or [`OTHER_TOPIC`].
+/// Ties go to the topic listed first.
+pub fn label_topic(text: &str) -> &'static str {
+    let text = text.to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| !(c.is_alphanumeric() || c == '-'))
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let mut best = (OTHER_TOPIC, 0);
+    for (topic, keywords) in TOPICS {
+        let hits = words.iter().filter(|word| keywords.contains(word)).count();
+        if hits > best.1 {
+            best = (topic, hits);
+        }
+    }
+    best.0
+}